@@ -1,4 +1,12 @@
 pub mod httpserver;
 pub mod ratelimiter;
+pub mod clientratelimiter;
+pub mod ratelimitstore;
+pub mod ratelimitpolicy;
 pub mod ratelimitermiddleware;
-pub mod authmiddleware;
\ No newline at end of file
+pub mod apikeys;
+pub mod jwtauth;
+pub mod authmiddleware;
+pub mod domains;
+pub mod audittrail;
+pub mod importcommand;
\ No newline at end of file
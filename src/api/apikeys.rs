@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+
+use chrono::Utc;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+
+use crate::{configuration::settings::ApiServer, constants::DEFAULT_API_KEY_NAME};
+use crate::models::apikey::{ApiKey, ApiKeyInfo, IssuedApiKey, NewApiKey, Scope};
+use crate::services::{hashserviceerror::HashServiceError, keystore::{InMemoryKeyStore, KeyStore}};
+
+use super::ratelimiter::{self, Limiter, RateLimitDecision};
+
+const HASH_ALGORITHM: &str = "sha256";
+const SALT_LENGTH: usize = 16;
+const SECRET_LENGTH: usize = 32;
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Hashes the secret of a key with a random salt, as "sha256$<salt>$<digest>".
+/// The digest is the SHA-256 of the hex salt followed by the secret.
+pub fn hash_secret(secret: &str) -> String {
+    let mut salt = [0u8; SALT_LENGTH];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let salt = hex::encode(salt);
+
+    format!("{}${}${}", HASH_ALGORITHM, salt, hex::encode(digest(&salt, secret)))
+}
+
+/// Whether the secret matches the hash, the digests are compared in constant time.
+pub fn verify_secret(secret: &str, hash: &str) -> bool {
+    let mut parts = hash.splitn(3, '$');
+    let (Some(algorithm), Some(salt), Some(expected)) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+
+    if algorithm != HASH_ALGORITHM {
+        return false;
+    }
+
+    match hex::decode(expected) {
+        Ok(expected) => digest(salt, secret).as_slice().ct_eq(&expected).into(),
+        Err(_) => false
+    }
+}
+
+fn digest(salt: &str, secret: &str) -> Vec<u8> {
+    let mut sha256 = Sha256::new();
+    sha256.update(salt);
+    sha256.update(secret);
+    sha256.finalize().to_vec()
+}
+
+#[derive(Error, Debug)]
+pub enum ApiKeyError {
+    #[error("API key '{name}' already exists")]
+    DuplicateKey {
+        name: String,
+    },
+    #[error("API key '{name}' doesn't exist")]
+    UnknownKey {
+        name: String,
+    },
+    #[error("API key '{name}' is configured in the settings and can't be changed through the API")]
+    ConfiguredKey {
+        name: String,
+    },
+    #[error("Invalid API key: {reason}")]
+    InvalidKey {
+        reason: String,
+    },
+    #[error("key store error")]
+    KeyStoreError(#[from] HashServiceError),
+}
+
+/// Random secret of an issued key, hex encoded.
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_LENGTH];
+    rand::thread_rng().fill_bytes(&mut secret);
+    hex::encode(secret)
+}
+
+/// API keys accepted by the server: the ones in the settings and the ones issued
+/// through the admin endpoints. Issued keys are cached and refreshed from the key
+/// store, so keys issued by another instance are accepted after the next refresh.
+pub struct ApiKeyRegistry {
+    configured: Vec<ApiKey>,
+    issued: RwLock<Vec<ApiKey>>,
+    store: tokio::sync::Mutex<Box<dyn KeyStore>>,
+    limiters: Mutex<HashMap<String, Box<dyn Limiter>>>,
+    required: bool,
+}
+
+impl ApiKeyRegistry {
+    /// Registry of the given keys, issued keys are only kept in memory.
+    pub fn new(keys: Vec<ApiKey>) -> Self {
+        ApiKeyRegistry::with_store(keys, Box::new(InMemoryKeyStore::new()))
+    }
+
+    pub fn with_store(keys: Vec<ApiKey>, store: Box<dyn KeyStore>) -> Self {
+        ApiKeyRegistry {
+            required: !keys.is_empty(),
+            configured: keys,
+            issued: RwLock::new(Vec::new()),
+            store: tokio::sync::Mutex::new(store),
+            limiters: Mutex::new(HashMap::new())
+        }
+    }
+
+    /// Keys of the settings. The single `api_key` becomes a key named "default" with every scope.
+    pub fn configured_keys(apiserver: &ApiServer) -> Vec<ApiKey> {
+        let mut keys = apiserver.api_keys.clone().unwrap_or_default();
+
+        if let Some(api_key) = &apiserver.api_key {
+            keys.push(ApiKey {
+                name: String::from(DEFAULT_API_KEY_NAME),
+                hash: hash_secret(api_key),
+                scopes: vec![Scope::Admin],
+                expires_at: None,
+                ratelimit: None,
+                created_at: None,
+                tenant: None
+            });
+        }
+
+        keys
+    }
+
+    pub fn from_settings(apiserver: &ApiServer) -> Self {
+        ApiKeyRegistry::new(ApiKeyRegistry::configured_keys(apiserver))
+    }
+
+    /// Loads the issued keys on startup. Requests are authenticated from then on when
+    /// any key is configured or stored, revoking every key doesn't turn it off.
+    pub async fn init(&mut self) -> Result<(), ApiKeyError> {
+        self.refresh().await?;
+        self.required |= !self.issued.get_mut().unwrap().is_empty();
+
+        Ok(())
+    }
+
+    /// Whether requests are authenticated, decided when the registry is created.
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// Key the secret belongs to, unless it has expired. Every key is checked, so the
+    /// time taken doesn't tell which key matched.
+    pub fn authenticate(&self, secret: &str) -> Option<ApiKey> {
+        let issued = self.issued.read().unwrap();
+
+        let mut authenticated = None;
+        for key in self.configured.iter().chain(issued.iter()) {
+            if verify_secret(secret, &key.hash) && authenticated.is_none() {
+                authenticated = Some(key);
+            }
+        }
+
+        let now = Utc::now();
+        authenticated.filter(|key| !key.is_expired_at(now)).cloned()
+    }
+
+    /// Takes a request from the key's own rate limit, `None` when it has none.
+    pub fn consume(&self, key: &ApiKey) -> Option<RateLimitDecision> {
+        let rate_limit = key.ratelimit.as_ref()?;
+        let mut limiters = self.limiters.lock().unwrap();
+
+        Some(limiters.entry(key.name.clone())
+            .or_insert_with(|| ratelimiter::build_limiter(rate_limit))
+            .try_consume(1))
+    }
+
+    /// Reloads the issued keys from the key store.
+    pub async fn refresh(&self) -> Result<(), ApiKeyError> {
+        let keys = self.store.lock().await.list().await?;
+        *self.issued.write().unwrap() = keys;
+
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<ApiKeyInfo> {
+        let issued = self.issued.read().unwrap();
+
+        self.configured.iter().map(|key| ApiKeyInfo::new(key, true))
+            .chain(issued.iter().map(|key| ApiKeyInfo::new(key, false)))
+            .collect()
+    }
+
+    /// Issues a new key, its secret is returned once and only its hash is kept.
+    pub async fn create(&self, new_key: NewApiKey) -> Result<IssuedApiKey, ApiKeyError> {
+        validate(&new_key)?;
+        if self.is_configured(&new_key.name) {
+            return Err(ApiKeyError::DuplicateKey { name: new_key.name });
+        }
+
+        let mut store = self.store.lock().await;
+        if store.find(&new_key.name).await?.is_some() {
+            return Err(ApiKeyError::DuplicateKey { name: new_key.name });
+        }
+
+        let secret = generate_secret();
+        let key = ApiKey {
+            name: new_key.name,
+            hash: hash_secret(&secret),
+            scopes: new_key.scopes,
+            expires_at: new_key.expires_at,
+            ratelimit: new_key.ratelimit,
+            created_at: Some(Utc::now()),
+            tenant: new_key.tenant
+        };
+        store.upsert(&key).await?;
+
+        let issued = IssuedApiKey { key: ApiKeyInfo::new(&key, false), secret };
+        self.issued.write().unwrap().push(key);
+
+        Ok(issued)
+    }
+
+    /// Revokes an issued key, it's rejected right away on this instance.
+    pub async fn revoke(&self, name: &str) -> Result<(), ApiKeyError> {
+        if self.is_configured(name) {
+            return Err(ApiKeyError::ConfiguredKey { name: name.to_string() });
+        }
+
+        if !self.store.lock().await.delete(name).await? {
+            return Err(ApiKeyError::UnknownKey { name: name.to_string() });
+        }
+
+        self.issued.write().unwrap().retain(|key| key.name != name);
+        self.limiters.lock().unwrap().remove(name);
+
+        Ok(())
+    }
+
+    /// Replaces the secret of an issued key, the previous secret stops working.
+    pub async fn rotate(&self, name: &str) -> Result<IssuedApiKey, ApiKeyError> {
+        if self.is_configured(name) {
+            return Err(ApiKeyError::ConfiguredKey { name: name.to_string() });
+        }
+
+        let mut store = self.store.lock().await;
+        let mut key = match store.find(name).await? {
+            Some(key) => key,
+            None => return Err(ApiKeyError::UnknownKey { name: name.to_string() })
+        };
+
+        let secret = generate_secret();
+        key.hash = hash_secret(&secret);
+        store.upsert(&key).await?;
+
+        let issued = IssuedApiKey { key: ApiKeyInfo::new(&key, false), secret };
+        let mut keys = self.issued.write().unwrap();
+        keys.retain(|issued_key| issued_key.name != name);
+        keys.push(key);
+
+        Ok(issued)
+    }
+
+    fn is_configured(&self, name: &str) -> bool {
+        self.configured.iter().any(|key| key.name == name)
+    }
+}
+
+/// Names end up in the keys of the stores, so they are kept to a safe set of characters.
+fn validate(new_key: &NewApiKey) -> Result<(), ApiKeyError> {
+    let valid_name = !new_key.name.is_empty()
+        && new_key.name.len() <= MAX_NAME_LENGTH
+        && new_key.name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid_name {
+        return Err(ApiKeyError::InvalidKey {
+            reason: format!("the name has to be 1 to {} letters, digits, '-', '_' or '.'", MAX_NAME_LENGTH)
+        });
+    }
+
+    if new_key.scopes.is_empty() {
+        return Err(ApiKeyError::InvalidKey { reason: String::from("at least one scope is required") });
+    }
+
+    if new_key.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(ApiKeyError::InvalidKey { reason: String::from("the expiry has to be in the future") });
+    }
+
+    Ok(())
+}
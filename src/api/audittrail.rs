@@ -0,0 +1,38 @@
+use crate::models::auditevent::{AuditEvent, AuditQuery};
+use crate::services::{auditlog::{AuditLog, InMemoryAuditLog}, hashserviceerror::HashServiceError};
+
+/// Records who changed links and keys, in the audit log of the storage backend.
+pub struct AuditTrail {
+    log: tokio::sync::Mutex<Box<dyn AuditLog>>,
+}
+
+impl AuditTrail {
+    /// Trail whose events are only kept in memory.
+    pub fn new() -> Self {
+        AuditTrail::with_log(Box::new(InMemoryAuditLog::new()))
+    }
+
+    pub fn with_log(log: Box<dyn AuditLog>) -> Self {
+        AuditTrail {
+            log: tokio::sync::Mutex::new(log)
+        }
+    }
+
+    /// Appends an event. The change it records was already made, so a failure is logged
+    /// rather than failing the request.
+    pub async fn record(&self, event: AuditEvent) {
+        if let Err(err) = self.log.lock().await.record(&event).await {
+            log::error!("Failed to record audit event {:?}: {}", event, err);
+        }
+    }
+
+    pub async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>, HashServiceError> {
+        self.log.lock().await.query(query).await
+    }
+}
+
+impl Default for AuditTrail {
+    fn default() -> Self {
+        AuditTrail::new()
+    }
+}
@@ -1,20 +1,58 @@
 use actix_web::{
     body::EitherBody,
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error, HttpResponse,
+    error, http::header, Error, HttpMessage, HttpResponse,
 };
 use futures_util::future::LocalBoxFuture;
 use std::{
     future::{ready, Ready},
+    sync::Arc,
 };
 
+use crate::{constants::API_KEY_HEADER, models::apikey::{Principal, Scope}};
+
+use super::apikeys::ApiKeyRegistry;
+use super::jwtauth::JwtValidator;
+use super::ratelimitermiddleware::{self, UserError};
+
+/// Scope a request needs, decided by the route it was matched to. `None` lets the
+/// request through without authenticating it.
+pub type ScopeSelector = fn(&ServiceRequest) -> Option<Scope>;
+
+/// Whether callers of a tenant may use the route the request was matched to.
+pub type TenantRoutes = fn(&ServiceRequest) -> bool;
+
+/// Authenticates requests by their `X-API-Key` header or, when JWT is configured,
+/// by their `Authorization: Bearer` token.
 pub struct ApiKeyMiddleware {
-    api_key: Option<String>,
+    registry: Arc<ApiKeyRegistry>,
+    jwt: Option<Arc<JwtValidator>>,
+    required_scope: ScopeSelector,
+    optional: bool,
+    tenant_routes: Option<TenantRoutes>,
 }
 
 impl ApiKeyMiddleware {
-    pub fn new(api_key: Option<String>) -> Self {
-        Self { api_key }
+    pub fn new(registry: Arc<ApiKeyRegistry>, required_scope: ScopeSelector) -> Self {
+        Self { registry, jwt: None, required_scope, optional: false, tenant_routes: None }
+    }
+
+    /// Restricts callers of a tenant to some of the routes, whatever their scopes.
+    pub fn tenant_routes(mut self, tenant_routes: TenantRoutes) -> Self {
+        self.tenant_routes = Some(tenant_routes);
+        self
+    }
+
+    /// Lets requests without credentials through anonymously, requests with
+    /// credentials are still authenticated.
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    pub fn with_jwt(mut self, jwt: Option<Arc<JwtValidator>>) -> Self {
+        self.jwt = jwt;
+        self
     }
 }
 
@@ -33,14 +71,22 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(ApiKeyMiddlewareService {
             service,
-            api_key: self.api_key.clone(),
+            registry: self.registry.clone(),
+            jwt: self.jwt.clone(),
+            required_scope: self.required_scope,
+            optional: self.optional,
+            tenant_routes: self.tenant_routes,
         }))
     }
 }
 
 pub struct ApiKeyMiddlewareService<S> {
     service: S,
-    api_key: Option<String>,
+    registry: Arc<ApiKeyRegistry>,
+    jwt: Option<Arc<JwtValidator>>,
+    required_scope: ScopeSelector,
+    optional: bool,
+    tenant_routes: Option<TenantRoutes>,
 }
 
 impl<S, B> Service<ServiceRequest> for ApiKeyMiddlewareService<S>
@@ -56,44 +102,100 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let api_key = self.api_key.clone();
-        
-        // If no API key is configured, skip authentication
-        if api_key.is_none() {
-            let fut = self.service.call(req);
-            return Box::pin(async move {
-                let res = fut.await?;
-                Ok(res.map_into_left_body())
-            });
-        }
+        let auth_disabled = !self.registry.is_required() && self.jwt.is_none();
+        let anonymous = self.optional && bearer_token(&req).is_none() && !req.headers().contains_key(API_KEY_HEADER);
+
+        // If no API key or JWT is configured, or the route needs none, skip authentication
+        let required_scope = match (self.required_scope)(&req) {
+            Some(scope) if !auth_disabled && !anonymous => scope,
+            _ => {
+                let fut = self.service.call(req);
+                return Box::pin(async move {
+                    let res = fut.await?;
+                    Ok(res.map_into_left_body())
+                });
+            }
+        };
 
-        let configured_key = api_key.unwrap();
-        
-        // Check API key
-        let valid_key = if let Some(request_api_key) = req.headers().get("X-API-Key") {
-            if let Ok(key_str) = request_api_key.to_str() {
-                key_str == configured_key
-            } else {
-                false
+        let principal = match (bearer_token(&req), &self.jwt) {
+            (Some(token), Some(jwt)) => match jwt.validate(token) {
+                Ok(principal) => principal,
+                Err(err) => {
+                    log::debug!("Rejected bearer token: {}", err);
+                    return reject(req, HttpResponse::Unauthorized()
+                        .json(serde_json::json!({
+                            "error": "Invalid bearer token"
+                        })));
+                }
+            },
+            _ => match self.authenticate_api_key(&req) {
+                Ok(principal) => principal,
+                Err(response) => return reject(req, response)
             }
-        } else {
-            false
         };
 
-        if !valid_key {
-            let response = HttpResponse::Unauthorized()
+        if !principal.has_scope(required_scope) {
+            return reject(req, HttpResponse::Forbidden()
                 .json(serde_json::json!({
-                    "error": "Invalid or missing API key"
-                }));
-            return Box::pin(async move {
-                Ok(req.into_response(response).map_into_right_body())
-            });
+                    "error": format!("'{}' lacks the '{}' scope", principal.name, required_scope)
+                })));
+        }
+
+        if let (Some(tenant), Some(tenant_routes)) = (&principal.tenant, self.tenant_routes) {
+            if !tenant_routes(&req) {
+                let message = format!("'{}' of tenant '{}' can't use this endpoint", principal.name, tenant);
+                return reject(req, HttpResponse::Forbidden()
+                    .json(serde_json::json!({
+                        "error": message
+                    })));
+            }
         }
 
+        req.extensions_mut().insert(principal);
+
         let fut = self.service.call(req);
         Box::pin(async move {
             let res = fut.await?;
             Ok(res.map_into_left_body())
         })
     }
-}
\ No newline at end of file
+}
+
+impl<S> ApiKeyMiddlewareService<S> {
+    /// Caller of the API key of the request, within the key's own rate limit.
+    fn authenticate_api_key(&self, req: &ServiceRequest) -> Result<Principal, HttpResponse> {
+        let api_key = req.headers().get(API_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+            .and_then(|key| self.registry.authenticate(key));
+
+        let api_key = match api_key {
+            Some(api_key) => api_key,
+            None => return Err(HttpResponse::Unauthorized()
+                .json(serde_json::json!({
+                    "error": "Invalid or missing API key"
+                })))
+        };
+
+        let decision = self.registry.consume(&api_key);
+        if let Some(decision) = decision.filter(|decision| !decision.allowed) {
+            let mut response = error::ResponseError::error_response(&UserError::TooManyRequests);
+            ratelimitermiddleware::insert_rate_limit_headers(response.headers_mut(), &decision);
+            return Err(response);
+        }
+
+        Ok(Principal { id: format!("key:{}", api_key.name), name: api_key.name, scopes: api_key.scopes, tenant: api_key.tenant })
+    }
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<&str> {
+    req.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim())
+}
+
+fn reject<B: 'static>(req: ServiceRequest, response: HttpResponse) -> LocalBoxFuture<'static, Result<ServiceResponse<EitherBody<B>>, Error>> {
+    Box::pin(async move {
+        Ok(req.into_response(response).map_into_right_body())
+    })
+}
@@ -0,0 +1,137 @@
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroUsize;
+
+use actix_web::http::header::HeaderMap;
+use ipnet::IpNet;
+use lru::LruCache;
+
+use crate::{configuration::settings::{ApiServer, RateLimit, RateLimitKey, DEFAULT_RATE_LIMIT}, constants::DEFAULT_RATE_LIMIT_MAX_CLIENTS, models::apikey::Principal};
+
+use super::ratelimiter::{self, Limiter, RateLimitDecision};
+use super::ratelimitstore::RateLimitStoreError;
+
+/// Headers a single proxy or CDN in front of the service sets to the client IP, most trusted first
+const CLIENT_IP_HEADERS: [&str; 3] = ["CF-Connecting-IP", "True-Client-IP", "X-Real-IP"];
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// Limiters keyed by client, so one noisy client can't lock out the others.
+pub struct ClientRateLimiter {
+    rate_limit: RateLimit,
+    buckets: LruCache<String, Box<dyn Limiter>>,
+}
+
+impl ClientRateLimiter {
+    pub fn new(rate_limit_options: Option<RateLimit>) -> Self {
+        let rate_limit = rate_limit_options.unwrap_or(DEFAULT_RATE_LIMIT);
+        let max_clients = rate_limit.max_clients.unwrap_or(DEFAULT_RATE_LIMIT_MAX_CLIENTS);
+
+        ClientRateLimiter {
+            rate_limit,
+            buckets: LruCache::new(NonZeroUsize::new(max_clients).unwrap_or(NonZeroUsize::MIN)),
+        }
+    }
+
+    pub fn consume(&mut self, key: &str, tokens: usize) -> RateLimitDecision {
+        let rate_limit = &self.rate_limit;
+        self.buckets.get_or_insert_mut(key.to_string(), || ratelimiter::build_limiter(rate_limit))
+            .try_consume(tokens)
+    }
+
+    /// Number of client buckets currently kept in memory.
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+}
+
+/// Identifies the client of a request: the caller the auth middleware authenticated, or
+/// else its IP. Unverified headers and the secrets of API keys never end up in a bucket key.
+pub fn client_key(key_extractor: RateLimitKey, principal: Option<&Principal>, client_ip: Option<IpAddr>) -> String {
+    if key_extractor == RateLimitKey::Global {
+        return String::from("global");
+    }
+
+    if key_extractor == RateLimitKey::ApiKeyOrIp {
+        if let Some(principal) = principal {
+            return principal.id.clone();
+        }
+    }
+
+    match client_ip {
+        Some(ip) => format!("ip:{}", ip),
+        None => String::from("ip:unknown")
+    }
+}
+
+/// Proxies allowed to tell the IP of the client they forward a request for. Anyone can send
+/// the proxy headers, so they are ignored unless the connection comes from a trusted proxy.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpNet>) -> Self {
+        TrustedProxies { networks }
+    }
+
+    pub fn from_settings(apiserver: &ApiServer) -> Result<Self, RateLimitStoreError> {
+        let networks = apiserver.trusted_proxies.iter().flatten()
+            .map(|network| parse_network(network).ok_or_else(|| RateLimitStoreError::InvalidTrustedProxy {
+                network: network.clone()
+            }))
+            .collect::<Result<Vec<IpNet>, RateLimitStoreError>>()?;
+
+        Ok(TrustedProxies::new(networks))
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(&ip))
+    }
+
+    /// IP of the client: the peer address of the connection, or the address a trusted
+    /// proxy forwarded the request for. `X-Forwarded-For` is read from the right, every
+    /// proxy appends the address it got the request from, so the first address that isn't
+    /// a trusted proxy is the client.
+    pub fn client_ip(&self, headers: &HeaderMap, peer_addr: Option<SocketAddr>) -> Option<IpAddr> {
+        let peer_ip = peer_addr.map(|addr| addr.ip())?;
+        if !self.is_trusted(peer_ip) {
+            return Some(peer_ip);
+        }
+
+        let header_ip = CLIENT_IP_HEADERS.iter()
+            .filter_map(|name| headers.get(*name))
+            .filter_map(|value| value.to_str().ok())
+            .find_map(|value| value.trim().parse::<IpAddr>().ok());
+        if let Some(ip) = header_ip {
+            return Some(ip);
+        }
+
+        let forwarded: Vec<&str> = headers.get_all(FORWARDED_FOR_HEADER)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        let mut client_ip = peer_ip;
+        for address in forwarded.into_iter().rev() {
+            if !self.is_trusted(client_ip) {
+                break;
+            }
+            match address.trim().parse::<IpAddr>() {
+                Ok(ip) => client_ip = ip,
+                // Whatever is left of a malformed entry can't be trusted
+                Err(_) => break
+            }
+        }
+
+        Some(client_ip)
+    }
+}
+
+/// Parses a network in CIDR notation, a single address is a network of its own.
+pub(crate) fn parse_network(network: &str) -> Option<IpNet> {
+    network.parse::<IpNet>().ok()
+        .or_else(|| network.parse::<IpAddr>().ok().map(IpNet::from))
+}
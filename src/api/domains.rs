@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use chrono::Utc;
+use thiserror::Error;
+
+use crate::models::domain::{Domain, NewDomain};
+use crate::services::{domainstore::{DomainStore, InMemoryDomainStore}, hashserviceerror::HashServiceError};
+
+const MAX_DOMAIN_LENGTH: usize = 253;
+const MAX_LABEL_LENGTH: usize = 63;
+
+#[derive(Error, Debug)]
+pub enum DomainError {
+    #[error("domain '{name}' already exists")]
+    DuplicateDomain {
+        name: String,
+    },
+    #[error("domain '{name}' doesn't exist")]
+    UnknownDomain {
+        name: String,
+    },
+    #[error("Invalid domain: {reason}")]
+    InvalidDomain {
+        reason: String,
+    },
+    #[error("domain store error")]
+    DomainStoreError(#[from] HashServiceError),
+}
+
+/// Host name of a `Host` header, lowercase and without the port.
+pub fn host_name(host: &str) -> String {
+    let host = host.trim().to_ascii_lowercase();
+    match host.rsplit_once(':') {
+        // An IPv6 address keeps its colons, its port follows the closing bracket
+        Some((name, port)) if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) && !name.ends_with(':') => name.to_string(),
+        _ => host
+    }
+}
+
+/// Custom domains short links are served on. Domains are cached and refreshed from
+/// the domain store, so domains added by another instance are served after the next refresh.
+pub struct DomainRegistry {
+    domains: RwLock<HashMap<String, Domain>>,
+    store: tokio::sync::Mutex<Box<dyn DomainStore>>,
+}
+
+impl DomainRegistry {
+    /// Registry whose domains are only kept in memory.
+    pub fn new() -> Self {
+        DomainRegistry::with_store(Box::new(InMemoryDomainStore::new()))
+    }
+
+    pub fn with_store(store: Box<dyn DomainStore>) -> Self {
+        DomainRegistry {
+            domains: RwLock::new(HashMap::new()),
+            store: tokio::sync::Mutex::new(store)
+        }
+    }
+
+    /// Domain a request was sent to, by its `Host` header.
+    pub fn find(&self, host: &str) -> Option<Domain> {
+        self.domains.read().unwrap().get(&host_name(host)).cloned()
+    }
+
+    pub fn list(&self) -> Vec<Domain> {
+        let mut domains: Vec<Domain> = self.domains.read().unwrap().values().cloned().collect();
+        domains.sort_by(|a, b| a.name.cmp(&b.name));
+        domains
+    }
+
+    /// Reloads the domains from the domain store.
+    pub async fn refresh(&self) -> Result<(), DomainError> {
+        let domains = self.store.lock().await.list().await?;
+        *self.domains.write().unwrap() = domains.into_iter()
+            .map(|domain| (domain.name.clone(), domain))
+            .collect();
+
+        Ok(())
+    }
+
+    pub async fn add(&self, new_domain: NewDomain) -> Result<Domain, DomainError> {
+        let name = new_domain.name.trim().to_ascii_lowercase();
+        validate(&name)?;
+
+        let mut store = self.store.lock().await;
+        // Another instance may have added the domain since the last refresh
+        if store.list().await?.iter().any(|domain| domain.name == name) {
+            return Err(DomainError::DuplicateDomain { name });
+        }
+
+        let domain = Domain {
+            name,
+            tenant: new_domain.tenant,
+            created_at: Some(Utc::now())
+        };
+        store.upsert(&domain).await?;
+        self.domains.write().unwrap().insert(domain.name.clone(), domain.clone());
+
+        Ok(domain)
+    }
+
+    /// Removes a domain, its links are no longer served.
+    pub async fn remove(&self, name: &str) -> Result<(), DomainError> {
+        let name = name.to_ascii_lowercase();
+        if !self.store.lock().await.delete(&name).await? {
+            return Err(DomainError::UnknownDomain { name });
+        }
+
+        self.domains.write().unwrap().remove(&name);
+
+        Ok(())
+    }
+}
+
+impl Default for DomainRegistry {
+    fn default() -> Self {
+        DomainRegistry::new()
+    }
+}
+
+/// Names are host names: dot separated labels of letters, digits and '-'.
+fn validate(name: &str) -> Result<(), DomainError> {
+    let labels: Vec<&str> = name.split('.').collect();
+    let valid_labels = labels.iter().all(|label| {
+        !label.is_empty()
+            && label.len() <= MAX_LABEL_LENGTH
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+
+    if name.len() > MAX_DOMAIN_LENGTH || labels.len() < 2 || !valid_labels {
+        return Err(DomainError::InvalidDomain {
+            reason: format!("'{}' is not a host name like 'go.example.com'", name)
+        });
+    }
+
+    Ok(())
+}
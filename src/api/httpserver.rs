@@ -1,24 +1,147 @@
-use crate::configuration::settings::Settings;
-use crate::constants::{APPLICATION_JSON, TEXT_HTML};
+use crate::configuration::settings::{ApiServer, Settings};
+use crate::name_of;
+use crate::constants::{APPLICATION_JSON, APPLICATION_NDJSON, DEFAULT_AUDIT_LIMIT, EXPORT_PAGE_SIZE, MAX_AUDIT_LIMIT, MAX_BULK_BODY_SIZE, MAX_BULK_LINKS, NDJSON_PAGE_SIZE, REPLICA_HEADER, TEXT_HTML};
+use crate::constants::{DEFAULT_ANTI_ENTROPY_RANGES, DEFAULT_LINK_SCHEME, DEFAULT_API_KEY_REFRESH_INTERVAL, DEFAULT_DESTINATION_RULES_RELOAD_INTERVAL, DEFAULT_DOMAIN_REFRESH_INTERVAL, DEFAULT_JWKS_REFRESH_INTERVAL, DEFAULT_TRASH_PURGE_INTERVAL, DEFAULT_TRASH_RETENTION_DAYS, RATE_LIMIT_POLICY_ADMIN, RATE_LIMIT_POLICY_REDIRECT, RATE_LIMIT_POLICY_SHORTEN, RATE_LIMIT_POLICY_SUMMARY};
+use crate::models::apikey::{ApiKeyInfo, NewApiKey, Principal, Scope};
+use crate::models::auditevent::{self, AuditAction, AuditEvent, AuditQuery, FieldChange};
+use crate::models::builders::build_link_info;
+use crate::models::domain::{Domain, NewDomain};
+use crate::models::linkinfo::{self, LinkInfo, LinkStatus};
 use crate::models::queryparams::QueryParams;
+use crate::services::antientropy::{self, MerkleTree};
+use crate::services::bulkimport::{self, BulkFormat, BulkLink};
+use crate::services::cluster::Cluster;
+use crate::services::destinationrules::{self, DestinationFilter};
+use crate::services::hashfunction;
 use crate::services::hashservice::HashService;
+use crate::services::hashserviceerror::{build_invalid_configuration_error, HashServiceError};
+use crate::services::hintstore::Hint;
+use crate::services::linkexport::ExportQuery;
+use crate::services::linkimport::{self, ImportReport, ImportSource};
+use crate::services::{auditlog, domainstore, keystore};
+use crate::services::tenants::{QuotaError, Tenants};
+use crate::services::urlnormalizer::{UrlError, UrlNormalizer};
+use crate::services::urlsafetychecker::{SafetyCheckers, Verdict};
 use crate::stats::collector;
 
 use actix_cors::Cors;
-use actix_web::dev::Service;
-use actix_web::{http, middleware, web, App, HttpRequest, HttpResponse};
+use actix_web::dev::{Service, ServiceRequest};
+use actix_web::{http, middleware, web, App, HttpMessage, HttpRequest, HttpResponse};
 use actix_web::HttpServer;
+use futures_util::StreamExt;
 use std::io;
-use std::sync::{Mutex, Arc};
+use std::net::IpAddr;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
 use serde::{Serialize, Deserialize};
+use tokio::sync::Mutex;
 
-use super::ratelimiter::RateLimiter;
+use super::ratelimitpolicy::RateLimitPolicies;
 use super::ratelimitermiddleware::RateLimiterMiddlewareService;
+use super::apikeys::{ApiKeyError, ApiKeyRegistry};
+use super::audittrail::AuditTrail;
+use super::clientratelimiter::TrustedProxies;
+use super::domains::{self, DomainError, DomainRegistry};
 use super::authmiddleware;
+use super::jwtauth::JwtValidator;
 
 #[derive(Debug, Deserialize)]
 pub struct ShortenRequest {
-   long_url: String
+   long_url: String,
+   /// Custom domain to shorten the url on, by default the domain the request was sent to
+   domain: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShortenResponse {
+    #[serde(flatten)]
+    pub link: LinkInfo,
+    /// Absolute short link, on the custom domain of the link
+    pub short_link: String,
+    /// When the link expires, always present so clients can rely on the field
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Image of the short link, when a QR code service is configured
+    pub qr_code_url: Option<String>,
+    /// Summary of the link with its statistics
+    pub stats_url: String,
+}
+
+/// Short url of a link, the path of a tenant's link also names the tenant.
+#[derive(Debug, Deserialize)]
+pub struct LinkPath {
+   short_url: String
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncParams {
+   ranges: Option<usize>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisableLinksRequest {
+   domain: String
+}
+
+/// Export to import through `POST /admin/links/import`.
+#[derive(Debug, Deserialize)]
+pub struct ImportParams {
+   source: ImportSource,
+   /// Report what would be imported without writing anything
+   #[serde(default)]
+   dry_run: bool
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkResponse {
+    pub created: usize,
+    /// Rows whose link was created before, they are returned as they are
+    pub existing: usize,
+    pub failed: usize,
+    /// Result of every row, in the order of the rows
+    pub results: Vec<BulkResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkResult {
+    /// Row of the request, starting at 1
+    pub row: usize,
+    pub status: BulkStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub short_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub short_link: Option<String>,
+    /// Machine readable kind of the error of a failed row
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl BulkResult {
+    fn failed(row: usize, error: &str, message: String) -> Self {
+        BulkResult { row, status: BulkStatus::Failed, short_url: None, short_link: None, error: Some(error.to_string()), message: Some(message) }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkStatus {
+    Created,
+    Existing,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisableLinksResponse {
+    pub domain: String,
+    /// Links disabled, over the shared links and the links of every tenant
+    pub disabled: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddNodeRequest {
+   hostname: String
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -26,28 +149,149 @@ struct Response {
     message: String
 }
 
+/// Rejected url to shorten, `error` tells the kind of the problem.
+#[derive(Debug, Serialize)]
+struct UrlErrorResponse {
+    error: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_schemes: Option<Vec<String>>,
+}
+
 pub struct AppData {
     pub settings: Settings,
-    pub hash_service: Box<dyn HashService>
+    /// Shared links, of callers without a tenant
+    pub hash_service: Box<dyn HashService>,
+    pub tenants: Tenants,
+    pub url_normalizer: UrlNormalizer,
+    pub destinations: Arc<DestinationFilter>,
+    pub url_safety: Arc<SafetyCheckers>,
+    pub disabled_page: DisabledPage,
+    pub audit: Arc<AuditTrail>
+}
+
+/// Answer of links disabled by an admin.
+#[derive(Clone)]
+pub struct DisabledPage {
+    pub status: http::StatusCode,
+    /// HTML page, an empty body without one
+    pub body: Option<String>,
+}
+
+impl DisabledPage {
+    pub fn from_settings(settings: &Settings) -> Result<Self, HashServiceError> {
+        let disabled_links = settings.disabled_links.clone().unwrap_or_default();
+        let invalid = |reason: String| build_invalid_configuration_error(settings.mode.to_string().as_str(), name_of!(disabled_links in Settings), &reason);
+
+        let status = match disabled_links.status {
+            None | Some(410) => http::StatusCode::GONE,
+            Some(404) => http::StatusCode::NOT_FOUND,
+            Some(status) => return Err(invalid(format!("status {} is neither 404 nor 410", status)))
+        };
+        let body = match &disabled_links.page_file {
+            Some(page_file) => Some(std::fs::read_to_string(page_file)
+                .map_err(|err| invalid(format!("can't read '{}': {}", page_file, err)))?),
+            None => None
+        };
+
+        Ok(DisabledPage { status, body })
+    }
+
+    fn response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status);
+        match &self.body {
+            Some(body) => response.content_type(TEXT_HTML).body(body.clone()),
+            None => response.finish()
+        }
+    }
+}
+
+impl Default for DisabledPage {
+    fn default() -> Self {
+        DisabledPage { status: http::StatusCode::GONE, body: None }
+    }
+}
+
+impl AppData {
+    pub fn new(settings: Settings, hash_service: Box<dyn HashService>) -> Self {
+        let url_normalizer = UrlNormalizer::from_settings(&settings);
+        AppData { settings, hash_service, tenants: Tenants::default(), url_normalizer, destinations: Arc::default(), url_safety: Arc::default(), disabled_page: DisabledPage::default(), audit: Arc::default() }
+    }
+
+    /// Links of the tenant, the shared links without one. `None` for an unknown tenant.
+    pub fn links_of(&mut self, tenant: Option<&str>) -> Option<&mut Box<dyn HashService>> {
+        match tenant {
+            Some(tenant) => self.tenants.get_mut(tenant).map(|tenant| &mut tenant.hash_service),
+            None => Some(&mut self.hash_service)
+        }
+    }
 }
 
 pub async fn start_http_server(settings: Settings, hash_service: Box<dyn HashService>) -> io::Result<()> {
     let application_url = settings.apiserver.application_url.clone();
     let allow_origin = settings.apiserver.allow_origin.clone();
-    let api_key = settings.apiserver.api_key.clone();
-    let rate_limiter = Arc::new(Mutex::new(RateLimiter::new(settings.ratelimit)));
+    let key_store = keystore::create_key_store(&settings).await
+        .map_err(io::Error::other)?;
+    let mut api_keys = ApiKeyRegistry::with_store(ApiKeyRegistry::configured_keys(&settings.apiserver), key_store);
+    api_keys.init().await
+        .map_err(io::Error::other)?;
+    let api_keys = Arc::new(api_keys);
+    start_key_refresh(api_keys.clone());
+    let domain_store = domainstore::create_domain_store(&settings).await
+        .map_err(io::Error::other)?;
+    let domains = Arc::new(DomainRegistry::with_store(domain_store));
+    domains.refresh().await
+        .map_err(io::Error::other)?;
+    start_domain_refresh(domains.clone());
+    let jwt = match &settings.jwt {
+        Some(jwt_config) => {
+            let jwt = Arc::new(JwtValidator::new(jwt_config).await.map_err(io::Error::other)?);
+            start_jwks_refresh(jwt.clone(), jwt_config.jwks_refresh_interval.unwrap_or(DEFAULT_JWKS_REFRESH_INTERVAL));
+            Some(jwt)
+        },
+        None => None
+    };
+    let policies = RateLimitPolicies::new(&settings).await
+        .map_err(io::Error::other)?;
+    let trusted_proxies = web::Data::new(TrustedProxies::from_settings(&settings.apiserver)
+        .map_err(io::Error::other)?);
+    let tenants = Tenants::from_settings(&settings).await
+        .map_err(io::Error::other)?;
+
+    let destinations = Arc::new(DestinationFilter::from_settings(&settings)
+        .map_err(io::Error::other)?);
+    start_destination_reload(destinations.clone());
+    let url_safety = Arc::new(SafetyCheckers::from_settings(&settings)
+        .map_err(io::Error::other)?);
+    let disabled_page = DisabledPage::from_settings(&settings)
+        .map_err(io::Error::other)?;
+    let trash = settings.trash.clone().unwrap_or_default();
+    let audit_log = auditlog::create_audit_log(&settings).await
+        .map_err(io::Error::other)?;
 
-    let appdata = web::Data::new(Mutex::new(AppData { settings, hash_service }));
+    let mut data = AppData::new(settings, hash_service);
+    data.tenants = tenants;
+    data.destinations = destinations;
+    data.url_safety = url_safety;
+    data.disabled_page = disabled_page;
+    data.audit = Arc::new(AuditTrail::with_log(audit_log));
+    let appdata = web::Data::new(Mutex::new(data));
+    start_trash_purge(appdata.clone(),
+        trash.retention_days.unwrap_or(DEFAULT_TRASH_RETENTION_DAYS),
+        trash.purge_interval.unwrap_or(DEFAULT_TRASH_PURGE_INTERVAL));
 
     HttpServer::new(move|| {
-        let rate_limiter = rate_limiter.clone();
+        let shorten_policy = policies.get(RATE_LIMIT_POLICY_SHORTEN);
+        let admin_policy = policies.get(RATE_LIMIT_POLICY_ADMIN);
+        let redirect_policy = policies.get(RATE_LIMIT_POLICY_REDIRECT);
+        let summary_policy = policies.get(RATE_LIMIT_POLICY_SUMMARY);
 
         let cors = Cors::default()
             .allowed_origin(&allow_origin)
             .allowed_origin_fn(|origin, _req_head| {
                 origin.as_bytes().ends_with(b".ivanenkomak.com")
             })
-            .allowed_methods(vec!["GET", "POST", "DELETE", "OPTIONS"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
             .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
             .allowed_header(http::header::CONTENT_TYPE)
             .allowed_header("X-API-Key")
@@ -61,24 +305,406 @@ pub async fn start_http_server(settings: Settings, hash_service: Box<dyn HashSer
             .service(hello)
             .service(
                 web::scope("/admin")
-                    .wrap(authmiddleware::ApiKeyMiddleware::new(api_key.clone()))
+                    // The last middleware runs first: callers are authenticated before they are rate limited
+                    .wrap_fn(move|req, srv| RateLimiterMiddlewareService::new(srv, admin_policy.clone()).call(req))
+                    .wrap(authmiddleware::ApiKeyMiddleware::new(api_keys.clone(), admin_scope).with_jwt(jwt.clone()).tenant_routes(tenant_admin_route))
                     .service(urls)
+                    .service(export)
+                    .service(sync_digest)
+                    .service(sync_range)
+                    .service(cluster_nodes)
+                    .service(add_node)
+                    .service(drain_node)
+                    .service(remove_node)
+                    .service(list_keys)
+                    .service(create_key)
+                    .service(revoke_key)
+                    .service(rotate_key)
+                    .service(tenant_usage)
+                    .service(disable_links)
+                    .service(list_domains)
+                    .service(add_domain)
+                    .service(remove_domain)
+                    .service(audit_events)
+                    .service(web::resource("/links/bulk")
+                        .app_data(web::PayloadConfig::new(MAX_BULK_BODY_SIZE))
+                        .route(web::post().to(bulk_shorten)))
+                    .service(web::resource("/links/import")
+                        .app_data(web::PayloadConfig::new(MAX_BULK_BODY_SIZE))
+                        .route(web::post().to(import_links)))
+                    .service(upsert)
                     .service(delete)
+                    .service(restore)
+            )
+            .service(
+                web::scope("/me")
+                    .wrap(authmiddleware::ApiKeyMiddleware::new(api_keys.clone(), public_scope).with_jwt(jwt.clone()))
+                    .service(my_links)
             )
-            .service(web::resource("/shorten").wrap_fn(move|req, srv| 
+            .service(web::resource("/shorten")
+                .wrap_fn(move|req, srv| 
                 {
-                    let rate_limiter = rate_limiter.clone();
-                    RateLimiterMiddlewareService::new(srv, rate_limiter).call(req)
-                }).route(web::post().to(shorten)))
-            .service(redirect)
-            .service(summary)
+                    let policy = shorten_policy.clone();
+                    RateLimiterMiddlewareService::new(srv, policy).call(req)
+                })
+                .wrap(authmiddleware::ApiKeyMiddleware::new(api_keys.clone(), public_scope).with_jwt(jwt.clone()).optional())
+                .route(web::post().to(shorten)))
+            // Registered last, the empty scope takes every request that's left
+            .service(
+                web::scope("")
+                    .wrap_fn(move|req, srv| {
+                        let policy = match req.match_pattern().as_deref() {
+                            Some("/{short_url}/summary" | "/t/{tenant}/{short_url}/summary") => summary_policy.clone(),
+                            _ => redirect_policy.clone()
+                        };
+                        RateLimiterMiddlewareService::new(srv, policy).call(req)
+                    })
+                    .wrap(authmiddleware::ApiKeyMiddleware::new(api_keys.clone(), public_scope).with_jwt(jwt.clone()).optional())
+                    .service(
+                        web::scope("/t/{tenant}")
+                            .service(redirect)
+                            .service(summary)
+                    )
+                    .service(redirect)
+                    .service(summary)
+            )
             .app_data(web::Data::clone(&appdata))
+            .app_data(web::Data::from(api_keys.clone()))
+            .app_data(web::Data::from(domains.clone()))
+            .app_data(trusted_proxies.clone())
     })
     .bind(application_url)?
     .run()
     .await
 }
 
+/// Picks up the keys issued or revoked by other instances.
+fn start_key_refresh(api_keys: Arc<ApiKeyRegistry>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(DEFAULT_API_KEY_REFRESH_INTERVAL));
+        // The first tick completes immediately, the keys were just loaded
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(err) = api_keys.refresh().await {
+                log::error!("Failed to refresh API keys: {}", err);
+            }
+        }
+    });
+}
+
+/// Purges the links that were in the trash longer than the retention period.
+fn start_trash_purge(appdata: web::Data<Mutex<AppData>>, retention_days: u64, purge_interval: u64) {
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(purge_interval));
+        loop {
+            interval.tick().await;
+            let deleted_before = Utc::now() - chrono::Duration::days(retention_days as i64);
+            let mut tenants: Vec<Option<String>> = vec![None];
+            tenants.extend(appdata.lock().await.tenants.ids().into_iter().map(Some));
+            for tenant in tenants {
+                // Locked for one tenant at a time, requests get in between the purges
+                let mut data = appdata.lock().await;
+                let links = match data.links_of(tenant.as_deref()) {
+                    Some(links) => links,
+                    None => continue
+                };
+                match links.purge_deleted(deleted_before).await {
+                    Ok(0) => (),
+                    Ok(purged) => log::info!("Purged {} deleted links of {}", purged, tenant.as_deref().unwrap_or("the shared links")),
+                    Err(err) => log::error!("Failed to purge deleted links: {}", err)
+                }
+                drop(data);
+                tokio::task::yield_now().await;
+            }
+        }
+    });
+}
+
+/// Picks up changes of the destination rules file.
+fn start_destination_reload(destinations: Arc<DestinationFilter>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(DEFAULT_DESTINATION_RULES_RELOAD_INTERVAL));
+        loop {
+            interval.tick().await;
+            match destinations.reload() {
+                Ok(true) => log::info!("Reloaded the destination rules"),
+                Ok(false) => (),
+                Err(err) => log::error!("Failed to reload the destination rules: {}", err)
+            }
+        }
+    });
+}
+
+/// Picks up the domains added or removed by other instances.
+fn start_domain_refresh(domains: Arc<DomainRegistry>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(DEFAULT_DOMAIN_REFRESH_INTERVAL));
+        // The first tick completes immediately, the domains were just loaded
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(err) = domains.refresh().await {
+                log::error!("Failed to refresh domains: {}", err);
+            }
+        }
+    });
+}
+
+/// Picks up rotated signing keys of the token issuer.
+fn start_jwks_refresh(jwt: Arc<JwtValidator>, refresh_interval: u64) {
+    if !jwt.uses_jwks() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(u64::max(refresh_interval, 1)));
+        // The first tick completes immediately, the keys were just loaded
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(err) = jwt.refresh().await {
+                log::error!("Failed to refresh JWKS: {}", err);
+            }
+        }
+    });
+}
+
+/// Scope a caller needs for an admin endpoint, the cluster management needs `admin`.
+fn admin_scope(req: &ServiceRequest) -> Option<Scope> {
+    let replica = req.headers().contains_key(REPLICA_HEADER);
+    let scope = match (req.method(), req.match_pattern().as_deref()) {
+        (&http::Method::PUT | &http::Method::DELETE, Some("/admin/{short_url}")) if replica => Scope::Cluster,
+        (&http::Method::GET, Some("/admin/urls" | "/admin/export" | "/admin/sync/digest" | "/admin/sync/range/{range}")) => Scope::LinksRead,
+        (&http::Method::PUT, Some("/admin/{short_url}")) => Scope::LinksWrite,
+        (&http::Method::DELETE, Some("/admin/{short_url}")) => Scope::LinksDelete,
+        (&http::Method::POST, Some("/admin/{short_url}/restore")) => Scope::LinksDelete,
+        (&http::Method::POST, Some("/admin/links/bulk" | "/admin/links/import")) => Scope::LinksWrite,
+        _ => Scope::Admin
+    };
+    Some(scope)
+}
+
+/// Admin endpoints callers of a tenant may use, the cluster is shared by every tenant.
+fn tenant_admin_route(req: &ServiceRequest) -> bool {
+    matches!(req.match_pattern().as_deref(),
+        Some("/admin/urls" | "/admin/{short_url}" | "/admin/{short_url}/restore" | "/admin/keys" | "/admin/keys/{name}" | "/admin/keys/{name}/rotate" | "/admin/tenants"
+            | "/admin/domains" | "/admin/domains/{name}" | "/admin/audit" | "/admin/links/bulk" | "/admin/links/import" | "/admin/export"))
+}
+
+/// Scope a caller of a public endpoint needs when it authenticates, redirects don't authenticate.
+fn public_scope(req: &ServiceRequest) -> Option<Scope> {
+    match req.match_pattern().as_deref() {
+        Some("/shorten") => Some(Scope::LinksWrite),
+        Some("/{short_url}/summary" | "/t/{tenant}/{short_url}/summary") => Some(Scope::StatsRead),
+        Some("/me/links") => Some(Scope::LinksRead),
+        _ => None
+    }
+}
+
+/// Caller authenticated by the auth middleware.
+fn principal_of(req: &HttpRequest) -> Option<Principal> {
+    req.extensions().get::<Principal>().cloned()
+}
+
+/// Audit event of a change the caller of a request made.
+fn audit_event(req: &HttpRequest, action: AuditAction, tenant: Option<String>) -> AuditEvent {
+    let mut event = AuditEvent::new(action, Utc::now());
+    event.actor = principal_of(req).map(|principal| principal.id);
+    event.tenant = tenant;
+    event.client_ip = client_ip(req).map(|ip| ip.to_string());
+    event
+}
+
+/// Address of the client, from the headers of trusted proxies like for the rate limits.
+fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    // Without configured proxies the client is the peer of the connection
+    match req.app_data::<web::Data<TrustedProxies>>() {
+        Some(trusted_proxies) => trusted_proxies.client_ip(req.headers(), req.peer_addr()),
+        None => req.peer_addr().map(|addr| addr.ip())
+    }
+}
+
+/// Audit event of a change of a link, from the link before and after the change.
+fn link_event(req: &HttpRequest, action: AuditAction, tenant: Option<String>, before: Option<&LinkInfo>, after: Option<&LinkInfo>) -> AuditEvent {
+    let mut event = audit_event(req, action, tenant);
+    event.link = after.or(before).map(|link| link.short_url.clone());
+    event.changes = auditevent::diff(before, after);
+    event
+}
+
+/// Audit event of a change of an API key.
+fn key_event(req: &HttpRequest, action: AuditAction, before: Option<&ApiKeyInfo>, after: Option<&ApiKeyInfo>) -> AuditEvent {
+    let mut event = audit_event(req, action, after.or(before).and_then(|key| key.tenant.clone()));
+    event.key = after.or(before).map(|key| key.name.clone());
+    event.changes = auditevent::diff(before, after);
+    event
+}
+
+/// Owners and admins manage a link. Admin routes without a principal have authentication disabled.
+fn may_manage(principal: Option<&Principal>, link: &LinkInfo) -> bool {
+    match principal {
+        None => true,
+        Some(principal) => principal.has_scope(Scope::Admin) || link.owner_id.as_deref() == Some(principal.id.as_str())
+    }
+}
+
+/// Admins without a tenant manage the links of every tenant.
+fn is_global_admin(principal: &Principal) -> bool {
+    principal.tenant.is_none() && principal.has_scope(Scope::Admin)
+}
+
+/// Tenant whose links a request works on, named by the path or else the tenant of the caller.
+fn tenant_of(req: &HttpRequest) -> Result<Option<String>, HttpResponse> {
+    match (req.match_info().get("tenant"), principal_of(req)) {
+        (Some(tenant), Some(principal)) if principal.tenant.as_deref() != Some(tenant) && !is_global_admin(&principal) => {
+            Err(forbidden("The link belongs to another tenant"))
+        },
+        (Some(tenant), _) => Ok(Some(tenant.to_string())),
+        (None, principal) => Ok(principal.and_then(|principal| principal.tenant))
+    }
+}
+
+/// Links a request works on, see `tenant_of`.
+fn links_for<'a>(data: &'a mut AppData, req: &HttpRequest) -> Result<&'a mut Box<dyn HashService>, HttpResponse> {
+    let tenant = tenant_of(req)?;
+    data.links_of(tenant.as_deref()).ok_or_else(unknown_tenant)
+}
+
+/// Host the request was sent to, as forwarded by a proxy.
+fn host_of(req: &HttpRequest) -> String {
+    req.connection_info().host().to_string()
+}
+
+/// Links a public request works on and the custom domain it was sent to. A custom
+/// domain serves the links of its tenant, other hosts resolve the tenant as `links_for`.
+fn links_for_host<'a>(data: &'a mut AppData, req: &HttpRequest, domains: &DomainRegistry) -> Result<(&'a mut Box<dyn HashService>, Option<String>), HttpResponse> {
+    let domain = match domains.find(&host_of(req)) {
+        Some(domain) => domain,
+        None => return Ok((links_for(data, req)?, None))
+    };
+
+    let path_tenant = req.match_info().get("tenant");
+    if path_tenant.is_some() && path_tenant != domain.tenant.as_deref() {
+        return Err(HttpResponse::NotFound().finish());
+    }
+    if principal_of(req).is_some_and(|principal| principal.tenant.is_some() && principal.tenant != domain.tenant) {
+        return Err(forbidden("The link belongs to another tenant"));
+    }
+
+    let hash_service = data.links_of(domain.tenant.as_deref()).ok_or_else(unknown_tenant)?;
+    Ok((hash_service, Some(domain.name)))
+}
+
+/// Custom domain a url is shortened on: the requested one, or else the one the request was sent to.
+/// Callers of a tenant shorten on the domains of their tenant, anonymous callers on shared domains.
+fn shorten_domain(req: &HttpRequest, requested: Option<&str>, domains: &DomainRegistry) -> Result<Option<Domain>, HttpResponse> {
+    let domain = match requested {
+        Some(name) => match domains.find(name) {
+            Some(domain) => domain,
+            None => return Err(HttpResponse::BadRequest()
+                .content_type(APPLICATION_JSON)
+                .json(Response { message: format!("Unknown domain '{}'", name) }))
+        },
+        None => match domains.find(&host_of(req)) {
+            Some(domain) => domain,
+            None => return Ok(None)
+        }
+    };
+
+    let allowed = match principal_of(req) {
+        Some(principal) => is_global_admin(&principal) || principal.tenant == domain.tenant,
+        None => domain.tenant.is_none()
+    };
+    if !allowed {
+        return Err(forbidden("The domain belongs to another tenant"));
+    }
+
+    Ok(Some(domain))
+}
+
+/// Absolute short link, links of a tenant on the service's own hostname have the tenant in their path.
+fn short_link(apiserver: &ApiServer, tenant: Option<&str>, link: &LinkInfo) -> String {
+    let scheme = apiserver.scheme.as_deref().unwrap_or(DEFAULT_LINK_SCHEME);
+    match (&link.domain, tenant) {
+        (Some(domain), _) => format!("{}://{}/{}", scheme, domain, link.code()),
+        (None, Some(tenant)) => format!("{}://{}/t/{}/{}", scheme, apiserver.hostname, tenant, link.short_url),
+        (None, None) => format!("{}://{}/{}", scheme, apiserver.hostname, link.short_url)
+    }
+}
+
+fn shorten_response(apiserver: &ApiServer, tenant: Option<&str>, mut link: LinkInfo) -> ShortenResponse {
+    let short_link = short_link(apiserver, tenant, &link);
+    let qr_code_url = apiserver.qr_code_url.as_ref().map(|template| {
+        let encoded: String = url::form_urlencoded::byte_serialize(short_link.as_bytes()).collect();
+        template.replace("{short_link}", &encoded)
+    });
+    // Statistics are served by the stats url, where they are restricted to the owner
+    link.analytics = None;
+
+    ShortenResponse {
+        stats_url: format!("{}/summary", short_link),
+        short_link,
+        // Taken out of the link, so the flattened link doesn't repeat it
+        expires_at: link.expires_at.take(),
+        qr_code_url,
+        link
+    }
+}
+
+/// Hosts short links are served on, links pointing to them would redirect in a loop.
+pub(crate) fn own_hosts(apiserver: &ApiServer, domains: &DomainRegistry) -> Vec<String> {
+    let mut hosts = vec![domains::host_name(&apiserver.hostname)];
+    hosts.extend(domains.list().into_iter().map(|domain| domain.name));
+    hosts
+}
+
+fn url_error_response(err: UrlError) -> HttpResponse {
+    let allowed_schemes = match &err {
+        UrlError::DisallowedScheme { allowed_schemes, .. } => Some(allowed_schemes.clone()),
+        _ => None
+    };
+    HttpResponse::BadRequest()
+        .content_type(APPLICATION_JSON)
+        .json(UrlErrorResponse { error: err.code(), message: err.to_string(), allowed_schemes })
+}
+
+fn unknown_tenant() -> HttpResponse {
+    HttpResponse::NotFound()
+        .content_type(APPLICATION_JSON)
+        .json(Response { message: String::from("Unknown tenant") })
+}
+
+fn quota_response(err: QuotaError) -> HttpResponse {
+    let message = Response { message: err.to_string() };
+    match err {
+        QuotaError::MaxLinks { .. } => HttpResponse::Forbidden().json(message),
+        QuotaError::MaxShortensPerDay { .. } => HttpResponse::TooManyRequests().json(message),
+        QuotaError::HashServiceError(err) => {
+            log::error!("{}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+fn forbidden(message: &str) -> HttpResponse {
+    HttpResponse::Forbidden()
+        .content_type(APPLICATION_JSON)
+        .json(Response { message: message.to_string() })
+}
+
+fn invalid_short_url(reason: String) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .content_type(APPLICATION_JSON)
+        .json(Response { message: reason })
+}
+
+fn short_url_taken(short_url: &str) -> HttpResponse {
+    HttpResponse::Conflict()
+        .content_type(APPLICATION_JSON)
+        .json(Response { message: format!("'{}' is the short url of another link", short_url) })
+}
+
 #[get("/hello")]
 async fn hello() -> HttpResponse {
 HttpResponse::Ok()
@@ -86,57 +712,603 @@ HttpResponse::Ok()
     .json(Response { message: String::from("hello")})
 }
 
-#[get("/urls")]
-async fn urls(query_params: web::Query<QueryParams>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
-    match appdata.lock().unwrap().hash_service.get_links(Some(query_params.0)).await {
+#[get("/urls")]
+async fn urls(query_params: web::Query<QueryParams>, appdata: web::Data<Mutex<AppData>>, req: HttpRequest) -> HttpResponse {
+    if accepts_ndjson(&req) {
+        return urls_ndjson(query_params.into_inner(), appdata, req).await;
+    }
+
+    let mut data = appdata.lock().await;
+    let hash_service = match links_for(&mut data, &req) {
+        Ok(hash_service) => hash_service,
+        Err(response) => return response
+    };
+    let result = match principal_of(&req) {
+        Some(principal) if !principal.has_scope(Scope::Admin) => {
+            hash_service.get_links_by_owner(&principal.id, Some(query_params.0)).await
+        },
+        _ => hash_service.get_links(Some(query_params.0)).await
+    };
+    match result {
+        Err(err) => {
+            log::error!("{}", err);
+            return HttpResponse::InternalServerError()
+                .finish();
+        }
+        Ok(urls) => {
+            HttpResponse::Ok()
+                .content_type(APPLICATION_JSON)
+                .json(urls)
+        }
+    }
+}
+
+/// One link per line, so coordinators can merge the results of several nodes as they arrive.
+/// The links are sent while they are read, a coordinator merges the streams of its nodes and
+/// the other backends are read a page at a time, so memory doesn't grow with the number of links.
+async fn urls_ndjson(query_params: QueryParams, appdata: web::Data<Mutex<AppData>>, req: HttpRequest) -> HttpResponse {
+    let tenant = match tenant_of(&req) {
+        Ok(tenant) => tenant,
+        Err(response) => return response
+    };
+    let owner_id = principal_of(&req)
+        .filter(|principal| !principal.has_scope(Scope::Admin))
+        .map(|principal| principal.id);
+    let merged = {
+        let mut data = appdata.lock().await;
+        let hash_service = match data.links_of(tenant.as_deref()) {
+            Some(hash_service) => hash_service,
+            None => return unknown_tenant()
+        };
+        match &owner_id {
+            Some(_) => Ok(None),
+            None => hash_service.stream_links(Some(query_params.clone())).await
+        }
+    };
+    let to_line = |link: &LinkInfo| serde_json::to_vec(link)
+        .map(|mut line| {
+            line.push(b'\n');
+            line
+        })
+        .unwrap_or_default();
+
+    let lines = match merged {
+        Ok(Some(links)) => links
+            .map(move |link| Ok::<_, actix_web::Error>(web::Bytes::from(to_line(&link))))
+            .boxed_local(),
+        Ok(None) => {
+            let start = (None, query_params.skip.unwrap_or(0), query_params.top);
+            futures_util::stream::unfold(Some(start), move |state: Option<(Option<String>, usize, Option<usize>)>| {
+                let (appdata, tenant, owner_id) = (appdata.clone(), tenant.clone(), owner_id.clone());
+                async move {
+                    let (after, skip, remaining) = state?;
+                    if remaining == Some(0) {
+                        return None;
+                    }
+                    let links = {
+                        let mut data = appdata.lock().await;
+                        data.links_of(tenant.as_deref()).unwrap()
+                            .get_links_after(after.as_deref(), owner_id.as_deref(), NDJSON_PAGE_SIZE).await
+                    };
+                    match links {
+                        Ok(links) => {
+                            let next_after = links.last().map(|link| link.short_url.clone());
+                            let is_last = links.len() < NDJSON_PAGE_SIZE;
+                            let skipped = skip.min(links.len());
+                            let sent: Vec<&LinkInfo> = links[skipped..].iter()
+                                .take(remaining.unwrap_or(usize::MAX))
+                                .collect();
+                            let next = (!is_last).then(|| (next_after, skip - skipped, remaining.map(|remaining| remaining - sent.len())));
+                            let chunk: Vec<u8> = sent.into_iter().flat_map(to_line).collect();
+                            Some((Ok(web::Bytes::from(chunk)), next))
+                        },
+                        Err(err) => {
+                            // The status was sent already, the list ends short
+                            log::error!("Listing links failed: {}", err);
+                            Some((Err(actix_web::error::ErrorInternalServerError(err.to_string())), None))
+                        }
+                    }
+                }
+            }).boxed_local()
+        },
+        Err(err) => {
+            log::error!("{}", err);
+            return HttpResponse::InternalServerError()
+                .finish();
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type(APPLICATION_NDJSON)
+        .streaming(lines)
+}
+
+#[get("/export")]
+async fn export(req: HttpRequest, query: web::Query<ExportQuery>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let query = query.into_inner();
+    let format = match query.format() {
+        Ok(format) => format,
+        Err(message) => return HttpResponse::BadRequest()
+            .content_type(APPLICATION_JSON)
+            .json(Response { message })
+    };
+    let tenant = match tenant_of(&req) {
+        Ok(tenant) => tenant,
+        Err(response) => return response
+    };
+    if appdata.lock().await.links_of(tenant.as_deref()).is_none() {
+        return unknown_tenant();
+    }
+    // Like GET /admin/urls, callers without the admin scope export their own links
+    let owner_id = principal_of(&req)
+        .filter(|principal| !principal.has_scope(Scope::Admin))
+        .map(|principal| principal.id);
+
+    let header = futures_util::stream::iter(format.header()
+        .map(|header| Ok::<_, actix_web::Error>(web::Bytes::from(header))));
+    // Links are read a page at a time while the response is sent, so memory doesn't grow with the number of links.
+    // Each page starts after the last short url of the previous one, links added or deleted meanwhile don't shift it.
+    let pages = futures_util::stream::unfold(Some(None), move |after: Option<Option<String>>| {
+        let (appdata, tenant, owner_id, query) = (appdata.clone(), tenant.clone(), owner_id.clone(), query.clone());
+        async move {
+            let after = after?;
+            let links = {
+                let mut data = appdata.lock().await;
+                data.links_of(tenant.as_deref()).unwrap()
+                    .get_links_after(after.as_deref(), owner_id.as_deref(), EXPORT_PAGE_SIZE).await
+            };
+            match links {
+                Ok(links) => {
+                    let next = (links.len() == EXPORT_PAGE_SIZE).then(|| links.last().map(|link| link.short_url.clone()));
+                    let chunk: String = links.iter()
+                        .flat_map(|link| query.rows(link))
+                        .map(|row| format.encode(&row))
+                        .collect();
+                    Some((Ok(web::Bytes::from(chunk)), next))
+                },
+                Err(err) => {
+                    // The status was sent already, the export ends short
+                    log::error!("Export failed: {}", err);
+                    Some((Err(actix_web::error::ErrorInternalServerError(err.to_string())), None))
+                }
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((http::header::CONTENT_DISPOSITION, format!("attachment; filename=\"links.{}\"", format.extension())))
+        .streaming(header.chain(pages))
+}
+
+fn accepts_ndjson(req: &HttpRequest) -> bool {
+    req.headers().get(http::header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains(APPLICATION_NDJSON))
+}
+
+/// Writes replicated by another instance must not be replicated again. They skip the checks
+/// of the node they were made on, so they are only taken from keys with the `cluster` scope.
+fn is_replica(req: &HttpRequest) -> Result<bool, HttpResponse> {
+    if !req.headers().contains_key(REPLICA_HEADER) {
+        return Ok(false);
+    }
+
+    match principal_of(req) {
+        Some(principal) if principal.scopes.contains(&Scope::Cluster) && principal.tenant.is_none() => Ok(true),
+        _ => Err(forbidden("Replicated writes need a key with the cluster scope"))
+    }
+}
+
+pub async fn shorten(req: HttpRequest, info: web::Json<ShortenRequest>, appdata: web::Data<Mutex<AppData>>, domains: web::Data<DomainRegistry>) -> HttpResponse {
+    let domain = match shorten_domain(&req, info.domain.as_deref(), &domains) {
+        Ok(domain) => domain.map(|domain| domain.name),
+        Err(response) => return response
+    };
+    // A custom domain serves the links of its tenant
+    let tenant = match (&domain, tenant_of(&req)) {
+        (Some(domain), _) => domains.find(domain).and_then(|domain| domain.tenant),
+        (None, Ok(tenant)) => tenant,
+        (None, Err(response)) => return response
+    };
+    let (long_url, url_safety) = {
+        let data = appdata.lock().await;
+        // Equivalent urls are stored in one form, so they share a link
+        let long_url = match data.url_normalizer.normalize(&info.long_url) {
+            Ok(long_url) => long_url,
+            Err(err) => return url_error_response(err)
+        };
+        if let Err(err) = data.destinations.check(&long_url, &own_hosts(&data.settings.apiserver, &domains)) {
+            return url_error_response(err);
+        }
+        (long_url, data.url_safety.clone())
+    };
+    // Checked without holding the app data, a scanning service may take a while to answer
+    let verdict = url_safety.check(&long_url).await;
+
+    let now = Utc::now();
+    let owner_id = principal_of(&req).map(|principal| principal.id);
+    let short_url = linkinfo::link_key(domain.as_deref(), &hashfunction::hash_for_owner(&long_url, domain.as_deref(), owner_id.as_deref()));
+    let mut data = appdata.lock().await;
+    let audit = data.audit.clone();
+    if let Some(tenant) = &tenant {
+        let checked = match data.tenants.get_mut(tenant) {
+            Some(tenant_links) => tenant_links.check_shorten(&short_url, now).await,
+            None => return unknown_tenant()
+        };
+        if let Err(err) = checked {
+            return quota_response(err);
+        }
+    }
+
+    let hash_service = match data.links_of(tenant.as_deref()) {
+        Some(hash_service) => hash_service,
+        None => return unknown_tenant()
+    };
+    // Shortening a url again returns the caller's stored link
+    let existing = match hash_service.find(&short_url).await {
+        Ok(existing) => existing,
+        Err(err) => {
+            log::error!("{}", err);
+            return HttpResponse::InternalServerError()
+                .finish();
+        }
+    };
+    // The hash of the url collides with the link of another url
+    if let Some(existing) = existing.as_ref().filter(|existing| existing.long_url != long_url) {
+        return short_url_taken(&existing.short_url);
+    }
+    // Authenticated callers get links of their own, the owner of a stored link never changes
+    let mut result = match (&owner_id, &domain) {
+        (Some(owner_id), _) => hash_service.insert_owned(&long_url, domain.as_deref(), owner_id).await,
+        (None, Some(domain)) => hash_service.insert_on_domain(&long_url, domain).await,
+        (None, None) => hash_service.insert(&long_url).await
+    };
+    if let Ok(link) = &mut result {
+        let mut changed = false;
+        // Shortening a deleted url again takes its link out of the trash
+        if link.status == LinkStatus::Deleted {
+            link.status = link.previous_status.take().unwrap_or_default();
+            link.deleted_at = None;
+            changed = true;
+        }
+        if let (LinkStatus::Active, Verdict::Flagged { threat }) = (link.status, &verdict) {
+            link.status = LinkStatus::Quarantined;
+            link.status_reason = Some(format!("flagged as {}", threat));
+            changed = true;
+        }
+        if changed {
+            link.touch(now);
+            let short_url = link.short_url.clone();
+            if let Err(err) = hash_service.update(&short_url, link).await {
+                result = Err(err);
+            }
+        }
+    }
+    if let Ok(link) = &result {
+        let event = match &existing {
+            None => Some(link_event(&req, AuditAction::Create, tenant.clone(), None, Some(link))),
+            Some(existing) if existing != link => Some(link_event(&req, AuditAction::Update, tenant.clone(), Some(existing), Some(link))),
+            Some(_) => None
+        };
+        if let Some(event) = event {
+            audit.record(event).await;
+        }
+    }
+    if let (Ok(_), Some(tenant_links)) = (&result, tenant.as_ref().and_then(|tenant| data.tenants.get_mut(tenant))) {
+        // The link is stored, a shorten that can't be counted is only logged
+        if let Err(err) = tenant_links.record_shortens(1, now).await {
+            log::error!("{}", err);
+        }
+    }
+    match result {
+        Err(HashServiceError::ShortUrlTaken { short_url }) => short_url_taken(&short_url),
+        Err(err) => {
+            log::error!("{}", err);
+            return HttpResponse::InternalServerError()
+                .finish();
+        }
+        Ok(value) => {
+            let response = shorten_response(&data.settings.apiserver, tenant.as_deref(), value);
+            if existing.is_some() {
+                return HttpResponse::Ok()
+                    .content_type(APPLICATION_JSON)
+                    .json(response);
+            }
+            HttpResponse::Created()
+                .insert_header((http::header::LOCATION, response.short_link.clone()))
+                .content_type(APPLICATION_JSON)
+                .json(response)
+        }
+    }
+}
+
+/// Row of a bulk import that passed the checks of its url.
+struct BulkRow {
+    row: usize,
+    long_url: String,
+    short_url: String,
+    link: BulkLink,
+}
+
+pub async fn bulk_shorten(req: HttpRequest, body: String, appdata: web::Data<Mutex<AppData>>, domains: web::Data<DomainRegistry>) -> HttpResponse {
+    let bad_request = |message: String| HttpResponse::BadRequest()
+        .content_type(APPLICATION_JSON)
+        .json(Response { message });
+    let content_type = req.headers().get(http::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or(APPLICATION_JSON);
+    let format = match BulkFormat::from_content_type(content_type) {
+        Some(format) => format,
+        None => return HttpResponse::UnsupportedMediaType()
+            .content_type(APPLICATION_JSON)
+            .json(Response { message: format!("'{}' is not JSON, CSV or NDJSON", content_type) })
+    };
+    let rows = match bulkimport::parse(format, &body) {
+        Ok(rows) => rows,
+        Err(reason) => return bad_request(reason)
+    };
+    if rows.len() > MAX_BULK_LINKS {
+        return bad_request(format!("{} links are more than the {} of one request", rows.len(), MAX_BULK_LINKS));
+    }
+    let tenant = match tenant_of(&req) {
+        Ok(tenant) => tenant,
+        Err(response) => return response
+    };
+
+    // The rows are checked like the urls of POST /shorten
+    let now = Utc::now();
+    let owner_id = principal_of(&req).map(|principal| principal.id);
+    let mut results = Vec::new();
+    let mut checked = Vec::new();
+    let url_safety = {
+        let data = appdata.lock().await;
+        let own_hosts = own_hosts(&data.settings.apiserver, &domains);
+        for (index, row) in rows.into_iter().enumerate() {
+            let row_number = index + 1;
+            let link = match row {
+                Ok(link) => link,
+                Err(reason) => {
+                    results.push(BulkResult::failed(row_number, "invalid_row", reason));
+                    continue;
+                }
+            };
+            let long_url = match data.url_normalizer.normalize(&link.long_url)
+                .and_then(|long_url| data.destinations.check(&long_url, &own_hosts).map(|_| long_url)) {
+                Ok(long_url) => long_url,
+                Err(err) => {
+                    results.push(BulkResult::failed(row_number, err.code(), err.to_string()));
+                    continue;
+                }
+            };
+            if let Err(reason) = link.alias.as_deref().map_or(Ok(()), bulkimport::validate_alias) {
+                results.push(BulkResult::failed(row_number, "invalid_alias", reason));
+                continue;
+            }
+            if link.expires_at.is_some_and(|expires_at| expires_at <= now) {
+                results.push(BulkResult::failed(row_number, "invalid_expires_at", String::from("expires_at is in the past")));
+                continue;
+            }
+
+            let short_url = link.alias.clone().unwrap_or_else(|| hashfunction::hash_for_owner(&long_url, None, owner_id.as_deref()));
+            checked.push(BulkRow { row: row_number, long_url, short_url, link });
+        }
+        data.url_safety.clone()
+    };
+    // Checked without holding the app data, verdicts are cached per domain
+    let mut verdicts = Vec::with_capacity(checked.len());
+    for row in &checked {
+        verdicts.push(url_safety.check(&row.long_url).await);
+    }
+
+    let mut data = appdata.lock().await;
+    let audit = data.audit.clone();
+    let hash_service = match data.links_of(tenant.as_deref()) {
+        Some(hash_service) => hash_service,
+        None => return unknown_tenant()
+    };
+    // Stored links of the rows in one lookup, the links of the request are added to them,
+    // a url may be listed more than once
+    let mut short_urls: Vec<String> = checked.iter().map(|row| row.short_url.clone()).collect();
+    short_urls.sort();
+    short_urls.dedup();
+    let mut batch = match hash_service.find_many(&short_urls).await {
+        Ok(stored) => stored,
+        Err(err) => {
+            log::error!("{}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let mut new_links = Vec::new();
+    let mut accepted = Vec::new();
+    for (row, verdict) in checked.into_iter().zip(verdicts) {
+        match batch.get(&row.short_url).cloned() {
+            // Importing a url again returns its link, like shortening it again
+            Some(existing) if existing.long_url == row.long_url => accepted.push((row.row, existing, BulkStatus::Existing)),
+            Some(_) => results.push(BulkResult::failed(row.row, "alias_taken", format!("'{}' is the short url of another link", row.short_url))),
+            None => {
+                let mut link = build_link_info(row.short_url.clone(), row.long_url.clone());
+                link.owner_id = owner_id.clone();
+                link.tags = row.link.tags.clone();
+                link.expires_at = row.link.expires_at;
+                if let Verdict::Flagged { threat } = verdict {
+                    link.status = LinkStatus::Quarantined;
+                    link.status_reason = Some(format!("flagged as {}", threat));
+                }
+                batch.insert(row.short_url, link.clone());
+                new_links.push(link.clone());
+                accepted.push((row.row, link, BulkStatus::Created));
+            }
+        }
+    }
+
+    if let Some(tenant_links) = tenant.as_ref().and_then(|tenant| data.tenants.get_mut(tenant)) {
+        if let Err(err) = tenant_links.check_bulk_shorten(accepted.len(), new_links.len(), now).await {
+            return quota_response(err);
+        }
+    }
+    match data.links_of(tenant.as_deref()).unwrap().insert_many(&new_links).await {
+        Err(HashServiceError::ShortUrlTaken { short_url }) => return short_url_taken(&short_url),
+        Err(err) => {
+            log::error!("{}", err);
+            return HttpResponse::InternalServerError().finish();
+        },
+        Ok(()) => ()
+    }
+    if let Some(tenant_links) = tenant.as_ref().and_then(|tenant| data.tenants.get_mut(tenant)) {
+        if let Err(err) = tenant_links.record_shortens(accepted.len(), now).await {
+            log::error!("{}", err);
+        }
+    }
+    for link in &new_links {
+        audit.record(link_event(&req, AuditAction::Create, tenant.clone(), None, Some(link))).await;
+    }
+
+    let apiserver = &data.settings.apiserver;
+    for (row, link, status) in accepted {
+        results.push(BulkResult {
+            row,
+            status,
+            short_link: Some(short_link(apiserver, tenant.as_deref(), &link)),
+            short_url: Some(link.short_url),
+            error: None,
+            message: None
+        });
+    }
+    results.sort_by_key(|result| result.row);
+
+    let count = |status: BulkStatus| results.iter().filter(|result| result.status == status).count();
+    HttpResponse::Ok()
+        .content_type(APPLICATION_JSON)
+        .json(BulkResponse {
+            created: count(BulkStatus::Created),
+            existing: count(BulkStatus::Existing),
+            failed: count(BulkStatus::Failed),
+            results
+        })
+}
+
+pub async fn import_links(req: HttpRequest, params: web::Query<ImportParams>, body: String, appdata: web::Data<Mutex<AppData>>, domains: web::Data<DomainRegistry>) -> HttpResponse {
+    let bad_request = |message: String| HttpResponse::BadRequest()
+        .content_type(APPLICATION_JSON)
+        .json(Response { message });
+    let rows = match linkimport::parse(params.source, &body) {
+        Ok(rows) => rows,
+        Err(reason) => return bad_request(reason)
+    };
+    if rows.len() > MAX_BULK_LINKS {
+        return bad_request(format!("{} links are more than the {} of one request, larger exports are imported with the import command", rows.len(), MAX_BULK_LINKS));
+    }
+    let tenant = match tenant_of(&req) {
+        Ok(tenant) => tenant,
+        Err(response) => return response
+    };
+
+    // The links are checked like the urls of POST /shorten
+    let now = Utc::now();
+    let (mut checked, results, url_safety) = {
+        let data = appdata.lock().await;
+        let own_hosts = own_hosts(&data.settings.apiserver, &domains);
+        let (checked, results) = linkimport::check(rows, |long_url| data.url_normalizer.normalize(long_url)
+            .and_then(|long_url| data.destinations.check(&long_url, &own_hosts).map(|_| long_url)));
+        (checked, results, data.url_safety.clone())
+    };
+    // Checked without holding the app data, verdicts are cached per domain
+    for row in checked.iter_mut() {
+        if let Verdict::Flagged { threat } = url_safety.check(&row.link.long_url).await {
+            row.threat = Some(threat);
+        }
+    }
+
+    let mut data = appdata.lock().await;
+    let audit = data.audit.clone();
+    let hash_service = match data.links_of(tenant.as_deref()) {
+        Some(hash_service) => hash_service,
+        None => return unknown_tenant()
+    };
+    let owner_id = principal_of(&req).map(|principal| principal.id);
+    let plan = match linkimport::plan(hash_service, checked, results, owner_id.as_deref()).await {
+        Ok(plan) => plan,
         Err(err) => {
             log::error!("{}", err);
-            return HttpResponse::InternalServerError()
-                .finish();
+            return HttpResponse::InternalServerError().finish();
         }
-        Ok(urls) => {
-            HttpResponse::Ok()
-                .content_type(APPLICATION_JSON)
-                .json(urls)
+    };
+
+    // A dry run is rejected like the import when the links don't fit the quotas
+    if let Some(tenant_links) = tenant.as_ref().and_then(|tenant| data.tenants.get_mut(tenant)) {
+        if let Err(err) = tenant_links.check_bulk_shorten(plan.links.len(), plan.links.len(), now).await {
+            return quota_response(err);
         }
     }
-}
-
-pub async fn shorten(info: web::Json<ShortenRequest>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
-    let mut data = appdata.lock().unwrap();
-    match data.hash_service.insert(&info.long_url).await {
-        Err(err) => {
-            log::error!("{}", err);
-            return HttpResponse::InternalServerError()
-                .finish();
+    if !params.dry_run {
+        match data.links_of(tenant.as_deref()).unwrap().insert_many(&plan.links).await {
+            Err(HashServiceError::ShortUrlTaken { short_url }) => return short_url_taken(&short_url),
+            Err(err) => {
+                log::error!("{}", err);
+                return HttpResponse::InternalServerError().finish();
+            },
+            Ok(()) => ()
         }
-        Ok(value) => {
-            HttpResponse::Ok()
-                .content_type(APPLICATION_JSON)
-                .json(value)
+        if let Some(tenant_links) = tenant.as_ref().and_then(|tenant| data.tenants.get_mut(tenant)) {
+            if let Err(err) = tenant_links.record_shortens(plan.links.len(), now).await {
+                log::error!("{}", err);
+            }
+        }
+        for link in &plan.links {
+            audit.record(link_event(&req, AuditAction::Create, tenant.clone(), None, Some(link))).await;
         }
     }
+
+    HttpResponse::Ok()
+        .content_type(APPLICATION_JSON)
+        .json(ImportReport::new(plan.results, params.dry_run))
 }
 
 #[get("/{short_url}")]
-async fn redirect(path: web::Path<String>, appdata: web::Data<Mutex<AppData>>, req: HttpRequest) -> HttpResponse {
+async fn redirect(path: web::Path<LinkPath>, appdata: web::Data<Mutex<AppData>>, domains: web::Data<DomainRegistry>, req: HttpRequest) -> HttpResponse {
     log::info!("Request headers:");
     for (name, value) in req.headers().iter() {
         log::info!("{}: {:?}", name, value);
     }
 
-    let analytic = collector::collect_stats(req.headers()).await;
+    let analytic = collector::collect_stats(req.headers(), client_ip(&req)).await;
 
-    let short_url = path.into_inner();
+    let short_url = path.into_inner().short_url;
     if short_url.is_empty() {
         return HttpResponse::BadRequest()
             .finish();
     }
 
-    let mut data = appdata.lock().unwrap();
-    let long_url: String = match data.hash_service.find(&short_url).await {
+    let mut data = appdata.lock().await;
+    let disabled_page = data.disabled_page.clone();
+    let (hash_service, domain) = match links_for_host(&mut data, &req, &domains) {
+        Ok(links) => links,
+        Err(response) => return response
+    };
+    let short_url = linkinfo::link_key(domain.as_deref(), &short_url);
+    let long_url: String = match hash_service.find(&short_url).await {
         Ok(v) => {
             match v {
+                // Short urls are only unique per domain
+                Some(value) if value.domain != domain => {
+                    return HttpResponse::NotFound()
+                        .finish();
+                }
+                Some(value) if value.status == LinkStatus::Quarantined => {
+                    return quarantine_page(&value);
+                }
+                Some(value) if value.status == LinkStatus::Deleted => {
+                    return HttpResponse::NotFound()
+                        .finish();
+                }
+                Some(value) if value.status == LinkStatus::Disabled => {
+                    return disabled_page.response();
+                }
+                Some(value) if value.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) => {
+                    return HttpResponse::Gone()
+                        .finish();
+                }
                 None => {
                     return HttpResponse::NotFound()
                         .finish();
@@ -146,7 +1318,8 @@ async fn redirect(path: web::Path<String>, appdata: web::Data<Mutex<AppData>>, r
                         value.analytics = Some(Vec::new());
                     }
                     value.analytics.as_mut().unwrap().push(analytic);
-                    data.hash_service.update(&short_url, &value).await.unwrap();
+                    value.touch(Utc::now());
+                    hash_service.update(&short_url, &value).await.unwrap();
                     value.long_url.clone()
                 }
             }
@@ -163,18 +1336,59 @@ async fn redirect(path: web::Path<String>, appdata: web::Data<Mutex<AppData>>, r
         .finish()
 }
 
+/// Warning shown instead of redirecting to a link flagged as unsafe.
+fn quarantine_page(link: &LinkInfo) -> HttpResponse {
+    let long_url = escape_html(&link.long_url);
+    let reason = escape_html(link.status_reason.as_deref().unwrap_or("unsafe"));
+    // Only web urls are offered to continue to, a link can't run script on this page
+    let lowercase_url = link.long_url.to_ascii_lowercase();
+    let continue_link = match lowercase_url.starts_with("http://") || lowercase_url.starts_with("https://") {
+        true => format!(r#"<p><a href="{}" rel="noopener noreferrer nofollow">Continue anyway</a></p>"#, long_url),
+        false => String::new()
+    };
+
+    HttpResponse::Ok()
+        .content_type(TEXT_HTML)
+        .insert_header((http::header::CACHE_CONTROL, "no-store"))
+        .body(format!(r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><meta name="robots" content="noindex"><title>Warning: this link may be unsafe</title></head>
+<body>
+<h1>This link may be unsafe</h1>
+<p>The link you followed points to a site that was {}. It may try to steal your information or harm your device.</p>
+<p>Destination: <code>{}</code></p>
+{}
+</body>
+</html>"#, reason, long_url, continue_link))
+}
+
+fn escape_html(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
 #[get("/{short_url}/summary")]
-async fn summary(path: web::Path<String>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
-    let short_url = path.into_inner();
+async fn summary(req: HttpRequest, path: web::Path<LinkPath>, appdata: web::Data<Mutex<AppData>>, domains: web::Data<DomainRegistry>) -> HttpResponse {
+    let short_url = path.into_inner().short_url;
     if short_url.is_empty() {
         return HttpResponse::BadRequest()
             .finish();
     }
 
-    let mut data = appdata.lock().unwrap();
-    let linkinfo = match data.hash_service.find(&short_url).await{
+    let mut data = appdata.lock().await;
+    let (hash_service, domain) = match links_for_host(&mut data, &req, &domains) {
+        Ok(links) => links,
+        Err(response) => return response
+    };
+    let linkinfo = match hash_service.find(&linkinfo::link_key(domain.as_deref(), &short_url)).await{
         Ok(v) => {
             match v {
+                Some(value) if value.domain != domain => {
+                    return HttpResponse::NotFound().finish();
+                }
                 None => {
                     return HttpResponse::NotFound().finish();
                 }
@@ -187,21 +1401,97 @@ async fn summary(path: web::Path<String>, appdata: web::Data<Mutex<AppData>>) ->
         }
     };
 
+    // Stats of an owned link are only shown to its owner and admins
+    if linkinfo.owner_id.is_some() {
+        match principal_of(&req) {
+            None => return HttpResponse::Unauthorized().finish(),
+            Some(principal) if !may_manage(Some(&principal), &linkinfo) => return forbidden("Only the owner of the link can read its stats"),
+            _ => ()
+        }
+    }
+
     HttpResponse::Ok()
         .content_type(APPLICATION_JSON)
         .json(linkinfo)
 }
 
+#[get("/links")]
+async fn my_links(req: HttpRequest, query_params: web::Query<QueryParams>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let principal = match principal_of(&req) {
+        Some(principal) => principal,
+        None => return HttpResponse::Unauthorized().finish()
+    };
+
+    let mut data = appdata.lock().await;
+    let hash_service = match links_for(&mut data, &req) {
+        Ok(hash_service) => hash_service,
+        Err(response) => return response
+    };
+    match hash_service.get_links_by_owner(&principal.id, Some(query_params.0)).await {
+        Ok(links) => HttpResponse::Ok()
+            .content_type(APPLICATION_JSON)
+            .json(links),
+        Err(err) => {
+            log::error!("{}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Link a caller wants to change, when it isn't allowed to the response to send instead.
+async fn managed_link(data: &mut AppData, req: &HttpRequest, short_url: &str) -> Result<Option<LinkInfo>, HttpResponse> {
+    let link = links_for(data, req)?.find(short_url).await.map_err(|err| {
+        log::error!("{}", err);
+        HttpResponse::InternalServerError().finish()
+    })?;
+
+    match &link {
+        Some(link) if !may_manage(principal_of(req).as_ref(), link) => Err(forbidden("Only the owner of the link can change it")),
+        _ => Ok(link)
+    }
+}
+
 #[delete("/{short_url}")]
-async fn delete(path: web::Path<String>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+async fn delete(req: HttpRequest, path: web::Path<String>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
     let short_url = path.into_inner();
     if short_url.is_empty() {
         return HttpResponse::BadRequest()
             .finish();
     }
+    if let Err(reason) = bulkimport::validate_short_url(&short_url) {
+        return invalid_short_url(reason);
+    }
 
-    let mut data = appdata.lock().unwrap();
-    let result: bool = match data.hash_service.delete(&short_url).await {
+    let replica = match is_replica(&req) {
+        Ok(replica) => replica,
+        Err(response) => return response
+    };
+    let mut data = appdata.lock().await;
+    let audit = data.audit.clone();
+    let result = if replica {
+        data.hash_service.apply_replica(&Hint::Delete(short_url)).await
+    } else {
+        // Links are moved to the trash, the purge deletes them for good
+        match managed_link(&mut data, &req, &short_url).await {
+            Ok(Some(link)) if link.status == LinkStatus::Deleted => Ok(true),
+            Ok(Some(mut link)) => {
+                let before = link.clone();
+                link.previous_status = Some(link.status);
+                link.status = LinkStatus::Deleted;
+                link.deleted_at = Some(Utc::now());
+                // Replicas that missed the delete take it over from this version
+                link.touch(Utc::now());
+                let deleted = links_for(&mut data, &req).unwrap().update(&short_url, &link).await;
+                if deleted.is_ok() {
+                    audit.record(link_event(&req, AuditAction::Delete, tenant_of(&req).unwrap(), Some(&before), Some(&link))).await;
+                }
+                deleted
+            },
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(response) => return response
+        }
+    };
+    let result: bool = match result {
         Ok(v) => v,
         Err(err) => {
             log::error!("{}", err);
@@ -216,4 +1506,493 @@ async fn delete(path: web::Path<String>, appdata: web::Data<Mutex<AppData>>) ->
         return HttpResponse::NotFound()
             .finish();
     }
-}
\ No newline at end of file
+}
+
+#[post("/{short_url}/restore")]
+async fn restore(req: HttpRequest, path: web::Path<String>, appdata: web::Data<Mutex<AppData>>, domains: web::Data<DomainRegistry>) -> HttpResponse {
+    let short_url = path.into_inner();
+
+    let not_in_trash = || HttpResponse::NotFound()
+        .content_type(APPLICATION_JSON)
+        .json(Response { message: format!("Link '{}' is not in the trash", short_url) });
+    let (long_url, url_safety) = {
+        let mut data = appdata.lock().await;
+        let link = match managed_link(&mut data, &req, &short_url).await {
+            Ok(Some(link)) if link.status == LinkStatus::Deleted => link,
+            Ok(_) => return not_in_trash(),
+            Err(response) => return response
+        };
+        // The destination may have been blocked since the link was deleted
+        if let Err(err) = data.destinations.check(&link.long_url, &own_hosts(&data.settings.apiserver, &domains)) {
+            return url_error_response(err);
+        }
+        (link.long_url, data.url_safety.clone())
+    };
+    // Checked without holding the app data, a scanning service may take a while to answer
+    let verdict = url_safety.check(&long_url).await;
+
+    let mut data = appdata.lock().await;
+    let audit = data.audit.clone();
+    let mut link = match managed_link(&mut data, &req, &short_url).await {
+        Ok(Some(link)) if link.status == LinkStatus::Deleted => link,
+        Ok(_) => return not_in_trash(),
+        Err(response) => return response
+    };
+    let before = link.clone();
+    link.status = link.previous_status.take().unwrap_or_default();
+    link.deleted_at = None;
+    if let (LinkStatus::Active, Verdict::Flagged { threat }) = (link.status, &verdict) {
+        link.status = LinkStatus::Quarantined;
+        link.status_reason = Some(format!("flagged as {}", threat));
+    }
+    link.touch(Utc::now());
+
+    match links_for(&mut data, &req).unwrap().update(&short_url, &link).await {
+        Ok(_) => {
+            audit.record(link_event(&req, AuditAction::Restore, tenant_of(&req).unwrap(), Some(&before), Some(&link))).await;
+            HttpResponse::Ok()
+                .content_type(APPLICATION_JSON)
+                .json(link)
+        },
+        Err(err) => {
+            log::error!("{}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[put("/{short_url}")]
+async fn upsert(req: HttpRequest, path: web::Path<String>, info: web::Json<LinkInfo>, appdata: web::Data<Mutex<AppData>>, domains: web::Data<DomainRegistry>) -> HttpResponse {
+    let short_url = path.into_inner();
+    if short_url.is_empty() || short_url != info.short_url {
+        return HttpResponse::BadRequest()
+            .finish();
+    }
+    if let Err(reason) = bulkimport::validate_short_url(&short_url) {
+        return invalid_short_url(reason);
+    }
+
+    let replica = match is_replica(&req) {
+        Ok(replica) => replica,
+        Err(response) => return response
+    };
+    let mut data = appdata.lock().await;
+    let audit = data.audit.clone();
+    let result = if replica {
+        data.hash_service.apply_replica(&Hint::Upsert(Box::new(info.into_inner()))).await.map(|_| ())
+    } else {
+        // Replicas store links as they are, the node the change was made on checked it.
+        // The url is checked and stored like the url of POST /shorten.
+        let long_url = match data.url_normalizer.normalize(&info.long_url)
+            .and_then(|long_url| data.destinations.check(&long_url, &own_hosts(&data.settings.apiserver, &domains)).map(|_| long_url)) {
+            Ok(long_url) => long_url,
+            Err(err) => return url_error_response(err)
+        };
+        let existing = match managed_link(&mut data, &req, &short_url).await {
+            Ok(existing) => existing,
+            Err(response) => return response
+        };
+        let tenant = tenant_of(&req).unwrap();
+        if let (None, Some(tenant_links)) = (&existing, tenant.as_ref().and_then(|tenant| data.tenants.get_mut(tenant))) {
+            if let Err(err) = tenant_links.check_new_links(1).await {
+                return quota_response(err);
+            }
+        }
+        let mut link = info.into_inner();
+        link.long_url = long_url;
+        // The change is newer than every stored version of the link
+        link.version = link.version.max(existing.as_ref().map_or(0, |existing| existing.version));
+        link.touch(Utc::now());
+        match principal_of(&req) {
+            // Only admins hand links over to someone else
+            Some(principal) if !principal.has_scope(Scope::Admin) => link.owner_id = Some(principal.id),
+            _ if link.owner_id.is_none() => link.owner_id = existing.as_ref().and_then(|existing| existing.owner_id.clone()),
+            _ => ()
+        }
+        let upserted = links_for(&mut data, &req).unwrap().upsert(&link).await;
+        if upserted.is_ok() {
+            let action = if existing.is_some() { AuditAction::Update } else { AuditAction::Create };
+            audit.record(link_event(&req, action, tenant, existing.as_ref(), Some(&link))).await;
+        }
+        upserted
+    };
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(HashServiceError::ShortUrlTaken { short_url }) => short_url_taken(&short_url),
+        Err(err) => {
+            log::error!("{}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/sync/digest")]
+async fn sync_digest(query_params: web::Query<SyncParams>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let ranges = query_params.ranges.unwrap_or(DEFAULT_ANTI_ENTROPY_RANGES);
+
+    let mut data = appdata.lock().await;
+    match data.hash_service.get_links(None).await {
+        Err(err) => {
+            log::error!("{}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+        Ok(links) => {
+            HttpResponse::Ok()
+                .content_type(APPLICATION_JSON)
+                .json(MerkleTree::build(&links, ranges).digest())
+        }
+    }
+}
+
+#[get("/sync/range/{range}")]
+async fn sync_range(path: web::Path<usize>, query_params: web::Query<SyncParams>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let range = path.into_inner();
+    let ranges = antientropy::normalize_ranges(query_params.ranges.unwrap_or(DEFAULT_ANTI_ENTROPY_RANGES));
+    if range >= ranges {
+        return HttpResponse::BadRequest()
+            .finish();
+    }
+
+    let mut data = appdata.lock().await;
+    match data.hash_service.get_links(None).await {
+        Err(err) => {
+            log::error!("{}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+        Ok(links) => {
+            let links: Vec<LinkInfo> = links.into_iter()
+                .filter(|link| antientropy::range_of(&link.short_url, ranges) == range)
+                .collect();
+
+            HttpResponse::Ok()
+                .content_type(APPLICATION_JSON)
+                .json(links)
+        }
+    }
+}
+
+async fn get_cluster(appdata: &web::Data<Mutex<AppData>>) -> Result<Arc<Cluster>, HttpResponse> {
+    appdata.lock().await.hash_service.cluster().ok_or_else(|| {
+        HttpResponse::NotFound()
+            .content_type(APPLICATION_JSON)
+            .json(Response { message: String::from("Cluster membership is only available in coordinator mode") })
+    })
+}
+
+fn cluster_error_response(err: HashServiceError) -> HttpResponse {
+    let message = Response { message: err.to_string() };
+    match err {
+        HashServiceError::UnknownNode { .. } => HttpResponse::NotFound().json(message),
+        HashServiceError::DuplicateNode { .. } | HashServiceError::NodeNotDrained { .. } => HttpResponse::Conflict().json(message),
+        HashServiceError::InvalidConfiguration { .. } => HttpResponse::BadRequest().json(message),
+        err => {
+            log::error!("{}", err);
+            HttpResponse::BadGateway().json(message)
+        }
+    }
+}
+
+#[get("/cluster/nodes")]
+async fn cluster_nodes(appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let cluster = match get_cluster(&appdata).await {
+        Ok(cluster) => cluster,
+        Err(response) => return response
+    };
+
+    HttpResponse::Ok()
+        .content_type(APPLICATION_JSON)
+        .json(cluster.members().await)
+}
+
+#[post("/cluster/nodes")]
+async fn add_node(info: web::Json<AddNodeRequest>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let cluster = match get_cluster(&appdata).await {
+        Ok(cluster) => cluster,
+        Err(response) => return response
+    };
+
+    match cluster.add_node(&info.hostname).await {
+        Ok(member) => HttpResponse::Accepted()
+            .content_type(APPLICATION_JSON)
+            .json(member),
+        Err(err) => cluster_error_response(err)
+    }
+}
+
+#[post("/cluster/nodes/{node:.+}/drain")]
+async fn drain_node(path: web::Path<String>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let cluster = match get_cluster(&appdata).await {
+        Ok(cluster) => cluster,
+        Err(response) => return response
+    };
+
+    match cluster.drain_node(&path.into_inner()).await {
+        Ok(member) => HttpResponse::Accepted()
+            .content_type(APPLICATION_JSON)
+            .json(member),
+        Err(err) => cluster_error_response(err)
+    }
+}
+
+#[delete("/cluster/nodes/{node:.+}")]
+async fn remove_node(path: web::Path<String>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let cluster = match get_cluster(&appdata).await {
+        Ok(cluster) => cluster,
+        Err(response) => return response
+    };
+
+    match cluster.remove_node(&path.into_inner()).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(err) => cluster_error_response(err)
+    }
+}
+/// Tenant of the caller, which only manages the keys of its tenant.
+fn key_tenant_of(req: &HttpRequest) -> Option<String> {
+    principal_of(req).and_then(|principal| principal.tenant)
+}
+
+/// Rejects changes of keys that don't belong to the tenant of the caller as unknown keys.
+fn check_key_tenant(req: &HttpRequest, name: &str, api_keys: &ApiKeyRegistry) -> Result<(), ApiKeyError> {
+    match key_tenant_of(req) {
+        Some(tenant) if !api_keys.list().iter().any(|key| key.name == name && key.tenant.as_ref() == Some(&tenant)) => {
+            Err(ApiKeyError::UnknownKey { name: name.to_string() })
+        },
+        _ => Ok(())
+    }
+}
+
+#[get("/keys")]
+async fn list_keys(req: HttpRequest, api_keys: web::Data<ApiKeyRegistry>) -> HttpResponse {
+    // Include the keys other instances issued since the last refresh
+    if let Err(err) = api_keys.refresh().await {
+        return key_error_response(err);
+    }
+
+    let mut keys = api_keys.list();
+    if let Some(tenant) = key_tenant_of(&req) {
+        keys.retain(|key| key.tenant.as_ref() == Some(&tenant));
+    }
+
+    HttpResponse::Ok()
+        .content_type(APPLICATION_JSON)
+        .json(keys)
+}
+
+#[post("/keys")]
+async fn create_key(req: HttpRequest, info: web::Json<NewApiKey>, api_keys: web::Data<ApiKeyRegistry>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let mut new_key = info.into_inner();
+    // Callers of a tenant issue keys of their own tenant only
+    if let Some(tenant) = key_tenant_of(&req) {
+        new_key.tenant = Some(tenant);
+    } else if let Some(tenant) = new_key.tenant.as_ref() {
+        if !appdata.lock().await.tenants.contains(tenant) {
+            return key_error_response(ApiKeyError::InvalidKey { reason: format!("unknown tenant '{}'", tenant) });
+        }
+    }
+
+    match api_keys.create(new_key).await {
+        Ok(issued) => {
+            let audit = appdata.lock().await.audit.clone();
+            audit.record(key_event(&req, AuditAction::KeyCreate, None, Some(&issued.key))).await;
+            HttpResponse::Created()
+                .content_type(APPLICATION_JSON)
+                .json(issued)
+        },
+        Err(err) => key_error_response(err)
+    }
+}
+
+#[delete("/keys/{name}")]
+async fn revoke_key(req: HttpRequest, path: web::Path<String>, api_keys: web::Data<ApiKeyRegistry>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let name = path.into_inner();
+    if let Err(err) = check_key_tenant(&req, &name, &api_keys) {
+        return key_error_response(err);
+    }
+
+    let before = api_keys.list().into_iter().find(|key| key.name == name);
+    match api_keys.revoke(&name).await {
+        Ok(()) => {
+            let audit = appdata.lock().await.audit.clone();
+            audit.record(key_event(&req, AuditAction::KeyRevoke, before.as_ref(), None)).await;
+            HttpResponse::NoContent().finish()
+        },
+        Err(err) => key_error_response(err)
+    }
+}
+
+#[post("/keys/{name}/rotate")]
+async fn rotate_key(req: HttpRequest, path: web::Path<String>, api_keys: web::Data<ApiKeyRegistry>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let name = path.into_inner();
+    if let Err(err) = check_key_tenant(&req, &name, &api_keys) {
+        return key_error_response(err);
+    }
+
+    let before = api_keys.list().into_iter().find(|key| key.name == name);
+    match api_keys.rotate(&name).await {
+        Ok(issued) => {
+            let audit = appdata.lock().await.audit.clone();
+            audit.record(key_event(&req, AuditAction::KeyRotate, before.as_ref(), Some(&issued.key))).await;
+            HttpResponse::Ok()
+                .content_type(APPLICATION_JSON)
+                .json(issued)
+        },
+        Err(err) => key_error_response(err)
+    }
+}
+
+#[get("/tenants")]
+async fn tenant_usage(req: HttpRequest, appdata: web::Data<Mutex<AppData>>, api_keys: web::Data<ApiKeyRegistry>) -> HttpResponse {
+    let caller_tenant = key_tenant_of(&req);
+    let keys = api_keys.list();
+    let now = Utc::now();
+
+    let mut data = appdata.lock().await;
+    let mut usage = Vec::new();
+    // Callers of a tenant only see their own usage
+    for id in data.tenants.ids().into_iter().filter(|id| caller_tenant.is_none() || caller_tenant.as_ref() == Some(id)) {
+        let api_key_count = keys.iter().filter(|key| key.tenant.as_ref() == Some(&id)).count();
+        match data.tenants.get_mut(&id).unwrap().usage(now, api_key_count).await {
+            Ok(tenant_usage) => usage.push(tenant_usage),
+            Err(err) => {
+                log::error!("{}", err);
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
+    HttpResponse::Ok()
+        .content_type(APPLICATION_JSON)
+        .json(usage)
+}
+
+#[post("/destinations/disable")]
+async fn disable_links(req: HttpRequest, info: web::Json<DisableLinksRequest>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let domain = match destinationrules::normalize_domain(&info.domain) {
+        Ok(domain) => domain,
+        Err(reason) => return HttpResponse::BadRequest()
+            .content_type(APPLICATION_JSON)
+            .json(Response { message: reason })
+    };
+    let reason = format!("domain '{}' is blocked", domain);
+
+    let mut data = appdata.lock().await;
+    let mut tenants: Vec<Option<String>> = vec![None];
+    tenants.extend(data.tenants.ids().into_iter().map(Some));
+    let mut disabled = 0;
+    for tenant in tenants {
+        let hash_service = data.links_of(tenant.as_deref()).unwrap();
+        match hash_service.disable_links_to(&domain, &reason).await {
+            Ok(count) => disabled += count,
+            Err(err) => {
+                log::error!("{}", err);
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
+    let mut event = audit_event(&req, AuditAction::DisableLinks, None);
+    event.changes.push(FieldChange { field: String::from("domain"), before: None, after: Some(serde_json::Value::from(domain.clone())) });
+    data.audit.clone().record(event).await;
+
+    HttpResponse::Ok()
+        .content_type(APPLICATION_JSON)
+        .json(DisableLinksResponse { domain, disabled })
+}
+
+#[get("/audit")]
+async fn audit_events(req: HttpRequest, query: web::Query<AuditQuery>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let mut query = query.into_inner();
+    // Callers of a tenant only see the changes of their tenant
+    query.tenant = key_tenant_of(&req);
+    // Pages are read on with `after`, the id of the last event of the previous page
+    query.limit = Some(query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT).min(MAX_AUDIT_LIMIT));
+
+    let audit = appdata.lock().await.audit.clone();
+    match audit.query(&query).await {
+        Ok(events) => HttpResponse::Ok()
+            .content_type(APPLICATION_JSON)
+            .json(events),
+        Err(err) => {
+            log::error!("{}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/domains")]
+async fn list_domains(req: HttpRequest, domains: web::Data<DomainRegistry>) -> HttpResponse {
+    // Include the domains other instances added since the last refresh
+    if let Err(err) = domains.refresh().await {
+        return domain_error_response(err);
+    }
+
+    let mut list = domains.list();
+    if let Some(tenant) = key_tenant_of(&req) {
+        list.retain(|domain| domain.tenant.as_ref() == Some(&tenant));
+    }
+
+    HttpResponse::Ok()
+        .content_type(APPLICATION_JSON)
+        .json(list)
+}
+
+#[post("/domains")]
+async fn add_domain(req: HttpRequest, info: web::Json<NewDomain>, domains: web::Data<DomainRegistry>, appdata: web::Data<Mutex<AppData>>) -> HttpResponse {
+    let mut new_domain = info.into_inner();
+    // Callers of a tenant add domains of their own tenant only
+    if let Some(tenant) = key_tenant_of(&req) {
+        new_domain.tenant = Some(tenant);
+    } else if let Some(tenant) = new_domain.tenant.as_ref() {
+        if !appdata.lock().await.tenants.contains(tenant) {
+            return domain_error_response(DomainError::InvalidDomain { reason: format!("unknown tenant '{}'", tenant) });
+        }
+    }
+
+    match domains.add(new_domain).await {
+        Ok(domain) => HttpResponse::Created()
+            .content_type(APPLICATION_JSON)
+            .json(domain),
+        Err(err) => domain_error_response(err)
+    }
+}
+
+#[delete("/domains/{name}")]
+async fn remove_domain(req: HttpRequest, path: web::Path<String>, domains: web::Data<DomainRegistry>) -> HttpResponse {
+    let name = path.into_inner();
+    if let Some(tenant) = key_tenant_of(&req) {
+        if domains.find(&name).and_then(|domain| domain.tenant) != Some(tenant) {
+            return domain_error_response(DomainError::UnknownDomain { name });
+        }
+    }
+
+    match domains.remove(&name).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(err) => domain_error_response(err)
+    }
+}
+
+fn domain_error_response(err: DomainError) -> HttpResponse {
+    let message = Response { message: err.to_string() };
+    match err {
+        DomainError::UnknownDomain { .. } => HttpResponse::NotFound().json(message),
+        DomainError::DuplicateDomain { .. } => HttpResponse::Conflict().json(message),
+        DomainError::InvalidDomain { .. } => HttpResponse::BadRequest().json(message),
+        DomainError::DomainStoreError(err) => {
+            log::error!("{}", err);
+            HttpResponse::InternalServerError().json(message)
+        }
+    }
+}
+
+fn key_error_response(err: ApiKeyError) -> HttpResponse {
+    let message = Response { message: err.to_string() };
+    match err {
+        ApiKeyError::UnknownKey { .. } => HttpResponse::NotFound().json(message),
+        ApiKeyError::DuplicateKey { .. } | ApiKeyError::ConfiguredKey { .. } => HttpResponse::Conflict().json(message),
+        ApiKeyError::InvalidKey { .. } => HttpResponse::BadRequest().json(message),
+        ApiKeyError::KeyStoreError(err) => {
+            log::error!("{}", err);
+            HttpResponse::InternalServerError().json(message)
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::{fs, io};
+
+use chrono::Utc;
+
+use crate::configuration::settings::{ImportArgs, Settings};
+use crate::constants::IMPORT_BATCH_SIZE;
+use crate::models::auditevent::{self, AuditAction, AuditEvent};
+use crate::services::destinationrules::DestinationFilter;
+use crate::services::hashservice::HashService;
+use crate::services::linkimport::{self, ImportReport};
+use crate::services::tenants::Tenants;
+use crate::services::urlnormalizer::UrlNormalizer;
+use crate::services::urlsafetychecker::{SafetyCheckers, Verdict};
+use crate::services::{auditlog, domainstore};
+
+use super::audittrail::AuditTrail;
+use super::domains::DomainRegistry;
+use super::httpserver::own_hosts;
+
+/// Runs the `import` command: checks the links of an export like `POST /admin/links/import`
+/// and writes them in batches, without the limit of a request. It's run by operators,
+/// so the quotas of the tenant don't apply. The report is printed as JSON.
+pub async fn run_import(settings: Settings, hash_service: Box<dyn HashService>, args: ImportArgs) -> io::Result<()> {
+    let body = fs::read_to_string(&args.file)?;
+    let rows = linkimport::parse(args.source, &body)
+        .map_err(|reason| io::Error::other(format!("can't read '{}': {}", args.file, reason)))?;
+
+    let domain_store = domainstore::create_domain_store(&settings).await
+        .map_err(io::Error::other)?;
+    let domains = DomainRegistry::with_store(domain_store);
+    domains.refresh().await
+        .map_err(io::Error::other)?;
+    let destinations = DestinationFilter::from_settings(&settings)
+        .map_err(io::Error::other)?;
+    let url_safety = SafetyCheckers::from_settings(&settings)
+        .map_err(io::Error::other)?;
+    let audit = AuditTrail::with_log(auditlog::create_audit_log(&settings).await
+        .map_err(io::Error::other)?);
+    let mut hash_service = match &args.tenant {
+        Some(tenant) => Tenants::from_settings(&settings).await
+            .map_err(io::Error::other)?
+            .remove(tenant)
+            .ok_or_else(|| io::Error::other(format!("unknown tenant '{}'", tenant)))?
+            .hash_service,
+        None => hash_service
+    };
+
+    let url_normalizer = UrlNormalizer::from_settings(&settings);
+    let own_hosts = own_hosts(&settings.apiserver, &domains);
+    let (mut checked, results) = linkimport::check(rows, |long_url| url_normalizer.normalize(long_url)
+        .and_then(|long_url| destinations.check(&long_url, &own_hosts).map(|_| long_url)));
+    for row in checked.iter_mut() {
+        if let Verdict::Flagged { threat } = url_safety.check(&row.link.long_url).await {
+            row.threat = Some(threat);
+        }
+    }
+    let plan = linkimport::plan(&mut hash_service, checked, results, None).await
+        .map_err(io::Error::other)?;
+
+    if !args.dry_run {
+        for (index, batch) in plan.links.chunks(IMPORT_BATCH_SIZE).enumerate() {
+            hash_service.insert_many(batch).await
+                .map_err(io::Error::other)?;
+            log::info!("Imported {} of {} links", index * IMPORT_BATCH_SIZE + batch.len(), plan.links.len());
+        }
+        for link in &plan.links {
+            let mut event = AuditEvent::new(AuditAction::Create, Utc::now());
+            event.tenant = args.tenant.clone();
+            event.link = Some(link.short_url.clone());
+            event.changes = auditevent::diff(None, Some(link));
+            audit.record(event).await;
+        }
+    }
+
+    let report = ImportReport::new(plan.results, args.dry_run);
+    println!("{}", serde_json::to_string_pretty(&report).map_err(io::Error::other)?);
+    log::info!("{} imported, {} existing, {} conflicts, {} failed{}", report.imported, report.existing, report.conflicts, report.failed,
+        if args.dry_run { " (dry run)" } else { "" });
+
+    Ok(())
+}
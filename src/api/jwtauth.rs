@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{configuration::settings::{Jwt, JwtAlgorithm}, models::apikey::{Principal, Scope}};
+
+const DEFAULT_NAME_CLAIM: &str = "sub";
+const DEFAULT_SCOPES_CLAIM: &str = "scope";
+
+#[derive(Error, Debug)]
+pub enum JwtError {
+    #[error("Missing configuration '{configuration}' for '{algorithm:?}' tokens.")]
+    MissingConfiguration {
+        algorithm: JwtAlgorithm,
+        configuration: String,
+    },
+    #[error("JWKS could not be loaded: {reason}")]
+    JwksError {
+        reason: String,
+    },
+    #[error("no signing key with id '{kid}'")]
+    UnknownKey {
+        kid: String,
+    },
+    #[error("invalid token")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+    #[error("claim '{claim}' is missing from the token")]
+    MissingClaim {
+        claim: String,
+    },
+}
+
+/// Validates bearer tokens of the configured issuer and maps their claims to scopes.
+pub struct JwtValidator {
+    config: Jwt,
+    validation: Validation,
+    /// Signing keys by `kid`, a shared secret has no id
+    keys: RwLock<HashMap<Option<String>, DecodingKey>>,
+}
+
+impl JwtValidator {
+    pub async fn new(config: &Jwt) -> Result<Self, JwtError> {
+        let mut validation = Validation::new(algorithm_of(config.algorithm));
+        validation.set_issuer(&[&config.issuer]);
+        validation.set_audience(&[&config.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+        validation.leeway = config.leeway.unwrap_or(validation.leeway);
+
+        let validator = JwtValidator { config: config.clone(), validation, keys: RwLock::new(HashMap::new()) };
+        validator.refresh().await?;
+
+        Ok(validator)
+    }
+
+    /// Whether the keys are loaded from a JWKS and have to be refreshed.
+    pub fn uses_jwks(&self) -> bool {
+        self.config.jwks_url.is_some() || self.config.jwks_file.is_some()
+    }
+
+    /// Reloads the signing keys. A failed reload keeps the keys loaded before.
+    pub async fn refresh(&self) -> Result<(), JwtError> {
+        let keys = match (&self.config.secret, &self.config.jwks_url, &self.config.jwks_file) {
+            (_, Some(jwks_url), _) => {
+                let jwks = reqwest::get(jwks_url).await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|err| JwtError::JwksError { reason: err.to_string() })?
+                    .text().await
+                    .map_err(|err| JwtError::JwksError { reason: err.to_string() })?;
+                parse_jwks(&jwks)?
+            },
+            (_, _, Some(jwks_file)) => {
+                let jwks = tokio::fs::read_to_string(jwks_file).await
+                    .map_err(|err| JwtError::JwksError { reason: err.to_string() })?;
+                parse_jwks(&jwks)?
+            },
+            (Some(secret), _, _) if self.config.algorithm == JwtAlgorithm::Hs256 => {
+                HashMap::from([(None, DecodingKey::from_secret(secret.as_bytes()))])
+            },
+            _ => return Err(JwtError::MissingConfiguration {
+                algorithm: self.config.algorithm,
+                configuration: String::from(if self.config.algorithm == JwtAlgorithm::Hs256 { "secret" } else { "jwks_url" })
+            })
+        };
+
+        *self.keys.write().unwrap() = keys;
+        Ok(())
+    }
+
+    /// Caller of a valid token, with the scopes its claims grant.
+    pub fn validate(&self, token: &str) -> Result<Principal, JwtError> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let keys = self.keys.read().unwrap();
+
+        let claims = match &header.kid {
+            Some(kid) if keys.contains_key(&Some(kid.clone())) => {
+                jsonwebtoken::decode::<Value>(token, &keys[&Some(kid.clone())], &self.validation)?.claims
+            },
+            Some(kid) if !keys.contains_key(&None) => return Err(JwtError::UnknownKey { kid: kid.clone() }),
+            // Without a matching id every key is tried
+            _ => {
+                let mut result = Err(JwtError::UnknownKey { kid: header.kid.clone().unwrap_or_default() });
+                for key in keys.values() {
+                    result = jsonwebtoken::decode::<Value>(token, key, &self.validation).map_err(JwtError::from);
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+                result?.claims
+            }
+        };
+
+        self.principal_of(&claims)
+    }
+
+    fn principal_of(&self, claims: &Value) -> Result<Principal, JwtError> {
+        let name_claim = self.config.name_claim.as_deref().unwrap_or(DEFAULT_NAME_CLAIM);
+        let name = claims.get(name_claim)
+            .and_then(|name| name.as_str())
+            .ok_or_else(|| JwtError::MissingClaim { claim: name_claim.to_string() })?;
+
+        let scopes_claim = self.config.scopes_claim.as_deref().unwrap_or(DEFAULT_SCOPES_CLAIM);
+        let values: Vec<&str> = match claims.get(scopes_claim) {
+            Some(Value::String(scopes)) => scopes.split_whitespace().collect(),
+            Some(Value::Array(scopes)) => scopes.iter().filter_map(|scope| scope.as_str()).collect(),
+            _ => Vec::new()
+        };
+
+        let mut scopes: Vec<Scope> = Vec::new();
+        for value in values {
+            // Keys of the settings may have been lowercased
+            let mapped = self.config.scope_mapping.iter().flatten()
+                .find(|(claim_value, _)| claim_value.eq_ignore_ascii_case(value))
+                .map(|(_, scopes)| scopes);
+            let granted = match mapped {
+                Some(mapped) => mapped.clone(),
+                None => serde_json::from_value::<Scope>(Value::String(value.to_string())).into_iter().collect()
+            };
+
+            for scope in granted {
+                if !scopes.contains(&scope) {
+                    scopes.push(scope);
+                }
+            }
+        }
+
+        let tenant = self.config.tenant_claim.as_ref()
+            .and_then(|tenant_claim| claims.get(tenant_claim))
+            .and_then(|tenant| tenant.as_str())
+            .map(String::from);
+
+        Ok(Principal { id: format!("jwt:{}/{}", self.config.issuer, name), name: name.to_string(), scopes, tenant })
+    }
+}
+
+fn parse_jwks(jwks: &str) -> Result<HashMap<Option<String>, DecodingKey>, JwtError> {
+    let jwks: JwkSet = serde_json::from_str(jwks)
+        .map_err(|err| JwtError::JwksError { reason: err.to_string() })?;
+
+    let mut keys = HashMap::new();
+    for jwk in &jwks.keys {
+        match DecodingKey::from_jwk(jwk) {
+            Ok(key) => {
+                keys.insert(jwk.common.key_id.clone(), key);
+            },
+            Err(err) => log::warn!("Skipping JWKS key {:?}: {}", jwk.common.key_id, err)
+        }
+    }
+
+    Ok(keys)
+}
+
+fn algorithm_of(algorithm: JwtAlgorithm) -> Algorithm {
+    match algorithm {
+        JwtAlgorithm::Hs256 => Algorithm::HS256,
+        JwtAlgorithm::Rs256 => Algorithm::RS256,
+        JwtAlgorithm::Es256 => Algorithm::ES256
+    }
+}
@@ -1,19 +1,65 @@
-use std::time::Instant;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
-use crate::{configuration::settings::RateLimit, constants::{DEFAULT_CAPACITY, DEFAULT_FILL_RATE}};
+use crate::{configuration::settings::{RateLimit, RateLimitAlgorithm}, constants::{DEFAULT_CAPACITY, DEFAULT_FILL_RATE}};
 
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Outcome of a rate limited request, reported to the client in the response headers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: usize,
+    pub remaining: usize,
+    /// Time until the next tokens are added, zero when the bucket is full
+    pub reset: Duration,
+}
+
+impl RateLimitDecision {
+    /// Time the client has to wait before a denied request can succeed.
+    pub fn retry_after(&self) -> Option<Duration> {
+        if self.allowed {
+            None
+        } else {
+            Some(self.reset)
+        }
+    }
+}
+
+/// Rate limiting algorithm keeping the state of a single client.
+pub trait Limiter: Send {
+    fn try_consume_at(&mut self, tokens: usize, now: Instant) -> RateLimitDecision;
+
+    fn try_consume(&mut self, tokens: usize) -> RateLimitDecision {
+        self.try_consume_at(tokens, Instant::now())
+    }
+}
+
+/// Builds the limiter of the configured algorithm. Every algorithm allows bursts of
+/// `capacity` requests and `fill_rate` requests per second on average.
+pub fn build_limiter(rate_limit: &RateLimit) -> Box<dyn Limiter> {
+    match rate_limit.algorithm.unwrap_or_default() {
+        RateLimitAlgorithm::TokenBucket => Box::new(RateLimiter::new(Some(rate_limit.clone()))),
+        RateLimitAlgorithm::SlidingWindowLog => Box::new(SlidingWindowLog::new(rate_limit)),
+        RateLimitAlgorithm::SlidingWindowCounter => Box::new(SlidingWindowCounter::new(rate_limit)),
+        RateLimitAlgorithm::Gcra => Box::new(Gcra::new(rate_limit))
+    }
+}
+
+/// Token bucket.
 #[derive(Clone)]
 pub struct RateLimiter {
     pub capacity: usize,
     pub tokens: usize,
     pub fill_rate: usize,
+    /// Time the tokens were refilled up to, the remainder of a token is carried over
     pub last_update: Instant,
 }
 
 impl RateLimiter {
     pub fn new(rate_limit_options: Option<RateLimit>) -> Self {
         match rate_limit_options {
-            Some(rate_limit) => {        
+            Some(rate_limit) => {
                 RateLimiter {
                     capacity: rate_limit.capacity,
                     tokens: rate_limit.capacity,
@@ -33,25 +79,231 @@ impl RateLimiter {
     }
 
     pub fn consume(&mut self, tokens: usize) -> bool {
-        self.update_tokens();
+        self.try_consume(tokens).allowed
+    }
 
-        if self.tokens >= tokens {
-            self.tokens -= tokens;
-            true
-        } else {
-            false
+    /// Time until the next token is added, zero when the bucket is full.
+    pub fn time_until_refill(&self) -> Duration {
+        self.time_until_refill_at(Instant::now())
+    }
+
+    fn time_until_refill_at(&self, now: Instant) -> Duration {
+        if self.tokens >= self.capacity || self.fill_rate == 0 {
+            return Duration::ZERO;
         }
+
+        let nanos_per_token = NANOS_PER_SEC.div_ceil(self.fill_rate as u128);
+        let elapsed = now.saturating_duration_since(self.last_update).as_nanos();
+        nanos_to_duration(nanos_per_token.saturating_sub(elapsed))
     }
 
     pub fn update_tokens(&mut self) {
-        let now = Instant::now();
-        let elapsed = now.duration_since(self.last_update);
-        let seconds = elapsed.as_secs();
-        let new_tokens = (self.fill_rate as u64 * seconds) as usize;
+        self.update_tokens_at(Instant::now());
+    }
 
-        if new_tokens > 0 {
-            self.tokens = usize::min(self.capacity, self.tokens + new_tokens);
+    fn update_tokens_at(&mut self, now: Instant) {
+        if self.tokens >= self.capacity || self.fill_rate == 0 {
+            // A full bucket doesn't save up time for later
             self.last_update = now;
+            return;
+        }
+
+        let elapsed = now.saturating_duration_since(self.last_update).as_nanos();
+        let new_tokens = elapsed * self.fill_rate as u128 / NANOS_PER_SEC;
+
+        if new_tokens >= (self.capacity - self.tokens) as u128 {
+            self.tokens = self.capacity;
+            self.last_update = now;
+        } else if new_tokens > 0 {
+            self.tokens += new_tokens as usize;
+            // Only move forward by the time the added tokens took
+            self.last_update += nanos_to_duration(new_tokens * NANOS_PER_SEC / self.fill_rate as u128);
+        }
+    }
+}
+
+impl Limiter for RateLimiter {
+    fn try_consume_at(&mut self, tokens: usize, now: Instant) -> RateLimitDecision {
+        self.update_tokens_at(now);
+
+        let allowed = self.tokens >= tokens;
+        if allowed {
+            self.tokens -= tokens;
+        }
+
+        RateLimitDecision {
+            allowed,
+            limit: self.capacity,
+            remaining: self.tokens,
+            reset: self.time_until_refill_at(now)
+        }
+    }
+}
+
+/// Keeps the time of every request in the window of `capacity / fill_rate`
+/// seconds. Exact, but its memory grows with the limit.
+pub struct SlidingWindowLog {
+    limit: usize,
+    window: Duration,
+    log: VecDeque<Instant>,
+}
+
+impl SlidingWindowLog {
+    pub fn new(rate_limit: &RateLimit) -> Self {
+        SlidingWindowLog { limit: rate_limit.capacity, window: window_of(rate_limit), log: VecDeque::new() }
+    }
+}
+
+impl Limiter for SlidingWindowLog {
+    fn try_consume_at(&mut self, tokens: usize, now: Instant) -> RateLimitDecision {
+        while self.log.front().is_some_and(|time| now.saturating_duration_since(*time) >= self.window) {
+            self.log.pop_front();
+        }
+
+        let allowed = self.log.len() + tokens <= self.limit;
+        if allowed {
+            for _ in 0..tokens {
+                self.log.push_back(now);
+            }
+        }
+
+        // The next slot frees up when the oldest request leaves the window
+        let reset = match self.log.front() {
+            Some(oldest) => self.window.saturating_sub(now.saturating_duration_since(*oldest)),
+            None => Duration::ZERO
+        };
+
+        RateLimitDecision {
+            allowed,
+            limit: self.limit,
+            remaining: self.limit.saturating_sub(self.log.len()),
+            reset
+        }
+    }
+}
+
+/// Counts requests in fixed windows and weighs the previous window by how much
+/// of it still overlaps the sliding window. Approximate, but constant in memory.
+pub struct SlidingWindowCounter {
+    limit: usize,
+    window: Duration,
+    window_start: Instant,
+    previous: usize,
+    current: usize,
+}
+
+impl SlidingWindowCounter {
+    pub fn new(rate_limit: &RateLimit) -> Self {
+        SlidingWindowCounter {
+            limit: rate_limit.capacity,
+            window: window_of(rate_limit),
+            window_start: Instant::now(),
+            previous: 0,
+            current: 0
         }
     }
-}
\ No newline at end of file
+
+    fn advance(&mut self, now: Instant) {
+        let window = self.window.as_nanos();
+        let windows = now.saturating_duration_since(self.window_start).as_nanos() / window;
+
+        if windows > 0 {
+            self.previous = if windows == 1 { self.current } else { 0 };
+            self.current = 0;
+            self.window_start += nanos_to_duration(windows * window);
+        }
+    }
+
+    fn estimate(&self, elapsed: Duration) -> f64 {
+        let overlap = 1.0 - elapsed.as_secs_f64() / self.window.as_secs_f64();
+        self.previous as f64 * overlap + self.current as f64
+    }
+}
+
+impl Limiter for SlidingWindowCounter {
+    fn try_consume_at(&mut self, tokens: usize, now: Instant) -> RateLimitDecision {
+        self.advance(now);
+        let elapsed = now.saturating_duration_since(self.window_start);
+
+        let allowed = self.estimate(elapsed) + tokens as f64 <= self.limit as f64;
+        if allowed {
+            self.current += tokens;
+        }
+
+        let estimate = self.estimate(elapsed);
+        let remaining = (self.limit as f64 - estimate).max(0.0).floor() as usize;
+        let until_next_window = self.window.saturating_sub(elapsed);
+
+        // The previous window's weight shrinks continuously, the current count only
+        // moves to the previous window when the next one starts
+        let reset = if remaining >= self.limit {
+            Duration::ZERO
+        } else if self.previous > 0 && self.current < self.limit {
+            let overlap = (self.limit - 1 - usize::min(self.current, self.limit - 1)) as f64 / self.previous as f64;
+            let target = self.window.mul_f64((1.0 - overlap).clamp(0.0, 1.0));
+            Duration::min(target.saturating_sub(elapsed), until_next_window)
+        } else {
+            until_next_window
+        };
+
+        RateLimitDecision { allowed, limit: self.limit, remaining, reset }
+    }
+}
+
+/// Generic cell rate algorithm. Tracks only the theoretical arrival time of the
+/// next request, which moves `1 / fill_rate` seconds ahead with every request.
+pub struct Gcra {
+    limit: usize,
+    // Nanoseconds, relative to origin
+    emission_interval: u128,
+    theoretical_arrival: u128,
+    origin: Instant,
+}
+
+impl Gcra {
+    pub fn new(rate_limit: &RateLimit) -> Self {
+        let emission_interval = match rate_limit.fill_rate {
+            0 => u64::MAX as u128,
+            fill_rate => NANOS_PER_SEC / fill_rate as u128
+        };
+
+        Gcra { limit: rate_limit.capacity, emission_interval, theoretical_arrival: 0, origin: Instant::now() }
+    }
+}
+
+impl Limiter for Gcra {
+    fn try_consume_at(&mut self, tokens: usize, now: Instant) -> RateLimitDecision {
+        let now = now.saturating_duration_since(self.origin).as_nanos();
+        let tolerance = self.emission_interval * self.limit as u128;
+
+        let new_arrival = u128::max(self.theoretical_arrival, now) + self.emission_interval * tokens as u128;
+        let allowed = new_arrival - now <= tolerance;
+        if allowed {
+            self.theoretical_arrival = new_arrival;
+        }
+
+        // Time the client is ahead of the sustained rate
+        let debt = self.theoretical_arrival.saturating_sub(now);
+        let remaining = ((tolerance - u128::min(debt, tolerance)) / self.emission_interval) as usize;
+        let reset = match debt % self.emission_interval {
+            0 if debt > 0 => self.emission_interval,
+            rest => rest
+        };
+
+        RateLimitDecision { allowed, limit: self.limit, remaining, reset: nanos_to_duration(reset) }
+    }
+}
+
+/// Window in which `capacity` requests are allowed at `fill_rate` requests per second.
+fn window_of(rate_limit: &RateLimit) -> Duration {
+    if rate_limit.fill_rate == 0 {
+        return Duration::from_secs(u32::MAX as u64);
+    }
+
+    let nanos = u128::max(rate_limit.capacity as u128 * NANOS_PER_SEC / rate_limit.fill_rate as u128, 1);
+    nanos_to_duration(nanos)
+}
+
+fn nanos_to_duration(nanos: u128) -> Duration {
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
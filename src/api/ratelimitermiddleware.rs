@@ -1,9 +1,17 @@
-use std::sync::{Mutex, Arc};
-use actix_web::{error, Result, Error, dev::{forward_ready, Service, ServiceRequest, ServiceResponse}, HttpResponse, http::{StatusCode, header::ContentType}};
+use std::sync::Arc;
+use std::time::Duration;
+use actix_web::{error, Result, Error, HttpMessage, dev::{forward_ready, Service, ServiceRequest, ServiceResponse}, HttpResponse, http::{StatusCode, header::{self, HeaderMap, HeaderName, HeaderValue}}};
 use derive_more::{Display, Error};
 use futures_util::future::LocalBoxFuture;
 
-use super::ratelimiter::RateLimiter;
+use crate::models::apikey::Principal;
+
+use super::ratelimiter::RateLimitDecision;
+use super::ratelimitpolicy::RateLimitPolicy;
+
+const RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
 
 #[derive(Debug, Display, Error)]
 pub enum UserError {
@@ -14,8 +22,9 @@ pub enum UserError {
 impl error::ResponseError for UserError {
     fn error_response(&self) -> HttpResponse {
         HttpResponse::build(self.status_code())
-            .insert_header(ContentType::html())
-            .body(self.to_string())
+            .json(serde_json::json!({
+                "error": self.to_string()
+            }))
     }
 
     fn status_code(&self) -> StatusCode {
@@ -27,12 +36,12 @@ impl error::ResponseError for UserError {
 
 pub struct RateLimiterMiddlewareService<S> {
     pub service: S,
-    pub rate_limiter: Arc<Mutex<RateLimiter>>,
+    pub policy: Arc<RateLimitPolicy>,
 }
 
 impl<S> RateLimiterMiddlewareService<S> {
-    pub fn new(service: S, rate_limiter: Arc<Mutex<RateLimiter>>) -> Self {
-        RateLimiterMiddlewareService { service, rate_limiter }
+    pub fn new(service: S, policy: Arc<RateLimitPolicy>) -> Self {
+        RateLimiterMiddlewareService { service, policy }
     }
 }
 
@@ -49,21 +58,54 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Runs after the auth middleware, so authenticated callers are limited by who they are
+        let principal = req.extensions().get::<Principal>().cloned();
+        let rate_limiter = match self.policy.store() {
+            Some(store) if !self.policy.bypasses(principal.as_ref(), req.headers(), req.peer_addr()) => store.clone(),
+            // Not limited or allowlisted
+            _ => return Box::pin(self.service.call(req))
+        };
+
+        let key = self.policy.client_key(principal.as_ref(), req.headers(), req.peer_addr());
         let fut = self.service.call(req);
-        let rate_limiter = self.rate_limiter.clone();
 
         Box::pin(async move {
-            // Access the rate limiter instance
-            let mut ratelimiter = rate_limiter.lock().unwrap();
+            // Check if the request can be processed based on the client's bucket.
+            // An unavailable store must not take the service down with it.
+            let decision = match rate_limiter.consume(&key, 1).await {
+                Ok(decision) => decision,
+                Err(err) => {
+                    log::error!("Rate limit store failed, letting the request through: {}", err);
+                    return fut.await;
+                }
+            };
 
-            // Check if the request can be processed based on the rate limiter
-            if ratelimiter.consume(1) {
+            if decision.allowed {
                 // Continue processing the request
-                let res = fut.await?;
+                let mut res = fut.await?;
+                insert_rate_limit_headers(res.headers_mut(), &decision);
                 Ok(res)
             } else {
-                Err(Error::from(UserError::TooManyRequests))
+                let mut response = error::ResponseError::error_response(&UserError::TooManyRequests);
+                insert_rate_limit_headers(response.headers_mut(), &decision);
+                Err(error::InternalError::from_response(UserError::TooManyRequests, response).into())
             }
         })
     }
-}
\ No newline at end of file
+}
+
+/// Adds the headers of the IETF RateLimit header fields draft, times are in whole seconds.
+pub(crate) fn insert_rate_limit_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    headers.insert(RATE_LIMIT_LIMIT, HeaderValue::from(decision.limit));
+    headers.insert(RATE_LIMIT_REMAINING, HeaderValue::from(decision.remaining));
+    headers.insert(RATE_LIMIT_RESET, HeaderValue::from(ceil_secs(decision.reset)));
+
+    if let Some(retry_after) = decision.retry_after() {
+        // Retrying right away would be denied again
+        headers.insert(header::RETRY_AFTER, HeaderValue::from(u64::max(ceil_secs(retry_after), 1)));
+    }
+}
+
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use actix_web::http::header::HeaderMap;
+use ipnet::IpNet;
+
+use crate::{configuration::settings::{RateLimit, RateLimitKey, Settings, DEFAULT_RATE_LIMIT}, constants::RATE_LIMIT_POLICY_SHORTEN, models::apikey::Principal};
+
+use super::clientratelimiter::{self, TrustedProxies};
+use super::ratelimitstore::{self, RateLimitStore, RateLimitStoreError};
+
+/// Rate limit attached to a route: where the buckets are kept, how clients are
+/// identified and which clients bypass the limit.
+pub struct RateLimitPolicy {
+    name: String,
+    store: Option<Arc<dyn RateLimitStore>>,
+    key_extractor: RateLimitKey,
+    /// Principals of the allowlisted API keys, "key:<name>"
+    allowed_keys: Vec<String>,
+    allowed_networks: Vec<IpNet>,
+    trusted_proxies: TrustedProxies,
+}
+
+impl RateLimitPolicy {
+    pub fn new(name: &str, rate_limit: &RateLimit, store: Arc<dyn RateLimitStore>) -> Result<Self, RateLimitStoreError> {
+        let allowed_networks = rate_limit.allowed_networks.iter().flatten()
+            .map(|network| clientratelimiter::parse_network(network).ok_or_else(|| RateLimitStoreError::InvalidNetwork {
+                policy: name.to_string(),
+                network: network.clone()
+            }))
+            .collect::<Result<Vec<IpNet>, RateLimitStoreError>>()?;
+
+        Ok(RateLimitPolicy {
+            name: name.to_string(),
+            store: Some(store),
+            key_extractor: rate_limit.key_extractor.unwrap_or_default(),
+            allowed_keys: rate_limit.allowed_keys.iter().flatten().map(|name| format!("key:{}", name)).collect(),
+            allowed_networks,
+            trusted_proxies: TrustedProxies::default()
+        })
+    }
+
+    /// Proxies whose client IP headers identify the client, by default none are trusted.
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Policy of a route without a configured limit.
+    pub fn unlimited(name: &str) -> Self {
+        RateLimitPolicy {
+            name: name.to_string(),
+            store: None,
+            key_extractor: RateLimitKey::default(),
+            allowed_keys: Vec::new(),
+            allowed_networks: Vec::new(),
+            trusted_proxies: TrustedProxies::default()
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Store of the buckets, `None` when the route isn't limited.
+    pub fn store(&self) -> Option<&Arc<dyn RateLimitStore>> {
+        self.store.as_ref()
+    }
+
+    /// Key of the client's bucket, by the authenticated caller or else the IP of the client.
+    /// Policies sharing a store don't share buckets.
+    pub fn client_key(&self, principal: Option<&Principal>, headers: &HeaderMap, peer_addr: Option<SocketAddr>) -> String {
+        let client_ip = self.trusted_proxies.client_ip(headers, peer_addr);
+        format!("{}:{}", self.name, clientratelimiter::client_key(self.key_extractor, principal, client_ip))
+    }
+
+    /// Whether the client is allowlisted by the name of the API key it authenticated with,
+    /// or by its network.
+    pub fn bypasses(&self, principal: Option<&Principal>, headers: &HeaderMap, peer_addr: Option<SocketAddr>) -> bool {
+        if principal.is_some_and(|principal| self.allowed_keys.contains(&principal.id)) {
+            return true;
+        }
+
+        if self.allowed_networks.is_empty() {
+            return false;
+        }
+
+        self.trusted_proxies.client_ip(headers, peer_addr)
+            .is_some_and(|ip| self.allowed_networks.iter().any(|network| network.contains(&ip)))
+    }
+}
+
+/// Policies by name. Routes whose policy isn't configured are not limited, except
+/// `shorten` which falls back to the top level rate limit.
+#[derive(Clone)]
+pub struct RateLimitPolicies {
+    policies: HashMap<String, Arc<RateLimitPolicy>>,
+}
+
+impl RateLimitPolicies {
+    pub async fn new(settings: &Settings) -> Result<Self, RateLimitStoreError> {
+        let rate_limit = settings.ratelimit.clone().unwrap_or(DEFAULT_RATE_LIMIT);
+        let trusted_proxies = TrustedProxies::from_settings(&settings.apiserver)?;
+        let mut policies: HashMap<String, Arc<RateLimitPolicy>> = HashMap::new();
+
+        for (name, policy_rate_limit) in rate_limit.policies.iter().flatten() {
+            let store = ratelimitstore::create_policy_store(policy_rate_limit, settings).await?;
+            let policy = RateLimitPolicy::new(name, policy_rate_limit, store)?
+                .with_trusted_proxies(trusted_proxies.clone());
+            policies.insert(name.clone(), Arc::new(policy));
+        }
+
+        if !policies.contains_key(RATE_LIMIT_POLICY_SHORTEN) {
+            let store = ratelimitstore::create_policy_store(&rate_limit, settings).await?;
+            let policy = RateLimitPolicy::new(RATE_LIMIT_POLICY_SHORTEN, &rate_limit, store)?
+                .with_trusted_proxies(trusted_proxies);
+            policies.insert(RATE_LIMIT_POLICY_SHORTEN.to_string(), Arc::new(policy));
+        }
+
+        Ok(RateLimitPolicies { policies })
+    }
+
+    pub fn get(&self, name: &str) -> Arc<RateLimitPolicy> {
+        match self.policies.get(name) {
+            Some(policy) => policy.clone(),
+            None => Arc::new(RateLimitPolicy::unlimited(name))
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use thiserror::Error;
+
+use crate::{configuration::settings::{RateLimit, RateLimitAlgorithm, RateLimitStoreMode, Settings, DEFAULT_RATE_LIMIT}, constants::RATE_LIMIT_KEY_PREFIX};
+
+use super::clientratelimiter::ClientRateLimiter;
+use super::ratelimiter::RateLimitDecision;
+
+#[derive(Error, Debug)]
+pub enum RateLimitStoreError {
+    #[error("rate limit store connection error")]
+    RedisConnectionError(#[from] redis::RedisError),
+    #[error("Missing configuration '{configuration}' for the '{store}' rate limit store.")]
+    MissingConfiguration {
+        store: String,
+        configuration: String,
+    },
+    #[error("The '{store}' rate limit store doesn't support the '{algorithm}' algorithm.")]
+    UnsupportedAlgorithm {
+        store: String,
+        algorithm: String,
+    },
+    #[error("Invalid network '{network}' in rate limit policy '{policy}'.")]
+    InvalidNetwork {
+        policy: String,
+        network: String,
+    },
+    #[error("Invalid trusted proxy '{network}'.")]
+    InvalidTrustedProxy {
+        network: String,
+    },
+}
+
+/// Where the token buckets of the clients are kept.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Takes tokens from the bucket of the client if there are enough.
+    async fn consume(&self, key: &str, tokens: usize) -> Result<RateLimitDecision, RateLimitStoreError>;
+}
+
+/// Buckets of this instance only, every instance enforces the limit on its own.
+#[async_trait]
+impl RateLimitStore for Mutex<ClientRateLimiter> {
+    async fn consume(&self, key: &str, tokens: usize) -> Result<RateLimitDecision, RateLimitStoreError> {
+        Ok(self.lock().unwrap().consume(key, tokens))
+    }
+}
+
+// Refills and takes tokens atomically. The time is taken from the Redis server,
+// so instances with skewed clocks share the same buckets.
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local fill_rate = tonumber(ARGV[2])
+local requested = tonumber(ARGV[3])
+
+local time = redis.call('TIME')
+local now = tonumber(time[1]) + tonumber(time[2]) / 1000000
+
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(bucket[1]) or capacity
+local updated_at = tonumber(bucket[2]) or now
+
+tokens = math.min(capacity, tokens + math.max(0, now - updated_at) * fill_rate)
+
+local allowed = 0
+if tokens >= requested then
+    tokens = tokens - requested
+    allowed = 1
+end
+
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', tostring(now))
+if fill_rate > 0 then
+    redis.call('EXPIRE', KEYS[1], math.ceil(capacity / fill_rate) + 1)
+end
+
+-- Lua numbers are truncated to integers when returned, so the tokens are sent as a string
+return {allowed, tostring(tokens)}
+"#;
+
+/// Buckets shared by every instance connected to the same Redis.
+pub struct RedisRateLimitStore {
+    rate_limit: RateLimit,
+    connection: ConnectionManager,
+    script: redis::Script,
+}
+
+impl RedisRateLimitStore {
+    pub async fn new(connection_string: &str, rate_limit: RateLimit) -> Result<Self, RateLimitStoreError> {
+        let client = redis::Client::open(connection_string)?;
+        let connection = ConnectionManager::new(client).await?;
+
+        Ok(RedisRateLimitStore { rate_limit, connection, script: redis::Script::new(TOKEN_BUCKET_SCRIPT) })
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    async fn consume(&self, key: &str, tokens: usize) -> Result<RateLimitDecision, RateLimitStoreError> {
+        // The manager is a cheap handle to a shared multiplexed connection
+        let mut connection = self.connection.clone();
+        let (allowed, remaining): (i32, String) = self.script
+            .key(format!("{}{}", RATE_LIMIT_KEY_PREFIX, key))
+            .arg(self.rate_limit.capacity)
+            .arg(self.rate_limit.fill_rate)
+            .arg(tokens)
+            .invoke_async(&mut connection)
+            .await?;
+
+        let remaining = remaining.parse::<f64>().unwrap_or(0.0);
+        let capacity = self.rate_limit.capacity as f64;
+        let fill_rate = self.rate_limit.fill_rate as f64;
+
+        // Tokens are refilled continuously, reset is the time until the next whole token
+        let reset = if remaining >= capacity || fill_rate <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((remaining.floor() + 1.0 - remaining) / fill_rate)
+        };
+
+        Ok(RateLimitDecision {
+            allowed: allowed == 1,
+            limit: self.rate_limit.capacity,
+            remaining: remaining.floor() as usize,
+            reset
+        })
+    }
+}
+
+pub async fn create_rate_limit_store(settings: &Settings) -> Result<Arc<dyn RateLimitStore>, RateLimitStoreError> {
+    let rate_limit = settings.ratelimit.clone().unwrap_or(DEFAULT_RATE_LIMIT);
+    create_policy_store(&rate_limit, settings).await
+}
+
+/// Creates the store of a single policy, the connection of a Redis store is taken from the settings.
+pub async fn create_policy_store(rate_limit: &RateLimit, settings: &Settings) -> Result<Arc<dyn RateLimitStore>, RateLimitStoreError> {
+    match rate_limit.store.unwrap_or_default() {
+        RateLimitStoreMode::InMemory => Ok(Arc::new(Mutex::new(ClientRateLimiter::new(Some(rate_limit.clone()))))),
+        RateLimitStoreMode::Redis => {
+            let algorithm = rate_limit.algorithm.unwrap_or_default();
+            if algorithm != RateLimitAlgorithm::TokenBucket {
+                return Err(RateLimitStoreError::UnsupportedAlgorithm {
+                    store: RateLimitStoreMode::Redis.to_string(),
+                    algorithm: algorithm.to_string()
+                });
+            }
+
+            match &settings.redis_config {
+                None => Err(RateLimitStoreError::MissingConfiguration {
+                    store: RateLimitStoreMode::Redis.to_string(),
+                    configuration: String::from("redis_config")
+                }),
+                Some(redis_config) => {
+                    Ok(Arc::new(RedisRateLimitStore::new(&redis_config.connection_string, rate_limit.clone()).await?))
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use config::{Config, ConfigError, File};
-use serde::Deserialize;
-use std::{env, fmt};
+use serde::{Deserialize, Serialize};
+
+use crate::models::apikey::{ApiKey, Scope};
+use crate::services::linkimport::ImportSource;
+use std::{collections::HashMap, env, fmt};
 use clap::Parser;
 
 use crate::constants::{DEFAULT_CAPACITY, DEFAULT_FILL_RATE};
@@ -12,7 +15,8 @@ pub enum Mode {
     Mongo,
     Coordinator,
     Redis,
-    Firestore
+    Firestore,
+    Gossip
 }
 
 impl fmt::Display for Mode {
@@ -21,13 +25,23 @@ impl fmt::Display for Mode {
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 #[allow(unused)]
 pub struct ApiServer {
     pub application_url: String,
     pub hostname: String,
     pub allow_origin: String,
+    /// Single key with every scope, kept for existing configurations
     pub api_key: Option<String>,
+    /// Named keys with their own scopes, e.g. [[apiserver.api_keys]]
+    pub api_keys: Option<Vec<ApiKey>>,
+    /// Scheme of the short links in responses, "https" by default
+    pub scheme: Option<String>,
+    /// Template of the QR code image of a link, "{short_link}" is replaced by the encoded short link
+    pub qr_code_url: Option<String>,
+    /// Proxies whose client IP headers are trusted, in CIDR notation or single addresses.
+    /// Clients connecting directly are identified by the address of the connection
+    pub trusted_proxies: Option<Vec<String>>,
     #[serde(rename = "GOOGLE_APPLICATION_CREDENTIALS")]
     pub google_application_credentials: Option<String>
 }
@@ -52,23 +66,233 @@ pub struct FirestoreConfig {
     pub project_id: String
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(unused)]
 pub struct RateLimit {
     pub capacity: usize,
-    pub fill_rate: usize
+    pub fill_rate: usize,
+    /// What identifies a client, every client gets its own bucket
+    pub key_extractor: Option<RateLimitKey>,
+    /// Number of client buckets kept, the least recently used ones are evicted
+    pub max_clients: Option<usize>,
+    /// Where the buckets are kept, Redis shares them between instances
+    pub store: Option<RateLimitStoreMode>,
+    pub algorithm: Option<RateLimitAlgorithm>,
+    /// Names of the API keys that bypass the limit, checked once the key authenticated
+    pub allowed_keys: Option<Vec<String>>,
+    /// Client networks in CIDR notation that bypass the limit, e.g. "10.0.0.0/8"
+    pub allowed_networks: Option<Vec<String>>,
+    /// Policies attached to routes by name, e.g. [ratelimit.policies.redirect].
+    /// Only read from the top level ratelimit section
+    pub policies: Option<HashMap<String, RateLimit>>
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum RateLimitAlgorithm {
+    #[default]
+    TokenBucket,
+    SlidingWindowLog,
+    SlidingWindowCounter,
+    Gcra
+}
+
+impl fmt::Display for RateLimitAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum RateLimitStoreMode {
+    #[default]
+    InMemory,
+    /// Uses the connection of `redis_config`
+    Redis
+}
+
+impl fmt::Display for RateLimitStoreMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum RateLimitKey {
+    /// Authenticated caller when the request has one, the client IP otherwise
+    #[default]
+    ApiKeyOrIp,
+    Ip,
+    /// A single bucket shared by every client
+    Global
 }
 
 #[derive(Clone, Debug, Deserialize)]
 #[allow(unused)]
 pub struct Coordinator {
-    pub hostnames: Vec<String>
+    pub hostnames: Vec<String>,
+    /// API key sent to the nodes' admin endpoints
+    pub api_key: Option<String>,
+    pub max_hints_per_node: Option<usize>,
+    /// Seconds between attempts to replay hints to unreachable nodes
+    pub hint_replay_interval: Option<u64>,
+    /// Seconds between anti-entropy runs, 0 disables them
+    pub anti_entropy_interval: Option<u64>,
+    pub anti_entropy_ranges: Option<usize>,
+    /// Seconds between health probes of the nodes
+    pub health_check_interval: Option<u64>,
+    /// Number of failed probes in a row after which a node is marked down
+    pub unhealthy_threshold: Option<u32>
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[allow(unused)]
+pub struct Gossip {
+    /// Address the gossip agent listens on for UDP messages, e.g. "0.0.0.0:7946"
+    pub bind_address: String,
+    /// Gossip address other instances reach this one on, defaults to bind_address
+    pub advertise_address: Option<String>,
+    /// Url other instances reach this instance's HTTP API on, defaults to http://{application_url}
+    pub advertise_url: Option<String>,
+    /// Gossip addresses of instances to join the cluster through
+    pub seeds: Vec<String>,
+    /// Mode of the local storage every instance keeps a full replica in
+    pub storage: Option<Mode>,
+    /// API key sent to the other instances' admin endpoints
+    pub api_key: Option<String>,
+    /// Shared secret the gossip messages are signed with, required
+    pub secret: Option<String>,
+    /// Milliseconds between probes of other instances
+    pub protocol_period: Option<u64>,
+    /// Milliseconds a suspected instance has to refute before it's declared dead
+    pub suspect_timeout: Option<u64>,
+    pub indirect_checks: Option<usize>,
+    pub max_hints_per_node: Option<usize>,
+    pub hint_replay_interval: Option<u64>,
+    pub anti_entropy_interval: Option<u64>,
+    pub anti_entropy_ranges: Option<usize>
+}
+
+impl Gossip {
+    /// Replication options shared with coordinator mode.
+    pub fn cluster_config(&self) -> Coordinator {
+        Coordinator {
+            hostnames: Vec::new(),
+            api_key: self.api_key.clone(),
+            max_hints_per_node: self.max_hints_per_node,
+            hint_replay_interval: self.hint_replay_interval,
+            anti_entropy_interval: self.anti_entropy_interval,
+            anti_entropy_ranges: self.anti_entropy_ranges,
+            health_check_interval: None,
+            unhealthy_threshold: None
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[allow(unused)]
+pub struct Jwt {
+    /// Expected `iss` claim
+    pub issuer: String,
+    /// Expected `aud` claim
+    pub audience: String,
+    pub algorithm: JwtAlgorithm,
+    /// Shared secret of HS256 tokens
+    pub secret: Option<String>,
+    /// JWKS the signing keys are taken from, by the `kid` of the token
+    pub jwks_url: Option<String>,
+    pub jwks_file: Option<String>,
+    /// Seconds between reloads of the JWKS
+    pub jwks_refresh_interval: Option<u64>,
+    /// Seconds of clock skew tolerated when checking the expiry
+    pub leeway: Option<u64>,
+    /// Claim naming the caller, "sub" by default
+    pub name_claim: Option<String>,
+    /// Claim holding the scopes as a space separated string or an array, "scope" by default
+    pub scopes_claim: Option<String>,
+    /// Scopes granted by the values of the scopes claim, e.g. { "shortener-admins" = ["admin"] }.
+    /// Values that aren't mapped are taken as scopes when they name one
+    pub scope_mapping: Option<HashMap<String, Vec<Scope>>>,
+    /// Claim naming the tenant of the caller, callers without one use the shared links
+    pub tenant_claim: Option<String>
 }
 
-pub const DEFAULT_RATE_LIMIT: RateLimit = RateLimit{ capacity: DEFAULT_CAPACITY, fill_rate: DEFAULT_FILL_RATE };
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum JwtAlgorithm {
+    #[serde(rename = "HS256")]
+    Hs256,
+    #[serde(rename = "RS256")]
+    Rs256,
+    #[serde(rename = "ES256")]
+    Es256
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[allow(unused)]
+pub struct Tenant {
+    /// Namespace of the tenant's links in the storage, letters, digits, '-' and '_'
+    pub id: String,
+    pub max_links: Option<usize>,
+    /// Counted per UTC day in the usage store of the backend, shared by every instance. In memory, every instance counts on its own
+    pub max_shortens_per_day: Option<usize>
+}
 
 #[derive(Clone, Debug, Deserialize)]
 #[allow(unused)]
+pub struct UrlValidation {
+    /// Schemes long urls may have, "http" and "https" by default
+    pub allowed_schemes: Option<Vec<String>>,
+    /// Sorts the query parameters by name, so urls that only differ in their order share a link
+    pub sort_query_params: Option<bool>
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[allow(unused)]
+pub struct Destinations {
+    /// File with more rules, one per line, reloaded when it changes
+    pub rules_file: Option<String>,
+    /// Domains links may not point to, their subdomains included
+    pub blocked_domains: Option<Vec<String>>,
+    /// Regular expressions of the urls links may not point to
+    pub blocked_patterns: Option<Vec<String>>,
+    /// When any allow rule is set, links may only point to urls matching one of them
+    pub allowed_domains: Option<Vec<String>>,
+    pub allowed_patterns: Option<Vec<String>>
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[allow(unused)]
+pub struct UrlSafety {
+    /// Hash prefixes of unsafe urls in the Safe Browsing format, one hex prefix per line
+    pub hash_prefix_file: Option<String>,
+    /// Scanning service every url is posted to
+    pub checker_url: Option<String>,
+    /// Seconds a verdict is cached for a domain, an hour by default
+    pub cache_ttl: Option<u64>,
+    pub cache_size: Option<usize>
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[allow(unused)]
+pub struct Trash {
+    /// Days a deleted link can be restored before it's purged, 30 by default
+    pub retention_days: Option<u64>,
+    /// Seconds between purges of the expired trash, an hour by default
+    pub purge_interval: Option<u64>
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[allow(unused)]
+pub struct DisabledLinks {
+    /// Status disabled links answer with, 404 or 410, 410 by default
+    pub status: Option<u16>,
+    /// HTML page disabled links answer with
+    pub page_file: Option<String>
+}
+
+pub const DEFAULT_RATE_LIMIT: RateLimit = RateLimit{ capacity: DEFAULT_CAPACITY, fill_rate: DEFAULT_FILL_RATE, key_extractor: None, max_clients: None, store: None, algorithm: None, allowed_keys: None, allowed_networks: None, policies: None };
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[allow(unused)]
 pub struct Settings {
     pub debug: bool,
     pub mode: Mode,
@@ -77,7 +301,22 @@ pub struct Settings {
     pub redis_config: Option<RedisConfig>,
     pub firestore_config: Option<FirestoreConfig>,
     pub ratelimit: Option<RateLimit>,
-    pub coordinator: Option<Coordinator>
+    pub coordinator: Option<Coordinator>,
+    pub gossip: Option<Gossip>,
+    /// Bearer tokens accepted on the admin endpoints next to the API keys
+    pub jwt: Option<Jwt>,
+    /// Workspaces with links of their own, e.g. [[tenants]]
+    pub tenants: Option<Vec<Tenant>>,
+    /// Checks and normalization of the urls to shorten
+    pub url_validation: Option<UrlValidation>,
+    /// Domains and urls links may or may not point to
+    pub destinations: Option<Destinations>,
+    /// Safety checks of the urls to shorten, flagged links are quarantined
+    pub url_safety: Option<UrlSafety>,
+    /// How long deleted links are kept
+    pub trash: Option<Trash>,
+    /// Answer of links disabled by an admin
+    pub disabled_links: Option<DisabledLinks>
 }
 
 #[derive(Parser)]
@@ -90,7 +329,42 @@ struct Args {
     application_url: Option<String>,
     /// List of host names separated by space to coordinate requests between
     #[arg(long)]
-    hostnames: Option<String>
+    hostnames: Option<String>,
+    /// Address the gossip agent listens on in gossip mode
+    #[arg(long)]
+    gossip_address: Option<String>,
+    /// List of gossip addresses separated by space to join the cluster through
+    #[arg(long)]
+    seeds: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>
+}
+
+/// Task to run instead of the HTTP server.
+#[derive(clap::Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Import links from the export of another URL shortener, keeping their codes
+    Import(ImportArgs),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct ImportArgs {
+    /// Shortener the export comes from
+    #[arg(long, value_enum)]
+    pub source: ImportSource,
+    /// Export file to read
+    pub file: String,
+    /// Tenant to import the links for, the shared links without one
+    #[arg(long)]
+    pub tenant: Option<String>,
+    /// Report what would be imported without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// Task given on the command line, none to run the HTTP server.
+pub fn read_command() -> Option<Command> {
+    Args::parse().command
 }
 
 impl Settings {
@@ -110,21 +384,33 @@ impl Settings {
 
         let args = Args::parse();
         if let Some(value) = args.mode {
-            config_builder = Box::new(config_builder.clone()
-                .set_override("mode", value.to_string())?)
+            *config_builder = config_builder.clone()
+                .set_override("mode", value.to_string())?;
         }
 
         if let Some(value) = args.application_url {
-            config_builder = Box::new(config_builder.clone()
+            *config_builder = config_builder.clone()
                 .set_override("apiserver.application_url", value.clone())?
-                .set_override("apiserver.hostname", value)?);
+                .set_override("apiserver.hostname", value)?;
         }
         
         if let Some(value) = args.hostnames {
             let hostnames = (value as String).split(' ').map(|x| x.to_string()).collect::<Vec<String>>();
 
-            config_builder = Box::new(config_builder.clone()
-                .set_override("coordinator.hostnames", hostnames)?);
+            *config_builder = config_builder.clone()
+                .set_override("coordinator.hostnames", hostnames)?;
+        }
+
+        if let Some(value) = args.gossip_address {
+            *config_builder = config_builder.clone()
+                .set_override("gossip.bind_address", value)?;
+        }
+
+        if let Some(value) = args.seeds {
+            let seeds = value.split(' ').map(|x| x.to_string()).collect::<Vec<String>>();
+
+            *config_builder = config_builder.clone()
+                .set_override("gossip.seeds", seeds)?;
         }
 
         let config = config_builder
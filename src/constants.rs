@@ -1,10 +1,81 @@
 pub const APPLICATION_JSON: &str = "application/json";
+pub const APPLICATION_NDJSON: &str = "application/x-ndjson";
 pub const TEXT_HTML: &str = "text/html";
+pub const TEXT_CSV: &str = "text/csv";
 pub const USER_AGENT_REGEX: &str = "regexes.yaml";
-pub const REMOTE_ADDR: &str = "Remote-Addr";
 
 pub const DEFAULT_CAPACITY: usize = 10;
 pub const DEFAULT_FILL_RATE: usize = 2;
+pub const DEFAULT_RATE_LIMIT_MAX_CLIENTS: usize = 10000;
+pub const API_KEY_HEADER: &str = "X-API-Key";
+pub const DEFAULT_API_KEY_NAME: &str = "default";
+pub const API_KEY_PREFIX: &str = "apikey:";
+pub const API_KEYS_COLLECTION_NAME: &str = "api_keys";
+pub const OWNER_INDEX_PREFIX: &str = "owner:";
+/// Set of the short urls of the links, counted instead of listing every key
+pub const LINK_INDEX_KEY: &str = "index:links";
+pub const TENANT_KEY_PREFIX: &str = "tenant:";
+pub const TENANTS_COLLECTION_NAME: &str = "tenants";
+pub const DOMAIN_PREFIX: &str = "domain:";
+pub const DOMAINS_COLLECTION_NAME: &str = "domains";
+pub const USAGE_KEY_PREFIX: &str = "usage:";
+pub const USAGE_COLLECTION_NAME: &str = "tenant_usage";
+/// Daily counters of the shortens are kept a day longer than the day they count
+pub const USAGE_KEY_TTL: i64 = 2 * 24 * 60 * 60;
+/// List earlier versions appended the events to, moved to the indexed events on startup
+pub const LEGACY_AUDIT_LOG_KEY: &str = "audit:events";
+pub const AUDIT_EVENTS_KEY: &str = "audit:event";
+pub const AUDIT_INDEX_PREFIX: &str = "audit:index:";
+pub const AUDIT_QUERY_PAGE_SIZE: usize = 500;
+pub const DEFAULT_AUDIT_LIMIT: usize = 100;
+pub const MAX_AUDIT_LIMIT: usize = 1000;
+pub const AUDIT_KEY_PREFIX: &str = "audit:";
+pub const AUDIT_COLLECTION_NAME: &str = "audit_events";
+pub const DEFAULT_DOMAIN_REFRESH_INTERVAL: u64 = 30;
+pub const DEFAULT_DESTINATION_RULES_RELOAD_INTERVAL: u64 = 30;
+pub const DEFAULT_LINK_SCHEME: &str = "https";
+pub const MAX_BULK_LINKS: usize = 10000;
+/// Body limit of a bulk request, room for `MAX_BULK_LINKS` links of 4 KiB
+pub const MAX_BULK_BODY_SIZE: usize = MAX_BULK_LINKS * 4 * 1024;
+pub const MAX_ALIAS_LENGTH: usize = 64;
+pub const EXPORT_PAGE_SIZE: usize = 500;
+/// Links read at once while the NDJSON list of the links is sent
+pub const NDJSON_PAGE_SIZE: usize = 500;
+pub const IMPORT_BATCH_SIZE: usize = 1000;
+pub const DEFAULT_ALLOWED_SCHEMES: [&str; 2] = ["http", "https"];
+pub const DEFAULT_API_KEY_REFRESH_INTERVAL: u64 = 30;
+pub const DEFAULT_JWKS_REFRESH_INTERVAL: u64 = 300;
+pub const RATE_LIMIT_KEY_PREFIX: &str = "ratelimit:";
+pub const RATE_LIMIT_POLICY_SHORTEN: &str = "shorten";
+pub const RATE_LIMIT_POLICY_REDIRECT: &str = "redirect";
+pub const RATE_LIMIT_POLICY_SUMMARY: &str = "summary";
+pub const RATE_LIMIT_POLICY_ADMIN: &str = "admin";
+
+pub const DEFAULT_MAX_HINTS_PER_NODE: usize = 10000;
+pub const DEFAULT_HINT_REPLAY_INTERVAL: u64 = 10;
+pub const DEFAULT_ANTI_ENTROPY_INTERVAL: u64 = 300;
+pub const DEFAULT_ANTI_ENTROPY_RANGES: usize = 16;
+pub const NODE_REQUEST_TIMEOUT: u64 = 5;
+pub const SAFETY_CHECK_TIMEOUT: u64 = 5;
+pub const DEFAULT_SAFETY_CACHE_TTL: u64 = 3600;
+pub const DEFAULT_SAFETY_CACHE_SIZE: usize = 10000;
+pub const DEFAULT_TRASH_RETENTION_DAYS: u64 = 30;
+pub const DEFAULT_TRASH_PURGE_INTERVAL: u64 = 3600;
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: u64 = 5;
+/// Seconds before a handoff to a joining node that missed links runs again
+pub const HANDOFF_RETRY_INTERVAL: u64 = 10;
+pub const DEFAULT_UNHEALTHY_THRESHOLD: u32 = 3;
+pub const HASH_RING_REPLICAS: isize = 10;
+pub const REPLICA_HEADER: &str = "X-Replica";
+
+pub const DEFAULT_GOSSIP_PROTOCOL_PERIOD: u64 = 1000;
+pub const DEFAULT_GOSSIP_SUSPECT_TIMEOUT: u64 = 5000;
+pub const DEFAULT_GOSSIP_INDIRECT_CHECKS: usize = 3;
+pub const GOSSIP_MAX_MESSAGE_SIZE: usize = 65507;
+/// Milliseconds a gossip message is accepted for, allowing for clock skew between instances
+pub const GOSSIP_MAX_MESSAGE_AGE: u64 = 30000;
+pub const GOSSIP_MAX_PIGGYBACK: usize = 8;
+pub const GOSSIP_RETRANSMIT_MULTIPLIER: u32 = 3;
 
 pub fn get_ip_url(ip: String) -> String {
     return format!("http://ip-api.com/json/{}", ip);
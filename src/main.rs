@@ -1,4 +1,4 @@
-use rust_short_url::{api, configuration::{self, settings::read_settings}, services};
+use rust_short_url::{api, configuration::{self, settings::{read_command, read_settings, Command}}, services};
 
 use std::{env,io};
 use configuration::settings::Settings;
@@ -26,5 +26,8 @@ async fn main() -> io::Result<()> {
         Ok(s) => s,
     };
     
-    api::httpserver::start_http_server(settings, hash_service).await
+    match read_command() {
+        Some(Command::Import(args)) => api::importcommand::run_import(settings, hash_service, args).await,
+        None => api::httpserver::start_http_server(settings, hash_service).await
+    }
 }
\ No newline at end of file
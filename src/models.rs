@@ -1,4 +1,8 @@
 pub mod linkinfo;
 pub mod queryparams;
 pub mod analytic;
-pub mod builders;
\ No newline at end of file
+pub mod builders;
+pub mod apikey;
+pub mod tenantusage;
+pub mod domain;
+pub mod auditevent;
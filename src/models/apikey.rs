@@ -0,0 +1,134 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::configuration::settings::RateLimit;
+
+/// What an API key is allowed to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "links:read")]
+    LinksRead,
+    #[serde(rename = "links:write")]
+    LinksWrite,
+    #[serde(rename = "links:delete")]
+    LinksDelete,
+    #[serde(rename = "stats:read")]
+    StatsRead,
+    /// Writes replicated by other cluster members, applied as they are. Only granted
+    /// explicitly, `admin` doesn't include it.
+    #[serde(rename = "cluster")]
+    Cluster,
+    /// Every scope, including the cluster management
+    #[serde(rename = "admin")]
+    Admin
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scope = match self {
+            Scope::LinksRead => "links:read",
+            Scope::LinksWrite => "links:write",
+            Scope::LinksDelete => "links:delete",
+            Scope::StatsRead => "stats:read",
+            Scope::Cluster => "cluster",
+            Scope::Admin => "admin"
+        };
+        write!(f, "{}", scope)
+    }
+}
+
+/// API key as it is stored, only the salted hash of its secret is kept.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    /// "sha256$<salt>$<digest>", see `apikeys::hash_secret`
+    pub hash: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Limit of this key on its own, on top of the route's rate limit
+    pub ratelimit: Option<RateLimit>,
+    /// Only set on keys issued through the admin endpoints
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// Tenant whose links the key works on, the shared links when it has none
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+impl ApiKey {
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        has_scope(&self.scopes, scope)
+    }
+}
+
+/// Key to issue through the admin endpoints, its secret is generated.
+#[derive(Clone, Debug, Deserialize)]
+pub struct NewApiKey {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub ratelimit: Option<RateLimit>,
+    pub tenant: Option<String>,
+}
+
+/// API key as it is listed, without its hash.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ApiKeyInfo {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub rate_limited: bool,
+    pub tenant: Option<String>,
+    /// Configured in the settings, it can't be revoked or rotated through the API
+    pub configured: bool,
+}
+
+impl ApiKeyInfo {
+    pub fn new(key: &ApiKey, configured: bool) -> Self {
+        ApiKeyInfo {
+            name: key.name.clone(),
+            scopes: key.scopes.clone(),
+            expires_at: key.expires_at,
+            created_at: key.created_at,
+            rate_limited: key.ratelimit.is_some(),
+            tenant: key.tenant.clone(),
+            configured
+        }
+    }
+}
+
+/// Newly issued key with its secret, which is only ever shown once.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IssuedApiKey {
+    #[serde(flatten)]
+    pub key: ApiKeyInfo,
+    pub secret: String,
+}
+
+/// Authenticated caller of a request, added to the request extensions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Principal {
+    /// Identity of the caller, namespaced by how it authenticated: "key:<name>" for API keys
+    /// and "jwt:<issuer>/<subject>" for bearer tokens, so a token can't pass for a key of the same name
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub tenant: Option<String>,
+}
+
+impl Principal {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        has_scope(&self.scopes, scope)
+    }
+}
+
+fn has_scope(scopes: &[Scope], scope: Scope) -> bool {
+    scopes.iter().any(|granted| *granted == scope || (*granted == Scope::Admin && scope != Scope::Cluster))
+}
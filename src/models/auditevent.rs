@@ -0,0 +1,138 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+/// Fields that change without anyone changing the link, or with every change, they aren't audited.
+const UNAUDITED_FIELDS: [&str; 3] = ["analytics", "version", "updated_at"];
+
+/// Immutable record of a change made through the admin endpoints or by shortening.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub action: AuditAction,
+    /// Principal that made the change, none when authentication is disabled or for anonymous shortens
+    #[serde(default)]
+    pub actor: Option<String>,
+    /// Tenant whose links or keys were changed, the shared ones have none
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Short url of the changed link
+    #[serde(default)]
+    pub link: Option<String>,
+    /// Name of the changed API key
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub client_ip: Option<String>,
+    #[serde(default)]
+    pub changes: Vec<FieldChange>,
+}
+
+impl AuditEvent {
+    pub fn new(action: AuditAction, timestamp: DateTime<Utc>) -> Self {
+        AuditEvent {
+            // Ordered by time, the random part keeps events of the same microsecond apart
+            id: format!("{:016x}-{:08x}", timestamp.timestamp_micros(), rand::random::<u32>()),
+            timestamp,
+            action,
+            actor: None,
+            tenant: None,
+            link: None,
+            key: None,
+            client_ip: None,
+            changes: Vec::new()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    Restore,
+    /// Links to a blocked domain were disabled
+    DisableLinks,
+    KeyCreate,
+    KeyRotate,
+    KeyRevoke,
+}
+
+/// Value of a field before and after a change, missing on the side the field didn't exist.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    #[serde(default)]
+    pub before: Option<Value>,
+    #[serde(default)]
+    pub after: Option<Value>,
+}
+
+/// Changed top level fields of two versions of a record, by field name.
+pub fn diff<T: Serialize>(before: Option<&T>, after: Option<&T>) -> Vec<FieldChange> {
+    let fields = |value: Option<&T>| match value.map(serde_json::to_value) {
+        Some(Ok(Value::Object(fields))) => fields,
+        _ => serde_json::Map::new()
+    };
+    let before = fields(before);
+    let after = fields(after);
+
+    let mut names: Vec<&String> = before.keys().chain(after.keys())
+        .filter(|name| !UNAUDITED_FIELDS.contains(&name.as_str()))
+        .collect();
+    names.sort();
+    names.dedup();
+
+    names.into_iter()
+        .filter(|name| before.get(*name) != after.get(*name))
+        .map(|name| FieldChange {
+            field: name.clone(),
+            before: before.get(name).cloned(),
+            after: after.get(name).cloned()
+        })
+        .collect()
+}
+
+/// Filter of `GET /admin/audit`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub link: Option<String>,
+    pub actor: Option<String>,
+    /// Only events at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only events after the event with this id, the last one of the previous page
+    pub after: Option<String>,
+    /// Most events returned, all of them when unset
+    pub limit: Option<usize>,
+    /// Only events of this tenant, set from the caller rather than the query string
+    #[serde(skip)]
+    pub tenant: Option<String>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        (self.link.is_none() || event.link == self.link)
+            && (self.actor.is_none() || event.actor == self.actor)
+            && (self.tenant.is_none() || event.tenant == self.tenant)
+            && !matches!(self.since, Some(since) if event.timestamp < since)
+            && !matches!(&self.after, Some(after) if event.id <= *after)
+    }
+
+    /// Smallest id of the events at or after `since`. Ids start with the time of the
+    /// event, so backends filter the time on the ids.
+    pub fn since_id(&self) -> Option<String> {
+        self.since.map(|since| format!("{:016x}", since.timestamp_micros()))
+    }
+
+    /// Earliest time in microseconds an event can have, from `since` and `after`.
+    pub fn min_micros(&self) -> Option<i64> {
+        let after = self.after.as_deref()
+            .and_then(|after| after.get(..16))
+            .and_then(|micros| i64::from_str_radix(micros, 16).ok());
+        let since = self.since.map(|since| since.timestamp_micros());
+
+        after.max(since)
+    }
+}
@@ -1,10 +1,24 @@
-use super::linkinfo::LinkInfo;
+use chrono::Utc;
+
+use super::linkinfo::{LinkInfo, LinkStatus};
 
 pub(crate) fn build_link_info(short_url: String, long_url: String) -> LinkInfo {
     let link_info = LinkInfo{
         short_url,
         long_url,
-        analytics: Some(Vec::new())
+        analytics: Some(Vec::new()),
+        owner_id: None,
+        domain: None,
+        created_at: Some(Utc::now()),
+        status: LinkStatus::Active,
+        status_reason: None,
+        deleted_at: None,
+        previous_status: None,
+        tags: None,
+        expires_at: None,
+        imported_clicks: None,
+        version: 0,
+        updated_at: None
     };
 
     link_info
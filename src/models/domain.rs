@@ -0,0 +1,21 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+/// Custom domain short links are served on, e.g. "go.brand-a.com".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Domain {
+    /// Lowercase host name without a port
+    pub name: String,
+    /// Tenant whose links the domain serves, the shared links when it has none
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Domain to add through the admin endpoints.
+#[derive(Clone, Debug, Deserialize)]
+pub struct NewDomain {
+    pub name: String,
+    pub tenant: Option<String>,
+}
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 use redis_macros::{FromRedisValue, ToRedisArgs};
 
@@ -8,4 +9,75 @@ pub struct LinkInfo {
     pub short_url: String,
     pub long_url: String,
     pub analytics: Option<Vec<Analytic>>,
+    /// Principal that created the link, links created anonymously have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_id: Option<String>,
+    /// Custom domain the link is served on, links of the service's own hostname have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    /// When the link was shortened, links stored before it was recorded have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub status: LinkStatus,
+    /// Why the link is not active, e.g. the blocked domain it points to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_reason: Option<String>,
+    /// When the link was moved to the trash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Status the link had before it was deleted, it gets it back when it's restored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_status: Option<LinkStatus>,
+    /// Labels to group links by, e.g. the campaign they were created for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// When the link stops redirecting, links without one never expire
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Clicks counted by the shortener the link was imported from, before it was moved here
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imported_clicks: Option<u64>,
+    /// Counts the changes of the link, replicas that disagree keep the higher version
+    #[serde(default)]
+    pub version: u64,
+    /// When the link was last changed, links stored before it was recorded have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl LinkInfo {
+    /// Records a change of the link, every write that isn't a replica of another one changes the version.
+    pub fn touch(&mut self, now: DateTime<Utc>) {
+        self.version += 1;
+        self.updated_at = Some(now);
+    }
+
+    /// Code of the link in its short link, the short url without its domain.
+    pub fn code(&self) -> &str {
+        self.short_url.split_once('@').map_or(&self.short_url, |(code, _)| code)
+    }
+}
+
+/// Short url a link is stored under: its code on the service's own hostname, and
+/// "<code>@<domain>" on a custom domain, so every domain has codes of its own.
+pub fn link_key(domain: Option<&str>, code: &str) -> String {
+    match domain {
+        Some(domain) => format!("{}@{}", code, domain),
+        None => code.to_string()
+    }
+}
+
+/// Whether a link is redirected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStatus {
+    #[default]
+    Active,
+    /// Kept with its analytics, but no longer redirected
+    Disabled,
+    /// Flagged by a safety check, shows a warning instead of redirecting
+    Quarantined,
+    /// In the trash until it's restored or purged
+    Deleted,
 }
\ No newline at end of file
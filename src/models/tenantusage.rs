@@ -0,0 +1,22 @@
+use serde::{Serialize, Deserialize};
+
+/// Usage of a tenant against its quotas.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TenantUsage {
+    pub tenant: String,
+    pub links: usize,
+    pub max_links: Option<usize>,
+    /// Shortens of the current UTC day on every instance
+    pub shortens_today: usize,
+    pub max_shortens_per_day: Option<usize>,
+    pub api_keys: usize,
+}
+
+/// Shortens of a tenant on a UTC day, as kept by the usage store.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DailyShortens {
+    pub tenant: String,
+    /// Day as `YYYY-MM-DD`
+    pub day: String,
+    pub shortens: i64,
+}
@@ -4,6 +4,42 @@ mod inmemoryhashservice;
 mod mongohashservice;
 mod redishashservice;
 mod coordinatorhashservice;
-mod hashserviceerror;
+pub mod hashserviceerror;
 mod firestorehashservice;
-pub mod hashfunction;
\ No newline at end of file
+pub mod hashfunction;
+pub mod hintstore;
+pub mod antientropy;
+pub mod cluster;
+mod nodeclient;
+pub mod linkmerge;
+pub mod gossip;
+mod gossiphashservice;
+pub mod keystore;
+mod inmemorykeystore;
+mod mongokeystore;
+mod rediskeystore;
+mod firestorekeystore;
+pub mod tenants;
+pub mod domainstore;
+mod inmemorydomainstore;
+mod mongodomainstore;
+mod redisdomainstore;
+mod firestoredomainstore;
+pub mod usagestore;
+mod inmemoryusagestore;
+mod mongousagestore;
+mod redisusagestore;
+mod firestoreusagestore;
+pub mod urlnormalizer;
+pub mod destinationrules;
+pub mod urlsafetychecker;
+pub mod hashprefixsafetychecker;
+mod httpsafetychecker;
+pub mod auditlog;
+mod inmemoryauditlog;
+mod mongoauditlog;
+mod redisauditlog;
+mod firestoreauditlog;
+pub mod bulkimport;
+pub mod linkexport;
+pub mod linkimport;
@@ -0,0 +1,146 @@
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+use crate::models::linkinfo::LinkInfo;
+
+/// Serialized form of a [`MerkleTree`] exchanged between the coordinator and its nodes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MerkleDigest {
+    pub ranges: usize,
+    pub leaves: Vec<String>,
+}
+
+/// Merkle tree over the key space split into a fixed number of ranges.
+///
+/// Every leaf is the digest of all links whose short url falls into that range,
+/// so two replicas only have to exchange the leaves to find out which ranges diverged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleTree {
+    ranges: usize,
+    // Binary heap layout: root at 0, children of i at 2i+1 and 2i+2, leaves at the end.
+    nodes: Vec<String>,
+}
+
+impl MerkleTree {
+    pub fn build<'a, I>(links: I, ranges: usize) -> Self
+    where
+        I: IntoIterator<Item = &'a LinkInfo>,
+    {
+        let ranges = normalize_ranges(ranges);
+
+        let mut buckets: Vec<Vec<&LinkInfo>> = vec![Vec::new(); ranges];
+        for link in links {
+            buckets[range_of(&link.short_url, ranges)].push(link);
+        }
+
+        let leaves = buckets.into_iter().map(|mut bucket| {
+            bucket.sort_by(|a, b| a.short_url.cmp(&b.short_url));
+
+            let mut sha256 = Sha256::new();
+            for link in bucket {
+                sha256.update(link.short_url.as_bytes());
+                sha256.update(serde_json::to_vec(link).unwrap_or_default());
+            }
+            format!("{:x}", sha256.finalize())
+        }).collect();
+
+        Self::from_leaves(leaves)
+    }
+
+    pub fn from_digest(digest: &MerkleDigest) -> Option<Self> {
+        if digest.leaves.is_empty() || digest.leaves.len() != digest.ranges || !digest.ranges.is_power_of_two() {
+            return None;
+        }
+
+        Some(Self::from_leaves(digest.leaves.clone()))
+    }
+
+    fn from_leaves(leaves: Vec<String>) -> Self {
+        let ranges = leaves.len();
+        let mut nodes = vec![String::new(); ranges - 1];
+        nodes.extend(leaves);
+
+        for i in (0..ranges - 1).rev() {
+            let mut sha256 = Sha256::new();
+            sha256.update(nodes[2 * i + 1].as_bytes());
+            sha256.update(nodes[2 * i + 2].as_bytes());
+            nodes[i] = format!("{:x}", sha256.finalize());
+        }
+
+        MerkleTree { ranges, nodes }
+    }
+
+    pub fn ranges(&self) -> usize {
+        self.ranges
+    }
+
+    pub fn root(&self) -> &str {
+        &self.nodes[0]
+    }
+
+    pub fn digest(&self) -> MerkleDigest {
+        MerkleDigest {
+            ranges: self.ranges,
+            leaves: self.nodes[self.ranges - 1..].to_vec(),
+        }
+    }
+
+    /// Returns the ranges whose content differs between the two trees, descending
+    /// only into subtrees whose hashes don't match.
+    pub fn diff(&self, other: &MerkleTree) -> Vec<usize> {
+        let mut diverged = Vec::new();
+        if self.ranges != other.ranges {
+            diverged.extend(0..usize::max(self.ranges, other.ranges));
+            return diverged;
+        }
+
+        let mut pending = vec![0];
+        while let Some(i) = pending.pop() {
+            if self.nodes[i] == other.nodes[i] {
+                continue;
+            }
+
+            if i >= self.ranges - 1 {
+                diverged.push(i - (self.ranges - 1));
+            } else {
+                pending.push(2 * i + 2);
+                pending.push(2 * i + 1);
+            }
+        }
+
+        diverged
+    }
+}
+
+pub fn normalize_ranges(ranges: usize) -> usize {
+    usize::max(ranges, 1).next_power_of_two()
+}
+
+pub fn range_of(short_url: &str, ranges: usize) -> usize {
+    let hash = Sha256::digest(short_url.as_bytes());
+    let value = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]) as usize;
+
+    value % normalize_ranges(ranges)
+}
+
+/// Picks the version of a link that wins when replicas disagree: the one with more changes,
+/// then the one changed last. Links stored before versions were recorded fall back to their
+/// analytics, which are only ever appended, so the replica that has seen more visits is newer.
+pub fn is_newer(candidate: &LinkInfo, current: &LinkInfo) -> bool {
+    if candidate.version != current.version {
+        return candidate.version > current.version;
+    }
+    if candidate.updated_at != current.updated_at {
+        return candidate.updated_at > current.updated_at;
+    }
+
+    let candidate_visits = candidate.analytics.as_ref().map_or(0, |a| a.len());
+    let current_visits = current.analytics.as_ref().map_or(0, |a| a.len());
+
+    if candidate_visits != current_visits {
+        return candidate_visits > current_visits;
+    }
+
+    // Same number of visits: fall back to a deterministic order so every run agrees.
+    candidate.long_url > current.long_url
+}
@@ -0,0 +1,38 @@
+use async_trait::async_trait;
+
+use crate::{configuration::settings::{Mode, Settings}, models::auditevent::{AuditEvent, AuditQuery}};
+
+use super::firestoreauditlog::FirestoreAuditLog;
+use super::hashserviceerror::HashServiceError;
+pub use super::inmemoryauditlog::InMemoryAuditLog;
+use super::mongoauditlog::MongoAuditLog;
+use super::redisauditlog::RedisAuditLog;
+
+/// Where audit events are kept. Events are only ever appended, never changed or removed.
+#[async_trait]
+pub trait AuditLog: Send + Sync {
+    async fn init(&mut self) -> Result<(), HashServiceError>;
+
+    async fn record(&mut self, event: &AuditEvent) -> Result<(), HashServiceError>;
+
+    /// Events matching the query, oldest first.
+    async fn query(&mut self, query: &AuditQuery) -> Result<Vec<AuditEvent>, HashServiceError>;
+}
+
+/// Creates the audit log of the backend the links are kept in, see `keystore::create_key_store`.
+pub async fn create_audit_log(settings: &Settings) -> Result<Box<dyn AuditLog>, HashServiceError> {
+    let mode = match &settings.mode {
+        Mode::Gossip => settings.gossip.as_ref().and_then(|gossip| gossip.storage.clone()).unwrap_or_default(),
+        mode => mode.clone()
+    };
+
+    let mut audit_log: Box<dyn AuditLog> = match (mode, &settings.mongo_config, &settings.redis_config, &settings.firestore_config) {
+        (Mode::Mongo, Some(mongo_config), _, _) => Box::new(MongoAuditLog::new(mongo_config)),
+        (Mode::Redis, _, Some(redis_config), _) => Box::new(RedisAuditLog::new(redis_config)),
+        (Mode::Firestore, _, _, Some(firestore_config)) => Box::new(FirestoreAuditLog::new(firestore_config)),
+        _ => Box::new(InMemoryAuditLog::new())
+    };
+
+    audit_log.init().await?;
+    Ok(audit_log)
+}
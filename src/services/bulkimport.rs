@@ -0,0 +1,181 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::constants::{APPLICATION_JSON, APPLICATION_NDJSON, MAX_ALIAS_LENGTH, TEXT_CSV};
+
+use super::{destinationrules, hashfunction};
+
+/// First path segments of the service's own routes, links can't be named after them.
+const RESERVED_ALIASES: [&str; 4] = ["admin", "me", "shorten", "t"];
+
+/// Link to create in a bulk import.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct BulkLink {
+    pub long_url: String,
+    /// Short url to use instead of the hash of the url
+    #[serde(default)]
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Format of a bulk import, by the content type of the request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BulkFormat {
+    /// An array of links
+    Json,
+    /// A header row naming the columns `long_url`, `alias`, `tags` and `expires_at`, then one link per row
+    Csv,
+    /// One link per line
+    Ndjson,
+}
+
+impl BulkFormat {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        match mime.as_str() {
+            APPLICATION_JSON => Some(BulkFormat::Json),
+            TEXT_CSV => Some(BulkFormat::Csv),
+            APPLICATION_NDJSON => Some(BulkFormat::Ndjson),
+            _ => None
+        }
+    }
+}
+
+/// Links of a bulk import. A body that can't be read at all is an error, a row that can't
+/// be read is an error of its own, so the other rows are still imported.
+pub fn parse(format: BulkFormat, body: &str) -> Result<Vec<Result<BulkLink, String>>, String> {
+    match format {
+        BulkFormat::Json => {
+            let values: Vec<serde_json::Value> = serde_json::from_str(body)
+                .map_err(|err| format!("the body is not a JSON array: {}", err))?;
+            Ok(values.into_iter()
+                .map(|value| serde_json::from_value(value).map_err(|err| err.to_string()))
+                .collect())
+        },
+        BulkFormat::Ndjson => Ok(body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|err| err.to_string()))
+            .collect()),
+        BulkFormat::Csv => parse_csv_links(body)
+    }
+}
+
+fn parse_csv_links(body: &str) -> Result<Vec<Result<BulkLink, String>>, String> {
+    let mut records = parse_csv(body)?.into_iter();
+    let header: Vec<String> = match records.next() {
+        Some(header) => header.iter().map(|name| name.trim().to_ascii_lowercase()).collect(),
+        None => return Ok(Vec::new())
+    };
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let long_url_column = column("long_url").ok_or("the header has no 'long_url' column")?;
+    let (alias_column, tags_column, expires_at_column) = (column("alias"), column("tags"), column("expires_at"));
+
+    Ok(records.map(|record| {
+        let cell = |index: Option<usize>| index
+            .and_then(|index| record.get(index))
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty());
+
+        let long_url = cell(Some(long_url_column)).ok_or("the row has no long_url")?;
+        let expires_at = match cell(expires_at_column) {
+            Some(expires_at) => Some(DateTime::parse_from_rfc3339(expires_at)
+                .map_err(|err| format!("expires_at '{}' is not an RFC 3339 time: {}", expires_at, err))?
+                .with_timezone(&Utc)),
+            None => None
+        };
+
+        Ok(BulkLink {
+            long_url: long_url.to_string(),
+            alias: cell(alias_column).map(str::to_string),
+            // Tags are comma separated within their quoted cell
+            tags: cell(tags_column).map(|tags| tags.split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect()),
+            expires_at
+        })
+    }).collect())
+}
+
+/// Records of a CSV text as described by RFC 4180: comma separated fields, which may be
+/// quoted to hold commas, line breaks and doubled quotes. Empty lines are skipped.
+pub fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => (),
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                let done = std::mem::take(&mut record);
+                if done.len() > 1 || !done[0].is_empty() {
+                    records.push(done);
+                }
+            },
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(String::from("a quoted field is not closed"));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// Aliases are used as short urls: letters, digits, '-' and '_'.
+pub fn validate_alias(alias: &str) -> Result<(), String> {
+    if alias.is_empty() || alias.len() > MAX_ALIAS_LENGTH {
+        return Err(format!("alias '{}' must have 1 to {} characters", alias, MAX_ALIAS_LENGTH));
+    }
+    if !alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("alias '{}' may only contain letters, digits, '-' and '_'", alias));
+    }
+    if RESERVED_ALIASES.contains(&alias.to_ascii_lowercase().as_str()) {
+        return Err(format!("alias '{}' is reserved", alias));
+    }
+    if hashfunction::is_hash_code(alias) {
+        return Err(format!("alias '{}' is reserved for generated short urls, which are 1 to 8 uppercase hex digits", alias));
+    }
+
+    Ok(())
+}
+
+/// Checks the short url of a link written by path: a generated code or an alias, followed by
+/// "@<domain>" on a custom domain. Stores keep links next to API keys, tenants and counters,
+/// so a short url must never name one of their keys.
+pub fn validate_short_url(short_url: &str) -> Result<(), String> {
+    let code = match short_url.split_once('@') {
+        Some((code, domain)) => {
+            if destinationrules::normalize_domain(domain).ok().as_deref() != Some(domain) {
+                return Err(format!("'{}' is not a lowercase domain", domain));
+            }
+            code
+        },
+        None => short_url
+    };
+    if hashfunction::is_hash_code(code) {
+        return Ok(());
+    }
+
+    validate_alias(code)
+}
@@ -0,0 +1,566 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{configuration::settings::{Coordinator, Mode}, constants, models::linkinfo::LinkInfo};
+
+use hash_ring::HashRing;
+use reqwest::Url;
+use serde::Serialize;
+use tokio::sync::{Mutex, RwLock};
+
+use super::antientropy::{self, MerkleTree};
+use super::hashserviceerror::{build_invalid_configuration_error, HashServiceError};
+use super::hintstore::{Hint, HintStore};
+use super::nodeclient::NodeClient;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Node {
+    pub scheme: String,
+    /// Host name or IP address, IPv6 addresses without brackets
+    pub host: String,
+    pub port: u16,
+    /// Path prefix the node is served under, empty or starting with '/'
+    pub base_path: String,
+    /// Share of the hash ring relative to the other nodes
+    pub weight: u32
+}
+
+impl Node {
+    /// Parses a node address such as `localhost:8080`, `[::1]:8080`,
+    /// `https://node.example.com/shortener` or `http://10.0.0.1:8080?weight=2`.
+    /// The scheme defaults to http and the port to the scheme's default port.
+    pub fn parse(hostname: &str) -> Result<Node, HashServiceError> {
+        let hostname = hostname.trim();
+        let invalid = |reason: &str| build_invalid_configuration_error(
+            &Mode::Coordinator.to_string(), "hostnames", &format!("'{}' {}", hostname, reason));
+
+        if hostname.is_empty() {
+            return Err(invalid("is empty"));
+        }
+
+        let address = if hostname.contains("://") { hostname.to_string() } else { format!("http://{}", hostname) };
+        let url = Url::parse(&address).map_err(|err| invalid(&format!("is not a valid url: {}", err)))?;
+
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(invalid("has to use the http or https scheme"));
+        }
+        if !url.username().is_empty() || url.password().is_some() {
+            return Err(invalid("must not contain credentials"));
+        }
+        if url.fragment().is_some() {
+            return Err(invalid("must not contain a fragment"));
+        }
+
+        let host = match url.host_str() {
+            Some(host) if !host.is_empty() => host.trim_start_matches('[').trim_end_matches(']').to_string(),
+            _ => return Err(invalid("has no host"))
+        };
+        let port = url.port_or_known_default().ok_or_else(|| invalid("has no port"))?;
+
+        let mut weight = 1;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "weight" => {
+                    weight = match value.parse::<u32>() {
+                        Ok(weight) if weight > 0 => weight,
+                        _ => return Err(invalid("has to have a positive integer weight"))
+                    };
+                }
+                _ => return Err(invalid(&format!("has unknown parameter '{}'", key)))
+            }
+        }
+
+        Ok(Node {
+            scheme: url.scheme().to_string(),
+            host,
+            port,
+            base_path: url.path().trim_end_matches('/').to_string(),
+            weight
+        })
+    }
+
+    fn host_for_url(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        }
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("{}://{}:{}{}", self.scheme, self.host_for_url(), self.port, self.base_path)
+    }
+}
+
+/// Identifies the node in the hint store, logs and the admin endpoints.
+impl fmt::Display for Node {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}:{}{}", self.host_for_url(), self.port, self.base_path)
+    }
+}
+
+/// One of the `weight` entries a node has on the hash ring.
+#[derive(Clone, Debug)]
+struct VirtualNode {
+    node: Node,
+    index: u32
+}
+
+impl fmt::Display for VirtualNode {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}#{}", self.node, self.index)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum NodeState {
+    /// Receives writes while the existing data is handed off to it, not used for reads yet
+    Joining,
+    Up,
+    /// Failed the last health probes, writes are kept as hints
+    Down,
+    /// Leaving the cluster, its data is handed off to the remaining nodes
+    Draining
+}
+
+#[derive(Clone, Debug)]
+struct Member {
+    node: Node,
+    state: NodeState,
+    consecutive_failures: u32,
+    last_seen: Option<chrono::DateTime<chrono::Utc>>
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MemberStatus {
+    pub id: String,
+    pub node: Node,
+    pub state: NodeState,
+    pub consecutive_failures: u32,
+    pub last_seen: Option<chrono::DateTime<chrono::Utc>>,
+    pub pending_hints: usize
+}
+
+/// Runtime view of the nodes the coordinator replicates to.
+///
+/// Membership can change while the coordinator is running: nodes are probed
+/// periodically and marked up or down, and can be added, drained and removed
+/// through the admin endpoints. The hash ring only contains nodes that are up.
+pub struct Cluster {
+    node_client: NodeClient,
+    unhealthy_threshold: u32,
+    anti_entropy_ranges: usize,
+    members: RwLock<Vec<Member>>,
+    ring: RwLock<HashRing<VirtualNode>>,
+    hints: Mutex<HintStore>
+}
+
+impl Cluster {
+    pub fn new(config: &Coordinator) -> Self {
+        Cluster {
+            node_client: NodeClient::new(config.api_key.clone()),
+            unhealthy_threshold: u32::max(config.unhealthy_threshold.unwrap_or(constants::DEFAULT_UNHEALTHY_THRESHOLD), 1),
+            anti_entropy_ranges: config.anti_entropy_ranges.unwrap_or(constants::DEFAULT_ANTI_ENTROPY_RANGES),
+            members: RwLock::new(Vec::new()),
+            ring: RwLock::new(HashRing::new(Vec::new(), constants::HASH_RING_REPLICAS)),
+            hints: Mutex::new(HintStore::new(config.max_hints_per_node.unwrap_or(constants::DEFAULT_MAX_HINTS_PER_NODE)))
+        }
+    }
+
+    pub(crate) fn node_client(&self) -> &NodeClient {
+        &self.node_client
+    }
+
+    /// Registers a node known at startup. Unreachable nodes are added as down
+    /// instead of failing, they are picked up by the health probes later.
+    pub async fn join(&self, node: Node) {
+        let state = match self.node_client.test_connection(&node).await {
+            Ok(()) => NodeState::Up,
+            Err(err) => {
+                log::warn!("Node {} is not reachable, marking it down: {}", node, err);
+                NodeState::Down
+            }
+        };
+
+        self.members.write().await.push(Member {
+            last_seen: if state == NodeState::Up { Some(chrono::Utc::now()) } else { None },
+            node,
+            state,
+            consecutive_failures: 0
+        });
+        self.rebuild_ring().await;
+    }
+
+    /// Sets the state of a node reported by an external failure detector, adding it
+    /// if it's unknown. Returns whether the node was added.
+    pub async fn set_member(&self, node: Node, state: NodeState) -> bool {
+        let added = {
+            let mut members = self.members.write().await;
+            match members.iter_mut().find(|member| member.node.to_string() == node.to_string()) {
+                Some(member) => {
+                    member.node = node;
+                    member.state = state;
+                    if state == NodeState::Up {
+                        member.last_seen = Some(chrono::Utc::now());
+                    }
+                    false
+                }
+                None => {
+                    members.push(Member {
+                        last_seen: if state == NodeState::Up { Some(chrono::Utc::now()) } else { None },
+                        node,
+                        state,
+                        consecutive_failures: 0
+                    });
+                    true
+                }
+            }
+        };
+
+        self.rebuild_ring().await;
+        added
+    }
+
+    pub async fn members(&self) -> Vec<MemberStatus> {
+        let hints = self.hints.lock().await;
+        self.members.read().await.iter().map(|member| MemberStatus {
+            id: member.node.to_string(),
+            node: member.node.clone(),
+            state: member.state,
+            consecutive_failures: member.consecutive_failures,
+            last_seen: member.last_seen,
+            pending_hints: hints.len(&member.node.to_string())
+        }).collect()
+    }
+
+    /// Nodes that can serve reads for the key, the owner on the hash ring first.
+    pub async fn read_nodes(&self, key: &str) -> Vec<Node> {
+        let mut nodes = self.nodes_in(&[NodeState::Up]).await;
+        if let Some(primary) = self.ring.read().await.get_node(key.to_string()) {
+            if let Some(index) = nodes.iter().position(|node| *node == primary.node) {
+                let primary = nodes.remove(index);
+                nodes.insert(0, primary);
+            }
+        }
+
+        nodes
+    }
+
+    /// Nodes that a write should be sent to, and nodes that are down and only get a hint.
+    pub async fn write_nodes(&self) -> (Vec<Node>, Vec<Node>) {
+        let members = self.members.read().await;
+        let reachable = members.iter()
+            .filter(|member| matches!(member.state, NodeState::Up | NodeState::Joining))
+            .map(|member| member.node.clone())
+            .collect();
+        let down = members.iter()
+            .filter(|member| member.state == NodeState::Down)
+            .map(|member| member.node.clone())
+            .collect();
+
+        (reachable, down)
+    }
+
+    async fn nodes_in(&self, states: &[NodeState]) -> Vec<Node> {
+        self.members.read().await.iter()
+            .filter(|member| states.contains(&member.state))
+            .map(|member| member.node.clone())
+            .collect()
+    }
+
+    async fn rebuild_ring(&self) {
+        let virtual_nodes = self.nodes_in(&[NodeState::Up]).await.into_iter()
+            .flat_map(|node| (0..node.weight).map(move |index| VirtualNode { node: node.clone(), index }))
+            .collect();
+        *self.ring.write().await = HashRing::new(virtual_nodes, constants::HASH_RING_REPLICAS);
+    }
+
+    async fn set_state(&self, id: &str, state: NodeState) {
+        if let Some(member) = self.members.write().await.iter_mut().find(|member| member.node.to_string() == id) {
+            member.state = state;
+        }
+        self.rebuild_ring().await;
+    }
+
+    /// Moves the member from one state to the other, unless its state changed in the
+    /// meantime, e.g. a joining node that was drained or removed during its handoff.
+    async fn transition(&self, id: &str, from: NodeState, to: NodeState) -> bool {
+        let changed = match self.members.write().await.iter_mut().find(|member| member.node.to_string() == id) {
+            Some(member) if member.state == from => {
+                member.state = to;
+                true
+            },
+            _ => false
+        };
+        if changed {
+            self.rebuild_ring().await;
+        }
+
+        changed
+    }
+
+    pub async fn store_hints(&self, nodes: &[Node], hint: Hint) {
+        if nodes.is_empty() {
+            return;
+        }
+
+        let mut hints = self.hints.lock().await;
+        for node in nodes {
+            log::warn!("Node {} missed a write for '{}', storing hint", node, hint.key());
+            hints.add(&node.to_string(), hint.clone());
+        }
+    }
+
+    /// Probes every node and flips its state between up and down. Joining and
+    /// draining nodes are left alone until their handoff completes.
+    pub async fn probe(&self) {
+        let members: Vec<Member> = self.members.read().await.clone();
+        let mut changed = false;
+
+        for member in members {
+            let result = self.node_client.test_connection(&member.node).await;
+
+            let mut members = self.members.write().await;
+            let current = match members.iter_mut().find(|current| current.node == member.node) {
+                Some(current) => current,
+                // Removed while it was being probed
+                None => continue
+            };
+
+            match result {
+                Ok(()) => {
+                    current.consecutive_failures = 0;
+                    current.last_seen = Some(chrono::Utc::now());
+                    if current.state == NodeState::Down {
+                        log::info!("Node {} is up again", current.node);
+                        current.state = NodeState::Up;
+                        changed = true;
+                    }
+                }
+                Err(err) => {
+                    current.consecutive_failures += 1;
+                    if current.state == NodeState::Up && current.consecutive_failures >= self.unhealthy_threshold {
+                        log::warn!("Node {} failed {} health probes, marking it down: {}", current.node, current.consecutive_failures, err);
+                        current.state = NodeState::Down;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        if changed {
+            self.rebuild_ring().await;
+        }
+    }
+
+    /// Delivers pending hints to every node that is up.
+    pub async fn replay_hints(&self) {
+        let pending_nodes = self.hints.lock().await.nodes();
+        let up_nodes = self.nodes_in(&[NodeState::Up, NodeState::Joining]).await;
+
+        for node in up_nodes.iter().filter(|node| pending_nodes.contains(&node.to_string())) {
+            let mut pending = self.hints.lock().await.take(&node.to_string());
+            log::info!("Replaying {} hint(s) to node {}", pending.len(), node);
+
+            while let Some(hint) = pending.pop_front() {
+                let result = match &hint {
+                    Hint::Upsert(link) => self.node_client.upsert(node, link).await,
+                    Hint::Delete(key) => self.node_client.delete(node, key).await.map(|_| ())
+                };
+
+                if let Err(err) = result {
+                    log::warn!("Failed to replay hint for '{}' to node {}: {}", hint.key(), node, err);
+                    pending.push_front(hint);
+                    break;
+                }
+            }
+
+            self.hints.lock().await.restore(&node.to_string(), pending);
+        }
+    }
+
+    /// Runs anti-entropy between all nodes that are up.
+    pub async fn sync(&self) {
+        let nodes = self.nodes_in(&[NodeState::Up]).await;
+        self.run_anti_entropy(&nodes).await;
+    }
+
+    /// Compares the Merkle digests of the given nodes and streams the links of
+    /// diverged ranges to the nodes that are missing them or hold an older version.
+    ///
+    /// Deletes are not tracked with tombstones, a node that missed a delete is only
+    /// repaired through its hint. Returns whether every node took part and every
+    /// repair was stored, a handoff that missed a node or a link has to run again.
+    pub async fn run_anti_entropy(&self, nodes: &[Node]) -> bool {
+        let mut complete = true;
+        let mut replicas: Vec<(&Node, MerkleTree)> = Vec::new();
+        for node in nodes {
+            match self.node_client.get_digest(node, self.anti_entropy_ranges).await {
+                Ok(tree) => replicas.push((node, tree)),
+                Err(err) => {
+                    log::warn!("Skipping node {} in anti-entropy: {}", node, err);
+                    complete = false;
+                }
+            }
+        }
+
+        if replicas.len() < 2 {
+            return complete;
+        }
+
+        let mut diverged: BTreeSet<usize> = BTreeSet::new();
+        for (_, tree) in &replicas[1..] {
+            diverged.extend(replicas[0].1.diff(tree));
+        }
+
+        if diverged.is_empty() {
+            log::debug!("Anti-entropy: all {} replicas are in sync", replicas.len());
+            return complete;
+        }
+
+        log::info!("Anti-entropy: {} of {} ranges diverged", diverged.len(), replicas[0].1.ranges());
+
+        for range in diverged {
+            let mut node_links: Vec<(&Node, HashMap<String, LinkInfo>)> = Vec::new();
+            for (node, tree) in &replicas {
+                match self.node_client.get_range(node, range, tree.ranges()).await {
+                    Ok(links) => node_links.push((node, links.into_iter().map(|link| (link.short_url.clone(), link)).collect())),
+                    Err(err) => {
+                        log::warn!("Failed to get range {} from node {}: {}", range, node, err);
+                        complete = false;
+                    }
+                }
+            }
+
+            let mut winners: HashMap<String, LinkInfo> = HashMap::new();
+            for (_, links) in &node_links {
+                for link in links.values() {
+                    match winners.get(&link.short_url) {
+                        Some(current) if !antientropy::is_newer(link, current) => {}
+                        _ => { winners.insert(link.short_url.clone(), link.clone()); }
+                    }
+                }
+            }
+
+            for (node, links) in &node_links {
+                for winner in winners.values() {
+                    if links.get(&winner.short_url) == Some(winner) {
+                        continue;
+                    }
+
+                    if let Err(err) = self.node_client.upsert(node, winner).await {
+                        log::warn!("Anti-entropy failed to repair '{}' on node {}: {}", winner.short_url, node, err);
+                        complete = false;
+                    }
+                }
+            }
+        }
+
+        complete
+    }
+
+    async fn find_member(&self, id: &str) -> Result<Member, HashServiceError> {
+        self.members.read().await.iter()
+            .find(|member| member.node.to_string() == id)
+            .cloned()
+            .ok_or_else(|| HashServiceError::UnknownNode { node: id.to_string() })
+    }
+
+    /// Adds a node at runtime. It receives writes right away and becomes
+    /// readable once the existing data has been handed off to it.
+    pub async fn add_node(self: &Arc<Self>, hostname: &str) -> Result<MemberStatus, HashServiceError> {
+        let node = Node::parse(hostname)?;
+        if self.find_member(&node.to_string()).await.is_ok() {
+            return Err(HashServiceError::DuplicateNode { node: node.to_string() });
+        }
+
+        self.node_client.test_connection(&node).await?;
+
+        self.members.write().await.push(Member {
+            node: node.clone(),
+            state: NodeState::Joining,
+            consecutive_failures: 0,
+            last_seen: Some(chrono::Utc::now())
+        });
+        log::info!("Node {} is joining the cluster", node);
+
+        let cluster = self.clone();
+        let joining = node.clone();
+        tokio::spawn(async move {
+            let id = joining.to_string();
+            let mut interval = tokio::time::interval(Duration::from_secs(constants::HANDOFF_RETRY_INTERVAL));
+            loop {
+                interval.tick().await;
+                // Drained or removed during the handoff
+                if cluster.find_member(&id).await.map_or(true, |member| member.state != NodeState::Joining) {
+                    log::info!("Node {} stopped joining, handoff cancelled", joining);
+                    return;
+                }
+
+                let mut nodes = cluster.nodes_in(&[NodeState::Up]).await;
+                nodes.push(joining.clone());
+                if !cluster.run_anti_entropy(&nodes).await {
+                    log::warn!("Handoff to node {} is incomplete, retrying in {}s", joining, constants::HANDOFF_RETRY_INTERVAL);
+                    continue;
+                }
+
+                if cluster.transition(&id, NodeState::Joining, NodeState::Up).await {
+                    log::info!("Handoff to node {} completed, node is up", joining);
+                }
+                return;
+            }
+        });
+
+        self.status_of(&node.to_string()).await
+    }
+
+    /// Takes a node out of the ring and hands off its data to the remaining nodes.
+    pub async fn drain_node(self: &Arc<Self>, id: &str) -> Result<MemberStatus, HashServiceError> {
+        let member = self.find_member(id).await?;
+        if member.state == NodeState::Draining {
+            return self.status_of(id).await;
+        }
+
+        self.set_state(id, NodeState::Draining).await;
+        self.hints.lock().await.take(id);
+        log::info!("Node {} is draining", member.node);
+
+        if member.state != NodeState::Down {
+            let cluster = self.clone();
+            tokio::spawn(async move {
+                let mut nodes = cluster.nodes_in(&[NodeState::Up]).await;
+                nodes.insert(0, member.node.clone());
+                if cluster.run_anti_entropy(&nodes).await {
+                    log::info!("Handoff from node {} completed, it can be removed", member.node);
+                } else {
+                    log::warn!("Handoff from node {} is incomplete, links it alone holds may be lost when it's removed", member.node);
+                }
+            });
+        }
+
+        self.status_of(id).await
+    }
+
+    /// Removes a node that is drained or down.
+    pub async fn remove_node(&self, id: &str) -> Result<(), HashServiceError> {
+        let member = self.find_member(id).await?;
+        if !matches!(member.state, NodeState::Draining | NodeState::Down) {
+            return Err(HashServiceError::NodeNotDrained { node: id.to_string() });
+        }
+
+        self.members.write().await.retain(|member| member.node.to_string() != id);
+        self.hints.lock().await.take(id);
+        self.rebuild_ring().await;
+        log::info!("Node {} was removed from the cluster", member.node);
+
+        Ok(())
+    }
+
+    async fn status_of(&self, id: &str) -> Result<MemberStatus, HashServiceError> {
+        self.members().await.into_iter()
+            .find(|member| member.id == id)
+            .ok_or_else(|| HashServiceError::UnknownNode { node: id.to_string() })
+    }
+}
@@ -1,173 +1,228 @@
-use std::collections::HashMap;
-use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::{services::hashservice, models::{linkinfo::LinkInfo, queryparams::QueryParams}, configuration};
+use crate::{services::hashservice, models::{linkinfo::LinkInfo, queryparams::QueryParams}, configuration, constants};
 
-use hash_ring::HashRing;
 use async_trait::async_trait;
+use futures_util::StreamExt;
 
+use super::cluster::{Cluster, Node};
 use super::hashserviceerror::HashServiceError;
-
-#[derive(Clone, Debug)]
-pub struct Node {
-    pub host: String,
-    pub port: u16
-}
-
-impl fmt::Display for Node {
-    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(fmt, "{}:{}", self.host, self.port)
-    }
-}
+use super::hintstore::Hint;
+use super::linkmerge::{self, LinkMerge, LinkSource, LinkStream};
 
 pub struct CoordinatorHashService {
     coordinator_config: configuration::settings::Coordinator,
-    host_port_pairs: Vec<(String, usize)>,
-    nodes: Vec<Node>,
-    hash_ring: Option<HashRing<Node>>
+    cluster: Arc<Cluster>
 }
 
 impl CoordinatorHashService {
     pub fn new(config: &configuration::settings::Coordinator) -> Self {
         CoordinatorHashService {
             coordinator_config: config.clone(),
-            host_port_pairs: Vec::new(),
-            nodes: Vec::new(),
-            hash_ring: None,
+            cluster: Arc::new(Cluster::new(config)),
         }
     }
+
+    /// NDJSON streams of the links of every node that is up, with the skip and top of the merged page.
+    async fn node_sources(&self, query_info: Option<QueryParams>) -> Result<(Vec<Box<dyn LinkSource>>, usize, Option<usize>), HashServiceError> {
+        let query_params = query_info.unwrap_or_default();
+        let skip = query_params.skip.unwrap_or(0);
+        let top = query_params.top;
+
+        // Every node has to return the first skip + top links, the page is cut after merging
+        let mut node_query_params = query_params.clone();
+        node_query_params.skip = None;
+        node_query_params.top = top.map(|top| top + skip);
+
+        let mut sources: Vec<Box<dyn LinkSource>> = Vec::new();
+        for node in self.cluster.read_nodes("").await {
+            match self.cluster.node_client().get_links_stream(&node, &node_query_params).await {
+                Ok(stream) => sources.push(Box::new(stream)),
+                Err(err) => log::warn!("Failed to get links from node {}: {}", node, err)
+            }
+        }
+
+        if sources.is_empty() {
+            return Err(HashServiceError::NoReachableNodes);
+        }
+
+        Ok((sources, skip, top))
+    }
+
+    fn start_background_jobs(&self) {
+        let health_check_interval = self.coordinator_config.health_check_interval
+            .unwrap_or(constants::DEFAULT_HEALTH_CHECK_INTERVAL);
+        let cluster = self.cluster.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(u64::max(health_check_interval, 1)));
+            loop {
+                interval.tick().await;
+                cluster.probe().await;
+            }
+        });
+
+        let replay_interval = self.coordinator_config.hint_replay_interval
+            .unwrap_or(constants::DEFAULT_HINT_REPLAY_INTERVAL);
+        let cluster = self.cluster.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(u64::max(replay_interval, 1)));
+            loop {
+                interval.tick().await;
+                cluster.replay_hints().await;
+            }
+        });
+
+        let anti_entropy_interval = self.coordinator_config.anti_entropy_interval
+            .unwrap_or(constants::DEFAULT_ANTI_ENTROPY_INTERVAL);
+        if anti_entropy_interval == 0 {
+            return;
+        }
+
+        let cluster = self.cluster.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(anti_entropy_interval));
+            // The first tick completes immediately, nodes were just checked in init
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                cluster.sync().await;
+            }
+        });
+    }
 }
 
 #[async_trait]
 impl hashservice::HashService for CoordinatorHashService {
-    async fn init(&mut self) -> Result<(), HashServiceError> {            
-        self.host_port_pairs = self.coordinator_config.hostnames.iter().map(|x| {
-            let key_value = x.split(':').map(|y| y.to_string()).collect::<Vec<String>>();
-            return (key_value[0].clone(), key_value[1].parse::<usize>().unwrap())
-        }).collect::<Vec<(String, usize)>>();
-        
-        let mut nodes: Vec<Node> = Vec::new();
-        for host_port in &self.host_port_pairs {
-            let host = host_port.0.clone();
-            let port = host_port.1;
-            
-            test_connection(&host, port).await?;
-
-            nodes.push(Node {
-                host: host.clone(),
-                port: port as u16
-            });
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        let nodes = self.coordinator_config.hostnames.iter()
+            .map(|hostname| Node::parse(hostname))
+            .collect::<Result<Vec<Node>, HashServiceError>>()?;
+
+        for (i, node) in nodes.iter().enumerate() {
+            if nodes[..i].contains(node) {
+                return Err(HashServiceError::DuplicateNode { node: node.to_string() });
+            }
+        }
+
+        for node in nodes {
+            self.cluster.join(node).await;
         }
 
-        self.nodes = nodes.clone();
-        self.hash_ring = Some(HashRing::new(nodes, 10));
+        self.start_background_jobs();
 
         Ok(())
     }
 
+    fn cluster(&self) -> Option<Arc<Cluster>> {
+        Some(self.cluster.clone())
+    }
+
+    /// Streams the links of every node that is up and merges them by short url,
+    /// so replicas that missed writes don't hide links that exist on other nodes.
     async fn get_links(&mut self, query_info: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError> {
-        let mut result: Vec<LinkInfo> = Vec::<LinkInfo>::new();
+        let (sources, skip, top) = self.node_sources(query_info).await?;
 
-        for node in &self.nodes {
-            let node_result = get_links_impl(&node.host, node.port.into(), query_info.clone()).await?;
+        Ok(linkmerge::merge_links(sources, skip, top).await)
+    }
 
-            if result.len() > 0 {
-                // TODO: Error handling in case values are different, so there is an inconsistency between replicas
-            } else {
-                result = node_result;
-            }
-        }
+    /// Merges the links of the nodes like `get_links`, while they are sent.
+    async fn stream_links(&mut self, query_info: Option<QueryParams>) -> Result<Option<LinkStream>, HashServiceError> {
+        let (sources, skip, top) = self.node_sources(query_info).await?;
 
-        Ok(result)
+        Ok(Some(LinkMerge::new(sources, skip, top).into_stream().boxed()))
     }
 
     async fn insert(&mut self, value: &str) -> Result<LinkInfo, HashServiceError> {
+        let (nodes, mut failed_nodes) = self.cluster.write_nodes().await;
         let mut result: Option<LinkInfo> = None;
 
-        for node in &self.nodes {
-            let node_result = match insert_impl(&node.host, node.port.into(), value).await {
-                Ok(value) => value,
-                Err(e) => panic!("{}", e)
-            };
-            
-            if result.is_some() {
-                // TODO: Error handling in case values are different, so there is an inconsistency between replicas
-            } else {
-                result = Some(node_result);
+        for node in nodes {
+            match self.cluster.node_client().insert(&node, value).await {
+                Ok(node_result) => {
+                    if result.is_none() {
+                        result = Some(node_result);
+                    }
+                }
+                Err(err) => {
+                    log::warn!("Failed to insert into node {}: {}", node, err);
+                    failed_nodes.push(node);
+                }
             }
         }
 
-        Ok(result.unwrap())
-    }
+        let link = result.ok_or(HashServiceError::NoReachableNodes)?;
+        self.cluster.store_hints(&failed_nodes, Hint::Upsert(Box::new(link.clone()))).await;
 
-    async fn update(&mut self, _: &str, _: &LinkInfo) -> Result<bool, HashServiceError> {
-        todo!()
+        Ok(link)
     }
 
-    async fn find(&mut self, key: &str) -> Result<Option<LinkInfo>, HashServiceError> {
-        let mut result: Option<LinkInfo> = None;
+    async fn update(&mut self, key: &str, value: &LinkInfo) -> Result<bool, HashServiceError> {
+        let mut value = value.clone();
+        value.short_url = key.to_string();
 
-        for node in &self.nodes {
-            let node_result = match find_impl(&node.host, node.port.into(), key).await {
-                Ok(value) => value,
-                Err(e) => panic!("{}", e)
-            };
-            
-            if result.is_some() {
-                // TODO: Error handling in case values are different, so there is an inconsistency between replicas
-            } else {
-                result = node_result;
+        self.upsert(&value).await?;
+        Ok(true)
+    }
+
+    async fn upsert(&mut self, value: &LinkInfo) -> Result<(), HashServiceError> {
+        let (nodes, mut failed_nodes) = self.cluster.write_nodes().await;
+        let mut succeeded = false;
+
+        for node in nodes {
+            match self.cluster.node_client().upsert(&node, value).await {
+                Ok(()) => succeeded = true,
+                Err(err) => {
+                    log::warn!("Failed to update node {}: {}", node, err);
+                    failed_nodes.push(node);
+                }
             }
         }
 
-        Ok(result)
-    }
+        if !succeeded {
+            return Err(HashServiceError::NoReachableNodes);
+        }
 
-    async fn delete(&mut self, _: &str) -> Result<bool, HashServiceError> {
-        todo!()
+        self.cluster.store_hints(&failed_nodes, Hint::Upsert(Box::new(value.clone()))).await;
+        Ok(())
     }
-}
 
-pub async fn test_connection(host: &String, port: usize) -> Result<(), HashServiceError> {
-    let _resp = reqwest::get(format!("http://{}:{}/hello", host, port))
-        .await?
-            .json::<HashMap<String, String>>()
-            .await?;
-
-    Ok(())
-}
+    async fn find(&mut self, key: &str) -> Result<Option<LinkInfo>, HashServiceError> {
+        let mut reachable = false;
+
+        for node in self.cluster.read_nodes(key).await {
+            match self.cluster.node_client().find(&node, key).await {
+                Ok(Some(value)) => return Ok(Some(value)),
+                // The node may have missed the write, keep asking the others
+                Ok(None) => reachable = true,
+                Err(err) => log::warn!("Failed to find '{}' on node {}: {}", key, node, err)
+            }
+        }
 
-pub async fn get_links_impl(host: &str, port: usize, _query_info: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError> {
-    // TODO: Implement QueryParams
+        if reachable {
+            Ok(None)
+        } else {
+            Err(HashServiceError::NoReachableNodes)
+        }
+    }
 
-    let urls = reqwest::get(format!("http://{}:{}/urls", host, port))
-        .await?
-            .json::<Vec<LinkInfo>>()
-            .await?;
-    
-    Ok(urls)
-}
+    async fn delete(&mut self, key: &str) -> Result<bool, HashServiceError> {
+        let (nodes, mut failed_nodes) = self.cluster.write_nodes().await;
+        let mut result: Option<bool> = None;
+
+        for node in nodes {
+            match self.cluster.node_client().delete(&node, key).await {
+                Ok(deleted) => result = Some(result.unwrap_or(false) || deleted),
+                Err(err) => {
+                    log::warn!("Failed to delete '{}' from node {}: {}", key, node, err);
+                    failed_nodes.push(node);
+                }
+            }
+        }
 
-pub async fn find_impl(host: &str, port: usize, key: &str) -> Result<Option<LinkInfo>, HashServiceError> {
-    let response = match reqwest::get(format!("http://{}:{}/{}/summary", host, port, key))
-        .await?
-        .json::<LinkInfo>()
-        .await {
-            Ok(value) => value,
-            Err(_) => return Ok(None)
-        };
+        let deleted = result.ok_or(HashServiceError::NoReachableNodes)?;
+        self.cluster.store_hints(&failed_nodes, Hint::Delete(key.to_string())).await;
 
-    Ok(Some(response))
+        Ok(deleted)
+    }
 }
-
-pub async fn insert_impl(host: &str, port: usize, value: &str) -> Result<LinkInfo, HashServiceError> {
-    let response = match reqwest::get(format!("http://{}:{}/shorten?long_url={}", host, port, value))
-        .await?
-            .json::<LinkInfo>()
-            .await {
-                Ok(value) => value,
-                Err(err) => panic!("{}", err)
-            };
-
-    Ok(response)
-}
\ No newline at end of file
@@ -0,0 +1,194 @@
+use std::fs;
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+use regex::Regex;
+use url::{Host, Url};
+
+use crate::configuration::settings::{Destinations, Settings};
+use crate::name_of;
+
+use super::hashserviceerror::{build_invalid_configuration_error, HashServiceError};
+use super::urlnormalizer::UrlError;
+
+/// Domains and urls links may or may not point to. Block rules win over allow rules,
+/// and once an allow rule is set only urls matching an allow rule are accepted.
+#[derive(Clone, Debug, Default)]
+pub struct DestinationRules {
+    blocked_domains: Vec<String>,
+    blocked_patterns: Vec<Regex>,
+    allowed_domains: Vec<String>,
+    allowed_patterns: Vec<Regex>,
+}
+
+impl DestinationRules {
+    pub fn new(destinations: &Destinations) -> Result<Self, String> {
+        let mut rules = DestinationRules::default();
+        for domain in destinations.blocked_domains.iter().flatten() {
+            rules.blocked_domains.push(normalize_domain(domain)?);
+        }
+        for domain in destinations.allowed_domains.iter().flatten() {
+            rules.allowed_domains.push(normalize_domain(domain)?);
+        }
+        for pattern in destinations.blocked_patterns.iter().flatten() {
+            rules.blocked_patterns.push(compile(pattern)?);
+        }
+        for pattern in destinations.allowed_patterns.iter().flatten() {
+            rules.allowed_patterns.push(compile(pattern)?);
+        }
+
+        Ok(rules)
+    }
+
+    /// Rules of a rules file: one `block <domain>`, `allow <domain>`, `block-pattern <regex>`
+    /// or `allow-pattern <regex>` per line. Empty lines and lines starting with '#' are skipped.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut destinations = Destinations::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (kind, value) = line.split_once(char::is_whitespace)
+                .map(|(kind, value)| (kind, value.trim()))
+                .ok_or_else(|| format!("line {} has no value: '{}'", number + 1, line))?;
+            let rules = match kind {
+                "block" => &mut destinations.blocked_domains,
+                "allow" => &mut destinations.allowed_domains,
+                "block-pattern" => &mut destinations.blocked_patterns,
+                "allow-pattern" => &mut destinations.allowed_patterns,
+                _ => return Err(format!("line {} has an unknown rule '{}'", number + 1, kind))
+            };
+            rules.get_or_insert_with(Vec::new).push(value.to_string());
+        }
+
+        DestinationRules::new(&destinations)
+    }
+
+    pub fn extend(&mut self, other: DestinationRules) {
+        self.blocked_domains.extend(other.blocked_domains);
+        self.blocked_patterns.extend(other.blocked_patterns);
+        self.allowed_domains.extend(other.allowed_domains);
+        self.allowed_patterns.extend(other.allowed_patterns);
+    }
+
+    /// Checks a normalized url against the rules.
+    pub fn check(&self, long_url: &str) -> Result<(), UrlError> {
+        let blocked = |reason: String| Err(UrlError::BlockedDestination { url: long_url.to_string(), reason });
+
+        if let Some(domain) = self.blocked_domains.iter().find(|domain| is_on_domain(long_url, domain)) {
+            return blocked(format!("domain '{}' is blocked", domain));
+        }
+        if let Some(pattern) = self.blocked_patterns.iter().find(|pattern| pattern.is_match(long_url)) {
+            return blocked(format!("it matches the blocked pattern '{}'", pattern));
+        }
+
+        let has_allow_rules = !self.allowed_domains.is_empty() || !self.allowed_patterns.is_empty();
+        let allowed = self.allowed_domains.iter().any(|domain| is_on_domain(long_url, domain))
+            || self.allowed_patterns.iter().any(|pattern| pattern.is_match(long_url));
+        if has_allow_rules && !allowed {
+            return blocked(String::from("it is not on the allowlist"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Destination rules of the settings and of the rules file, which is reloaded when it changes.
+#[derive(Default)]
+pub struct DestinationFilter {
+    configured: DestinationRules,
+    rules_file: Option<String>,
+    /// Modification time of the loaded rules file and the rules in effect
+    loaded: RwLock<(Option<SystemTime>, DestinationRules)>,
+}
+
+impl DestinationFilter {
+    pub fn new(rules: DestinationRules) -> Self {
+        DestinationFilter {
+            configured: rules.clone(),
+            rules_file: None,
+            loaded: RwLock::new((None, rules))
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Result<Self, HashServiceError> {
+        let destinations = settings.destinations.clone().unwrap_or_default();
+        let configured = DestinationRules::new(&destinations)
+            .map_err(|reason| invalid_rules(settings, &reason))?;
+
+        let filter = DestinationFilter {
+            loaded: RwLock::new((None, configured.clone())),
+            configured,
+            rules_file: destinations.rules_file
+        };
+        filter.reload().map_err(|reason| invalid_rules(settings, &reason))?;
+
+        Ok(filter)
+    }
+
+    /// Loads the rules file again when it was modified since it was last loaded.
+    /// Returns whether the rules changed, broken rules leave the current ones in effect.
+    pub fn reload(&self) -> Result<bool, String> {
+        let rules_file = match &self.rules_file {
+            Some(rules_file) => rules_file,
+            None => return Ok(false)
+        };
+
+        let modified = fs::metadata(rules_file).and_then(|metadata| metadata.modified())
+            .map_err(|err| format!("can't read '{}': {}", rules_file, err))?;
+        if self.loaded.read().unwrap().0 == Some(modified) {
+            return Ok(false);
+        }
+
+        let text = fs::read_to_string(rules_file)
+            .map_err(|err| format!("can't read '{}': {}", rules_file, err))?;
+        let mut rules = self.configured.clone();
+        rules.extend(DestinationRules::parse(&text).map_err(|reason| format!("'{}' {}", rules_file, reason))?);
+        *self.loaded.write().unwrap() = (Some(modified), rules);
+
+        Ok(true)
+    }
+
+    /// Checks a normalized url against the rules, and that it doesn't point back to
+    /// one of `own_hosts`, the hosts short links are served on.
+    pub fn check(&self, long_url: &str, own_hosts: &[String]) -> Result<(), UrlError> {
+        if own_hosts.iter().any(|host| host_of(long_url).as_deref() == Some(host)) {
+            return Err(UrlError::RedirectLoop { url: long_url.to_string() });
+        }
+
+        self.loaded.read().unwrap().1.check(long_url)
+    }
+}
+
+/// Whether the host of the url is the domain or one of its subdomains.
+pub fn is_on_domain(long_url: &str, domain: &str) -> bool {
+    match host_of(long_url) {
+        Some(host) => host == domain || host.ends_with(&format!(".{}", domain)),
+        None => false
+    }
+}
+
+/// Lowercase ASCII form of a domain rule, international domains in punycode.
+pub fn normalize_domain(domain: &str) -> Result<String, String> {
+    let domain = domain.trim().trim_start_matches("*.").trim_start_matches('.');
+    match Host::parse(domain) {
+        Ok(Host::Domain(domain)) => Ok(domain),
+        Ok(host) => Ok(host.to_string()),
+        Err(err) => Err(format!("'{}' is not a domain: {}", domain, err))
+    }
+}
+
+fn host_of(long_url: &str) -> Option<String> {
+    Url::parse(long_url).ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+}
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|err| format!("'{}' is not a regular expression: {}", pattern, err))
+}
+
+fn invalid_rules(settings: &Settings, reason: &str) -> HashServiceError {
+    build_invalid_configuration_error(settings.mode.to_string().as_str(), name_of!(destinations in Settings), reason)
+}
@@ -0,0 +1,40 @@
+use async_trait::async_trait;
+
+use crate::{configuration::settings::{Mode, Settings}, models::domain::Domain};
+
+use super::firestoredomainstore::FirestoreDomainStore;
+use super::hashserviceerror::HashServiceError;
+pub use super::inmemorydomainstore::InMemoryDomainStore;
+use super::mongodomainstore::MongoDomainStore;
+use super::redisdomainstore::RedisDomainStore;
+
+/// Where the custom domains added through the admin endpoints are kept, by name.
+#[async_trait]
+pub trait DomainStore: Send + Sync {
+    async fn init(&mut self) -> Result<(), HashServiceError>;
+
+    async fn list(&mut self) -> Result<Vec<Domain>, HashServiceError>;
+
+    /// Inserts the domain or replaces the stored one with the same name.
+    async fn upsert(&mut self, domain: &Domain) -> Result<(), HashServiceError>;
+
+    async fn delete(&mut self, name: &str) -> Result<bool, HashServiceError>;
+}
+
+/// Creates the domain store of the backend the links are kept in, see `keystore::create_key_store`.
+pub async fn create_domain_store(settings: &Settings) -> Result<Box<dyn DomainStore>, HashServiceError> {
+    let mode = match &settings.mode {
+        Mode::Gossip => settings.gossip.as_ref().and_then(|gossip| gossip.storage.clone()).unwrap_or_default(),
+        mode => mode.clone()
+    };
+
+    let mut domain_store: Box<dyn DomainStore> = match (mode, &settings.mongo_config, &settings.redis_config, &settings.firestore_config) {
+        (Mode::Mongo, Some(mongo_config), _, _) => Box::new(MongoDomainStore::new(mongo_config)),
+        (Mode::Redis, _, Some(redis_config), _) => Box::new(RedisDomainStore::new(redis_config)),
+        (Mode::Firestore, _, _, Some(firestore_config)) => Box::new(FirestoreDomainStore::new(firestore_config)),
+        _ => Box::new(InMemoryDomainStore::new())
+    };
+
+    domain_store.init().await?;
+    Ok(domain_store)
+}
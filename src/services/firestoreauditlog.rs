@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use firestore::*;
+
+use crate::{configuration, constants::AUDIT_COLLECTION_NAME, models::auditevent::{AuditEvent, AuditQuery}};
+
+use super::auditlog::AuditLog;
+use super::hashserviceerror::HashServiceError;
+
+/// Events in their own collection, the id of an event is its document id.
+pub struct FirestoreAuditLog {
+    firestore_config: configuration::settings::FirestoreConfig,
+    db: Option<FirestoreDb>,
+}
+
+impl FirestoreAuditLog {
+    pub fn new(config: &configuration::settings::FirestoreConfig) -> Self {
+        FirestoreAuditLog {
+            firestore_config: config.clone(),
+            db: None
+        }
+    }
+}
+
+#[async_trait]
+impl AuditLog for FirestoreAuditLog {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        self.db = Some(FirestoreDb::new(self.firestore_config.project_id.clone()).await?);
+
+        Ok(())
+    }
+
+    async fn record(&mut self, event: &AuditEvent) -> Result<(), HashServiceError> {
+        self.db.as_mut().unwrap().fluent()
+            .insert()
+            .into(AUDIT_COLLECTION_NAME)
+            .document_id(&event.id)
+            .object(event)
+            .execute::<()>()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn query(&mut self, query: &AuditQuery) -> Result<Vec<AuditEvent>, HashServiceError> {
+        let since_id = query.since_id();
+        let mut select = self.db.as_mut().unwrap().fluent()
+            .select()
+            .from(AUDIT_COLLECTION_NAME)
+            // Ids start with the time of the event, so the time is compared on the ids
+            .filter(|q| q.for_all([
+                query.link.as_ref().and_then(|link| q.field("link").eq(link)),
+                query.actor.as_ref().and_then(|actor| q.field("actor").eq(actor)),
+                query.tenant.as_ref().and_then(|tenant| q.field("tenant").eq(tenant)),
+                since_id.as_ref().and_then(|since_id| q.field("id").greater_than_or_equal(since_id)),
+                query.after.as_ref().and_then(|after| q.field("id").greater_than(after)),
+            ]))
+            .order_by([("id", FirestoreQueryDirection::Ascending)]);
+        if let Some(limit) = query.limit {
+            select = select.limit(limit as u32);
+        }
+
+        let events: Vec<AuditEvent> = select
+            .obj()
+            .query()
+            .await?;
+
+        Ok(events)
+    }
+}
@@ -0,0 +1,74 @@
+use async_trait::async_trait;
+use firestore::*;
+
+use crate::{configuration, constants::DOMAINS_COLLECTION_NAME, models::domain::Domain};
+
+use super::domainstore::DomainStore;
+use super::hashserviceerror::HashServiceError;
+
+/// Domains in their own collection, the name of a domain is its document id.
+pub struct FirestoreDomainStore {
+    firestore_config: configuration::settings::FirestoreConfig,
+    db: Option<FirestoreDb>,
+}
+
+impl FirestoreDomainStore {
+    pub fn new(config: &configuration::settings::FirestoreConfig) -> Self {
+        FirestoreDomainStore {
+            firestore_config: config.clone(),
+            db: None
+        }
+    }
+}
+
+#[async_trait]
+impl DomainStore for FirestoreDomainStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        self.db = Some(FirestoreDb::new(self.firestore_config.project_id.clone()).await?);
+
+        Ok(())
+    }
+
+    async fn list(&mut self) -> Result<Vec<Domain>, HashServiceError> {
+        let domains: Vec<Domain> = self.db.as_mut().unwrap().fluent()
+            .select()
+            .from(DOMAINS_COLLECTION_NAME)
+            .order_by([("name", FirestoreQueryDirection::Ascending)])
+            .obj()
+            .query()
+            .await?;
+
+        Ok(domains)
+    }
+
+    async fn upsert(&mut self, domain: &Domain) -> Result<(), HashServiceError> {
+        // Updates without a precondition create the document when it doesn't exist
+        self.db.as_mut().unwrap().fluent()
+            .update()
+            .in_col(DOMAINS_COLLECTION_NAME)
+            .document_id(&domain.name)
+            .object(domain)
+            .execute::<()>()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&mut self, name: &str) -> Result<bool, HashServiceError> {
+        let found: Option<Domain> = self.db.as_mut().unwrap().fluent()
+            .select()
+            .by_id_in(DOMAINS_COLLECTION_NAME)
+            .obj()
+            .one(name)
+            .await?;
+
+        self.db.as_mut().unwrap().fluent()
+            .delete()
+            .from(DOMAINS_COLLECTION_NAME)
+            .document_id(name)
+            .execute()
+            .await?;
+
+        Ok(found.is_some())
+    }
+}
@@ -1,22 +1,49 @@
-use crate::{configuration, models::{builders::build_link_info, linkinfo::LinkInfo, queryparams::QueryParams}, services::hashservice};
+use std::collections::HashMap;
+
+use crate::{configuration, constants::TENANTS_COLLECTION_NAME, models::{builders::build_link_info, linkinfo::LinkInfo, queryparams::QueryParams}, services::hashservice};
 
 use firestore::*;
+use futures_util::TryStreamExt;
+use serde::Deserialize;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 
 use super::{hashfunction, hashserviceerror::HashServiceError};
 
 const COLLECTION_NAME: &'static str = "links";
+/// Firestore commits at most this many writes at once
+const FIRESTORE_MAX_BATCH_WRITES: usize = 500;
+
+/// Result of the count aggregation of the links
+#[derive(Deserialize)]
+struct LinkCount {
+    count: usize,
+}
 
 pub struct FirestoreHashService {
     firestore_config: configuration::settings::FirestoreConfig,
     db: Option<FirestoreDb>,
+    tenant: Option<String>,
+    /// Document the links collection belongs to, "tenants/<id>" for the links of a tenant
+    parent: String,
 }
 
 impl FirestoreHashService {
     pub fn new(config: &configuration::settings::FirestoreConfig) -> impl hashservice::HashService {
         FirestoreHashService {
             firestore_config: config.clone(),
-            db: None
+            db: None,
+            tenant: None,
+            parent: String::new()
+        }
+    }
+
+    pub fn for_tenant(config: &configuration::settings::FirestoreConfig, tenant: &str) -> impl hashservice::HashService {
+        FirestoreHashService {
+            firestore_config: config.clone(),
+            db: None,
+            tenant: Some(tenant.to_string()),
+            parent: String::new()
         }
     }
 }
@@ -24,36 +51,144 @@ impl FirestoreHashService {
 #[async_trait]
 impl hashservice::HashService for FirestoreHashService {
     async fn init(&mut self) -> Result<(), HashServiceError> {
-        self.db = Some(FirestoreDb::new(self.firestore_config.project_id.clone()).await?);
+        let db = FirestoreDb::new(self.firestore_config.project_id.clone()).await?;
+        self.parent = match &self.tenant {
+            Some(tenant) => db.parent_path(TENANTS_COLLECTION_NAME, tenant)?.into(),
+            None => db.get_documents_path().clone()
+        };
+        self.db = Some(db);
 
         Ok(())
     }
 
     async fn get_links(&mut self, query_params: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError> {
-        let urls : Vec<LinkInfo> = self.db.as_mut().unwrap().fluent()
+        let query_params = query_params.unwrap_or_default();
+
+        let mut query = self.db.as_mut().unwrap().fluent()
             .select()
             .from(COLLECTION_NAME)
+            .parent(&self.parent)
+            .order_by([("short_url", FirestoreQueryDirection::Ascending)]);
+        if let Some(skip) = query_params.skip {
+            query = query.offset(skip as u32);
+        }
+        if let Some(top) = query_params.top {
+            query = query.limit(top as u32);
+        }
+
+        let urls : Vec<LinkInfo> = query
             .obj()
             .query()
             .await?;
 
-        let query_params = match query_params {
-            Some(value) => value,
-            None => return Ok(urls)
-        };
+        Ok(urls)
+    }
 
-        let top = query_params.top.unwrap_or(urls.len());
-        let skip = query_params.skip.unwrap_or(0);
-        
-        Ok(urls.into_iter().skip(skip).take(top).collect())
+    async fn get_links_after(&mut self, after: Option<&str>, owner_id: Option<&str>, top: usize) -> Result<Vec<LinkInfo>, HashServiceError> {
+        let (after, owner_id) = (after.map(String::from), owner_id.map(String::from));
+
+        // The links of an owner need the composite index on owner_id and short_url of firestore.indexes.json
+        let urls: Vec<LinkInfo> = self.db.as_mut().unwrap().fluent()
+            .select()
+            .from(COLLECTION_NAME)
+            .parent(&self.parent)
+            .filter(|q| q.for_all([
+                after.clone().and_then(|after| q.field("short_url").greater_than(after)),
+                owner_id.clone().and_then(|owner_id| q.field("owner_id").eq(owner_id))
+            ]))
+            .order_by([("short_url", FirestoreQueryDirection::Ascending)])
+            .limit(top as u32)
+            .obj()
+            .query()
+            .await?;
+
+        Ok(urls)
+    }
+
+    async fn count_links(&mut self) -> Result<usize, HashServiceError> {
+        // Counted by the server, without reading the links
+        let counts: Vec<LinkCount> = self.db.as_mut().unwrap().fluent()
+            .select()
+            .from(COLLECTION_NAME)
+            .parent(&self.parent)
+            .aggregate(|a| a.fields([a.field("count").count()]))
+            .obj()
+            .query()
+            .await?;
+
+        Ok(counts.first().map_or(0, |link_count| link_count.count))
+    }
+
+    async fn purge_deleted(&mut self, deleted_before: DateTime<Utc>) -> Result<usize, HashServiceError> {
+        let db = self.db.as_ref().unwrap();
+        // Only the links in the trash are read, their deletion time is checked here
+        let trashed = db.fluent()
+            .select()
+            .from(COLLECTION_NAME)
+            .parent(&self.parent)
+            .filter(|q| q.field("status").eq("deleted"))
+            .query()
+            .await?;
+        let mut purged: Vec<(String, DateTime<Utc>)> = Vec::new();
+        for document in trashed {
+            let link: LinkInfo = FirestoreDb::deserialize_doc_to(&document)?;
+            if let (true, Some(update_time)) = (hashservice::is_purgeable(&link, deleted_before), document.update_time) {
+                purged.push((link.short_url, timestamp_utils::from_timestamp(update_time)?));
+            }
+        }
+
+        // A link restored since it was read has another update time, its delete fails alone
+        let writer = db.create_simple_batch_writer().await?;
+        let mut purged_count = 0;
+        for chunk in purged.chunks(FIRESTORE_MAX_BATCH_WRITES) {
+            let mut batch = writer.new_batch();
+            for (short_url, update_time) in chunk {
+                db.fluent()
+                    .delete()
+                    .from(COLLECTION_NAME)
+                    .document_id(short_url)
+                    .parent(&self.parent)
+                    .precondition(FirestoreWritePrecondition::UpdateTime(*update_time))
+                    .add_to_batch(&mut batch)?;
+            }
+            let response = batch.write().await?;
+            purged_count += response.statuses.iter().filter(|status| status.code == 0).count();
+        }
+
+        Ok(purged_count)
+    }
+
+    async fn get_links_by_owner(&mut self, owner_id: &str, query_params: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError> {
+        let query_params = query_params.unwrap_or_default();
+        let owner_id = owner_id.to_string();
+
+        // Needs the composite index on owner_id and short_url of firestore.indexes.json
+        let mut query = self.db.as_mut().unwrap().fluent()
+            .select()
+            .from(COLLECTION_NAME)
+            .parent(&self.parent)
+            .filter(|q| q.field("owner_id").eq(owner_id.clone()))
+            .order_by([("short_url", FirestoreQueryDirection::Ascending)]);
+        if let Some(skip) = query_params.skip {
+            query = query.offset(skip as u32);
+        }
+        if let Some(top) = query_params.top {
+            query = query.limit(top as u32);
+        }
+
+        let urls: Vec<LinkInfo> = query
+            .obj()
+            .query()
+            .await?;
+
+        Ok(urls)
     }
 
     async fn insert(&mut self, value: &str) -> Result<LinkInfo, HashServiceError> {
         let hash_value = hashfunction::hash(value);
 
-        let find_result = self.find(&hash_value).await?;
-        if find_result.is_some() {
-            return Ok(find_result.unwrap());
+        if let Some(existing) = self.find(&hash_value).await? {
+            return hashservice::stored_link_of(existing, value);
         }
         
         let new_link = build_link_info(hash_value.clone(), String::from(value));
@@ -62,6 +197,7 @@ impl hashservice::HashService for FirestoreHashService {
             .insert()
             .into(COLLECTION_NAME)
             .document_id(&hash_value)
+            .parent(&self.parent)
             .object(&new_link)
             .execute::<()>()
             .await?;
@@ -73,6 +209,7 @@ impl hashservice::HashService for FirestoreHashService {
             .update()
             .in_col(COLLECTION_NAME)
             .document_id(&key)
+            .parent(&self.parent)
             .object(value)
             .execute::<()>()
             .await?;
@@ -80,10 +217,45 @@ impl hashservice::HashService for FirestoreHashService {
         Ok(true)
     }
 
+    async fn upsert(&mut self, value: &LinkInfo) -> Result<(), HashServiceError> {
+        // Updates without a precondition create the document when it doesn't exist
+        self.db.as_mut().unwrap().fluent()
+            .update()
+            .in_col(COLLECTION_NAME)
+            .document_id(&value.short_url)
+            .parent(&self.parent)
+            .object(value)
+            .execute::<()>()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn insert_many(&mut self, values: &[LinkInfo]) -> Result<(), HashServiceError> {
+        let db = self.db.as_ref().unwrap();
+        let writer = db.create_simple_batch_writer().await?;
+        for chunk in values.chunks(FIRESTORE_MAX_BATCH_WRITES) {
+            let mut batch = writer.new_batch();
+            for value in chunk {
+                db.fluent()
+                    .update()
+                    .in_col(COLLECTION_NAME)
+                    .document_id(&value.short_url)
+                    .parent(&self.parent)
+                    .object(value)
+                    .add_to_batch(&mut batch)?;
+            }
+            batch.write().await?;
+        }
+
+        Ok(())
+    }
+
     async fn find(&mut self, key: &str) -> Result<Option<LinkInfo>, HashServiceError> {
         let find_result: Option<LinkInfo> = self.db.as_mut().unwrap().fluent()
             .select()
             .by_id_in(COLLECTION_NAME)
+            .parent(&self.parent)
             .obj()
             .one(&key)
             .await?;
@@ -96,11 +268,33 @@ impl hashservice::HashService for FirestoreHashService {
         return Ok(Some(found_link))
     }
 
+    async fn find_many(&mut self, keys: &[String]) -> Result<HashMap<String, LinkInfo>, HashServiceError> {
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        // One batched get of the documents
+        let found: Vec<(String, Option<LinkInfo>)> = self.db.as_mut().unwrap().fluent()
+            .select()
+            .by_id_in(COLLECTION_NAME)
+            .parent(&self.parent)
+            .obj()
+            .batch_with_errors(keys)
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(found.into_iter()
+            .filter_map(|(key, link)| link.map(|link| (key, link)))
+            .collect())
+    }
+
     async fn delete(&mut self, key: &str) -> Result<bool, HashServiceError> {
         self.db.as_mut().unwrap().fluent()
             .delete()
             .from(COLLECTION_NAME)
             .document_id(&key)
+            .parent(&self.parent)
             .execute()
             .await?;
 
@@ -0,0 +1,80 @@
+use async_trait::async_trait;
+use firestore::*;
+
+use crate::{configuration, constants::API_KEYS_COLLECTION_NAME, models::apikey::ApiKey};
+
+use super::hashserviceerror::HashServiceError;
+use super::keystore::KeyStore;
+
+/// Keys in their own collection, the name of a key is its document id.
+pub struct FirestoreKeyStore {
+    firestore_config: configuration::settings::FirestoreConfig,
+    db: Option<FirestoreDb>,
+}
+
+impl FirestoreKeyStore {
+    pub fn new(config: &configuration::settings::FirestoreConfig) -> Self {
+        FirestoreKeyStore {
+            firestore_config: config.clone(),
+            db: None
+        }
+    }
+}
+
+#[async_trait]
+impl KeyStore for FirestoreKeyStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        self.db = Some(FirestoreDb::new(self.firestore_config.project_id.clone()).await?);
+
+        Ok(())
+    }
+
+    async fn list(&mut self) -> Result<Vec<ApiKey>, HashServiceError> {
+        let keys: Vec<ApiKey> = self.db.as_mut().unwrap().fluent()
+            .select()
+            .from(API_KEYS_COLLECTION_NAME)
+            .order_by([("name", FirestoreQueryDirection::Ascending)])
+            .obj()
+            .query()
+            .await?;
+
+        Ok(keys)
+    }
+
+    async fn find(&mut self, name: &str) -> Result<Option<ApiKey>, HashServiceError> {
+        let find_result: Option<ApiKey> = self.db.as_mut().unwrap().fluent()
+            .select()
+            .by_id_in(API_KEYS_COLLECTION_NAME)
+            .obj()
+            .one(name)
+            .await?;
+
+        Ok(find_result)
+    }
+
+    async fn upsert(&mut self, key: &ApiKey) -> Result<(), HashServiceError> {
+        // Updates without a precondition create the document when it doesn't exist
+        self.db.as_mut().unwrap().fluent()
+            .update()
+            .in_col(API_KEYS_COLLECTION_NAME)
+            .document_id(&key.name)
+            .object(key)
+            .execute::<()>()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&mut self, name: &str) -> Result<bool, HashServiceError> {
+        let found = self.find(name).await?.is_some();
+
+        self.db.as_mut().unwrap().fluent()
+            .delete()
+            .from(API_KEYS_COLLECTION_NAME)
+            .document_id(name)
+            .execute()
+            .await?;
+
+        Ok(found)
+    }
+}
@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use firestore::*;
+
+use crate::{configuration, constants::USAGE_COLLECTION_NAME, models::tenantusage::DailyShortens};
+
+use super::hashserviceerror::HashServiceError;
+use super::usagestore::UsageStore;
+
+/// A document per tenant and day in its own collection, "<tenant>_<day>" is its id.
+pub struct FirestoreUsageStore {
+    firestore_config: configuration::settings::FirestoreConfig,
+    db: Option<FirestoreDb>,
+}
+
+impl FirestoreUsageStore {
+    pub fn new(config: &configuration::settings::FirestoreConfig) -> Self {
+        FirestoreUsageStore {
+            firestore_config: config.clone(),
+            db: None
+        }
+    }
+}
+
+fn document_id(tenant: &str, day: NaiveDate) -> String {
+    format!("{}_{}", tenant, day.format("%Y-%m-%d"))
+}
+
+#[async_trait]
+impl UsageStore for FirestoreUsageStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        self.db = Some(FirestoreDb::new(self.firestore_config.project_id.clone()).await?);
+
+        Ok(())
+    }
+
+    async fn shortens_on(&mut self, tenant: &str, day: NaiveDate) -> Result<usize, HashServiceError> {
+        let found: Option<DailyShortens> = self.db.as_mut().unwrap().fluent()
+            .select()
+            .by_id_in(USAGE_COLLECTION_NAME)
+            .obj()
+            .one(&document_id(tenant, day))
+            .await?;
+
+        Ok(found.map_or(0, |daily| daily.shortens.max(0) as usize))
+    }
+
+    async fn record_shortens(&mut self, tenant: &str, day: NaiveDate, shortens: usize) -> Result<(), HashServiceError> {
+        let daily = DailyShortens { tenant: tenant.to_string(), day: day.format("%Y-%m-%d").to_string(), shortens: 0 };
+        let db = self.db.as_ref().unwrap();
+        // The count is incremented by the server, the other fields are only written
+        let writer = db.create_simple_batch_writer().await?;
+        let mut batch = writer.new_batch();
+        db.fluent()
+            .update()
+            .fields(["tenant", "day"])
+            .in_col(USAGE_COLLECTION_NAME)
+            .document_id(document_id(tenant, day))
+            .object(&daily)
+            .transforms(|t| t.fields([t.field("shortens").increment(shortens as i64)]))
+            .add_to_batch(&mut batch)?;
+        batch.write().await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,490 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use rand::seq::SliceRandom;
+use serde::{Serialize, Deserialize};
+use sha2::Sha256;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::constants;
+
+/// Size of the HMAC-SHA256 signature in front of every datagram
+const SIGNATURE_SIZE: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemberState {
+    Alive,
+    /// Didn't answer a direct or indirect probe, can still refute by raising its incarnation
+    Suspect,
+    Dead
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GossipMember {
+    /// Address the member's gossip agent listens on, identifies the member
+    pub address: String,
+    /// Url of the member's HTTP API
+    pub http_url: String,
+    pub incarnation: u64,
+    pub state: MemberState
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum GossipMessage {
+    Ping { seq: u64, from: GossipMember, updates: Vec<GossipMember> },
+    PingReq { seq: u64, from: GossipMember, target: String, updates: Vec<GossipMember> },
+    Ack { seq: u64, from: GossipMember, updates: Vec<GossipMember> }
+}
+
+/// Message on the wire, with the time it was sent so old ones can't be replayed later.
+#[derive(Serialize, Deserialize)]
+struct Envelope<M> {
+    sent_at: u64,
+    message: M
+}
+
+#[derive(Clone, Debug)]
+pub struct GossipOptions {
+    pub bind_address: String,
+    /// Address other members reach this agent on, defaults to the bound address
+    pub advertise_address: Option<String>,
+    pub http_url: String,
+    pub seeds: Vec<String>,
+    pub protocol_period: Duration,
+    pub suspect_timeout: Duration,
+    pub indirect_checks: usize,
+    /// Shared secret every message is signed with, messages signed with another one are dropped
+    pub secret: String
+}
+
+enum PendingAck {
+    Local(oneshot::Sender<()>),
+    /// Probe sent on behalf of another member, its ack is forwarded to the requester
+    Forward { requester: SocketAddr, seq: u64 }
+}
+
+struct State {
+    local: GossipMember,
+    members: HashMap<String, GossipMember>,
+    /// Members that sent a signed message from the address they advertise. Members only
+    /// heard of through updates are probed, but not reported until they answer themselves.
+    verified: HashSet<String>,
+    suspected_at: HashMap<String, Instant>,
+    // Updates to piggyback on outgoing messages and how many more times to send them
+    updates: Vec<(GossipMember, u32)>,
+    pending: HashMap<u64, (PendingAck, Instant)>,
+    next_seq: u64,
+    probe_order: Vec<String>
+}
+
+/// SWIM-style failure detector and membership protocol over UDP.
+///
+/// Every protocol period the agent pings one member. If it doesn't answer in time,
+/// `indirect_checks` other members are asked to ping it on the agent's behalf, and
+/// only then the member is suspected. Suspects that don't refute within
+/// `suspect_timeout` are declared dead. Membership changes are piggybacked on the
+/// protocol messages and reported through the channel returned by [`GossipAgent::start`].
+/// Every datagram is signed with the shared secret, unsigned or stale ones are dropped.
+pub struct GossipAgent {
+    options: GossipOptions,
+    socket: UdpSocket,
+    state: Mutex<State>,
+    events: mpsc::UnboundedSender<GossipMember>,
+    tasks: Mutex<Vec<JoinHandle<()>>>
+}
+
+impl GossipAgent {
+    pub async fn start(options: GossipOptions) -> io::Result<(Arc<GossipAgent>, mpsc::UnboundedReceiver<GossipMember>)> {
+        let socket = UdpSocket::bind(&options.bind_address).await?;
+        let address = match &options.advertise_address {
+            Some(address) => address.clone(),
+            None => socket.local_addr()?.to_string()
+        };
+
+        // Starting from the current time lets a restarted member override its own death
+        let incarnation = now_millis();
+        let local = GossipMember { address, http_url: options.http_url.clone(), incarnation, state: MemberState::Alive };
+        log::info!("Gossip agent {} listening on {}", local.address, socket.local_addr()?);
+
+        let (events, receiver) = mpsc::unbounded_channel();
+        let agent = Arc::new(GossipAgent {
+            options,
+            socket,
+            state: Mutex::new(State {
+                local,
+                members: HashMap::new(),
+                verified: HashSet::new(),
+                suspected_at: HashMap::new(),
+                updates: Vec::new(),
+                pending: HashMap::new(),
+                next_seq: 0,
+                probe_order: Vec::new()
+            }),
+            events,
+            tasks: Mutex::new(Vec::new())
+        });
+
+        let receive_task = tokio::spawn(agent.clone().receive_loop());
+        let protocol_task = tokio::spawn(agent.clone().protocol_loop());
+        agent.tasks.lock().unwrap().extend([receive_task, protocol_task]);
+
+        Ok((agent, receiver))
+    }
+
+    pub fn local_member(&self) -> GossipMember {
+        self.state.lock().unwrap().local.clone()
+    }
+
+    pub fn members(&self) -> Vec<GossipMember> {
+        self.state.lock().unwrap().members.values().cloned().collect()
+    }
+
+    /// Stops answering and probing, other members will eventually declare this one dead.
+    pub fn shutdown(&self) {
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+    }
+
+    async fn receive_loop(self: Arc<Self>) {
+        let mut buffer = vec![0u8; constants::GOSSIP_MAX_MESSAGE_SIZE];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(err) => {
+                    log::warn!("Failed to receive gossip message: {}", err);
+                    continue;
+                }
+            };
+
+            match self.open(&buffer[..len]) {
+                Ok(message) => self.handle(message, from).await,
+                Err(reason) => log::warn!("Ignoring gossip message from {}: {}", from, reason)
+            }
+        }
+    }
+
+    async fn handle(&self, message: GossipMessage, from_address: SocketAddr) {
+        match message {
+            GossipMessage::Ping { seq, from, updates } => {
+                let direct = is_sent_by(&from, from_address).await;
+                let is_new = self.apply_all(from, updates, direct);
+                let reply = {
+                    let mut state = self.state.lock().unwrap();
+                    // A member we haven't seen before gets the full membership to bootstrap from
+                    let updates = if is_new { full_membership(&state) } else { piggyback(&mut state) };
+                    GossipMessage::Ack { seq, from: state.local.clone(), updates }
+                };
+                self.send(from_address, &reply).await;
+            }
+            GossipMessage::PingReq { seq, from, target, updates } => {
+                let direct = is_sent_by(&from, from_address).await;
+                self.apply_all(from, updates, direct);
+                let (ping_seq, ping) = {
+                    let mut state = self.state.lock().unwrap();
+                    let ping_seq = next_seq(&mut state);
+                    state.pending.insert(ping_seq, (PendingAck::Forward { requester: from_address, seq }, Instant::now()));
+                    let updates = piggyback(&mut state);
+                    (ping_seq, GossipMessage::Ping { seq: ping_seq, from: state.local.clone(), updates })
+                };
+                log::trace!("Probing {} on behalf of {} ({})", target, from_address, ping_seq);
+                self.send_to(&target, &ping).await;
+            }
+            GossipMessage::Ack { seq, from, updates } => {
+                // Acks forwarded by a helper come from the helper's address, they don't verify the member
+                let direct = is_sent_by(&from, from_address).await;
+                self.apply_all(from.clone(), updates, direct);
+                let pending = self.state.lock().unwrap().pending.remove(&seq);
+                match pending {
+                    Some((PendingAck::Local(sender), _)) => { let _ = sender.send(()); }
+                    Some((PendingAck::Forward { requester, seq }, _)) => {
+                        let ack = {
+                            let mut state = self.state.lock().unwrap();
+                            GossipMessage::Ack { seq, from, updates: piggyback(&mut state) }
+                        };
+                        self.send(requester, &ack).await;
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    async fn protocol_loop(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(self.options.protocol_period);
+        loop {
+            interval.tick().await;
+            self.expire();
+
+            match self.next_probe_target() {
+                Some(target) => self.probe(target).await,
+                None => self.join_seeds().await
+            }
+        }
+    }
+
+    async fn join_seeds(&self) {
+        let local_address = self.state.lock().unwrap().local.address.clone();
+        for seed in self.options.seeds.iter().filter(|seed| **seed != local_address) {
+            let ping = {
+                let mut state = self.state.lock().unwrap();
+                let seq = next_seq(&mut state);
+                GossipMessage::Ping { seq, from: state.local.clone(), updates: Vec::new() }
+            };
+            self.send_to(seed, &ping).await;
+        }
+    }
+
+    async fn probe(&self, target: GossipMember) {
+        let (sender, mut receiver) = oneshot::channel();
+        let (seq, ping) = {
+            let mut state = self.state.lock().unwrap();
+            let seq = next_seq(&mut state);
+            state.pending.insert(seq, (PendingAck::Local(sender), Instant::now()));
+            let updates = piggyback(&mut state);
+            (seq, GossipMessage::Ping { seq, from: state.local.clone(), updates })
+        };
+
+        let ack_timeout = self.options.protocol_period / 3;
+        self.send_to(&target.address, &ping).await;
+        if tokio::time::timeout(ack_timeout, &mut receiver).await.is_ok() {
+            return;
+        }
+
+        let (helpers, ping_req) = {
+            let mut state = self.state.lock().unwrap();
+            let mut helpers: Vec<String> = state.members.values()
+                .filter(|member| member.state == MemberState::Alive && member.address != target.address)
+                .map(|member| member.address.clone())
+                .collect();
+            helpers.shuffle(&mut rand::thread_rng());
+            helpers.truncate(self.options.indirect_checks);
+
+            let updates = piggyback(&mut state);
+            (helpers, GossipMessage::PingReq { seq, from: state.local.clone(), target: target.address.clone(), updates })
+        };
+
+        for helper in &helpers {
+            self.send_to(helper, &ping_req).await;
+        }
+
+        if tokio::time::timeout(ack_timeout * 2, &mut receiver).await.is_ok() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.pending.remove(&seq);
+        if let Some(current) = state.members.get(&target.address).cloned() {
+            if current.state == MemberState::Alive && current.incarnation == target.incarnation {
+                log::warn!("Gossip member {} didn't answer, suspecting it", current.address);
+                let suspect = GossipMember { state: MemberState::Suspect, ..current };
+                self.apply(&mut state, suspect);
+            }
+        }
+    }
+
+    /// Declares suspects dead once their timeout passed and drops stale pending acks.
+    fn expire(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        let expired: Vec<String> = state.suspected_at.iter()
+            .filter(|(_, since)| since.elapsed() >= self.options.suspect_timeout)
+            .map(|(address, _)| address.clone())
+            .collect();
+        for address in expired {
+            if let Some(current) = state.members.get(&address).cloned() {
+                log::warn!("Gossip member {} didn't refute the suspicion, declaring it dead", address);
+                let dead = GossipMember { state: MemberState::Dead, ..current };
+                self.apply(&mut state, dead);
+            }
+        }
+
+        let stale_after = self.options.protocol_period * 2;
+        state.pending.retain(|_, (_, created)| created.elapsed() < stale_after);
+    }
+
+    fn next_probe_target(&self) -> Option<GossipMember> {
+        let mut state = self.state.lock().unwrap();
+
+        // Probe members in a shuffled round-robin order, so every member is probed within a bounded time
+        loop {
+            if state.probe_order.is_empty() {
+                let mut order: Vec<String> = state.members.values()
+                    .filter(|member| member.state != MemberState::Dead)
+                    .map(|member| member.address.clone())
+                    .collect();
+                if order.is_empty() {
+                    return None;
+                }
+                order.shuffle(&mut rand::thread_rng());
+                state.probe_order = order;
+            }
+
+            let address = state.probe_order.pop()?;
+            if let Some(member) = state.members.get(&address) {
+                if member.state != MemberState::Dead {
+                    return Some(member.clone());
+                }
+            }
+        }
+    }
+
+    /// Applies the sender and the piggybacked updates, returns whether the sender was unknown.
+    /// `direct` tells that the sender sent the message itself, from the address it advertises.
+    fn apply_all(&self, from: GossipMember, updates: Vec<GossipMember>, direct: bool) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let is_new = !state.members.contains_key(&from.address);
+
+        let address = from.address.clone();
+        self.apply(&mut state, from);
+        if direct && address != state.local.address && state.verified.insert(address.clone()) {
+            log::info!("Gossip member {} is verified", address);
+            if let Some(member) = state.members.get(&address) {
+                let _ = self.events.send(member.clone());
+            }
+        }
+        for update in updates {
+            self.apply(&mut state, update);
+        }
+
+        is_new
+    }
+
+    fn apply(&self, state: &mut State, update: GossipMember) {
+        if update.address == state.local.address {
+            // Someone suspects or declared this member dead, refute with a higher incarnation
+            if update.state != MemberState::Alive && update.incarnation >= state.local.incarnation {
+                state.local.incarnation = update.incarnation + 1;
+                log::info!("Refuting suspicion with incarnation {}", state.local.incarnation);
+                let local = state.local.clone();
+                enqueue(state, local);
+            }
+            return;
+        }
+
+        let accepted = match state.members.get(&update.address) {
+            None => update.state != MemberState::Dead,
+            Some(current) => match update.state {
+                MemberState::Alive => update.incarnation > current.incarnation,
+                MemberState::Suspect => update.incarnation > current.incarnation
+                    || (update.incarnation == current.incarnation && current.state == MemberState::Alive),
+                MemberState::Dead => update.incarnation > current.incarnation
+                    || (update.incarnation == current.incarnation && current.state != MemberState::Dead)
+            }
+        };
+
+        if !accepted {
+            return;
+        }
+
+        if update.state == MemberState::Suspect {
+            state.suspected_at.entry(update.address.clone()).or_insert_with(Instant::now);
+        } else {
+            state.suspected_at.remove(&update.address);
+        }
+
+        log::debug!("Gossip member {} is {:?} (incarnation {})", update.address, update.state, update.incarnation);
+        state.members.insert(update.address.clone(), update.clone());
+        enqueue(state, update.clone());
+        if state.verified.contains(&update.address) {
+            let _ = self.events.send(update);
+        }
+    }
+
+    async fn send(&self, address: SocketAddr, message: &GossipMessage) {
+        match serde_json::to_vec(&Envelope { sent_at: now_millis(), message }) {
+            Ok(payload) => {
+                let mut datagram = self.signature(&payload).finalize().into_bytes().to_vec();
+                datagram.extend_from_slice(&payload);
+                if let Err(err) = self.socket.send_to(&datagram, address).await {
+                    log::debug!("Failed to send gossip message to {}: {}", address, err);
+                }
+            }
+            Err(err) => log::error!("Failed to serialize gossip message: {}", err)
+        }
+    }
+
+    /// Checks the signature and the age of a datagram and returns its message.
+    fn open(&self, datagram: &[u8]) -> Result<GossipMessage, String> {
+        if datagram.len() < SIGNATURE_SIZE {
+            return Err(String::from("message is not signed"));
+        }
+
+        let (signature, payload) = datagram.split_at(SIGNATURE_SIZE);
+        // Compared in constant time
+        self.signature(payload).verify_slice(signature)
+            .map_err(|_| String::from("invalid signature"))?;
+        let envelope: Envelope<GossipMessage> = serde_json::from_slice(payload)
+            .map_err(|err| err.to_string())?;
+        let age = now_millis().abs_diff(envelope.sent_at);
+        if age > constants::GOSSIP_MAX_MESSAGE_AGE {
+            return Err(format!("message is {}ms old", age));
+        }
+
+        Ok(envelope.message)
+    }
+
+    fn signature(&self, payload: &[u8]) -> Hmac<Sha256> {
+        let mut signature = Hmac::<Sha256>::new_from_slice(self.options.secret.as_bytes())
+            .expect("HMAC takes keys of any size");
+        signature.update(payload);
+        signature
+    }
+
+    async fn send_to(&self, address: &str, message: &GossipMessage) {
+        match tokio::net::lookup_host(address).await.map(|mut addresses| addresses.next()) {
+            Ok(Some(address)) => self.send(address, message).await,
+            _ => log::debug!("Failed to resolve gossip address {}", address)
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Whether the message came from the address the member advertises, not relayed by another member.
+async fn is_sent_by(member: &GossipMember, from_address: SocketAddr) -> bool {
+    match tokio::net::lookup_host(&member.address).await {
+        Ok(mut addresses) => addresses.any(|address| address == from_address),
+        Err(_) => false
+    }
+}
+
+fn next_seq(state: &mut State) -> u64 {
+    state.next_seq += 1;
+    state.next_seq
+}
+
+fn enqueue(state: &mut State, update: GossipMember) {
+    // Enough retransmissions to reach every member with high probability
+    let transmissions = constants::GOSSIP_RETRANSMIT_MULTIPLIER * (usize::BITS - (state.members.len() + 1).leading_zeros());
+    state.updates.retain(|(pending, _)| pending.address != update.address);
+    state.updates.push((update, transmissions));
+}
+
+fn piggyback(state: &mut State) -> Vec<GossipMember> {
+    // Least sent updates first
+    state.updates.sort_by_key(|update| std::cmp::Reverse(update.1));
+
+    let mut updates = Vec::new();
+    for (update, transmissions) in state.updates.iter_mut().take(constants::GOSSIP_MAX_PIGGYBACK) {
+        updates.push(update.clone());
+        *transmissions -= 1;
+    }
+    state.updates.retain(|(_, transmissions)| *transmissions > 0);
+
+    updates
+}
+
+fn full_membership(state: &State) -> Vec<GossipMember> {
+    let mut members: Vec<GossipMember> = state.members.values().cloned().collect();
+    members.push(state.local.clone());
+    members
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{services::hashservice, models::{linkinfo::LinkInfo, queryparams::QueryParams}, configuration, constants};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use super::cluster::{Cluster, Node, NodeState};
+use super::gossip::{GossipAgent, GossipMember, GossipOptions, MemberState};
+use super::hashserviceerror::HashServiceError;
+use super::hintstore::Hint;
+
+/// Leaderless mode: every instance keeps a full replica in its local storage,
+/// serves reads from it and replicates writes to the members found through gossip.
+pub struct GossipHashService {
+    gossip_config: configuration::settings::Gossip,
+    http_url: String,
+    storage: Box<dyn hashservice::HashService>,
+    cluster: Arc<Cluster>,
+    agent: Option<Arc<GossipAgent>>
+}
+
+impl GossipHashService {
+    pub fn new(config: &configuration::settings::Gossip, apiserver: &configuration::settings::ApiServer, storage: Box<dyn hashservice::HashService>) -> Self {
+        let http_url = match &config.advertise_url {
+            Some(advertise_url) => advertise_url.clone(),
+            None => format!("http://{}", apiserver.application_url)
+        };
+
+        GossipHashService {
+            gossip_config: config.clone(),
+            http_url,
+            storage,
+            cluster: Arc::new(Cluster::new(&config.cluster_config())),
+            agent: None
+        }
+    }
+
+    fn start_background_jobs(&self, local: Node, events: mpsc::UnboundedReceiver<GossipMember>) {
+        let cluster = self.cluster.clone();
+        let event_local = local.clone();
+        tokio::spawn(track_members(cluster, event_local, events));
+
+        let replay_interval = self.gossip_config.hint_replay_interval
+            .unwrap_or(constants::DEFAULT_HINT_REPLAY_INTERVAL);
+        let cluster = self.cluster.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(u64::max(replay_interval, 1)));
+            loop {
+                interval.tick().await;
+                cluster.replay_hints().await;
+            }
+        });
+
+        let anti_entropy_interval = self.gossip_config.anti_entropy_interval
+            .unwrap_or(constants::DEFAULT_ANTI_ENTROPY_INTERVAL);
+        if anti_entropy_interval == 0 {
+            return;
+        }
+
+        let cluster = self.cluster.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(anti_entropy_interval));
+            // The first tick completes immediately, new members are synced when they are seen
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let mut nodes = vec![local.clone()];
+                nodes.extend(cluster.read_nodes("").await);
+                cluster.run_anti_entropy(&nodes).await;
+            }
+        });
+    }
+
+    async fn replicate(&self, hint: Hint) {
+        let (nodes, mut failed_nodes) = self.cluster.write_nodes().await;
+
+        for node in nodes {
+            let result = match &hint {
+                Hint::Upsert(link) => self.cluster.node_client().upsert(&node, link).await,
+                Hint::Delete(key) => self.cluster.node_client().delete(&node, key).await.map(|_| ())
+            };
+
+            if let Err(err) = result {
+                log::warn!("Failed to replicate '{}' to node {}: {}", hint.key(), node, err);
+                failed_nodes.push(node);
+            }
+        }
+
+        self.cluster.store_hints(&failed_nodes, hint).await;
+    }
+}
+
+/// Mirrors the gossip membership into the cluster. Members that are seen for the
+/// first time are synced with the local replica right away.
+async fn track_members(cluster: Arc<Cluster>, local: Node, mut events: mpsc::UnboundedReceiver<GossipMember>) {
+    while let Some(member) = events.recv().await {
+        let node = match Node::parse(&member.http_url) {
+            Ok(node) => node,
+            Err(err) => {
+                log::warn!("Ignoring gossip member {} with invalid url: {}", member.address, err);
+                continue;
+            }
+        };
+
+        let state = match member.state {
+            // Suspects keep receiving writes, failed ones are hinted anyway
+            MemberState::Alive | MemberState::Suspect => NodeState::Up,
+            MemberState::Dead => NodeState::Down
+        };
+
+        log::info!("Gossip member {} ({}) is {:?}", member.address, node, member.state);
+        let added = cluster.set_member(node.clone(), state).await;
+
+        if added && state == NodeState::Up {
+            let cluster = cluster.clone();
+            let nodes = vec![local.clone(), node];
+            tokio::spawn(async move {
+                cluster.run_anti_entropy(&nodes).await;
+            });
+        }
+    }
+}
+
+#[async_trait]
+impl hashservice::HashService for GossipHashService {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        self.storage.init().await?;
+
+        let local = Node::parse(&self.http_url)?;
+        let options = GossipOptions {
+            bind_address: self.gossip_config.bind_address.clone(),
+            advertise_address: self.gossip_config.advertise_address.clone(),
+            http_url: self.http_url.clone(),
+            seeds: self.gossip_config.seeds.clone(),
+            protocol_period: Duration::from_millis(self.gossip_config.protocol_period.unwrap_or(constants::DEFAULT_GOSSIP_PROTOCOL_PERIOD)),
+            suspect_timeout: Duration::from_millis(self.gossip_config.suspect_timeout.unwrap_or(constants::DEFAULT_GOSSIP_SUSPECT_TIMEOUT)),
+            indirect_checks: self.gossip_config.indirect_checks.unwrap_or(constants::DEFAULT_GOSSIP_INDIRECT_CHECKS),
+            secret: self.gossip_config.secret.clone().unwrap_or_default()
+        };
+
+        let (agent, events) = GossipAgent::start(options).await?;
+        self.agent = Some(agent);
+        self.start_background_jobs(local, events);
+
+        Ok(())
+    }
+
+    fn cluster(&self) -> Option<Arc<Cluster>> {
+        Some(self.cluster.clone())
+    }
+
+    async fn get_links(&mut self, query_info: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError> {
+        self.storage.get_links(query_info).await
+    }
+
+    async fn count_links(&mut self) -> Result<usize, HashServiceError> {
+        self.storage.count_links().await
+    }
+
+    async fn get_links_after(&mut self, after: Option<&str>, owner_id: Option<&str>, top: usize) -> Result<Vec<LinkInfo>, HashServiceError> {
+        self.storage.get_links_after(after, owner_id, top).await
+    }
+
+    async fn get_links_by_owner(&mut self, owner_id: &str, query_info: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError> {
+        self.storage.get_links_by_owner(owner_id, query_info).await
+    }
+
+    async fn insert(&mut self, value: &str) -> Result<LinkInfo, HashServiceError> {
+        let link = self.storage.insert(value).await?;
+        self.replicate(Hint::Upsert(Box::new(link.clone()))).await;
+
+        Ok(link)
+    }
+
+    async fn update(&mut self, key: &str, value: &LinkInfo) -> Result<bool, HashServiceError> {
+        if !self.storage.update(key, value).await? {
+            return Ok(false);
+        }
+
+        let mut value = value.clone();
+        value.short_url = key.to_string();
+        self.replicate(Hint::Upsert(Box::new(value))).await;
+
+        Ok(true)
+    }
+
+    async fn upsert(&mut self, value: &LinkInfo) -> Result<(), HashServiceError> {
+        self.storage.upsert(value).await?;
+        self.replicate(Hint::Upsert(Box::new(value.clone()))).await;
+
+        Ok(())
+    }
+
+    async fn find(&mut self, key: &str) -> Result<Option<LinkInfo>, HashServiceError> {
+        self.storage.find(key).await
+    }
+
+    async fn find_many(&mut self, keys: &[String]) -> Result<HashMap<String, LinkInfo>, HashServiceError> {
+        self.storage.find_many(keys).await
+    }
+
+    async fn delete(&mut self, key: &str) -> Result<bool, HashServiceError> {
+        let deleted = self.storage.delete(key).await?;
+        self.replicate(Hint::Delete(key.to_string())).await;
+
+        Ok(deleted)
+    }
+
+    /// Writes from other instances were already replicated by them, only apply them locally.
+    async fn apply_replica(&mut self, hint: &Hint) -> Result<bool, HashServiceError> {
+        self.storage.apply_replica(hint).await
+    }
+}
+
+impl Drop for GossipHashService {
+    fn drop(&mut self) {
+        if let Some(agent) = &self.agent {
+            agent.shutdown();
+        }
+    }
+}
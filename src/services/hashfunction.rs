@@ -10,4 +10,33 @@ pub fn hash(value_to_hash: &str) -> String {
 
     // Format the u32 as an 8-digit string
     return format!("{:X}", hash_value)
-}
\ No newline at end of file
+}
+
+/// Short url of a long url on a custom domain, a url gets a short url of its own on every domain.
+pub fn hash_on_domain(value_to_hash: &str, domain: Option<&str>) -> String {
+    match domain {
+        Some(domain) => hash(&format!("{}/{}", domain, value_to_hash)),
+        None => hash(value_to_hash)
+    }
+}
+
+/// Short url of a long url shortened by an authenticated caller. Every owner gets a link of its
+/// own, so shortening a url never hands over the link of another caller or an anonymous one.
+pub fn hash_for_owner(value_to_hash: &str, domain: Option<&str>, owner_id: Option<&str>) -> String {
+    let owner_id = match owner_id {
+        Some(owner_id) => owner_id,
+        None => return hash_on_domain(value_to_hash, domain)
+    };
+
+    // Urls don't contain line breaks, the owner can't be confused with a part of the url
+    match domain {
+        Some(domain) => hash(&format!("{}\n{}/{}", owner_id, domain, value_to_hash)),
+        None => hash(&format!("{}\n{}", owner_id, value_to_hash))
+    }
+}
+
+/// Whether a code has the form of a hash: 1 to 8 uppercase hex digits. Aliases can't take
+/// these codes, or they could squat the short urls of urls shortened later.
+pub fn is_hash_code(code: &str) -> bool {
+    (1..=8).contains(&code.len()) && code.chars().all(|c| c.is_ascii_digit() || ('A'..='F').contains(&c))
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fs;
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::configuration::settings::Settings;
+use crate::name_of;
+
+use super::hashserviceerror::{build_invalid_configuration_error, HashServiceError};
+use super::urlsafetychecker::{UrlSafetyChecker, Verdict};
+
+/// Length of a full SHA-256 hash in bytes
+const HASH_LENGTH: usize = 32;
+const DEFAULT_THREAT: &str = "UNSAFE";
+
+/// Local list of SHA-256 hashes of unsafe url expressions, as distributed by Safe Browsing.
+/// A url is flagged when the hash of one of its host suffix and path prefix expressions is
+/// listed. Only full hashes are listed: a short prefix also matches the hashes of many safe
+/// expressions, Safe Browsing needs a full-hash lookup to confirm it.
+pub struct HashPrefixSafetyChecker {
+    /// Threat of each full hash
+    hashes: HashMap<Vec<u8>, String>,
+}
+
+impl HashPrefixSafetyChecker {
+    /// Hashes of a list file: one hex encoded SHA-256 hash of 32 bytes per line, optionally
+    /// followed by its threat, e.g. "5d4c...3b2a MALWARE". Lines starting with '#' are skipped.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut checker = HashPrefixSafetyChecker { hashes: HashMap::new() };
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let hash = parts.next().and_then(|hash| hex::decode(hash).ok())
+                .filter(|hash| hash.len() == HASH_LENGTH)
+                .ok_or_else(|| format!("line {} is not a hex SHA-256 hash of {} bytes, prefixes need a full-hash lookup", number + 1, HASH_LENGTH))?;
+            let threat = parts.next().unwrap_or(DEFAULT_THREAT).to_string();
+
+            checker.hashes.insert(hash, threat);
+        }
+
+        Ok(checker)
+    }
+
+    pub fn from_file(settings: &Settings, path: &str) -> Result<Self, HashServiceError> {
+        let invalid = |reason: String| build_invalid_configuration_error(settings.mode.to_string().as_str(), name_of!(url_safety in Settings), &reason);
+        let text = fs::read_to_string(path)
+            .map_err(|err| invalid(format!("can't read '{}': {}", path, err)))?;
+
+        HashPrefixSafetyChecker::parse(&text).map_err(|reason| invalid(format!("'{}' {}", path, reason)))
+    }
+
+    fn threat_of(&self, expression: &str) -> Option<&String> {
+        let hash = Sha256::digest(expression.as_bytes());
+        self.hashes.get(hash.as_slice())
+    }
+}
+
+#[async_trait]
+impl UrlSafetyChecker for HashPrefixSafetyChecker {
+    async fn check(&self, long_url: &str) -> Result<Verdict, HashServiceError> {
+        let threat = Url::parse(long_url).ok()
+            .and_then(|url| expressions(&url).iter().find_map(|expression| self.threat_of(expression).cloned()));
+
+        Ok(match threat {
+            Some(threat) => Verdict::Flagged { threat },
+            None => Verdict::Safe
+        })
+    }
+}
+
+/// Host suffix and path prefix combinations of a url, e.g. "b.c/1/" of "http://a.b.c/1/2.html?x".
+/// The host itself and up to four suffixes of its last five labels, the path with and without
+/// the query, and the root with up to three directories below it.
+pub fn expressions(url: &Url) -> Vec<String> {
+    let host = match url.host_str() {
+        Some(host) => host,
+        None => return Vec::new()
+    };
+
+    let mut hosts = vec![host.to_string()];
+    // IP addresses are only looked up as they are
+    if url.domain().is_some() {
+        let labels: Vec<&str> = host.split('.').collect();
+        let first = labels.len().saturating_sub(5).max(1);
+        for start in first..labels.len().saturating_sub(1) {
+            hosts.push(labels[start..].join("."));
+        }
+    }
+
+    let path = url.path();
+    let mut paths = Vec::new();
+    if let Some(query) = url.query() {
+        paths.push(format!("{}?{}", path, query));
+    }
+    paths.push(path.to_string());
+    let mut directory = String::from("/");
+    paths.push(directory.clone());
+    let components: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    for component in components.iter().take(components.len() - 1).take(3) {
+        directory.push_str(component);
+        directory.push('/');
+        paths.push(directory.clone());
+    }
+
+    let mut expressions = Vec::new();
+    for host in &hosts {
+        for path in &paths {
+            let expression = format!("{}{}", host, path);
+            if !expressions.contains(&expression) {
+                expressions.push(expression);
+            }
+        }
+    }
+    expressions
+}
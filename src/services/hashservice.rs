@@ -1,20 +1,195 @@
-use crate::models::{linkinfo::LinkInfo, queryparams::QueryParams};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+use crate::models::{builders::build_link_info, linkinfo::{self, LinkInfo, LinkStatus}, queryparams::QueryParams};
 
 use async_trait::async_trait;
 
+use super::cluster::Cluster;
+use super::destinationrules;
+use super::hashfunction;
 use super::hashserviceerror::HashServiceError;
+use super::hintstore::Hint;
+use super::linkmerge::LinkStream;
 
 #[async_trait]
 pub trait HashService: Send + Sync {
     async fn init(&mut self) -> Result<(), HashServiceError>;
 
+    /// Cluster membership, only available when coordinating requests between nodes.
+    fn cluster(&self) -> Option<Arc<Cluster>> {
+        None
+    }
+
     async fn get_links(&mut self, query_info: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError>;
 
+    /// Links in short url order as a stream that is read while the response is sent. Only
+    /// coordinators, which merge the links of their nodes, have one. The links of the other
+    /// backends are read a page at a time with `get_links_after`.
+    async fn stream_links(&mut self, _query_info: Option<QueryParams>) -> Result<Option<LinkStream>, HashServiceError> {
+        Ok(None)
+    }
+
+    /// Links of the owner ordered by short url. Backends that keep an index on the
+    /// owner override it, the others filter every link.
+    async fn get_links_by_owner(&mut self, owner_id: &str, query_info: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError> {
+        let query_params = query_info.unwrap_or_default();
+        let links = self.get_links(None).await?
+            .into_iter()
+            .filter(|link| link.owner_id.as_deref() == Some(owner_id));
+
+        Ok(page_of(links, &query_params))
+    }
+
+    /// Next `top` links in short url order after the short url `after`, of the owner if one is
+    /// given. Unlike skipping, the position of a page doesn't shift when links are added or
+    /// deleted in between. Backends override it with a range query, the others filter every link.
+    async fn get_links_after(&mut self, after: Option<&str>, owner_id: Option<&str>, top: usize) -> Result<Vec<LinkInfo>, HashServiceError> {
+        let mut links = match owner_id {
+            Some(owner_id) => self.get_links_by_owner(owner_id, None).await?,
+            None => self.get_links(None).await?
+        };
+        links.retain(|link| after.is_none_or(|after| link.short_url.as_str() > after));
+        links.sort_by(|a, b| a.short_url.cmp(&b.short_url));
+        links.truncate(top);
+
+        Ok(links)
+    }
+
+    async fn count_links(&mut self) -> Result<usize, HashServiceError> {
+        Ok(self.get_links(None).await?.len())
+    }
+
+    /// Disables the active links pointing to the domain or one of its subdomains, e.g. after
+    /// the domain was blocked. Returns how many links were disabled.
+    async fn disable_links_to(&mut self, domain: &str, reason: &str) -> Result<usize, HashServiceError> {
+        let mut disabled = 0;
+        for mut link in self.get_links(None).await? {
+            if link.status != LinkStatus::Active || !destinationrules::is_on_domain(&link.long_url, domain) {
+                continue;
+            }
+
+            link.status = LinkStatus::Disabled;
+            link.status_reason = Some(reason.to_string());
+            link.touch(Utc::now());
+            self.upsert(&link).await?;
+            disabled += 1;
+        }
+
+        Ok(disabled)
+    }
+
+    /// Deletes the links that were moved to the trash before `deleted_before` for good.
+    /// Returns how many links were purged. Backends that can query by status override it,
+    /// the others read all their links.
+    async fn purge_deleted(&mut self, deleted_before: DateTime<Utc>) -> Result<usize, HashServiceError> {
+        let mut purged = 0;
+        for link in self.get_links(None).await? {
+            if is_purgeable(&link, deleted_before) && self.delete(&link.short_url).await? {
+                purged += 1;
+            }
+        }
+
+        Ok(purged)
+    }
+
+    /// Inserts the link of the url, or returns the stored one when the url was shortened before.
     async fn insert(&mut self, value: &str) -> Result<LinkInfo, HashServiceError>;
 
+    /// Inserts the link of the url on a custom domain, or returns the stored one when
+    /// the url was shortened on the domain before.
+    async fn insert_on_domain(&mut self, value: &str, domain: &str) -> Result<LinkInfo, HashServiceError> {
+        let short_url = linkinfo::link_key(Some(domain), &hashfunction::hash_on_domain(value, Some(domain)));
+        if let Some(existing) = self.find(&short_url).await? {
+            return stored_link_of(existing, value);
+        }
+
+        let mut new_link = build_link_info(short_url, String::from(value));
+        new_link.domain = Some(domain.to_string());
+        self.upsert(&new_link).await?;
+
+        Ok(new_link)
+    }
+
+    /// Inserts the link of the url owned by the caller, or returns the caller's stored one.
+    /// Links of other owners and anonymous links of the url are left alone.
+    async fn insert_owned(&mut self, value: &str, domain: Option<&str>, owner_id: &str) -> Result<LinkInfo, HashServiceError> {
+        let short_url = linkinfo::link_key(domain, &hashfunction::hash_for_owner(value, domain, Some(owner_id)));
+        if let Some(existing) = self.find(&short_url).await? {
+            return stored_link_of(existing, value);
+        }
+
+        let mut new_link = build_link_info(short_url, String::from(value));
+        new_link.domain = domain.map(String::from);
+        new_link.owner_id = Some(owner_id.to_string());
+        self.upsert(&new_link).await?;
+
+        Ok(new_link)
+    }
+
+    /// Stores new links as they are, e.g. the links of a bulk import. Backends override it
+    /// with a batched write, the others store the links one by one.
+    async fn insert_many(&mut self, values: &[LinkInfo]) -> Result<(), HashServiceError> {
+        for value in values {
+            self.upsert(value).await?;
+        }
+
+        Ok(())
+    }
+
     async fn update(&mut self, key: &str, value: &LinkInfo) -> Result<bool, HashServiceError>;
 
+    /// Inserts the link as is or replaces the stored one with the same short url.
+    async fn upsert(&mut self, value: &LinkInfo) -> Result<(), HashServiceError>;
+
     async fn find(&mut self, key: &str) -> Result<Option<LinkInfo>, HashServiceError>;
 
+    /// Stored links of the short urls by short url, short urls without a link are left out.
+    /// Backends override it with one lookup for all of them, the others find the links one by one.
+    async fn find_many(&mut self, keys: &[String]) -> Result<HashMap<String, LinkInfo>, HashServiceError> {
+        let mut links = HashMap::new();
+        for key in keys {
+            if let Some(link) = self.find(key).await? {
+                links.insert(key.clone(), link);
+            }
+        }
+
+        Ok(links)
+    }
+
     async fn delete(&mut self, key: &str) -> Result<bool, HashServiceError>;
-}
\ No newline at end of file
+
+    /// Applies a write another instance already coordinated. Only services that
+    /// replicate writes themselves have to keep it from being replicated again.
+    async fn apply_replica(&mut self, hint: &Hint) -> Result<bool, HashServiceError> {
+        match hint {
+            Hint::Upsert(link) => self.upsert(link).await.map(|_| true),
+            Hint::Delete(key) => self.delete(key).await
+        }
+    }
+}
+
+/// Whether the link was moved to the trash before `deleted_before` and can be deleted for good.
+pub fn is_purgeable(link: &LinkInfo, deleted_before: DateTime<Utc>) -> bool {
+    link.status == LinkStatus::Deleted && link.deleted_at.is_some_and(|deleted_at| deleted_at < deleted_before)
+}
+
+/// Stored link of a url that was shortened before. A link of another url under the same
+/// short url is a collision of the hash, it's never returned as the link of the url.
+pub fn stored_link_of(existing: LinkInfo, value: &str) -> Result<LinkInfo, HashServiceError> {
+    if existing.long_url != value {
+        return Err(HashServiceError::ShortUrlTaken { short_url: existing.short_url });
+    }
+
+    Ok(existing)
+}
+
+/// Applies skip and top to links that are already ordered.
+pub fn page_of(links: impl IntoIterator<Item = LinkInfo>, query_params: &QueryParams) -> Vec<LinkInfo> {
+    links.into_iter()
+        .skip(query_params.skip.unwrap_or(0))
+        .take(query_params.top.unwrap_or(usize::MAX))
+        .collect()
+}
@@ -15,6 +15,37 @@ pub enum HashServiceError {
         mode: String,
         configuration: String,
     },
+    #[error("none of the nodes could be reached")]
+    NoReachableNodes,
+    #[error("node '{node}' responded with status {status}")]
+    NodeError {
+        node: String,
+        status: u16,
+    },
+    #[error("node '{node}' is not a member of the cluster")]
+    UnknownNode {
+        node: String,
+    },
+    #[error("node '{node}' is already a member of the cluster")]
+    DuplicateNode {
+        node: String,
+    },
+    #[error("node '{node}' has to be drained before it can be removed")]
+    NodeNotDrained {
+        node: String,
+    },
+    #[error("Invalid configuration '{configuration}' in '{mode}' mode: {reason}")]
+    InvalidConfiguration {
+        mode: String,
+        configuration: String,
+        reason: String,
+    },
+    #[error("short url '{short_url}' is taken by another url")]
+    ShortUrlTaken {
+        short_url: String,
+    },
+    #[error("invalid data")]
+    SerializationError(#[from] serde_json::Error),
     #[error("Internal error")]
     IOError(#[from] io::Error),
     #[error("unknown data store error")]
@@ -25,4 +56,8 @@ pub enum HashServiceError {
 
 pub fn build_configuration_error(mode: &str, configuration: &str) -> HashServiceError {
     HashServiceError::MissingConfiguration{ mode: mode.to_string(), configuration: configuration.to_string() }
+}
+
+pub fn build_invalid_configuration_error(mode: &str, configuration: &str, reason: &str) -> HashServiceError {
+    HashServiceError::InvalidConfiguration{ mode: mode.to_string(), configuration: configuration.to_string(), reason: reason.to_string() }
 }
\ No newline at end of file
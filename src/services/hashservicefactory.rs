@@ -4,23 +4,45 @@ use crate::name_of;
 
 use super::coordinatorhashservice::CoordinatorHashService;
 use super::firestorehashservice::FirestoreHashService;
+use super::gossiphashservice::GossipHashService;
 use super::hashservice::{self};
-use super::hashserviceerror::build_configuration_error;
+use super::hashserviceerror::{build_configuration_error, build_invalid_configuration_error};
 use super::hashserviceerror::HashServiceError;
 use super::inmemoryhashservice::InMemoryHashService;
 use super::mongohashservice::MongoHashService;
 use super::redishashservice::RedisHashService;
 
 pub async fn create_hash_service(settings: &Settings) -> Result<Box<dyn hashservice::HashService>, HashServiceError> {
-    let mut hash_service: Box<dyn hashservice::HashService> = match &settings.mode {
+    let mut hash_service = build_hash_service(&settings.mode, settings, None)?;
+
+    hash_service.init().await?;
+    Ok(hash_service)
+}
+
+/// Creates the service of the links of a tenant, kept apart from the shared links in the same storage.
+pub async fn create_tenant_hash_service(settings: &Settings, tenant: &str) -> Result<Box<dyn hashservice::HashService>, HashServiceError> {
+    let mut hash_service = build_hash_service(&settings.mode, settings, Some(tenant))?;
+
+    hash_service.init().await?;
+    Ok(hash_service)
+}
+
+fn build_hash_service(mode: &Mode, settings: &Settings, tenant: Option<&str>) -> Result<Box<dyn hashservice::HashService>, HashServiceError> {
+    // Requests are forwarded to other nodes, which don't know the tenant
+    if tenant.is_some() && matches!(mode, Mode::Coordinator | Mode::Gossip) {
+        return Err(build_invalid_configuration_error(mode.to_string().as_str(), name_of!(tenants in Settings), "is only supported by the storage modes"));
+    }
+
+    let hash_service: Box<dyn hashservice::HashService> = match mode {
         Mode::InMemory => {
             Box::new(InMemoryHashService::new())
         },
         Mode::Mongo => {
             match &settings.mongo_config {
                 None => return Err(build_configuration_error(Mode::Mongo.to_string().as_str(), name_of!(mongo_config in Settings))),
-                Some(mongo_config) => {
-                    Box::new(MongoHashService::new(mongo_config))
+                Some(mongo_config) => match tenant {
+                    Some(tenant) => Box::new(MongoHashService::for_tenant(mongo_config, tenant)),
+                    None => Box::new(MongoHashService::new(mongo_config))
                 }
             }
         },
@@ -35,21 +57,40 @@ pub async fn create_hash_service(settings: &Settings) -> Result<Box<dyn hashserv
         Mode::Redis => {
             match &settings.redis_config {
                 None => return Err(build_configuration_error(Mode::Redis.to_string().as_str(), name_of!(redis_config in Settings))),
-                Some(redis_config) => {
-                    Box::new(RedisHashService::new(redis_config))
+                Some(redis_config) => match tenant {
+                    Some(tenant) => Box::new(RedisHashService::for_tenant(redis_config, tenant)),
+                    None => Box::new(RedisHashService::new(redis_config))
                 }
             }
         },
         Mode::Firestore => {
             match &settings.firestore_config {
                 None => return Err(build_configuration_error(Mode::Firestore.to_string().as_str(), name_of!(firestore_config in Settings))),
-                Some(firestore_config) => {
-                    Box::new(FirestoreHashService::new(firestore_config))
+                Some(firestore_config) => match tenant {
+                    Some(tenant) => Box::new(FirestoreHashService::for_tenant(firestore_config, tenant)),
+                    None => Box::new(FirestoreHashService::new(firestore_config))
+                }
+            }
+        },
+        Mode::Gossip => {
+            match &settings.gossip {
+                None => return Err(build_configuration_error(Mode::Gossip.to_string().as_str(), name_of!(gossip in Settings))),
+                Some(gossip_config) => {
+                    let storage_mode = gossip_config.storage.clone().unwrap_or_default();
+                    if matches!(storage_mode, Mode::Coordinator | Mode::Gossip) {
+                        return Err(build_invalid_configuration_error(Mode::Gossip.to_string().as_str(), "storage", "has to be a storage mode"));
+                    }
+                    // Unsigned messages would let anyone on the network join the cluster and receive its writes
+                    if gossip_config.secret.as_deref().is_none_or(str::is_empty) {
+                        return Err(build_invalid_configuration_error(Mode::Gossip.to_string().as_str(), "secret", "is required to sign the gossip messages"));
+                    }
+
+                    let storage = build_hash_service(&storage_mode, settings, None)?;
+                    Box::new(GossipHashService::new(gossip_config, &settings.apiserver, storage))
                 }
             }
         }
     };
 
-    hash_service.init().await?;
     Ok(hash_service)
 }
\ No newline at end of file
@@ -0,0 +1,85 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::models::linkinfo::LinkInfo;
+
+/// Write that could not be delivered to a replica and has to be replayed once it is reachable again.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Hint {
+    Upsert(Box<LinkInfo>),
+    Delete(String),
+}
+
+impl Hint {
+    pub fn key(&self) -> &str {
+        match self {
+            Hint::Upsert(link) => &link.short_url,
+            Hint::Delete(key) => key,
+        }
+    }
+}
+
+/// Bounded per-node queues of pending hints.
+///
+/// Only the latest hint for a given key is kept, so a node that missed several
+/// updates of the same link only receives the final state on replay.
+pub struct HintStore {
+    max_hints_per_node: usize,
+    hints: HashMap<String, VecDeque<Hint>>,
+}
+
+impl HintStore {
+    pub fn new(max_hints_per_node: usize) -> Self {
+        HintStore {
+            max_hints_per_node,
+            hints: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, node: &str, hint: Hint) {
+        let queue = self.hints.entry(node.to_string()).or_default();
+        queue.retain(|pending| pending.key() != hint.key());
+        queue.push_back(hint);
+
+        while queue.len() > self.max_hints_per_node {
+            if let Some(dropped) = queue.pop_front() {
+                log::warn!("Hint queue for node {} is full, dropping hint for '{}'", node, dropped.key());
+            }
+        }
+    }
+
+    /// Nodes that have at least one pending hint.
+    pub fn nodes(&self) -> Vec<String> {
+        self.hints.iter()
+            .filter(|(_, queue)| !queue.is_empty())
+            .map(|(node, _)| node.clone())
+            .collect()
+    }
+
+    pub fn len(&self, node: &str) -> usize {
+        self.hints.get(node).map_or(0, |queue| queue.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hints.values().all(|queue| queue.is_empty())
+    }
+
+    /// Removes and returns every pending hint for `node` in the order they were recorded.
+    pub fn take(&mut self, node: &str) -> VecDeque<Hint> {
+        self.hints.remove(node).unwrap_or_default()
+    }
+
+    /// Puts back hints that could not be replayed. Hints recorded for the same key
+    /// while the replay was in flight are newer and take precedence.
+    pub fn restore(&mut self, node: &str, remaining: VecDeque<Hint>) {
+        let queue = self.hints.entry(node.to_string()).or_default();
+        for hint in remaining.into_iter().rev() {
+            if !queue.iter().any(|pending| pending.key() == hint.key()) {
+                queue.push_front(hint);
+            }
+        }
+
+        while queue.len() > self.max_hints_per_node {
+            queue.pop_front();
+        }
+    }
+}
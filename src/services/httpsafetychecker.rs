@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::SAFETY_CHECK_TIMEOUT;
+
+use super::hashserviceerror::HashServiceError;
+use super::urlsafetychecker::{UrlSafetyChecker, Verdict};
+
+const DEFAULT_THREAT: &str = "UNSAFE";
+
+#[derive(Serialize)]
+struct CheckRequest<'a> {
+    url: &'a str,
+}
+
+#[derive(Deserialize)]
+struct CheckResponse {
+    flagged: bool,
+    #[serde(default)]
+    threat: Option<String>,
+}
+
+/// Asks a scanning service about every url. The service is posted `{"url": "..."}`
+/// and answers `{"flagged": true, "threat": "MALWARE"}`.
+pub struct HttpSafetyChecker {
+    client: reqwest::Client,
+    checker_url: String,
+}
+
+impl HttpSafetyChecker {
+    pub fn new(checker_url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(SAFETY_CHECK_TIMEOUT))
+            .build()
+            .unwrap_or_default();
+
+        HttpSafetyChecker { client, checker_url: checker_url.to_string() }
+    }
+}
+
+#[async_trait]
+impl UrlSafetyChecker for HttpSafetyChecker {
+    async fn check(&self, long_url: &str) -> Result<Verdict, HashServiceError> {
+        let response = self.client.post(&self.checker_url)
+            .json(&CheckRequest { url: long_url })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<CheckResponse>()
+            .await?;
+
+        Ok(match response.flagged {
+            true => Verdict::Flagged { threat: response.threat.unwrap_or_else(|| DEFAULT_THREAT.to_string()) },
+            false => Verdict::Safe
+        })
+    }
+}
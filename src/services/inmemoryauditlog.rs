@@ -0,0 +1,40 @@
+use async_trait::async_trait;
+
+use crate::models::auditevent::{AuditEvent, AuditQuery};
+
+use super::auditlog::AuditLog;
+use super::hashserviceerror::HashServiceError;
+
+#[derive(Default)]
+pub struct InMemoryAuditLog {
+    events: Vec<AuditEvent>,
+}
+
+impl InMemoryAuditLog {
+    pub fn new() -> Self {
+        InMemoryAuditLog {
+            events: Vec::new()
+        }
+    }
+}
+
+#[async_trait]
+impl AuditLog for InMemoryAuditLog {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        Ok(())
+    }
+
+    async fn record(&mut self, event: &AuditEvent) -> Result<(), HashServiceError> {
+        self.events.push(event.clone());
+
+        Ok(())
+    }
+
+    async fn query(&mut self, query: &AuditQuery) -> Result<Vec<AuditEvent>, HashServiceError> {
+        Ok(self.events.iter()
+            .filter(|event| query.matches(event))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect())
+    }
+}
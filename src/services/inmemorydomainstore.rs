@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::models::domain::Domain;
+
+use super::domainstore::DomainStore;
+use super::hashserviceerror::HashServiceError;
+
+#[derive(Default)]
+pub struct InMemoryDomainStore {
+    domains: HashMap<String, Domain>,
+}
+
+impl InMemoryDomainStore {
+    pub fn new() -> Self {
+        InMemoryDomainStore {
+            domains: HashMap::new()
+        }
+    }
+}
+
+#[async_trait]
+impl DomainStore for InMemoryDomainStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        Ok(())
+    }
+
+    async fn list(&mut self) -> Result<Vec<Domain>, HashServiceError> {
+        let mut domains: Vec<Domain> = self.domains.values().cloned().collect();
+        domains.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(domains)
+    }
+
+    async fn upsert(&mut self, domain: &Domain) -> Result<(), HashServiceError> {
+        self.domains.insert(domain.name.clone(), domain.clone());
+
+        Ok(())
+    }
+
+    async fn delete(&mut self, name: &str) -> Result<bool, HashServiceError> {
+        Ok(self.domains.remove(name).is_some())
+    }
+}
@@ -1,19 +1,46 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use crate::{models::{builders::build_link_info, linkinfo::LinkInfo, queryparams::QueryParams}, services::{hashfunction, hashservice}};
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 
 use super::hashserviceerror::HashServiceError;
 
 pub struct InMemoryHashService {
     pub urls: HashMap<String, LinkInfo>,
+    /// Short urls by owner
+    owners: HashMap<String, BTreeSet<String>>,
 }
 
 impl InMemoryHashService {
     pub fn new() -> Self {
         InMemoryHashService {
-            urls: HashMap::new()
+            urls: HashMap::new(),
+            owners: HashMap::new()
+        }
+    }
+
+    /// Stores the link and moves it to the index of its owner.
+    fn store(&mut self, value: LinkInfo) {
+        if let Some(previous) = self.urls.get(&value.short_url).cloned() {
+            self.unindex(&previous);
+        }
+
+        if let Some(owner_id) = &value.owner_id {
+            self.owners.entry(owner_id.clone()).or_default().insert(value.short_url.clone());
+        }
+        self.urls.insert(value.short_url.clone(), value);
+    }
+
+    fn unindex(&mut self, link: &LinkInfo) {
+        if let Some(owner_id) = &link.owner_id {
+            if let Some(short_urls) = self.owners.get_mut(owner_id) {
+                short_urls.remove(&link.short_url);
+                if short_urls.is_empty() {
+                    self.owners.remove(owner_id);
+                }
+            }
         }
     }
 }
@@ -25,24 +52,50 @@ impl hashservice::HashService for InMemoryHashService {
 
         let new_link = build_link_info(hash_value.clone(), String::from(value));
 
-        self.urls.entry(hash_value.clone()).or_insert(new_link.clone());
+        if let Some(existing) = self.urls.get(&hash_value) {
+            return hashservice::stored_link_of(existing.clone(), value);
+        }
+
+        self.store(new_link.clone());
 
         return Ok(new_link)
     }
 
     async fn update(&mut self, key: &str, value: &LinkInfo) -> Result<bool, HashServiceError> {
-        match self.urls.get_mut(key) {
-            None => return Ok(false),
-            Some(link) => {
-                *link = value.clone();
-                return Ok(true)
-            }
+        if !self.urls.contains_key(key) {
+            return Ok(false);
         }
+
+        let mut value = value.clone();
+        value.short_url = key.to_string();
+        self.store(value);
+
+        Ok(true)
+    }
+
+    async fn upsert(&mut self, value: &LinkInfo) -> Result<(), HashServiceError> {
+        self.store(value.clone());
+        Ok(())
+    }
+
+    async fn count_links(&mut self) -> Result<usize, HashServiceError> {
+        Ok(self.urls.len())
+    }
+
+    async fn get_links_by_owner(&mut self, owner_id: &str, query_params: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError> {
+        let query_params = query_params.unwrap_or_default();
+        let links = self.owners.get(owner_id).into_iter().flatten()
+            .filter_map(|short_url| self.urls.get(short_url).cloned());
+
+        Ok(hashservice::page_of(links, &query_params))
     }
 
     async fn get_links(&mut self, query_params: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError>
     {
-        let urls = self.urls.iter().map(|key_value| key_value.1.clone()).collect();
+        let mut urls: Vec<LinkInfo> = self.urls.iter().map(|key_value| key_value.1.clone()).collect();
+        // Stable order by short url, so pages don't overlap and coordinators can merge results of several nodes
+        urls.sort_by(|a, b| a.short_url.cmp(&b.short_url));
+
         let query_params = match query_params {
             Some(value) => value,
             None => return Ok(urls)
@@ -54,6 +107,18 @@ impl hashservice::HashService for InMemoryHashService {
         Ok(urls.into_iter().skip(skip).take(top).collect())
     }
 
+    async fn get_links_after(&mut self, after: Option<&str>, owner_id: Option<&str>, top: usize) -> Result<Vec<LinkInfo>, HashServiceError> {
+        let is_after = |short_url: &String| after.is_none_or(|after| short_url.as_str() > after);
+        let mut short_urls: Vec<&String> = match owner_id {
+            // The index of the owner is in order already
+            Some(owner_id) => self.owners.get(owner_id).into_iter().flatten().filter(|short_url| is_after(short_url)).take(top).collect(),
+            None => self.urls.keys().filter(|short_url| is_after(short_url)).collect()
+        };
+        short_urls.sort();
+
+        Ok(short_urls.into_iter().take(top).filter_map(|short_url| self.urls.get(short_url).cloned()).collect())
+    }
+
     async fn find(&mut self, key: &str) -> Result<Option<LinkInfo>, HashServiceError> {
         #[cfg(debug_assertions)]
         // Print the content of the HashMap
@@ -70,7 +135,27 @@ impl hashservice::HashService for InMemoryHashService {
     }
 
     async fn delete(&mut self, key: &str) -> Result<bool, HashServiceError> {
-        Ok(self.urls.remove(key).is_some())
+        match self.urls.remove(key) {
+            Some(link) => {
+                self.unindex(&link);
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    async fn purge_deleted(&mut self, deleted_before: DateTime<Utc>) -> Result<usize, HashServiceError> {
+        let purged: Vec<String> = self.urls.values()
+            .filter(|link| hashservice::is_purgeable(link, deleted_before))
+            .map(|link| link.short_url.clone())
+            .collect();
+        for short_url in &purged {
+            if let Some(link) = self.urls.remove(short_url) {
+                self.unindex(&link);
+            }
+        }
+
+        Ok(purged.len())
     }
 
     async fn init(&mut self) -> Result<(), HashServiceError> {
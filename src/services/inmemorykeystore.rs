@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::models::apikey::ApiKey;
+
+use super::hashserviceerror::HashServiceError;
+use super::keystore::KeyStore;
+
+#[derive(Default)]
+pub struct InMemoryKeyStore {
+    keys: HashMap<String, ApiKey>,
+}
+
+impl InMemoryKeyStore {
+    pub fn new() -> Self {
+        InMemoryKeyStore {
+            keys: HashMap::new()
+        }
+    }
+}
+
+#[async_trait]
+impl KeyStore for InMemoryKeyStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        Ok(())
+    }
+
+    async fn list(&mut self) -> Result<Vec<ApiKey>, HashServiceError> {
+        let mut keys: Vec<ApiKey> = self.keys.values().cloned().collect();
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(keys)
+    }
+
+    async fn find(&mut self, name: &str) -> Result<Option<ApiKey>, HashServiceError> {
+        Ok(self.keys.get(name).cloned())
+    }
+
+    async fn upsert(&mut self, key: &ApiKey) -> Result<(), HashServiceError> {
+        self.keys.insert(key.name.clone(), key.clone());
+
+        Ok(())
+    }
+
+    async fn delete(&mut self, name: &str) -> Result<bool, HashServiceError> {
+        Ok(self.keys.remove(name).is_some())
+    }
+}
@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+
+use super::hashserviceerror::HashServiceError;
+use super::usagestore::UsageStore;
+
+/// Counts of this instance only, every instance enforces the quotas on its own.
+#[derive(Default)]
+pub struct InMemoryUsageStore {
+    /// Day and count of the shortens by tenant, only the latest day is kept
+    shortens: HashMap<String, (NaiveDate, usize)>,
+}
+
+impl InMemoryUsageStore {
+    pub fn new() -> Self {
+        InMemoryUsageStore {
+            shortens: HashMap::new()
+        }
+    }
+}
+
+#[async_trait]
+impl UsageStore for InMemoryUsageStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        Ok(())
+    }
+
+    async fn shortens_on(&mut self, tenant: &str, day: NaiveDate) -> Result<usize, HashServiceError> {
+        Ok(match self.shortens.get(tenant) {
+            Some((counted_day, shortens)) if *counted_day == day => *shortens,
+            _ => 0
+        })
+    }
+
+    async fn record_shortens(&mut self, tenant: &str, day: NaiveDate, shortens: usize) -> Result<(), HashServiceError> {
+        let counted = self.shortens_on(tenant, day).await?;
+        self.shortens.insert(tenant.to_string(), (day, counted + shortens));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+
+use crate::{configuration::settings::{Mode, Settings}, models::apikey::ApiKey};
+
+use super::firestorekeystore::FirestoreKeyStore;
+use super::hashserviceerror::HashServiceError;
+pub use super::inmemorykeystore::InMemoryKeyStore;
+use super::mongokeystore::MongoKeyStore;
+use super::rediskeystore::RedisKeyStore;
+
+/// Where the API keys issued through the admin endpoints are kept, by name.
+#[async_trait]
+pub trait KeyStore: Send + Sync {
+    async fn init(&mut self) -> Result<(), HashServiceError>;
+
+    async fn list(&mut self) -> Result<Vec<ApiKey>, HashServiceError>;
+
+    async fn find(&mut self, name: &str) -> Result<Option<ApiKey>, HashServiceError>;
+
+    /// Inserts the key or replaces the stored one with the same name.
+    async fn upsert(&mut self, key: &ApiKey) -> Result<(), HashServiceError>;
+
+    async fn delete(&mut self, name: &str) -> Result<bool, HashServiceError>;
+}
+
+/// Creates the key store of the backend the links are kept in. A coordinator doesn't
+/// keep data of its own, so its keys are only kept in memory.
+pub async fn create_key_store(settings: &Settings) -> Result<Box<dyn KeyStore>, HashServiceError> {
+    let mode = match &settings.mode {
+        Mode::Gossip => settings.gossip.as_ref().and_then(|gossip| gossip.storage.clone()).unwrap_or_default(),
+        mode => mode.clone()
+    };
+
+    let mut key_store: Box<dyn KeyStore> = match (mode, &settings.mongo_config, &settings.redis_config, &settings.firestore_config) {
+        (Mode::Mongo, Some(mongo_config), _, _) => Box::new(MongoKeyStore::new(mongo_config)),
+        (Mode::Redis, _, Some(redis_config), _) => Box::new(RedisKeyStore::new(redis_config)),
+        (Mode::Firestore, _, _, Some(firestore_config)) => Box::new(FirestoreKeyStore::new(firestore_config)),
+        _ => Box::new(InMemoryKeyStore::new())
+    };
+
+    key_store.init().await?;
+    Ok(key_store)
+}
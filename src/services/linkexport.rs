@@ -0,0 +1,177 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::constants::{APPLICATION_NDJSON, TEXT_CSV};
+use crate::models::{analytic::Analytic, linkinfo::LinkInfo};
+
+/// Columns of an export, in the order of `ExportRow`.
+pub const EXPORT_COLUMNS: [&str; 18] = [
+    "short_url", "long_url", "domain", "owner_id", "status", "tags", "created_at", "expires_at", "deleted_at",
+    "clicked_at", "language", "os", "ip", "location", "referrer", "device_type", "browser", "user_agent"
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => TEXT_CSV,
+            ExportFormat::Ndjson => APPLICATION_NDJSON,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    /// Header line of the export, only CSV has one.
+    pub fn header(&self) -> Option<String> {
+        match self {
+            ExportFormat::Csv => Some(csv_record(EXPORT_COLUMNS.iter().map(|column| Some(*column)))),
+            ExportFormat::Ndjson => None,
+        }
+    }
+
+    /// Line of a row, ending with a line break.
+    pub fn encode(&self, row: &ExportRow) -> String {
+        match self {
+            ExportFormat::Csv => csv_record(row.fields().into_iter()),
+            ExportFormat::Ndjson => {
+                let mut line = serde_json::to_string(row).unwrap_or_default();
+                line.push('\n');
+                line
+            },
+        }
+    }
+}
+
+/// Filter of `GET /admin/export`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ExportQuery {
+    /// `csv` or `ndjson`, CSV by default
+    pub format: Option<String>,
+    /// Only clicks at or after this time
+    pub from: Option<DateTime<Utc>>,
+    /// Only clicks before this time, links created later are left out
+    pub to: Option<DateTime<Utc>>,
+    /// Only links with this tag
+    pub tag: Option<String>,
+}
+
+impl ExportQuery {
+    pub fn format(&self) -> Result<ExportFormat, String> {
+        match self.format.as_deref().map(str::to_ascii_lowercase).as_deref() {
+            None | Some("csv") => Ok(ExportFormat::Csv),
+            Some("ndjson") => Ok(ExportFormat::Ndjson),
+            Some(format) => Err(format!("format '{}' is neither csv nor ndjson", format)),
+        }
+    }
+
+    /// Rows of a link: one per click in the date range, or one without a click when it has none.
+    pub fn rows(&self, link: &LinkInfo) -> Vec<ExportRow> {
+        let tagged = match &self.tag {
+            Some(tag) => link.tags.iter().flatten().any(|link_tag| link_tag == tag),
+            None => true
+        };
+        let created_later = matches!((self.to, link.created_at), (Some(to), Some(created_at)) if created_at >= to);
+        if !tagged || created_later {
+            return Vec::new();
+        }
+
+        let clicks: Vec<&Analytic> = link.analytics.iter().flatten()
+            .filter(|analytic| !matches!(self.from, Some(from) if analytic.created_at < from))
+            .filter(|analytic| !matches!(self.to, Some(to) if analytic.created_at >= to))
+            .collect();
+        if clicks.is_empty() {
+            return vec![ExportRow::new(link, None)];
+        }
+
+        clicks.into_iter()
+            .map(|analytic| ExportRow::new(link, Some(analytic)))
+            .collect()
+    }
+}
+
+/// Flat row of an export: a link and one of its clicks. Every value is a string or null,
+/// times are RFC 3339 in UTC, so spreadsheets and warehouses load rows without a schema of their own.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExportRow {
+    pub short_url: Option<String>,
+    pub long_url: Option<String>,
+    pub domain: Option<String>,
+    pub owner_id: Option<String>,
+    pub status: Option<String>,
+    /// Comma separated
+    pub tags: Option<String>,
+    pub created_at: Option<String>,
+    pub expires_at: Option<String>,
+    pub deleted_at: Option<String>,
+    pub clicked_at: Option<String>,
+    pub language: Option<String>,
+    pub os: Option<String>,
+    pub ip: Option<String>,
+    pub location: Option<String>,
+    pub referrer: Option<String>,
+    pub device_type: Option<String>,
+    pub browser: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ExportRow {
+    pub fn new(link: &LinkInfo, analytic: Option<&Analytic>) -> Self {
+        let click = |field: fn(&Analytic) -> &Option<String>| analytic.and_then(|analytic| field(analytic).clone());
+        ExportRow {
+            short_url: Some(link.short_url.clone()),
+            long_url: Some(link.long_url.clone()),
+            domain: link.domain.clone(),
+            owner_id: link.owner_id.clone(),
+            status: serde_json::to_value(link.status).ok().and_then(|status| status.as_str().map(str::to_string)),
+            tags: link.tags.as_ref().map(|tags| tags.join(",")),
+            created_at: link.created_at.map(timestamp),
+            expires_at: link.expires_at.map(timestamp),
+            deleted_at: link.deleted_at.map(timestamp),
+            clicked_at: analytic.map(|analytic| timestamp(analytic.created_at)),
+            language: click(|analytic| &analytic.language),
+            os: click(|analytic| &analytic.os),
+            ip: click(|analytic| &analytic.ip),
+            location: click(|analytic| &analytic.location),
+            referrer: click(|analytic| &analytic.referrer),
+            device_type: click(|analytic| &analytic.device_type),
+            browser: click(|analytic| &analytic.browser),
+            user_agent: click(|analytic| &analytic.user_agent),
+        }
+    }
+
+    /// Values in the order of `EXPORT_COLUMNS`.
+    pub fn fields(&self) -> Vec<Option<&str>> {
+        [
+            &self.short_url, &self.long_url, &self.domain, &self.owner_id, &self.status, &self.tags,
+            &self.created_at, &self.expires_at, &self.deleted_at, &self.clicked_at, &self.language, &self.os,
+            &self.ip, &self.location, &self.referrer, &self.device_type, &self.browser, &self.user_agent
+        ].into_iter().map(|field| field.as_deref()).collect()
+    }
+}
+
+/// CSV line of the values, quoted where RFC 4180 requires it. Missing values are empty.
+pub fn csv_record<'a>(values: impl Iterator<Item = Option<&'a str>>) -> String {
+    let mut line = values
+        .map(|value| match value.unwrap_or_default() {
+            value if value.contains([',', '"', '\n', '\r']) => format!("\"{}\"", value.replace('"', "\"\"")),
+            value => value.to_string()
+        })
+        .collect::<Vec<String>>()
+        .join(",");
+    line.push('\n');
+    line
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
@@ -0,0 +1,569 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use crate::models::builders::build_link_info;
+use crate::models::linkinfo::{LinkInfo, LinkStatus};
+
+use super::bulkimport;
+use super::hashservice::HashService;
+use super::hashserviceerror::HashServiceError;
+use super::urlnormalizer::UrlError;
+
+/// Shortener an export comes from.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    /// CSV export of the links, or the JSON of the bitlinks API
+    Bitly,
+    /// SQL dump of the url table, or a JSON list of links
+    Yourls,
+    /// JSON of the short urls API, or the CSV export of the web client
+    Shlink,
+}
+
+/// Names of the fields of an export, lowercase without separators, so `Long URL`,
+/// `long_url` and `longUrl` are one name. The first name present is used.
+struct Fields {
+    code: &'static [&'static str],
+    long_url: &'static [&'static str],
+    created_at: &'static [&'static str],
+    clicks: &'static [&'static str],
+    tags: &'static [&'static str],
+    expires_at: &'static [&'static str],
+}
+
+impl ImportSource {
+    fn fields(&self) -> Fields {
+        match self {
+            ImportSource::Bitly => Fields {
+                code: &["bitlink", "link", "shortlink", "shorturl", "id"],
+                long_url: &["longurl", "destinationurl", "destination", "url"],
+                created_at: &["createdat", "created", "datecreated", "creationdate"],
+                clicks: &["clicks", "totalclicks", "userclicks", "engagements"],
+                tags: &["tags"],
+                expires_at: &["expirationat", "expiresat"]
+            },
+            ImportSource::Yourls => Fields {
+                code: &["keyword", "shorturl"],
+                long_url: &["url", "longurl"],
+                created_at: &["timestamp", "created"],
+                clicks: &["clicks"],
+                tags: &[],
+                expires_at: &[]
+            },
+            ImportSource::Shlink => Fields {
+                code: &["shortcode", "shorturl"],
+                long_url: &["longurl"],
+                created_at: &["datecreated", "createdat"],
+                clicks: &["visitssummarytotal", "visitscount", "visits"],
+                tags: &["tags"],
+                expires_at: &["metavaliduntil", "validuntil"]
+            },
+        }
+    }
+}
+
+/// Link read from an export, with the code it had in the shortener it comes from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportedLink {
+    pub short_url: String,
+    pub long_url: String,
+    pub clicks: Option<u64>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub tags: Option<Vec<String>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Links of an export. JSON is told apart by its first character, a YOURLS export holding
+/// `INSERT INTO` is a SQL dump, anything else is CSV. Like a bulk import, a body that can't
+/// be read is an error and a row that can't be read is an error of its own.
+pub fn parse(source: ImportSource, body: &str) -> Result<Vec<Result<ImportedLink, String>>, String> {
+    let fields = source.fields();
+    let trimmed = body.trim_start_matches('\u{feff}').trim_start();
+    let records = if trimmed.starts_with('[') || trimmed.starts_with('{') {
+        let value: Value = serde_json::from_str(trimmed)
+            .map_err(|err| format!("the body is not JSON: {}", err))?;
+        json_records(value)?.into_iter().map(|record| match record {
+            Value::Object(_) => Ok(flatten(&record)),
+            _ => Err(String::from("the link is not a JSON object"))
+        }).collect()
+    } else if source == ImportSource::Yourls && trimmed.to_ascii_uppercase().contains("INSERT INTO") {
+        parse_yourls_sql(trimmed)?.into_iter().map(Ok).collect()
+    } else {
+        csv_records(trimmed)?.into_iter().map(Ok).collect::<Vec<_>>()
+    };
+
+    Ok(records.into_iter()
+        .map(|record| record.and_then(|record| imported_link(&fields, &record)))
+        .collect())
+}
+
+fn imported_link(fields: &Fields, record: &HashMap<String, String>) -> Result<ImportedLink, String> {
+    let value = |names: &[&str]| names.iter()
+        .find_map(|name| record.get(*name))
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty());
+
+    let code = value(fields.code).and_then(short_code).ok_or("the link has no short code")?;
+    let long_url = value(fields.long_url).ok_or("the link has no long url")?;
+    let clicks = match value(fields.clicks) {
+        Some(clicks) => Some(clicks.parse::<u64>()
+            .map_err(|_| format!("clicks '{}' is not a number", clicks))?),
+        None => None
+    };
+    let tags = value(fields.tags).map(|tags| tags.split([',', '|'])
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<String>>())
+        .filter(|tags| !tags.is_empty());
+
+    Ok(ImportedLink {
+        short_url: code.to_string(),
+        long_url: long_url.to_string(),
+        clicks,
+        created_at: value(fields.created_at).map(parse_time).transpose()?,
+        tags,
+        expires_at: value(fields.expires_at).map(parse_time).transpose()?
+    })
+}
+
+/// Code of a short link. Bitly and Shlink list the whole short link, e.g. `bit.ly/3xYzAbc`,
+/// its code is the last segment of the path.
+fn short_code(value: &str) -> Option<&str> {
+    let path = match value.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').map_or("", |(_, path)| path),
+        None => value.split_once('/').map_or(value, |(_, path)| path)
+    };
+    let path = path.split(['?', '#']).next().unwrap_or_default().trim_end_matches('/');
+    path.rsplit('/').next().filter(|code| !code.is_empty())
+}
+
+/// Times of the exports: RFC 3339, `2024-03-01 09:30:00` in UTC, with or without an offset, or Unix seconds.
+pub fn parse_time(text: &str) -> Result<DateTime<Utc>, String> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let without_utc = text.trim_end_matches(" UTC");
+    if let Ok(time) = NaiveDateTime::parse_from_str(without_utc, "%Y-%m-%d %H:%M:%S%.f") {
+        return Ok(Utc.from_utc_datetime(&time));
+    }
+    if let Ok(time) = DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f %z") {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Some(time) = text.parse::<i64>().ok().and_then(|seconds| Utc.timestamp_opt(seconds, 0).single()) {
+        return Ok(time);
+    }
+
+    Err(format!("'{}' is not a time", text))
+}
+
+/// Lowercase field name without separators.
+fn field_name(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Links of a JSON export: an array, or the list of an API response, e.g. `{"shortUrls": {"data": [...]}}`.
+fn json_records(value: Value) -> Result<Vec<Value>, String> {
+    match value {
+        Value::Array(records) => Ok(records),
+        Value::Object(mut object) => {
+            for key in ["links", "shortUrls", "data"] {
+                if let Some(inner) = object.remove(key) {
+                    return json_records(inner);
+                }
+            }
+            // YOURLS lists links as an object keyed by "link_1", "link_2", ...
+            if !object.is_empty() && object.values().all(Value::is_object) {
+                return Ok(object.into_iter().map(|(_, record)| record).collect());
+            }
+            Err(String::from("the JSON has no list of links"))
+        },
+        _ => Err(String::from("the JSON has no list of links"))
+    }
+}
+
+/// Values of a JSON link by field name, nested objects with the names joined,
+/// e.g. `visitsSummary.total` as `visitssummarytotal`, and arrays comma separated.
+fn flatten(record: &Value) -> HashMap<String, String> {
+    fn add(prefix: &str, value: &Value, fields: &mut HashMap<String, String>) {
+        match value {
+            Value::Object(object) => {
+                for (name, value) in object {
+                    add(&format!("{}{}", prefix, field_name(name)), value, fields);
+                }
+            },
+            Value::Array(values) => {
+                let values: Vec<String> = values.iter()
+                    .filter_map(|value| match value {
+                        Value::String(text) => Some(text.clone()),
+                        Value::Number(number) => Some(number.to_string()),
+                        _ => None
+                    })
+                    .collect();
+                fields.insert(prefix.to_string(), values.join(","));
+            },
+            Value::String(text) => { fields.insert(prefix.to_string(), text.clone()); },
+            Value::Number(number) => { fields.insert(prefix.to_string(), number.to_string()); },
+            Value::Bool(_) | Value::Null => ()
+        }
+    }
+
+    let mut fields = HashMap::new();
+    add("", record, &mut fields);
+    fields
+}
+
+/// Rows of a CSV export by the field names of its header.
+fn csv_records(body: &str) -> Result<Vec<HashMap<String, String>>, String> {
+    let mut records = bulkimport::parse_csv(body)?.into_iter();
+    let header: Vec<String> = match records.next() {
+        Some(header) => header.iter().map(|name| field_name(name)).collect(),
+        None => return Ok(Vec::new())
+    };
+
+    Ok(records
+        .map(|record| header.iter().cloned().zip(record).collect())
+        .collect())
+}
+
+/// Columns of the YOURLS url table, in the order of its dumps.
+const YOURLS_COLUMNS: [&str; 6] = ["keyword", "url", "title", "timestamp", "ip", "clicks"];
+
+/// Value of a SQL dump.
+#[derive(Debug, PartialEq)]
+enum SqlToken {
+    /// Keyword or identifier, quoted ones without their backticks
+    Word(String),
+    Text(String),
+    Number(String),
+    Symbol(char),
+}
+
+/// Rows of the `INSERT INTO` statements of a YOURLS url table (`yourls_url`, or with the
+/// prefix of the installation) in a MySQL dump. Other tables, e.g. the click log, are skipped.
+fn parse_yourls_sql(body: &str) -> Result<Vec<HashMap<String, String>>, String> {
+    let tokens = sql_tokens(body)?;
+    let mut records = Vec::new();
+    let mut index = 0;
+    let word = |index: usize, expected: &str| matches!(tokens.get(index), Some(SqlToken::Word(word)) if word.eq_ignore_ascii_case(expected));
+    let symbol = |index: usize, expected: char| tokens.get(index) == Some(&SqlToken::Symbol(expected));
+
+    while index < tokens.len() {
+        if !(word(index, "INSERT") && word(index + 1, "INTO")) {
+            index += 1;
+            continue;
+        }
+        index += 2;
+        let table = match tokens.get(index) {
+            Some(SqlToken::Word(table)) => table.to_ascii_lowercase(),
+            _ => return Err(String::from("an INSERT INTO statement has no table"))
+        };
+        index += 1;
+
+        let mut columns: Vec<String> = YOURLS_COLUMNS.iter().map(|column| column.to_string()).collect();
+        if symbol(index, '(') {
+            columns.clear();
+            index += 1;
+            while let Some(SqlToken::Word(column)) = tokens.get(index) {
+                columns.push(field_name(column));
+                index += if symbol(index + 1, ',') { 2 } else { 1 };
+            }
+            if !symbol(index, ')') {
+                return Err(format!("the columns of table '{}' are not closed", table));
+            }
+            index += 1;
+        }
+        if !word(index, "VALUES") {
+            return Err(format!("the INSERT INTO statement of table '{}' has no VALUES", table));
+        }
+        index += 1;
+
+        // Tables of the installation end with "url", e.g. "yourls_url"
+        let url_table = table.ends_with("url");
+        while symbol(index, '(') {
+            index += 1;
+            let mut record = HashMap::new();
+            let mut column = 0;
+            while !symbol(index, ')') {
+                let value = match tokens.get(index) {
+                    Some(SqlToken::Text(value) | SqlToken::Number(value)) => Some(value.clone()),
+                    Some(SqlToken::Word(word)) if word.eq_ignore_ascii_case("NULL") => None,
+                    _ => return Err(format!("a row of table '{}' is not closed", table))
+                };
+                if let (Some(name), Some(value)) = (columns.get(column), value) {
+                    record.insert(name.clone(), value);
+                }
+                column += 1;
+                index += if symbol(index + 1, ',') { 2 } else { 1 };
+            }
+            index += 1;
+            if url_table {
+                records.push(record);
+            }
+            if symbol(index, ',') {
+                index += 1;
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// Tokens of a SQL dump without its comments. Strings are quoted with `'`, with doubled
+/// quotes or backslash escapes as MySQL writes them.
+fn sql_tokens(body: &str) -> Result<Vec<SqlToken>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = body.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => (),
+            '-' if chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            },
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            },
+            '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            text.push('\'');
+                        },
+                        Some('\'') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => text.push('\n'),
+                            Some('r') => text.push('\r'),
+                            Some('t') => text.push('\t'),
+                            Some('0') => text.push('\0'),
+                            Some(c) => text.push(c),
+                            None => return Err(String::from("a quoted value is not closed"))
+                        },
+                        Some(c) => text.push(c),
+                        None => return Err(String::from("a quoted value is not closed"))
+                    }
+                }
+                tokens.push(SqlToken::Text(text));
+            },
+            '`' => {
+                let name: String = chars.by_ref().take_while(|c| *c != '`').collect();
+                tokens.push(SqlToken::Word(name));
+            },
+            c if c.is_ascii_digit() || (c == '-' && chars.peek().is_some_and(char::is_ascii_digit)) => {
+                let mut number = String::from(c);
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+                tokens.push(SqlToken::Number(number));
+            },
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_' || *c == '$') {
+                    word.push(c);
+                }
+                tokens.push(SqlToken::Word(word));
+            },
+            c => tokens.push(SqlToken::Symbol(c))
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Link of an export whose url and code passed the checks.
+#[derive(Clone, Debug)]
+pub struct ImportRow {
+    /// Row of the export, starting at 1
+    pub row: usize,
+    pub link: ImportedLink,
+    /// Threat a safety check flagged the url as, the link is imported quarantined
+    pub threat: Option<String>,
+}
+
+/// Checks the rows of an export: `check_url` normalizes the url and checks it like the
+/// urls of `POST /shorten`, codes are checked like the aliases of a bulk import.
+pub fn check(rows: Vec<Result<ImportedLink, String>>, check_url: impl Fn(&str) -> Result<String, UrlError>) -> (Vec<ImportRow>, Vec<ImportResult>) {
+    let mut checked = Vec::new();
+    let mut results = Vec::new();
+    for (index, row) in rows.into_iter().enumerate() {
+        let row_number = index + 1;
+        let mut link = match row {
+            Ok(link) => link,
+            Err(reason) => {
+                results.push(ImportResult::failed(row_number, None, "invalid_row", reason));
+                continue;
+            }
+        };
+        if let Err(reason) = bulkimport::validate_alias(&link.short_url) {
+            results.push(ImportResult::failed(row_number, Some(link.short_url), "invalid_code", reason));
+            continue;
+        }
+        match check_url(&link.long_url) {
+            Ok(long_url) => link.long_url = long_url,
+            Err(err) => {
+                results.push(ImportResult::failed(row_number, Some(link.short_url), err.code(), err.to_string()));
+                continue;
+            }
+        }
+        checked.push(ImportRow { row: row_number, link, threat: None });
+    }
+
+    (checked, results)
+}
+
+/// Links to write and the outcome of every row.
+#[derive(Debug, Default)]
+pub struct ImportPlan {
+    pub links: Vec<LinkInfo>,
+    pub results: Vec<ImportResult>,
+}
+
+/// Compares the checked rows with the links of the hash service. A code that is already the
+/// short url of the same url is left as it is, of another url it's a conflict and nothing is written.
+pub async fn plan(hash_service: &mut Box<dyn HashService>, rows: Vec<ImportRow>, mut results: Vec<ImportResult>, owner_id: Option<&str>) -> Result<ImportPlan, HashServiceError> {
+    let mut links = Vec::new();
+    // Links of the export by code, a code may be listed more than once
+    let mut batch: HashMap<String, LinkInfo> = HashMap::new();
+    for row in rows {
+        let short_url = row.link.short_url.clone();
+        let existing = match batch.get(&short_url) {
+            Some(link) => Some(link.clone()),
+            None => hash_service.find(&short_url).await?
+        };
+        let status = match existing {
+            Some(existing) if existing.long_url == row.link.long_url => ImportStatus::Existing,
+            Some(existing) => {
+                results.push(ImportResult {
+                    row: row.row,
+                    status: ImportStatus::Conflict,
+                    message: Some(format!("'{}' is the short url of {}", short_url, existing.long_url)),
+                    short_url: Some(short_url),
+                    long_url: Some(row.link.long_url),
+                    existing_long_url: Some(existing.long_url),
+                    clicks: row.link.clicks,
+                    error: Some(String::from("code_taken"))
+                });
+                continue;
+            },
+            None => {
+                let mut link = build_link_info(short_url.clone(), row.link.long_url.clone());
+                link.owner_id = owner_id.map(str::to_string);
+                link.created_at = row.link.created_at.or(link.created_at);
+                link.tags = row.link.tags.clone();
+                link.expires_at = row.link.expires_at;
+                link.imported_clicks = row.link.clicks;
+                if let Some(threat) = &row.threat {
+                    link.status = LinkStatus::Quarantined;
+                    link.status_reason = Some(format!("flagged as {}", threat));
+                }
+                batch.insert(short_url.clone(), link.clone());
+                links.push(link);
+                ImportStatus::Imported
+            }
+        };
+        results.push(ImportResult {
+            row: row.row,
+            status,
+            short_url: Some(short_url),
+            long_url: Some(row.link.long_url),
+            existing_long_url: None,
+            clicks: row.link.clicks,
+            error: None,
+            message: None
+        });
+    }
+    results.sort_by_key(|result| result.row);
+
+    Ok(ImportPlan { links, results })
+}
+
+/// Outcome of an import, of a dry run the links that would be imported.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub imported: usize,
+    pub existing: usize,
+    pub conflicts: usize,
+    pub failed: usize,
+    pub results: Vec<ImportResult>,
+}
+
+impl ImportReport {
+    pub fn new(results: Vec<ImportResult>, dry_run: bool) -> Self {
+        let count = |status: ImportStatus| results.iter().filter(|result| result.status == status).count();
+        ImportReport {
+            dry_run,
+            imported: count(ImportStatus::Imported),
+            existing: count(ImportStatus::Existing),
+            conflicts: count(ImportStatus::Conflict),
+            failed: count(ImportStatus::Failed),
+            results
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportResult {
+    /// Row of the export, starting at 1
+    pub row: usize,
+    pub status: ImportStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub short_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub long_url: Option<String>,
+    /// Url the code already points to, of a conflict
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub existing_long_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clicks: Option<u64>,
+    /// Machine readable kind of the error of a conflict or a failed row
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl ImportResult {
+    fn failed(row: usize, short_url: Option<String>, error: &str, message: String) -> Self {
+        ImportResult { row, status: ImportStatus::Failed, short_url, long_url: None, existing_long_url: None, clicks: None, error: Some(error.to_string()), message: Some(message) }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    Imported,
+    /// The code already is the short url of the same url
+    Existing,
+    /// The code is the short url of another url
+    Conflict,
+    Failed,
+}
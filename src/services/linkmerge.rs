@@ -0,0 +1,143 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use async_trait::async_trait;
+use futures_util::{stream::BoxStream, Stream};
+
+use crate::models::linkinfo::LinkInfo;
+
+use super::antientropy;
+use super::hashserviceerror::HashServiceError;
+
+/// Links that are produced while they are sent, e.g. the merged links of several nodes.
+pub type LinkStream = BoxStream<'static, LinkInfo>;
+
+/// Sequence of links ordered by short url, e.g. the NDJSON response of one node.
+#[async_trait]
+pub trait LinkSource: Send {
+    async fn next_link(&mut self) -> Result<Option<LinkInfo>, HashServiceError>;
+}
+
+#[async_trait]
+impl LinkSource for std::vec::IntoIter<LinkInfo> {
+    async fn next_link(&mut self) -> Result<Option<LinkInfo>, HashServiceError> {
+        Ok(self.next())
+    }
+}
+
+/// K-way merge of sorted sources that keeps a single link per short url.
+///
+/// Only the current head of every source is held in memory. When several sources
+/// return the same short url the newest version wins. Sources that fail are logged
+/// and dropped, the remaining ones are still merged. Stops as soon as `skip + top`
+/// links were produced.
+pub struct LinkMerge {
+    sources: Vec<Box<dyn LinkSource>>,
+    heads: Vec<Option<LinkInfo>>,
+    heap: BinaryHeap<Reverse<(String, usize)>>,
+    /// The heads are read on the first call of `next`
+    started: bool,
+    skip: usize,
+    /// Links that can still be produced, unbounded without one
+    remaining: Option<usize>,
+}
+
+impl LinkMerge {
+    pub fn new(sources: Vec<Box<dyn LinkSource>>, skip: usize, top: Option<usize>) -> Self {
+        LinkMerge {
+            heads: vec![None; sources.len()],
+            sources,
+            heap: BinaryHeap::new(),
+            started: false,
+            skip,
+            remaining: top,
+        }
+    }
+
+    /// Next merged link, `None` once the sources are exhausted or `top` links were produced.
+    pub async fn next(&mut self) -> Option<LinkInfo> {
+        if !self.started {
+            self.started = true;
+            for index in 0..self.sources.len() {
+                self.advance(index).await;
+            }
+        }
+
+        while self.remaining != Some(0) {
+            let Reverse((short_url, index)) = self.heap.pop()?;
+            let mut indexes = vec![index];
+            while let Some(Reverse((next_short_url, next_index))) = self.heap.peek() {
+                if *next_short_url != short_url {
+                    break;
+                }
+                indexes.push(*next_index);
+                self.heap.pop();
+            }
+
+            let mut winner: Option<LinkInfo> = None;
+            for index in indexes {
+                let link = match self.heads[index].take() {
+                    Some(link) => link,
+                    None => continue
+                };
+
+                let is_winner = match &winner {
+                    Some(current) => antientropy::is_newer(&link, current),
+                    None => true
+                };
+                if is_winner {
+                    winner = Some(link);
+                }
+
+                self.advance(index).await;
+            }
+
+            if let Some(winner) = winner {
+                if self.skip > 0 {
+                    self.skip -= 1;
+                } else {
+                    self.remaining = self.remaining.map(|remaining| remaining - 1);
+                    return Some(winner);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Merged links as a stream, e.g. the body of a response that is sent while the sources are read.
+    pub fn into_stream(self) -> impl Stream<Item = LinkInfo> + Send + 'static {
+        futures_util::stream::unfold(self, |mut merge| async move {
+            merge.next().await.map(|link| (link, merge))
+        })
+    }
+
+    /// Reads the next head of the source.
+    async fn advance(&mut self, index: usize) {
+        if let Some(next) = next_or_drop(self.sources[index].as_mut(), index).await {
+            self.heap.push(Reverse((next.short_url.clone(), index)));
+            self.heads[index] = Some(next);
+        }
+    }
+}
+
+/// Collects the merged links of the sources, see `LinkMerge`.
+pub async fn merge_links(sources: Vec<Box<dyn LinkSource>>, skip: usize, top: Option<usize>) -> Vec<LinkInfo> {
+    let mut merge = LinkMerge::new(sources, skip, top);
+    let mut result: Vec<LinkInfo> = Vec::new();
+    while let Some(link) = merge.next().await {
+        result.push(link);
+    }
+
+    result
+}
+
+async fn next_or_drop(source: &mut dyn LinkSource, index: usize) -> Option<LinkInfo> {
+    match source.next_link().await {
+        Ok(link) => link,
+        Err(err) => {
+            log::warn!("Dropping source {} from the merge: {}", index, err);
+            None
+        }
+    }
+}
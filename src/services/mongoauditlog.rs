@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use mongodb::{bson::doc, options::{ClientOptions, FindOptions, IndexOptions, ServerApi, ServerApiVersion}, Client, Collection, IndexModel};
+
+use crate::{configuration, constants::AUDIT_COLLECTION_NAME, models::auditevent::{AuditEvent, AuditQuery}};
+
+use super::auditlog::AuditLog;
+use super::hashserviceerror::HashServiceError;
+
+/// Events in their own collection of the links' database.
+pub struct MongoAuditLog {
+    mongo_config: configuration::settings::MongoConfig,
+    collection: Option<Collection<AuditEvent>>
+}
+
+impl MongoAuditLog {
+    pub fn new(config: &configuration::settings::MongoConfig) -> Self {
+        MongoAuditLog {
+            mongo_config: config.clone(),
+            collection: None
+        }
+    }
+}
+
+#[async_trait]
+impl AuditLog for MongoAuditLog {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        let mut client_options = ClientOptions::parse(&self.mongo_config.connection_string).await?;
+        let server_api = ServerApi::builder().version(ServerApiVersion::V1).build();
+        client_options.server_api = Some(server_api);
+        let client = Client::with_options(client_options)?;
+
+        let collection = client.database(self.mongo_config.database_name.as_str()).collection::<AuditEvent>(AUDIT_COLLECTION_NAME);
+        // Events are read in id order, which is their time order
+        let id_index = IndexModel::builder()
+            .keys(doc! { "id": 1 })
+            .options(IndexOptions::builder().name(String::from("id")).build())
+            .build();
+        collection.create_index(id_index, None).await?;
+        self.collection = Some(collection);
+
+        Ok(())
+    }
+
+    async fn record(&mut self, event: &AuditEvent) -> Result<(), HashServiceError> {
+        self.collection.as_mut().unwrap().insert_one(event, None).await?;
+
+        Ok(())
+    }
+
+    async fn query(&mut self, query: &AuditQuery) -> Result<Vec<AuditEvent>, HashServiceError> {
+        let mut filter = doc! {};
+        if let Some(link) = &query.link {
+            filter.insert("link", link);
+        }
+        if let Some(actor) = &query.actor {
+            filter.insert("actor", actor);
+        }
+        if let Some(tenant) = &query.tenant {
+            filter.insert("tenant", tenant);
+        }
+        // Timestamps are stored as strings, the time is compared on the ids that start with it
+        let mut id_filter = doc! {};
+        if let Some(since_id) = query.since_id() {
+            id_filter.insert("$gte", since_id);
+        }
+        if let Some(after) = &query.after {
+            id_filter.insert("$gt", after);
+        }
+        if !id_filter.is_empty() {
+            filter.insert("id", id_filter);
+        }
+        let find_options = FindOptions::builder()
+            .sort(doc! { "id": 1 })
+            .limit(query.limit.map(|limit| limit as i64))
+            .build();
+
+        let cursor = self.collection.as_mut().unwrap().find(filter, find_options).await?;
+        let events: Vec<AuditEvent> = cursor.try_collect().await?;
+
+        Ok(events)
+    }
+}
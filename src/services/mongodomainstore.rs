@@ -0,0 +1,64 @@
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use mongodb::{bson::doc, options::{ClientOptions, FindOptions, ReplaceOptions, ServerApi, ServerApiVersion}, Client, Collection};
+
+use crate::{configuration, constants::DOMAINS_COLLECTION_NAME, models::domain::Domain};
+
+use super::domainstore::DomainStore;
+use super::hashserviceerror::HashServiceError;
+
+/// Domains in their own collection of the links' database.
+pub struct MongoDomainStore {
+    mongo_config: configuration::settings::MongoConfig,
+    collection: Option<Collection<Domain>>
+}
+
+impl MongoDomainStore {
+    pub fn new(config: &configuration::settings::MongoConfig) -> Self {
+        MongoDomainStore {
+            mongo_config: config.clone(),
+            collection: None
+        }
+    }
+}
+
+#[async_trait]
+impl DomainStore for MongoDomainStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        let mut client_options = ClientOptions::parse(&self.mongo_config.connection_string).await?;
+        let server_api = ServerApi::builder().version(ServerApiVersion::V1).build();
+        client_options.server_api = Some(server_api);
+        let client = Client::with_options(client_options)?;
+
+        self.collection = Some(client.database(self.mongo_config.database_name.as_str()).collection::<Domain>(DOMAINS_COLLECTION_NAME));
+
+        Ok(())
+    }
+
+    async fn list(&mut self) -> Result<Vec<Domain>, HashServiceError> {
+        let find_options = FindOptions::builder()
+            .sort(doc! { "name": 1 })
+            .build();
+
+        let cursor = self.collection.as_mut().unwrap().find(doc! {}, find_options).await?;
+
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn upsert(&mut self, domain: &Domain) -> Result<(), HashServiceError> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.collection.as_mut().unwrap().replace_one(
+            doc! { "name": &domain.name }, domain, options
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn delete(&mut self, name: &str) -> Result<bool, HashServiceError> {
+        let delete_result = self.collection.as_mut().unwrap().delete_one(
+            doc! { "name": name }, None
+        ).await?;
+
+        Ok(delete_result.deleted_count > 0)
+    }
+}
@@ -1,11 +1,17 @@
+use std::collections::HashMap;
+
 use crate::{configuration, models::{builders::build_link_info, linkinfo::LinkInfo, queryparams::QueryParams}, services::{hashfunction, hashservice}};
 use futures_util::TryStreamExt;
-use mongodb::{ bson::doc, options::{ ClientOptions, ServerApi, ServerApiVersion }, Client, Collection };
+use mongodb::{ bson::doc, error::{ Error, ErrorKind, WriteFailure }, options::{ ClientOptions, FindOptions, IndexOptions, ReplaceOptions, ServerApi, ServerApiVersion }, Client, Collection, IndexModel };
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 
 use super::hashserviceerror::HashServiceError;
 
+/// Code of the error of a write that violates a unique index.
+const DUPLICATE_KEY_ERROR_CODE: i32 = 11000;
+
 pub struct MongoHashService {
     mongo_config: configuration::settings::MongoConfig,
     collection: Option<Collection<LinkInfo>>
@@ -18,6 +24,17 @@ impl MongoHashService {
             collection: None
         }
     }
+
+    /// Keeps the links of the tenant in a collection of their own, "<collection_name>_<tenant>".
+    pub fn for_tenant(config: &configuration::settings::MongoConfig, tenant: &str) -> impl hashservice::HashService {
+        let mut mongo_config = config.clone();
+        mongo_config.collection_name = format!("{}_{}", config.collection_name, tenant);
+
+        MongoHashService {
+            mongo_config,
+            collection: None
+        }
+    }
 }
 
 #[async_trait]
@@ -25,15 +42,21 @@ impl hashservice::HashService for MongoHashService {
     async fn insert(&mut self, value: &str) -> Result<LinkInfo, HashServiceError> {
         let hash_value = hashfunction::hash(value);
 
-        let find_result = self.find(&hash_value).await?;
-        if find_result.is_some() {
-            return Ok(find_result.unwrap());
+        if let Some(existing) = self.find(&hash_value).await? {
+            return hashservice::stored_link_of(existing, value);
         }
         
         let new_link = build_link_info(hash_value.clone(), String::from(value));
 
-        self.collection.as_mut().unwrap().insert_one(new_link.clone(), None).await?;
-        Ok(new_link)
+        match self.collection.as_mut().unwrap().insert_one(new_link.clone(), None).await {
+            Ok(_) => Ok(new_link),
+            // The url was shortened by another request in the meantime
+            Err(err) if duplicate_key_index(&err).is_some() => match self.find(&hash_value).await? {
+                Some(existing) => hashservice::stored_link_of(existing, value),
+                None => Err(HashServiceError::ShortUrlTaken { short_url: hash_value })
+            },
+            Err(err) => Err(err.into())
+        }
     }
 
     async fn update(&mut self, key: &str, value: &LinkInfo) -> Result<bool, HashServiceError> {
@@ -44,6 +67,31 @@ impl hashservice::HashService for MongoHashService {
         Ok(update_result.modified_count > 0)
     }
 
+    async fn upsert(&mut self, value: &LinkInfo) -> Result<(), HashServiceError> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        match self.collection.as_mut().unwrap().replace_one(
+            doc! { "short_url": &value.short_url }, value, options
+        ).await {
+            Ok(_) => Ok(()),
+            Err(err) if duplicate_key_index(&err).is_some() => Err(HashServiceError::ShortUrlTaken { short_url: value.short_url.clone() }),
+            Err(err) => Err(err.into())
+        }
+    }
+
+    async fn insert_many(&mut self, values: &[LinkInfo]) -> Result<(), HashServiceError> {
+        if values.is_empty() {
+            return Ok(());
+        }
+
+        match self.collection.as_mut().unwrap().insert_many(values, None).await {
+            Ok(_) => Ok(()),
+            Err(err) => match duplicate_key_index(&err) {
+                Some(index) => Err(HashServiceError::ShortUrlTaken { short_url: values[index].short_url.clone() }),
+                None => Err(err.into())
+            }
+        }
+    }
+
     async fn find(&mut self, key: &str) -> Result<Option<LinkInfo>, HashServiceError> {
         let find_result = self.collection.as_mut().unwrap().find_one(
             doc! { "short_url": key }, None
@@ -52,6 +100,19 @@ impl hashservice::HashService for MongoHashService {
         return Ok(find_result)
     }
 
+    async fn find_many(&mut self, keys: &[String]) -> Result<HashMap<String, LinkInfo>, HashServiceError> {
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let cursor = self.collection.as_mut().unwrap().find(
+            doc! { "short_url": { "$in": keys } }, None
+        ).await?;
+        let links: Vec<LinkInfo> = cursor.try_collect().await?;
+
+        Ok(links.into_iter().map(|link| (link.short_url.clone(), link)).collect())
+    }
+
     async fn delete(&mut self, key: &str) -> Result<bool, HashServiceError> {
         let delete_result = self.collection.as_mut().unwrap().delete_one(
             doc! { "short_url": key }, None
@@ -60,6 +121,27 @@ impl hashservice::HashService for MongoHashService {
         return Ok(delete_result.deleted_count > 0)
     }
 
+    async fn purge_deleted(&mut self, deleted_before: DateTime<Utc>) -> Result<usize, HashServiceError> {
+        // Only the links in the trash are read, their deletion time is checked here
+        let collection = self.collection.as_ref().unwrap();
+        let cursor = collection.find(doc! { "status": "deleted" }, None).await?;
+        let trashed: Vec<LinkInfo> = cursor.try_collect().await?;
+        let purged: Vec<String> = trashed.into_iter()
+            .filter(|link| hashservice::is_purgeable(link, deleted_before))
+            .map(|link| link.short_url)
+            .collect();
+        if purged.is_empty() {
+            return Ok(0);
+        }
+
+        // Links restored in the meantime are left alone
+        let delete_result = collection.delete_many(
+            doc! { "short_url": { "$in": &purged }, "status": "deleted" }, None
+        ).await?;
+
+        Ok(delete_result.deleted_count as usize)
+    }
+
     async fn init(&mut self) -> Result<(), HashServiceError> {
         let mut client_options = ClientOptions::parse(&self.mongo_config.connection_string).await?;
         // Set the server_api field of the client_options object to Stable API version 1
@@ -71,7 +153,22 @@ impl hashservice::HashService for MongoHashService {
         client.database("admin").run_command(doc! { "ping": 1 }, None).await?;
         log::debug!("Pinged your deployment. You successfully connected to MongoDB!");
 
-        self.collection = Some(client.database(self.mongo_config.database_name.as_str()).collection::<LinkInfo>(self.mongo_config.collection_name.as_str()));
+        let collection = client.database(self.mongo_config.database_name.as_str()).collection::<LinkInfo>(self.mongo_config.collection_name.as_str());
+        // Links of an owner are listed in short url order
+        let owner_index = IndexModel::builder()
+            .keys(doc! { "owner_id": 1, "short_url": 1 })
+            .options(IndexOptions::builder().name(String::from("owner_id")).sparse(true).build())
+            .build();
+        collection.create_index(owner_index, None).await?;
+        // Links are found by short url, and exported in short url order. Concurrent inserts
+        // of a short url fail on the unique index instead of storing it twice.
+        let short_url_index = IndexModel::builder()
+            .keys(doc! { "short_url": 1 })
+            .options(IndexOptions::builder().name(String::from("short_url")).unique(true).build())
+            .build();
+        collection.create_index(short_url_index, None).await?;
+
+        self.collection = Some(collection);
 
         Ok(())
     }
@@ -83,20 +180,71 @@ impl hashservice::HashService for MongoHashService {
             None => return Ok([].to_vec())
         };
         
+        let query_params = query_params.unwrap_or_default();
+        let find_options = FindOptions::builder()
+            .sort(doc! { "short_url": 1 })
+            .skip(query_params.skip.map(|skip| skip as u64))
+            .limit(query_params.top.map(|top| top as i64))
+            .build();
+
         let cursor = coll.find(
-            doc! {}, None
+            doc! {}, find_options
         ).await?;
-        
-        let urls: Vec<LinkInfo> = cursor.try_collect().await.expect("");
 
-        let query_params = match query_params {
-            Some(value) => value,
-            None => return Ok(urls)
-        };
+        let urls: Vec<LinkInfo> = cursor.try_collect().await?;
 
-        let top = query_params.top.unwrap_or(urls.len());
-        let skip = query_params.skip.unwrap_or(0);
-        
-        Ok(urls.into_iter().skip(skip).take(top).collect())
+        Ok(urls)
+    }
+
+    async fn count_links(&mut self) -> Result<usize, HashServiceError> {
+        let count = self.collection.as_mut().unwrap().count_documents(doc! {}, None).await?;
+
+        Ok(count as usize)
+    }
+
+    async fn get_links_after(&mut self, after: Option<&str>, owner_id: Option<&str>, top: usize) -> Result<Vec<LinkInfo>, HashServiceError> {
+        let mut filter = doc! {};
+        if let Some(after) = after {
+            filter.insert("short_url", doc! { "$gt": after });
+        }
+        if let Some(owner_id) = owner_id {
+            filter.insert("owner_id", owner_id);
+        }
+        // Served by the short_url index, or the owner_id index for the links of an owner
+        let find_options = FindOptions::builder()
+            .sort(doc! { "short_url": 1 })
+            .limit(top as i64)
+            .build();
+
+        let cursor = self.collection.as_mut().unwrap().find(filter, find_options).await?;
+
+        Ok(cursor.try_collect().await?)
     }
-}
\ No newline at end of file
+
+    async fn get_links_by_owner(&mut self, owner_id: &str, query_params: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError> {
+        let query_params = query_params.unwrap_or_default();
+        let find_options = FindOptions::builder()
+            .sort(doc! { "short_url": 1 })
+            .skip(query_params.skip.map(|skip| skip as u64))
+            .limit(query_params.top.map(|top| top as i64))
+            .build();
+
+        let cursor = self.collection.as_mut().unwrap().find(
+            doc! { "owner_id": owner_id }, find_options
+        ).await?;
+
+        Ok(cursor.try_collect().await?)
+    }
+}
+
+/// Position in the written batch of a write the unique index on `short_url` rejected,
+/// 0 for a single write. `None` for every other error.
+fn duplicate_key_index(err: &Error) -> Option<usize> {
+    match err.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == DUPLICATE_KEY_ERROR_CODE => Some(0),
+        ErrorKind::BulkWrite(failure) => failure.write_errors.iter().flatten()
+            .find(|write_error| write_error.code == DUPLICATE_KEY_ERROR_CODE)
+            .map(|write_error| write_error.index),
+        _ => None
+    }
+}
@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use mongodb::{bson::doc, options::{ClientOptions, FindOptions, ReplaceOptions, ServerApi, ServerApiVersion}, Client, Collection};
+
+use crate::{configuration, constants::API_KEYS_COLLECTION_NAME, models::apikey::ApiKey};
+
+use super::hashserviceerror::HashServiceError;
+use super::keystore::KeyStore;
+
+/// Keys in their own collection of the links' database.
+pub struct MongoKeyStore {
+    mongo_config: configuration::settings::MongoConfig,
+    collection: Option<Collection<ApiKey>>
+}
+
+impl MongoKeyStore {
+    pub fn new(config: &configuration::settings::MongoConfig) -> Self {
+        MongoKeyStore {
+            mongo_config: config.clone(),
+            collection: None
+        }
+    }
+}
+
+#[async_trait]
+impl KeyStore for MongoKeyStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        let mut client_options = ClientOptions::parse(&self.mongo_config.connection_string).await?;
+        let server_api = ServerApi::builder().version(ServerApiVersion::V1).build();
+        client_options.server_api = Some(server_api);
+        let client = Client::with_options(client_options)?;
+
+        self.collection = Some(client.database(self.mongo_config.database_name.as_str()).collection::<ApiKey>(API_KEYS_COLLECTION_NAME));
+
+        Ok(())
+    }
+
+    async fn list(&mut self) -> Result<Vec<ApiKey>, HashServiceError> {
+        let find_options = FindOptions::builder()
+            .sort(doc! { "name": 1 })
+            .build();
+
+        let cursor = self.collection.as_mut().unwrap().find(doc! {}, find_options).await?;
+
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn find(&mut self, name: &str) -> Result<Option<ApiKey>, HashServiceError> {
+        let find_result = self.collection.as_mut().unwrap().find_one(
+            doc! { "name": name }, None
+        ).await?;
+
+        Ok(find_result)
+    }
+
+    async fn upsert(&mut self, key: &ApiKey) -> Result<(), HashServiceError> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.collection.as_mut().unwrap().replace_one(
+            doc! { "name": &key.name }, key, options
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn delete(&mut self, name: &str) -> Result<bool, HashServiceError> {
+        let delete_result = self.collection.as_mut().unwrap().delete_one(
+            doc! { "name": name }, None
+        ).await?;
+
+        Ok(delete_result.deleted_count > 0)
+    }
+}
@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use mongodb::{bson::doc, options::{ClientOptions, IndexOptions, ServerApi, ServerApiVersion, UpdateOptions}, Client, Collection, IndexModel};
+
+use crate::{configuration, constants::USAGE_COLLECTION_NAME, models::tenantusage::DailyShortens};
+
+use super::hashserviceerror::HashServiceError;
+use super::usagestore::UsageStore;
+
+/// A document per tenant and day in its own collection of the links' database.
+pub struct MongoUsageStore {
+    mongo_config: configuration::settings::MongoConfig,
+    collection: Option<Collection<DailyShortens>>
+}
+
+impl MongoUsageStore {
+    pub fn new(config: &configuration::settings::MongoConfig) -> Self {
+        MongoUsageStore {
+            mongo_config: config.clone(),
+            collection: None
+        }
+    }
+}
+
+#[async_trait]
+impl UsageStore for MongoUsageStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        let mut client_options = ClientOptions::parse(&self.mongo_config.connection_string).await?;
+        let server_api = ServerApi::builder().version(ServerApiVersion::V1).build();
+        client_options.server_api = Some(server_api);
+        let client = Client::with_options(client_options)?;
+
+        let collection = client.database(self.mongo_config.database_name.as_str()).collection::<DailyShortens>(USAGE_COLLECTION_NAME);
+        // Instances counting the first shorten of a day at once upsert one document
+        let day_index = IndexModel::builder()
+            .keys(doc! { "tenant": 1, "day": 1 })
+            .options(IndexOptions::builder().name(String::from("tenant_day")).unique(true).build())
+            .build();
+        collection.create_index(day_index, None).await?;
+        self.collection = Some(collection);
+
+        Ok(())
+    }
+
+    async fn shortens_on(&mut self, tenant: &str, day: NaiveDate) -> Result<usize, HashServiceError> {
+        let found = self.collection.as_mut().unwrap().find_one(
+            doc! { "tenant": tenant, "day": day.format("%Y-%m-%d").to_string() }, None
+        ).await?;
+
+        Ok(found.map_or(0, |daily| daily.shortens.max(0) as usize))
+    }
+
+    async fn record_shortens(&mut self, tenant: &str, day: NaiveDate, shortens: usize) -> Result<(), HashServiceError> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        self.collection.as_mut().unwrap().update_one(
+            doc! { "tenant": tenant, "day": day.format("%Y-%m-%d").to_string() },
+            doc! { "$inc": { "shortens": shortens as i64 } },
+            options
+        ).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::{constants, models::{linkinfo::LinkInfo, queryparams::QueryParams}};
+
+use async_trait::async_trait;
+use reqwest::{header, StatusCode};
+
+use super::antientropy::{MerkleDigest, MerkleTree};
+use super::cluster::Node;
+use super::hashserviceerror::HashServiceError;
+use super::linkmerge::LinkSource;
+
+/// HTTP client used by the coordinator to talk to the nodes it replicates to.
+#[derive(Clone)]
+pub struct NodeClient {
+    client: reqwest::Client,
+    // Without a total timeout, listing a large node may take longer than a single request
+    streaming_client: reqwest::Client,
+    api_key: Option<String>,
+}
+
+impl NodeClient {
+    pub fn new(api_key: Option<String>) -> Self {
+        let timeout = Duration::from_secs(constants::NODE_REQUEST_TIMEOUT);
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap_or_default();
+        let streaming_client = reqwest::Client::builder()
+            .connect_timeout(timeout)
+            .build()
+            .unwrap_or_default();
+
+        NodeClient { client, streaming_client, api_key }
+    }
+
+    fn with_api_key(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(api_key) => request.header(constants::API_KEY_HEADER, api_key),
+            None => request
+        }
+    }
+
+    pub async fn test_connection(&self, node: &Node) -> Result<(), HashServiceError> {
+        let _resp = self.client.get(format!("{}/hello", node.base_url()))
+            .send()
+            .await?
+                .json::<HashMap<String, String>>()
+                .await?;
+
+        Ok(())
+    }
+
+    /// Requests the node's links as NDJSON, ordered by short url.
+    pub async fn get_links_stream(&self, node: &Node, query_params: &QueryParams) -> Result<NdjsonLinkStream, HashServiceError> {
+        let response = self.with_api_key(self.streaming_client.get(format!("{}/admin/urls", node.base_url())))
+            .header(header::ACCEPT, constants::APPLICATION_NDJSON)
+            .query(query_params)
+            .send()
+            .await?;
+        check_status(node, &response)?;
+
+        Ok(NdjsonLinkStream { response, buffer: Vec::new(), finished: false })
+    }
+
+    pub async fn find(&self, node: &Node, key: &str) -> Result<Option<LinkInfo>, HashServiceError> {
+        // Summaries of owned links are only shown to their owner and admins
+        let response = self.with_api_key(self.client.get(format!("{}/{}/summary", node.base_url(), key)))
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        check_status(node, &response)?;
+
+        Ok(Some(response.json::<LinkInfo>().await?))
+    }
+
+    pub async fn insert(&self, node: &Node, value: &str) -> Result<LinkInfo, HashServiceError> {
+        let response = self.client.post(format!("{}/shorten", node.base_url()))
+            .json(&serde_json::json!({ "long_url": value }))
+            .send()
+            .await?;
+        check_status(node, &response)?;
+
+        Ok(response.json::<LinkInfo>().await?)
+    }
+
+    pub async fn upsert(&self, node: &Node, value: &LinkInfo) -> Result<(), HashServiceError> {
+        let response = self.with_api_key(self.client.put(format!("{}/admin/{}", node.base_url(), value.short_url)))
+            .header(constants::REPLICA_HEADER, "true")
+            .json(value)
+            .send()
+            .await?;
+        check_status(node, &response)
+    }
+
+    pub async fn delete(&self, node: &Node, key: &str) -> Result<bool, HashServiceError> {
+        let response = self.with_api_key(self.client.delete(format!("{}/admin/{}", node.base_url(), key)))
+            .header(constants::REPLICA_HEADER, "true")
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        check_status(node, &response)?;
+
+        Ok(true)
+    }
+
+    pub async fn get_digest(&self, node: &Node, ranges: usize) -> Result<MerkleTree, HashServiceError> {
+        let response = self.with_api_key(self.client.get(format!("{}/admin/sync/digest", node.base_url())))
+            .query(&[("ranges", ranges)])
+            .send()
+            .await?;
+        check_status(node, &response)?;
+
+        let digest = response.json::<MerkleDigest>().await?;
+        MerkleTree::from_digest(&digest).ok_or(HashServiceError::Unknown)
+    }
+
+    pub async fn get_range(&self, node: &Node, range: usize, ranges: usize) -> Result<Vec<LinkInfo>, HashServiceError> {
+        let response = self.with_api_key(self.client.get(format!("{}/admin/sync/range/{}", node.base_url(), range)))
+            .query(&[("ranges", ranges)])
+            .send()
+            .await?;
+        check_status(node, &response)?;
+
+        Ok(response.json::<Vec<LinkInfo>>().await?)
+    }
+}
+
+/// Links of one node, parsed line by line while the response body is still arriving.
+pub struct NdjsonLinkStream {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+#[async_trait]
+impl LinkSource for NdjsonLinkStream {
+    async fn next_link(&mut self) -> Result<Option<LinkInfo>, HashServiceError> {
+        loop {
+            let line: Vec<u8> = match self.buffer.iter().position(|byte| *byte == b'\n') {
+                Some(position) => self.buffer.drain(..=position).collect(),
+                // The last line doesn't have to end with a new line
+                None if self.finished => std::mem::take(&mut self.buffer),
+                None => {
+                    match self.response.chunk().await? {
+                        Some(chunk) => self.buffer.extend_from_slice(&chunk),
+                        None => self.finished = true
+                    }
+                    continue;
+                }
+            };
+
+            if line.iter().all(|byte| byte.is_ascii_whitespace()) {
+                if self.finished && self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                continue;
+            }
+
+            return Ok(Some(serde_json::from_slice::<LinkInfo>(&line)?));
+        }
+    }
+}
+
+fn check_status(node: &Node, response: &reqwest::Response) -> Result<(), HashServiceError> {
+    if response.status().is_success() {
+        return Ok(());
+    }
+
+    Err(HashServiceError::NodeError { node: node.to_string(), status: response.status().as_u16() })
+}
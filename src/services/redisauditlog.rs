@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use crate::{configuration, constants::{AUDIT_EVENTS_KEY, AUDIT_INDEX_PREFIX, AUDIT_QUERY_PAGE_SIZE, LEGACY_AUDIT_LOG_KEY}, models::auditevent::{AuditEvent, AuditQuery}};
+
+use super::auditlog::AuditLog;
+use super::hashserviceerror::HashServiceError;
+
+/// Events as JSON strings in a hash by id, indexed by sorted sets scored with the time of
+/// the event: one of every event and one per link, actor and tenant. Their keys are prefixed
+/// so they aren't listed as links.
+pub struct RedisAuditLog {
+    redis_config: configuration::settings::RedisConfig,
+    connection: Option<ConnectionManager>,
+}
+
+impl RedisAuditLog {
+    pub fn new(config: &configuration::settings::RedisConfig) -> Self {
+        RedisAuditLog {
+            redis_config: config.clone(),
+            connection: None
+        }
+    }
+
+    fn connection(&self) -> ConnectionManager {
+        self.connection.clone().unwrap()
+    }
+
+    /// Moves the events of the list earlier versions appended to into the indexes.
+    async fn migrate_legacy_log(&mut self) -> Result<(), HashServiceError> {
+        let values: Vec<String> = self.connection().lrange(LEGACY_AUDIT_LOG_KEY, 0, -1).await?;
+        for value in &values {
+            let event: AuditEvent = serde_json::from_str(value)?;
+            self.record(&event).await?;
+        }
+        if !values.is_empty() {
+            self.connection().del::<_, ()>(LEGACY_AUDIT_LOG_KEY).await?;
+            log::info!("Moved {} audit events to the indexed audit log", values.len());
+        }
+
+        Ok(())
+    }
+}
+
+fn index_key(field: &str, value: &str) -> String {
+    format!("{}{}:{}", AUDIT_INDEX_PREFIX, field, value)
+}
+
+/// Index to read the events of a query from, the most selective filter is used.
+fn query_index(query: &AuditQuery) -> String {
+    match (&query.link, &query.actor, &query.tenant) {
+        (Some(link), _, _) => index_key("link", link),
+        (None, Some(actor), _) => index_key("actor", actor),
+        (None, None, Some(tenant)) => index_key("tenant", tenant),
+        (None, None, None) => format!("{}all", AUDIT_INDEX_PREFIX)
+    }
+}
+
+#[async_trait]
+impl AuditLog for RedisAuditLog {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        let client = redis::Client::open(self.redis_config.connection_string.clone())?;
+        self.connection = Some(ConnectionManager::new(client).await?);
+        self.migrate_legacy_log().await?;
+
+        Ok(())
+    }
+
+    async fn record(&mut self, event: &AuditEvent) -> Result<(), HashServiceError> {
+        let value = serde_json::to_string(event)?;
+        let score = event.timestamp.timestamp_micros();
+
+        let mut pipeline = redis::pipe();
+        pipeline.atomic()
+            .hset(AUDIT_EVENTS_KEY, &event.id, value).ignore()
+            .zadd(format!("{}all", AUDIT_INDEX_PREFIX), &event.id, score).ignore();
+        for (field, value) in [("link", &event.link), ("actor", &event.actor), ("tenant", &event.tenant)] {
+            if let Some(value) = value {
+                pipeline.zadd(index_key(field, value), &event.id, score).ignore();
+            }
+        }
+        pipeline.query_async::<_, ()>(&mut self.connection()).await?;
+
+        Ok(())
+    }
+
+    async fn query(&mut self, query: &AuditQuery) -> Result<Vec<AuditEvent>, HashServiceError> {
+        let index = query_index(query);
+        let min = query.min_micros().map_or(String::from("-inf"), |micros| micros.to_string());
+        let limit = query.limit.unwrap_or(usize::MAX);
+
+        // The index narrows the events down by time and one filter, the others are checked
+        // a page at a time until the limit is reached
+        let mut events = Vec::new();
+        let mut offset = 0;
+        loop {
+            let ids: Vec<String> = self.connection()
+                .zrangebyscore_limit(&index, &min, "+inf", offset as isize, AUDIT_QUERY_PAGE_SIZE as isize).await?;
+            if ids.is_empty() {
+                break;
+            }
+            offset += ids.len();
+
+            let values: Vec<Option<String>> = redis::cmd("HMGET").arg(AUDIT_EVENTS_KEY).arg(&ids)
+                .query_async(&mut self.connection()).await?;
+            for value in values.into_iter().flatten() {
+                let event: AuditEvent = serde_json::from_str(&value)?;
+                if query.matches(&event) {
+                    events.push(event);
+                    if events.len() >= limit {
+                        return Ok(events);
+                    }
+                }
+            }
+
+            if ids.len() < AUDIT_QUERY_PAGE_SIZE {
+                break;
+            }
+        }
+
+        Ok(events)
+    }
+}
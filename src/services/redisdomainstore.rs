@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use crate::{configuration, constants::DOMAIN_PREFIX, models::domain::Domain};
+
+use super::domainstore::DomainStore;
+use super::hashserviceerror::HashServiceError;
+
+/// Domains as JSON strings next to the links, their names are prefixed so they
+/// aren't listed as links.
+pub struct RedisDomainStore {
+    redis_config: configuration::settings::RedisConfig,
+    connection: Option<ConnectionManager>,
+}
+
+impl RedisDomainStore {
+    pub fn new(config: &configuration::settings::RedisConfig) -> Self {
+        RedisDomainStore {
+            redis_config: config.clone(),
+            connection: None
+        }
+    }
+
+    fn connection(&self) -> ConnectionManager {
+        self.connection.clone().unwrap()
+    }
+}
+
+#[async_trait]
+impl DomainStore for RedisDomainStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        let client = redis::Client::open(self.redis_config.connection_string.clone())?;
+        self.connection = Some(ConnectionManager::new(client).await?);
+
+        Ok(())
+    }
+
+    async fn list(&mut self) -> Result<Vec<Domain>, HashServiceError> {
+        let mut connection = self.connection();
+        let mut names: Vec<String> = connection.keys(format!("{}*", DOMAIN_PREFIX)).await?;
+        names.sort();
+
+        let mut domains = Vec::new();
+        for name in names {
+            let value: Option<String> = connection.get(&name).await?;
+            if let Some(value) = value {
+                domains.push(serde_json::from_str(&value)?);
+            }
+        }
+
+        Ok(domains)
+    }
+
+    async fn upsert(&mut self, domain: &Domain) -> Result<(), HashServiceError> {
+        let value = serde_json::to_string(domain)?;
+        self.connection().set::<_, _, ()>(format!("{}{}", DOMAIN_PREFIX, domain.name), value).await?;
+
+        Ok(())
+    }
+
+    async fn delete(&mut self, name: &str) -> Result<bool, HashServiceError> {
+        let deleted: usize = self.connection().del(format!("{}{}", DOMAIN_PREFIX, name)).await?;
+
+        Ok(deleted > 0)
+    }
+}
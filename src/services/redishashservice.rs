@@ -1,4 +1,6 @@
-use crate::{configuration, models::{builders::build_link_info, linkinfo::LinkInfo, queryparams::QueryParams}, services::hashservice};
+use std::collections::HashMap;
+
+use crate::{configuration, constants::{API_KEY_PREFIX, AUDIT_KEY_PREFIX, DOMAIN_PREFIX, LINK_INDEX_KEY, OWNER_INDEX_PREFIX, RATE_LIMIT_KEY_PREFIX, TENANT_KEY_PREFIX, USAGE_KEY_PREFIX}, models::{builders::build_link_info, linkinfo::LinkInfo, queryparams::QueryParams}, services::hashservice};
 use async_trait::async_trait;
 use redis::{Commands, JsonCommands};
 
@@ -7,15 +9,87 @@ use super::{hashfunction, hashserviceerror::HashServiceError};
 pub struct RedisHashService {
     redis_config: configuration::settings::RedisConfig,
     connection: Option<redis::Connection>,
+    /// Prefix of every key, "tenant:<id>:" for the links of a tenant
+    prefix: String,
 }
 
 impl RedisHashService {
     pub fn new(config: &configuration::settings::RedisConfig) -> impl hashservice::HashService {
         RedisHashService {
             redis_config: config.clone(),
-            connection: None
+            connection: None,
+            prefix: String::new()
         }
     }
+
+    pub fn for_tenant(config: &configuration::settings::RedisConfig, tenant: &str) -> impl hashservice::HashService {
+        RedisHashService {
+            redis_config: config.clone(),
+            connection: None,
+            prefix: format!("{}{}:", TENANT_KEY_PREFIX, tenant)
+        }
+    }
+
+    fn key(&self, short_url: &str) -> String {
+        format!("{}{}", self.prefix, short_url)
+    }
+
+    fn owner_key(&self, owner_id: &str) -> String {
+        format!("{}{}{}", self.prefix, OWNER_INDEX_PREFIX, owner_id)
+    }
+
+    fn link_index_key(&self) -> String {
+        format!("{}{}", self.prefix, LINK_INDEX_KEY)
+    }
+
+    /// Short urls of the stored links in order, found by listing the keys.
+    fn stored_short_urls(&mut self) -> Result<Vec<String>, HashServiceError> {
+        let pattern = format!("{}*", self.prefix);
+        let stored_keys: Vec<String> = self.connection.as_mut().unwrap().keys(pattern)?;
+        let mut keys: Vec<String> = stored_keys.into_iter()
+            .map(|key| key[self.prefix.len()..].to_string())
+            .collect();
+        // Rate limit buckets, API keys, domains, usage counters, the indexes and the links of tenants share the database with the links
+        keys.retain(|key| key != LINK_INDEX_KEY
+            && [RATE_LIMIT_KEY_PREFIX, API_KEY_PREFIX, AUDIT_KEY_PREFIX, DOMAIN_PREFIX, USAGE_KEY_PREFIX, OWNER_INDEX_PREFIX, TENANT_KEY_PREFIX].iter().all(|prefix| !key.starts_with(prefix)));
+        keys.sort();
+
+        Ok(keys)
+    }
+
+    /// Stored link, `None` when the key doesn't exist.
+    fn stored_link(&mut self, short_url: &str) -> Result<Option<LinkInfo>, HashServiceError> {
+        let key = self.key(short_url);
+        let connection = self.connection.as_mut().unwrap();
+        if !connection.exists::<_, bool>(&key)? {
+            return Ok(None);
+        }
+
+        let result = connection.json_get::<&str, &str, String>(&key, "$")?;
+        let found_links: Vec<LinkInfo> = serde_json::from_str(result.as_str())?;
+
+        Ok(found_links.into_iter().next())
+    }
+
+    /// Moves the short url between the sets indexing the links of every owner.
+    fn index_owner(&mut self, previous: Option<&LinkInfo>, current: Option<&LinkInfo>) -> Result<(), HashServiceError> {
+        let current_owner = current.and_then(|link| link.owner_id.as_ref());
+        let previous_key = previous
+            .and_then(|previous| previous.owner_id.as_ref().filter(|owner| Some(*owner) != current_owner))
+            .map(|previous_owner| self.owner_key(previous_owner));
+        let current_key = current_owner.map(|current_owner| self.owner_key(current_owner));
+        let connection = self.connection.as_mut().unwrap();
+
+        if let (Some(previous), Some(previous_key)) = (previous, previous_key) {
+            connection.srem::<_, _, ()>(previous_key, &previous.short_url)?;
+        }
+
+        if let (Some(current), Some(current_key)) = (current, current_key) {
+            connection.sadd::<_, _, ()>(current_key, &current.short_url)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -24,27 +98,120 @@ impl hashservice::HashService for RedisHashService {
         let client = redis::Client::open(self.redis_config.connection_string.clone()).unwrap();
         self.connection = Some(client.get_connection().unwrap());
 
+        // Links stored before the index was kept are added to it once
+        let index_key = self.link_index_key();
+        if !self.connection.as_mut().unwrap().exists::<_, bool>(&index_key)? {
+            let short_urls = self.stored_short_urls()?;
+            if !short_urls.is_empty() {
+                self.connection.as_mut().unwrap().sadd::<_, _, ()>(&index_key, short_urls)?;
+            }
+        }
+
         Ok(())
     }
 
     async fn insert(&mut self, value: &str) -> Result<LinkInfo, HashServiceError> {
         let hash_value = hashfunction::hash(value);
 
+        // Shortening a url again must not drop its statistics and owner
+        if let Some(existing) = self.stored_link(&hash_value)? {
+            return hashservice::stored_link_of(existing, value);
+        }
+
         let new_link = build_link_info(hash_value.clone(), String::from(value));
         
-        self.connection.as_mut().unwrap().json_set::<_, _, _, LinkInfo>(&hash_value, "$", &new_link)?;
+        let key = self.key(&hash_value);
+        let index_key = self.link_index_key();
+        self.connection.as_mut().unwrap().json_set::<_, _, _, LinkInfo>(&key, "$", &new_link)?;
+        self.connection.as_mut().unwrap().sadd::<_, _, ()>(index_key, &hash_value)?;
 
         Ok(new_link)
     }
 
     async fn update(&mut self, key: &str, value: &LinkInfo) -> Result<bool, HashServiceError> {
-        self.connection.as_mut().unwrap().json_set::<_, _, _, LinkInfo>(key, "$", value)?;
+        let previous = self.stored_link(key)?;
+        let stored_key = self.key(key);
+        let index_key = self.link_index_key();
+        self.connection.as_mut().unwrap().json_set::<_, _, _, LinkInfo>(&stored_key, "$", value)?;
+        self.connection.as_mut().unwrap().sadd::<_, _, ()>(index_key, key)?;
+
+        let mut value = value.clone();
+        value.short_url = key.to_string();
+        self.index_owner(previous.as_ref(), Some(&value))?;
 
         Ok(true)
     }
 
+    async fn upsert(&mut self, value: &LinkInfo) -> Result<(), HashServiceError> {
+        let previous = self.stored_link(&value.short_url)?;
+        let key = self.key(&value.short_url);
+        let index_key = self.link_index_key();
+        self.connection.as_mut().unwrap().json_set::<_, _, _, LinkInfo>(&key, "$", value)?;
+        self.connection.as_mut().unwrap().sadd::<_, _, ()>(index_key, &value.short_url)?;
+        self.index_owner(previous.as_ref(), Some(value))?;
+
+        Ok(())
+    }
+
+    async fn insert_many(&mut self, values: &[LinkInfo]) -> Result<(), HashServiceError> {
+        // One round trip for the links and the owner index
+        let mut pipeline = redis::pipe();
+        for value in values {
+            pipeline.json_set(self.key(&value.short_url), "$", value)?.ignore();
+            pipeline.sadd(self.link_index_key(), &value.short_url).ignore();
+            if let Some(owner_id) = &value.owner_id {
+                pipeline.sadd(self.owner_key(owner_id), &value.short_url).ignore();
+            }
+        }
+        pipeline.query::<()>(self.connection.as_mut().unwrap())?;
+
+        Ok(())
+    }
+
+    async fn count_links(&mut self) -> Result<usize, HashServiceError> {
+        let index_key = self.link_index_key();
+        Ok(self.connection.as_mut().unwrap().scard(index_key)?)
+    }
+
+    async fn get_links_by_owner(&mut self, owner_id: &str, query_params: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError> {
+        let owner_key = self.owner_key(owner_id);
+        let mut short_urls: Vec<String> = self.connection.as_mut().unwrap().smembers(owner_key)?;
+        short_urls.sort();
+
+        let query_params = query_params.unwrap_or_default();
+        let mut links = Vec::new();
+        for short_url in short_urls.into_iter().skip(query_params.skip.unwrap_or(0)).take(query_params.top.unwrap_or(usize::MAX)) {
+            if let Some(link) = self.stored_link(&short_url)? {
+                links.push(link);
+            }
+        }
+
+        Ok(links)
+    }
+
+    async fn get_links_after(&mut self, after: Option<&str>, owner_id: Option<&str>, top: usize) -> Result<Vec<LinkInfo>, HashServiceError> {
+        // Only the short urls of the index are read in full, the links of the page one by one
+        let index_key = match owner_id {
+            Some(owner_id) => self.owner_key(owner_id),
+            None => self.link_index_key()
+        };
+        let mut short_urls: Vec<String> = self.connection.as_mut().unwrap().smembers(index_key)?;
+        short_urls.retain(|short_url| after.is_none_or(|after| short_url.as_str() > after));
+        short_urls.sort();
+
+        let mut links = Vec::new();
+        for short_url in short_urls.into_iter().take(top) {
+            if let Some(link) = self.stored_link(&short_url)? {
+                links.push(link);
+            }
+        }
+
+        Ok(links)
+    }
+
     async fn find(&mut self, key: &str) -> Result<Option<LinkInfo>, HashServiceError> {
-        let result = self.connection.as_mut().unwrap().json_get::<&str, &str, String>(key, "$")?;
+        let key = self.key(key);
+        let result = self.connection.as_mut().unwrap().json_get::<&str, &str, String>(&key, "$")?;
         
         let found_links: Vec<LinkInfo> = match serde_json::from_str(result.as_str()) {
             Ok(v) => v,
@@ -58,20 +225,49 @@ impl hashservice::HashService for RedisHashService {
         return Ok(Some(found_links.first().unwrap().clone()));
     }
 
+    async fn find_many(&mut self, keys: &[String]) -> Result<HashMap<String, LinkInfo>, HashServiceError> {
+        // One round trip, missing keys answer nil
+        let mut pipeline = redis::pipe();
+        for key in keys {
+            pipeline.json_get(self.key(key), "$")?;
+        }
+        let results: Vec<Option<String>> = pipeline.query(self.connection.as_mut().unwrap())?;
+
+        let mut links = HashMap::new();
+        for (key, result) in keys.iter().zip(results) {
+            let Some(result) = result else { continue };
+            let found_links: Vec<LinkInfo> = serde_json::from_str(&result)?;
+            if let Some(link) = found_links.into_iter().next() {
+                links.insert(key.clone(), link);
+            }
+        }
+
+        Ok(links)
+    }
+
     async fn delete(&mut self, key: &str) -> Result<bool, HashServiceError> {
-        let result = self.connection.as_mut().unwrap().del(key)?;
+        let previous = self.stored_link(key)?;
+        let stored_key = self.key(key);
+        let index_key = self.link_index_key();
+        let result = self.connection.as_mut().unwrap().del(stored_key)?;
+        self.connection.as_mut().unwrap().srem::<_, _, ()>(index_key, key)?;
+        self.index_owner(previous.as_ref(), None)?;
 
         Ok(result)
     }
 
     async fn get_links(&mut self, query_params: Option<QueryParams>) -> Result<Vec<LinkInfo>, HashServiceError> {
-        // Get all keys
-        let keys: Vec<String> = self.connection.as_mut().unwrap().keys("*")?;
+        let keys = self.stored_short_urls()?;
+
+        let query_params = query_params.unwrap_or_default();
+        let top = query_params.top.unwrap_or(keys.len());
+        let skip = query_params.skip.unwrap_or(0);
 
         let mut links: Vec<LinkInfo> = vec![];
         
         // Iterate over keys and get their values
-        for key in keys {
+        for key in keys.into_iter().skip(skip).take(top) {
+            let key = self.key(&key);
             let result = self.connection.as_mut().unwrap().json_get::<&str, &str, String>(key.as_str(), "$")?;
             
             let found_links: Vec<LinkInfo> = match serde_json::from_str(result.as_str()) {
@@ -82,15 +278,6 @@ impl hashservice::HashService for RedisHashService {
             links.push(found_links.first().unwrap().clone());
         }
 
-        // Filter
-        let query_params = match query_params {
-            Some(value) => value,
-            None => return Ok(links)
-        };
-
-        let top = query_params.top.unwrap_or(links.len());
-        let skip = query_params.skip.unwrap_or(0);
-        
-        Ok(links.into_iter().skip(skip).take(top).collect())
+        Ok(links)
     }
 }
\ No newline at end of file
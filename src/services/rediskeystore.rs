@@ -0,0 +1,76 @@
+use async_trait::async_trait;
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use crate::{configuration, constants::API_KEY_PREFIX, models::apikey::ApiKey};
+
+use super::hashserviceerror::HashServiceError;
+use super::keystore::KeyStore;
+
+/// Keys as JSON strings next to the links, their names are prefixed so they
+/// aren't listed as links.
+pub struct RedisKeyStore {
+    redis_config: configuration::settings::RedisConfig,
+    connection: Option<ConnectionManager>,
+}
+
+impl RedisKeyStore {
+    pub fn new(config: &configuration::settings::RedisConfig) -> Self {
+        RedisKeyStore {
+            redis_config: config.clone(),
+            connection: None
+        }
+    }
+
+    fn connection(&self) -> ConnectionManager {
+        // The manager is a cheap handle to a shared multiplexed connection
+        self.connection.clone().unwrap()
+    }
+}
+
+#[async_trait]
+impl KeyStore for RedisKeyStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        let client = redis::Client::open(self.redis_config.connection_string.clone())?;
+        self.connection = Some(ConnectionManager::new(client).await?);
+
+        Ok(())
+    }
+
+    async fn list(&mut self) -> Result<Vec<ApiKey>, HashServiceError> {
+        let mut connection = self.connection();
+        let mut names: Vec<String> = connection.keys(format!("{}*", API_KEY_PREFIX)).await?;
+        names.sort();
+
+        let mut keys = Vec::new();
+        for name in names {
+            let value: Option<String> = connection.get(&name).await?;
+            if let Some(value) = value {
+                keys.push(serde_json::from_str(&value)?);
+            }
+        }
+
+        Ok(keys)
+    }
+
+    async fn find(&mut self, name: &str) -> Result<Option<ApiKey>, HashServiceError> {
+        let value: Option<String> = self.connection().get(format!("{}{}", API_KEY_PREFIX, name)).await?;
+
+        match value {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None)
+        }
+    }
+
+    async fn upsert(&mut self, key: &ApiKey) -> Result<(), HashServiceError> {
+        let value = serde_json::to_string(key)?;
+        self.connection().set::<_, _, ()>(format!("{}{}", API_KEY_PREFIX, key.name), value).await?;
+
+        Ok(())
+    }
+
+    async fn delete(&mut self, name: &str) -> Result<bool, HashServiceError> {
+        let deleted: usize = self.connection().del(format!("{}{}", API_KEY_PREFIX, name)).await?;
+
+        Ok(deleted > 0)
+    }
+}
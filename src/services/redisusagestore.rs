@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use crate::{configuration, constants::{USAGE_KEY_PREFIX, USAGE_KEY_TTL}};
+
+use super::hashserviceerror::HashServiceError;
+use super::usagestore::UsageStore;
+
+/// A counter per tenant and day next to the links, "usage:<tenant>:<day>",
+/// that expires once the day is over.
+pub struct RedisUsageStore {
+    redis_config: configuration::settings::RedisConfig,
+    connection: Option<ConnectionManager>,
+}
+
+impl RedisUsageStore {
+    pub fn new(config: &configuration::settings::RedisConfig) -> Self {
+        RedisUsageStore {
+            redis_config: config.clone(),
+            connection: None
+        }
+    }
+
+    fn connection(&self) -> ConnectionManager {
+        self.connection.clone().unwrap()
+    }
+}
+
+fn usage_key(tenant: &str, day: NaiveDate) -> String {
+    format!("{}{}:{}", USAGE_KEY_PREFIX, tenant, day.format("%Y-%m-%d"))
+}
+
+#[async_trait]
+impl UsageStore for RedisUsageStore {
+    async fn init(&mut self) -> Result<(), HashServiceError> {
+        let client = redis::Client::open(self.redis_config.connection_string.clone())?;
+        self.connection = Some(ConnectionManager::new(client).await?);
+
+        Ok(())
+    }
+
+    async fn shortens_on(&mut self, tenant: &str, day: NaiveDate) -> Result<usize, HashServiceError> {
+        let shortens: Option<usize> = self.connection().get(usage_key(tenant, day)).await?;
+
+        Ok(shortens.unwrap_or(0))
+    }
+
+    async fn record_shortens(&mut self, tenant: &str, day: NaiveDate, shortens: usize) -> Result<(), HashServiceError> {
+        let key = usage_key(tenant, day);
+        redis::pipe()
+            .atomic()
+            .incr(&key, shortens).ignore()
+            .expire(&key, USAGE_KEY_TTL).ignore()
+            .query_async::<_, ()>(&mut self.connection())
+            .await?;
+
+        Ok(())
+    }
+}
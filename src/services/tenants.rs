@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+use crate::{configuration::settings::{Settings, Tenant}, models::tenantusage::TenantUsage, name_of};
+
+use super::hashservice::HashService;
+use super::hashservicefactory::create_tenant_hash_service;
+use super::hashserviceerror::{build_invalid_configuration_error, HashServiceError};
+use super::usagestore::{self, InMemoryUsageStore, SharedUsageStore, UsageStore};
+
+#[derive(Error, Debug)]
+pub enum QuotaError {
+    #[error("tenant '{tenant}' reached its limit of {max_links} links")]
+    MaxLinks {
+        tenant: String,
+        max_links: usize,
+    },
+    #[error("tenant '{tenant}' reached its limit of {max_shortens_per_day} shortens per day")]
+    MaxShortensPerDay {
+        tenant: String,
+        max_shortens_per_day: usize,
+    },
+    #[error(transparent)]
+    HashServiceError(#[from] HashServiceError),
+}
+
+/// Links of a tenant with its quotas.
+pub struct TenantLinks {
+    pub config: Tenant,
+    pub hash_service: Box<dyn HashService>,
+    /// Where the shortens per day are counted
+    usage: SharedUsageStore,
+}
+
+impl TenantLinks {
+    /// Counts the shortens of the tenant on this instance, see `with_usage_store`.
+    pub fn new(config: Tenant, hash_service: Box<dyn HashService>) -> Self {
+        let usage_store: Box<dyn UsageStore> = Box::new(InMemoryUsageStore::new());
+        TenantLinks {
+            config,
+            hash_service,
+            usage: Arc::new(Mutex::new(usage_store))
+        }
+    }
+
+    /// Counts the shortens of the tenant in a store shared with other instances.
+    pub fn with_usage_store(self, usage: SharedUsageStore) -> Self {
+        TenantLinks {
+            usage,
+            ..self
+        }
+    }
+
+    /// Checks the quotas before a url is shortened to `short_url`. Shortening a url
+    /// again returns the stored link, so it only counts against the shortens per day.
+    pub async fn check_shorten(&mut self, short_url: &str, now: DateTime<Utc>) -> Result<(), QuotaError> {
+        if let Some(max_shortens_per_day) = self.config.max_shortens_per_day {
+            if self.shortens_on(now).await? >= max_shortens_per_day {
+                return Err(QuotaError::MaxShortensPerDay { tenant: self.config.id.clone(), max_shortens_per_day });
+            }
+        }
+
+        if let Some(max_links) = self.config.max_links {
+            let shortened_before = self.hash_service.find(short_url).await?.is_some();
+            if !shortened_before && self.hash_service.count_links().await? >= max_links {
+                return Err(QuotaError::MaxLinks { tenant: self.config.id.clone(), max_links });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the quotas before a bulk import shortens `shortens` urls, of which
+    /// `new_links` weren't shortened before. The import is accepted or rejected as a whole.
+    pub async fn check_bulk_shorten(&mut self, shortens: usize, new_links: usize, now: DateTime<Utc>) -> Result<(), QuotaError> {
+        if let Some(max_shortens_per_day) = self.config.max_shortens_per_day {
+            if self.shortens_on(now).await? + shortens > max_shortens_per_day {
+                return Err(QuotaError::MaxShortensPerDay { tenant: self.config.id.clone(), max_shortens_per_day });
+            }
+        }
+
+        self.check_new_links(new_links).await
+    }
+
+    /// Checks the limit of links before `new_links` links are added, e.g. by an upsert.
+    pub async fn check_new_links(&mut self, new_links: usize) -> Result<(), QuotaError> {
+        if let Some(max_links) = self.config.max_links {
+            if new_links > 0 && self.hash_service.count_links().await? + new_links > max_links {
+                return Err(QuotaError::MaxLinks { tenant: self.config.id.clone(), max_links });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts shortens of the tenant on the UTC day of `now`.
+    pub async fn record_shortens(&mut self, shortens: usize, now: DateTime<Utc>) -> Result<(), HashServiceError> {
+        if shortens == 0 {
+            return Ok(());
+        }
+
+        self.usage.lock().await.record_shortens(&self.config.id, now.date_naive(), shortens).await
+    }
+
+    pub async fn usage(&mut self, now: DateTime<Utc>, api_keys: usize) -> Result<TenantUsage, HashServiceError> {
+        Ok(TenantUsage {
+            tenant: self.config.id.clone(),
+            links: self.hash_service.count_links().await?,
+            max_links: self.config.max_links,
+            shortens_today: self.shortens_on(now).await?,
+            max_shortens_per_day: self.config.max_shortens_per_day,
+            api_keys
+        })
+    }
+
+    async fn shortens_on(&self, now: DateTime<Utc>) -> Result<usize, HashServiceError> {
+        self.usage.lock().await.shortens_on(&self.config.id, now.date_naive()).await
+    }
+}
+
+/// Tenants by id, every tenant has its links in a namespace of its own.
+#[derive(Default)]
+pub struct Tenants {
+    tenants: HashMap<String, TenantLinks>,
+}
+
+impl Tenants {
+    pub async fn from_settings(settings: &Settings) -> Result<Self, HashServiceError> {
+        let mut tenants = Tenants::default();
+        if settings.tenants.as_ref().is_none_or(|tenants| tenants.is_empty()) {
+            return Ok(tenants);
+        }
+
+        let usage_store = usagestore::create_usage_store(settings).await?;
+        for tenant in settings.tenants.iter().flatten() {
+            // The id becomes part of key prefixes and collection names
+            let valid_id = !tenant.id.is_empty() && tenant.id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_id {
+                return Err(build_invalid_configuration_error(settings.mode.to_string().as_str(), name_of!(tenants in Settings),
+                    format!("tenant id '{}' may only contain letters, digits, '-' and '_'", tenant.id).as_str()));
+            }
+            if tenants.contains(&tenant.id) {
+                return Err(build_invalid_configuration_error(settings.mode.to_string().as_str(), name_of!(tenants in Settings),
+                    format!("tenant id '{}' is used more than once", tenant.id).as_str()));
+            }
+
+            let hash_service = create_tenant_hash_service(settings, &tenant.id).await?;
+            tenants.insert(TenantLinks::new(tenant.clone(), hash_service).with_usage_store(usage_store.clone()));
+        }
+
+        Ok(tenants)
+    }
+
+    pub fn insert(&mut self, tenant: TenantLinks) {
+        self.tenants.insert(tenant.config.id.clone(), tenant);
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.tenants.contains_key(id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut TenantLinks> {
+        self.tenants.get_mut(id)
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<TenantLinks> {
+        self.tenants.remove(id)
+    }
+
+    /// Ids of the tenants in order.
+    pub fn ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.tenants.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+}
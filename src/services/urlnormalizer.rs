@@ -0,0 +1,96 @@
+use thiserror::Error;
+use url::Url;
+
+use crate::configuration::settings::Settings;
+use crate::constants::DEFAULT_ALLOWED_SCHEMES;
+
+#[derive(Error, Debug)]
+pub enum UrlError {
+    #[error("'{url}' is not an absolute url: {reason}")]
+    InvalidUrl {
+        url: String,
+        reason: String,
+    },
+    #[error("scheme '{scheme}' is not allowed, allowed schemes are {}", allowed_schemes.join(", "))]
+    DisallowedScheme {
+        scheme: String,
+        allowed_schemes: Vec<String>,
+    },
+    #[error("'{url}' can't be shortened: {reason}")]
+    BlockedDestination {
+        url: String,
+        reason: String,
+    },
+    #[error("'{url}' points to a short link of this service")]
+    RedirectLoop {
+        url: String,
+    },
+}
+
+impl UrlError {
+    /// Machine readable kind of the error, returned to clients next to the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            UrlError::InvalidUrl { .. } => "invalid_url",
+            UrlError::DisallowedScheme { .. } => "disallowed_scheme",
+            UrlError::BlockedDestination { .. } => "blocked_destination",
+            UrlError::RedirectLoop { .. } => "redirect_loop",
+        }
+    }
+}
+
+/// Checks the urls to shorten and brings them into one form, so equivalent urls hash to the same link.
+#[derive(Clone, Debug)]
+pub struct UrlNormalizer {
+    allowed_schemes: Vec<String>,
+    sort_query_params: bool,
+}
+
+impl UrlNormalizer {
+    pub fn new(allowed_schemes: Vec<String>, sort_query_params: bool) -> Self {
+        UrlNormalizer {
+            allowed_schemes: allowed_schemes.iter().map(|scheme| scheme.to_ascii_lowercase()).collect(),
+            sort_query_params
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        let url_validation = settings.url_validation.as_ref();
+        let allowed_schemes = url_validation
+            .and_then(|url_validation| url_validation.allowed_schemes.clone())
+            .unwrap_or_else(|| DEFAULT_ALLOWED_SCHEMES.iter().map(|scheme| scheme.to_string()).collect());
+        let sort_query_params = url_validation
+            .and_then(|url_validation| url_validation.sort_query_params)
+            .unwrap_or(false);
+
+        UrlNormalizer::new(allowed_schemes, sort_query_params)
+    }
+
+    /// Parses the url, lowercases its scheme and host, converts an international host to
+    /// punycode and removes the default port. Rejects relative urls and disallowed schemes.
+    pub fn normalize(&self, long_url: &str) -> Result<String, UrlError> {
+        let long_url = long_url.trim();
+        let mut url = Url::parse(long_url).map_err(|err| UrlError::InvalidUrl {
+            url: long_url.to_string(),
+            reason: err.to_string()
+        })?;
+
+        if !self.allowed_schemes.iter().any(|scheme| scheme == url.scheme()) {
+            return Err(UrlError::DisallowedScheme {
+                scheme: url.scheme().to_string(),
+                allowed_schemes: self.allowed_schemes.clone()
+            });
+        }
+        if self.sort_query_params {
+            if let Some(query) = url.query().filter(|query| !query.is_empty()) {
+                // Sorted as written, so the encoding of the parameters stays as it is
+                let mut params: Vec<&str> = query.split('&').collect();
+                params.sort_by_key(|param| param.split('=').next().unwrap_or_default());
+                let query = params.join("&");
+                url.set_query(Some(&query));
+            }
+        }
+
+        Ok(url.to_string())
+    }
+}
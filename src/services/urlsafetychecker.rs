@@ -0,0 +1,106 @@
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use lru::LruCache;
+
+use crate::configuration::settings::Settings;
+use crate::constants::{DEFAULT_SAFETY_CACHE_SIZE, DEFAULT_SAFETY_CACHE_TTL};
+
+use super::hashserviceerror::HashServiceError;
+pub use super::hashprefixsafetychecker::HashPrefixSafetyChecker;
+pub use super::httpsafetychecker::HttpSafetyChecker;
+
+/// Outcome of a safety check.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    Safe,
+    Flagged {
+        /// Kind of threat, e.g. "MALWARE" or "SOCIAL_ENGINEERING"
+        threat: String,
+    },
+}
+
+/// Tells whether a url is known to be unsafe, e.g. to host malware or phishing.
+#[async_trait]
+pub trait UrlSafetyChecker: Send + Sync {
+    async fn check(&self, long_url: &str) -> Result<Verdict, HashServiceError>;
+}
+
+/// The configured checkers, the first one that flags a url decides. Verdicts are
+/// cached per url, so the checkers are asked once per url and cache period. A verdict
+/// about one path says nothing about the other paths of the host.
+pub struct SafetyCheckers {
+    checkers: Vec<Box<dyn UrlSafetyChecker>>,
+    cache: Mutex<LruCache<String, (Instant, Verdict)>>,
+    ttl: Duration,
+}
+
+impl SafetyCheckers {
+    pub fn new(checkers: Vec<Box<dyn UrlSafetyChecker>>, ttl: Duration, cache_size: usize) -> Self {
+        SafetyCheckers {
+            checkers,
+            cache: Mutex::new(LruCache::new(NonZeroUsize::new(cache_size).unwrap_or(NonZeroUsize::MIN))),
+            ttl
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Result<Self, HashServiceError> {
+        let url_safety = settings.url_safety.clone().unwrap_or_default();
+        let mut checkers: Vec<Box<dyn UrlSafetyChecker>> = Vec::new();
+        if let Some(hash_prefix_file) = &url_safety.hash_prefix_file {
+            checkers.push(Box::new(HashPrefixSafetyChecker::from_file(settings, hash_prefix_file)?));
+        }
+        if let Some(checker_url) = &url_safety.checker_url {
+            checkers.push(Box::new(HttpSafetyChecker::new(checker_url)));
+        }
+
+        Ok(SafetyCheckers::new(
+            checkers,
+            Duration::from_secs(url_safety.cache_ttl.unwrap_or(DEFAULT_SAFETY_CACHE_TTL)),
+            url_safety.cache_size.unwrap_or(DEFAULT_SAFETY_CACHE_SIZE)))
+    }
+
+    /// Checks a normalized url. A checker that fails is logged and skipped, so an
+    /// unreachable scanning service doesn't stop urls from being shortened.
+    pub async fn check(&self, long_url: &str) -> Verdict {
+        if self.checkers.is_empty() {
+            return Verdict::Safe;
+        }
+
+        if let Some((checked_at, verdict)) = self.cache.lock().unwrap().get(long_url) {
+            if checked_at.elapsed() < self.ttl {
+                return verdict.clone();
+            }
+        }
+
+        let mut verdict = Verdict::Safe;
+        let mut failed = false;
+        for checker in &self.checkers {
+            match checker.check(long_url).await {
+                Ok(Verdict::Safe) => (),
+                Ok(flagged) => {
+                    verdict = flagged;
+                    break;
+                }
+                Err(err) => {
+                    log::error!("Safety check of '{}' failed: {}", long_url, err);
+                    failed = true;
+                }
+            }
+        }
+
+        // A failed check is tried again the next time the url is checked
+        if !failed || verdict != Verdict::Safe {
+            self.cache.lock().unwrap().put(long_url.to_string(), (Instant::now(), verdict.clone()));
+        }
+        verdict
+    }
+}
+
+impl Default for SafetyCheckers {
+    fn default() -> Self {
+        SafetyCheckers::new(Vec::new(), Duration::from_secs(DEFAULT_SAFETY_CACHE_TTL), DEFAULT_SAFETY_CACHE_SIZE)
+    }
+}
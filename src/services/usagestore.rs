@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use tokio::sync::Mutex;
+
+use crate::configuration::settings::{Mode, Settings};
+
+use super::firestoreusagestore::FirestoreUsageStore;
+use super::hashserviceerror::HashServiceError;
+pub use super::inmemoryusagestore::InMemoryUsageStore;
+use super::mongousagestore::MongoUsageStore;
+use super::redisusagestore::RedisUsageStore;
+
+/// Usage store shared by the tenants of an instance.
+pub type SharedUsageStore = Arc<Mutex<Box<dyn UsageStore>>>;
+
+/// Where the shortens of the tenants are counted per UTC day, so every instance
+/// enforces the quota of a tenant against the same count.
+#[async_trait]
+pub trait UsageStore: Send + Sync {
+    async fn init(&mut self) -> Result<(), HashServiceError>;
+
+    /// Shortens of the tenant on the day.
+    async fn shortens_on(&mut self, tenant: &str, day: NaiveDate) -> Result<usize, HashServiceError>;
+
+    /// Adds shortens to the count of the tenant on the day.
+    async fn record_shortens(&mut self, tenant: &str, day: NaiveDate, shortens: usize) -> Result<(), HashServiceError>;
+}
+
+/// Creates the usage store of the backend the links are kept in, see `keystore::create_key_store`.
+pub async fn create_usage_store(settings: &Settings) -> Result<SharedUsageStore, HashServiceError> {
+    let mode = match &settings.mode {
+        Mode::Gossip => settings.gossip.as_ref().and_then(|gossip| gossip.storage.clone()).unwrap_or_default(),
+        mode => mode.clone()
+    };
+
+    let mut usage_store: Box<dyn UsageStore> = match (mode, &settings.mongo_config, &settings.redis_config, &settings.firestore_config) {
+        (Mode::Mongo, Some(mongo_config), _, _) => Box::new(MongoUsageStore::new(mongo_config)),
+        (Mode::Redis, _, Some(redis_config), _) => Box::new(RedisUsageStore::new(redis_config)),
+        (Mode::Firestore, _, _, Some(firestore_config)) => Box::new(FirestoreUsageStore::new(firestore_config)),
+        _ => Box::new(InMemoryUsageStore::new())
+    };
+
+    usage_store.init().await?;
+    Ok(Arc::new(Mutex::new(usage_store)))
+}
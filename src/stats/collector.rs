@@ -1,17 +1,20 @@
+use std::net::IpAddr;
+
 use actix_web::http::header::{self, HeaderMap};
 use user_agent_parser::UserAgentParser;
 use serde_json::Value;
 
 use crate::{constants, models::analytic::{self, Analytic}};
 
-pub async fn collect_stats(headers: &HeaderMap) -> Analytic {    
+/// Analytic of a visit, `client_ip` is the address of the visitor as told by the trusted proxies.
+pub async fn collect_stats(headers: &HeaderMap, client_ip: Option<IpAddr>) -> Analytic {    
     // Debug: print all headers for complete analysis
     log::debug!("=== All Headers ===");
     for (name, value) in headers.iter() {
         log::debug!("{}: {:?}", name, value);
     }
     let language = extract_language(&headers);
-    let ip = extract_ip(&headers);
+    let ip = client_ip.map(|ip| ip.to_string());
     let os = extract_os(&headers);
     let referrer = extract_referrer(&headers);
     let device_type = extract_device_type(&headers);
@@ -57,73 +60,6 @@ fn extract_os(headers: &HeaderMap) -> Option<String> {
     None
 }
 
-fn extract_ip(headers: &HeaderMap) -> Option<String> {
-    // Debug: print IP-related headers specifically
-    log::info!("=== IP Detection Headers ===");
-    for header_name in &["CF-Connecting-IP", "True-Client-IP", "X-Real-IP", "X-Forwarded-For", "Remote-Addr"] {
-        if let Some(value) = headers.get(*header_name) {
-            log::info!("{}: {:?}", header_name, value);
-        }
-    }
-    // Priority order for IP extraction (most trusted first)
-    
-    // 1. Cloudflare's connecting IP (most reliable when behind Cloudflare)
-    if let Some(ip) = headers.get("CF-Connecting-IP")
-        .and_then(|ip| ip.to_str().ok())
-        .map(|s| s.trim().to_string()) {
-        if !ip.is_empty() && is_valid_ip(&ip) {
-            return Some(ip);
-        }
-    }
-    
-    // 2. True-Client-IP (used by some CDNs)
-    if let Some(ip) = headers.get("True-Client-IP")
-        .and_then(|ip| ip.to_str().ok())
-        .map(|s| s.trim().to_string()) {
-        if !ip.is_empty() && is_valid_ip(&ip) {
-            return Some(ip);
-        }
-    }
-    
-    // 3. X-Real-IP (used by nginx and other proxies)
-    if let Some(ip) = headers.get("X-Real-IP")
-        .and_then(|ip| ip.to_str().ok())
-        .map(|s| s.trim().to_string()) {
-        if !ip.is_empty() && is_valid_ip(&ip) {
-            return Some(ip);
-        }
-    }
-    
-    // 4. X-Forwarded-For (can contain multiple IPs, take the first/leftmost)
-    if let Some(ip) = headers.get(header::X_FORWARDED_FOR.as_str())
-        .and_then(|ip| ip.to_str().ok())
-        .and_then(|s| s.split(',').next())
-        .map(|s| s.trim().to_string()) {
-        if !ip.is_empty() && is_valid_ip(&ip) {
-            return Some(ip);
-        }
-    }
-    
-    // 5. Fallback to remote address (direct connection)
-    headers.get(constants::REMOTE_ADDR)
-        .and_then(|ip| ip.to_str().ok())
-        .map(|s| s.trim().to_string())
-        .filter(|ip| !ip.is_empty() && is_valid_ip(ip))
-}
-
-fn is_valid_ip(ip: &str) -> bool {
-    // Basic IP validation - check if it's a valid IPv4 or IPv6
-    if ip.contains(':') {
-        // IPv6 basic validation
-        ip.chars().all(|c| c.is_ascii_hexdigit() || c == ':') && ip.matches(':').count() >= 2
-    } else {
-        // IPv4 validation
-        ip.split('.')
-            .filter_map(|part| part.parse::<u8>().ok())
-            .count() == 4
-    }
-}
-
 fn extract_referrer(headers: &HeaderMap) -> Option<String> {
     headers
         .get(header::REFERER.as_str())
@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod tests {
+    use rust_short_url::{models::linkinfo::{LinkInfo, LinkStatus}, services::antientropy::{self, MerkleTree}};
+
+    #[test]
+    fn test_identical_replicas_have_no_diff() {
+        // Arrange
+        let links = setup_links(20);
+        let mut reversed = links.clone();
+        reversed.reverse();
+
+        // Act
+        let tree1 = MerkleTree::build(&links, 16);
+        let tree2 = MerkleTree::build(&reversed, 16);
+
+        // Assert
+        assert_eq!(tree1.root(), tree2.root());
+        assert!(tree1.diff(&tree2).is_empty());
+    }
+
+    #[test]
+    fn test_missing_link_diverges_single_range() {
+        // Arrange
+        let links = setup_links(20);
+        let missing = links[7].clone();
+        let partial: Vec<LinkInfo> = links.iter().filter(|link| link.short_url != missing.short_url).cloned().collect();
+
+        // Act
+        let tree1 = MerkleTree::build(&links, 16);
+        let tree2 = MerkleTree::build(&partial, 16);
+
+        // Assert
+        assert_ne!(tree1.root(), tree2.root());
+        assert_eq!(tree1.diff(&tree2), vec![antientropy::range_of(&missing.short_url, 16)]);
+    }
+
+    #[test]
+    fn test_digest_roundtrip() {
+        // Arrange
+        let links = setup_links(5);
+        let tree = MerkleTree::build(&links, 10);
+
+        // Act
+        let digest = tree.digest();
+        let restored = MerkleTree::from_digest(&digest).expect("digest is invalid");
+
+        // Assert
+        assert_eq!(digest.ranges, 16);
+        assert_eq!(restored, tree);
+    }
+
+    #[test]
+    fn test_link_with_more_visits_is_newer() {
+        // Arrange
+        let links = setup_links(1);
+        let mut visited = links[0].clone();
+        visited.analytics = Some(vec![]);
+        let mut newer = visited.clone();
+        newer.analytics.as_mut().unwrap().push(rust_short_url::models::analytic::Analytic {
+            created_at: chrono::Utc::now(),
+            language: None,
+            os: None,
+            ip: None,
+            location: None,
+            referrer: None,
+            device_type: None,
+            browser: None,
+            user_agent: None,
+        });
+
+        // Act & Assert
+        assert!(antientropy::is_newer(&newer, &visited));
+        assert!(!antientropy::is_newer(&visited, &newer));
+    }
+
+    #[test]
+    fn test_link_with_higher_version_is_newer() {
+        // Arrange
+        let links = setup_links(1);
+        let now = chrono::Utc::now();
+        let mut changed = links[0].clone();
+        changed.long_url = String::from("https://a.example/");
+        changed.touch(now);
+        let mut changed_later = links[0].clone();
+        changed_later.long_url = String::from("https://a.example/");
+        changed_later.touch(now + chrono::Duration::seconds(1));
+        // More visits, but fewer changes, and a url that sorts after the changed one
+        let mut visited = links[0].clone();
+        visited.long_url = String::from("https://z.example/");
+        visited.analytics = Some(vec![]);
+
+        // Act & Assert
+        assert!(antientropy::is_newer(&changed, &visited));
+        assert!(!antientropy::is_newer(&visited, &changed));
+        assert!(antientropy::is_newer(&changed_later, &changed));
+        assert!(!antientropy::is_newer(&changed, &changed_later));
+    }
+
+    fn setup_links(count: usize) -> Vec<LinkInfo> {
+        (0..count).map(|i| LinkInfo {
+            short_url: format!("CODE{}", i),
+            long_url: format!("https://doc.rust-lang.org/{}", i),
+            analytics: None,
+            owner_id: None,
+            domain: None,
+            created_at: None,
+            status: LinkStatus::Active,
+            status_reason: None,
+            deleted_at: None,
+            previous_status: None,
+            tags: None,
+            expires_at: None,
+            imported_clicks: None,
+            version: 0,
+            updated_at: None,
+        }).collect()
+    }
+}
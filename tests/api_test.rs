@@ -1,8 +1,11 @@
 #[cfg(test)]
 mod tests {
     use actix_web::{test, App, web, middleware, dev::Service, http};
-    use std::sync::{Arc, Mutex};
-    use rust_short_url::{api::{httpserver::{hello, redirect, shorten, summary, AppData}, ratelimiter::RateLimiter, ratelimitermiddleware::{RateLimiterMiddlewareService, UserError}}, configuration::settings::{ApiServer, RateLimit, Settings}, models::linkinfo::LinkInfo, services::hashservicefactory::create_hash_service};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use sha2::{Digest, Sha256};
+    use tokio::sync::Mutex;
+    use rust_short_url::{api::{apikeys::{self, ApiKeyRegistry}, audittrail::AuditTrail, clientratelimiter::TrustedProxies, domains::DomainRegistry, authmiddleware::ApiKeyMiddleware, jwtauth::JwtValidator, httpserver::{add_domain, audit_events, bulk_shorten, create_key, delete, export, disable_links, hello, import_links, list_domains, list_keys, my_links, redirect, remove_domain, restore, revoke_key, rotate_key, shorten, summary, tenant_usage, upsert, urls, AppData, BulkResponse, BulkStatus, DisabledPage, DisableLinksResponse, ShortenResponse}, ratelimitpolicy::RateLimitPolicies, ratelimitermiddleware::{RateLimiterMiddlewareService, UserError}}, configuration::settings::{ApiServer, Destinations, DisabledLinks, Jwt, JwtAlgorithm, RateLimit, Settings, Tenant, DEFAULT_RATE_LIMIT}, constants::{MAX_BULK_BODY_SIZE, RATE_LIMIT_POLICY_SHORTEN}, models::{apikey::{ApiKey, ApiKeyInfo, IssuedApiKey, Scope}, auditevent::{AuditAction, AuditEvent}, domain::Domain, linkinfo::{LinkInfo, LinkStatus}, tenantusage::TenantUsage}, services::{antientropy, destinationrules::DestinationFilter, hashfunction, hashservicefactory::create_hash_service, linkimport::{ImportReport, ImportStatus}, tenants::Tenants, urlsafetychecker::{HashPrefixSafetyChecker, SafetyCheckers}}};
 
     #[actix_web::test]
     async fn test_index_get() {
@@ -33,7 +36,7 @@ mod tests {
         let long_url = "https://doc.rust-lang.org/1";
         let settings = setup_settings();
         let hash_service = create_hash_service(&settings).await.unwrap();
-        let appdata = web::Data::new(Mutex::new(AppData { settings, hash_service }));
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
 
         let app = test::init_service({
             App::new()
@@ -42,6 +45,7 @@ mod tests {
                 // register HTTP requests handlers
                 .service(web::resource("/shorten").route(web::post().to(shorten)))
                 .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
         }).await;
         
         // Act
@@ -59,13 +63,569 @@ mod tests {
         assert_eq!(link_info.long_url, long_url);
     }
 
+    #[actix_web::test]
+    async fn test_shorten_hash_collision() {
+        // Arrange
+        let long_url = "https://doc.rust-lang.org/";
+        let settings = setup_settings();
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+        hash_service.upsert(&setup_link(&hashfunction::hash(long_url), "https://example.com/")).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::resource("/shorten").route(web::post().to(shorten)))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+
+        // Act
+        let resp = test::call_service(&app, test::TestRequest::post().uri("/shorten")
+            .set_json(serde_json::json!({ "long_url": long_url })).to_request()).await;
+        let stored = appdata.lock().await.hash_service.find(&hashfunction::hash(long_url)).await.unwrap().unwrap();
+
+        // Assert
+        assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+        assert_eq!(stored.long_url, "https://example.com/");
+    }
+
+    #[actix_web::test]
+    async fn test_shorten_normalizes_urls() {
+        // Arrange
+        let settings = setup_settings();
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::resource("/shorten").route(web::post().to(shorten)))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let shorten_req = |long_url: &str| test::TestRequest::post().uri("/shorten")
+            .set_json(serde_json::json!({ "long_url": long_url }))
+            .to_request();
+
+        // Act
+        let first: ShortenResponse = test::call_and_read_body_json(&app, shorten_req("https://Doc.Rust-Lang.org:443/std")).await;
+        let equivalent = test::call_service(&app, shorten_req("HTTPS://doc.rust-lang.org/std")).await;
+        let equivalent_status = equivalent.status();
+        let equivalent: ShortenResponse = test::read_body_json(equivalent).await;
+        let javascript = test::call_service(&app, shorten_req("javascript:alert(1)")).await;
+        let javascript_status = javascript.status();
+        let javascript: serde_json::Value = test::read_body_json(javascript).await;
+        let relative = test::call_service(&app, shorten_req("/relative")).await;
+        let relative_status = relative.status();
+        let relative: serde_json::Value = test::read_body_json(relative).await;
+
+        // Assert
+        assert_eq!(first.link.long_url, "https://doc.rust-lang.org/std");
+        assert_eq!(equivalent_status, http::StatusCode::OK);
+        assert_eq!(equivalent.link.short_url, first.link.short_url);
+        assert_eq!(javascript_status, http::StatusCode::BAD_REQUEST);
+        assert_eq!(javascript["error"], "disallowed_scheme");
+        assert_eq!(javascript["allowed_schemes"], serde_json::json!(["http", "https"]));
+        assert_eq!(relative_status, http::StatusCode::BAD_REQUEST);
+        assert_eq!(relative["error"], "invalid_url");
+    }
+
+    #[actix_web::test]
+    async fn test_blocked_destinations() {
+        // Arrange
+        let mut settings = setup_settings();
+        settings.apiserver.hostname = String::from("surl.example.com");
+        settings.destinations = Some(Destinations { blocked_domains: Some(vec![String::from("phishing.example")]), ..Destinations::default() });
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let mut data = AppData::new(settings.clone(), hash_service);
+        let existing = data.hash_service.insert("https://login.malware.example/").await.unwrap();
+        data.destinations = Arc::new(DestinationFilter::from_settings(&settings).unwrap());
+        let appdata = web::Data::new(Mutex::new(data));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::scope("/admin").service(disable_links).service(upsert))
+                .service(web::resource("/shorten").route(web::post().to(shorten)))
+                .service(web::scope("").service(redirect))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let shorten_req = |long_url: &str| test::TestRequest::post().uri("/shorten")
+            .set_json(serde_json::json!({ "long_url": long_url }))
+            .to_request();
+
+        // Act
+        let blocked = test::call_service(&app, shorten_req("https://www.phishing.example/login")).await;
+        let blocked_status = blocked.status();
+        let blocked: serde_json::Value = test::read_body_json(blocked).await;
+        let redirect_loop = test::call_service(&app, shorten_req("https://surl.example.com/abc123")).await;
+        let redirect_loop_status = redirect_loop.status();
+        let redirect_loop: serde_json::Value = test::read_body_json(redirect_loop).await;
+        let mut changed = existing.clone();
+        changed.long_url = String::from("https://phishing.example/");
+        let blocked_update = test::call_service(&app, test::TestRequest::put().uri(&format!("/admin/{}", existing.short_url))
+            .set_json(&changed).to_request()).await;
+        changed.long_url = String::from("javascript:alert(1)");
+        let script_update = test::call_service(&app, test::TestRequest::put().uri(&format!("/admin/{}", existing.short_url))
+            .set_json(&changed).to_request()).await;
+        let script_update_status = script_update.status();
+        let script_update: serde_json::Value = test::read_body_json(script_update).await;
+        changed.long_url = String::from("HTTPS://Docs.RS:443");
+        let normalized_update = test::call_service(&app, test::TestRequest::put().uri("/admin/docs")
+            .set_json(LinkInfo { short_url: String::from("docs"), ..changed.clone() }).to_request()).await;
+        let normalized = appdata.lock().await.hash_service.find("docs").await.unwrap().unwrap();
+
+        let before_disable = test::call_service(&app, test::TestRequest::get().uri(&format!("/{}", existing.short_url)).to_request()).await;
+        let disabled: DisableLinksResponse = test::call_and_read_body_json(&app, test::TestRequest::post().uri("/admin/destinations/disable")
+            .set_json(serde_json::json!({ "domain": "Malware.example" })).to_request()).await;
+        let after_disable = test::call_service(&app, test::TestRequest::get().uri(&format!("/{}", existing.short_url)).to_request()).await;
+        let disabled_link = appdata.lock().await.hash_service.find(&existing.short_url).await.unwrap().unwrap();
+
+        // Assert
+        assert_eq!(blocked_status, http::StatusCode::BAD_REQUEST);
+        assert_eq!(blocked["error"], "blocked_destination");
+        assert_eq!(redirect_loop_status, http::StatusCode::BAD_REQUEST);
+        assert_eq!(redirect_loop["error"], "redirect_loop");
+        assert_eq!(blocked_update.status(), http::StatusCode::BAD_REQUEST);
+        assert_eq!(script_update_status, http::StatusCode::BAD_REQUEST);
+        assert_eq!(script_update["error"], "disallowed_scheme");
+        assert_eq!(normalized_update.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(normalized.long_url, "https://docs.rs/");
+        assert!(before_disable.status().is_redirection());
+        assert_eq!(disabled.domain, "malware.example");
+        assert_eq!(disabled.disabled, 1);
+        assert_eq!(after_disable.status(), http::StatusCode::GONE);
+        assert_eq!(disabled_link.status, LinkStatus::Disabled);
+        assert_eq!(disabled_link.status_reason.as_deref(), Some("domain 'malware.example' is blocked"));
+    }
+
+    #[actix_web::test]
+    async fn test_quarantine() {
+        // Arrange
+        let settings = setup_settings();
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let mut data = AppData::new(settings, hash_service);
+        let prefixes = format!("{} SOCIAL_ENGINEERING", hex::encode(Sha256::digest("phishing.example/".as_bytes())));
+        let checker = HashPrefixSafetyChecker::parse(&prefixes).unwrap();
+        data.url_safety = Arc::new(SafetyCheckers::new(vec![Box::new(checker)], std::time::Duration::from_secs(60), 10));
+        let appdata = web::Data::new(Mutex::new(data));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::resource("/shorten").route(web::post().to(shorten)))
+                .service(web::scope("").service(redirect))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let shorten_req = |long_url: &str| test::TestRequest::post().uri("/shorten")
+            .set_json(serde_json::json!({ "long_url": long_url }))
+            .to_request();
+
+        // Act
+        let flagged: ShortenResponse = test::call_and_read_body_json(&app, shorten_req("https://phishing.example/login?a=1&b=2")).await;
+        let safe: ShortenResponse = test::call_and_read_body_json(&app, shorten_req("https://doc.rust-lang.org/")).await;
+        let interstitial = test::call_service(&app, test::TestRequest::get().uri(&format!("/{}", flagged.link.short_url)).to_request()).await;
+        let interstitial_status = interstitial.status();
+        let has_location = interstitial.headers().contains_key(http::header::LOCATION);
+        let page = String::from_utf8(test::read_body(interstitial).await.to_vec()).unwrap();
+        let redirected = test::call_service(&app, test::TestRequest::get().uri(&format!("/{}", safe.link.short_url)).to_request()).await;
+
+        // Assert
+        assert_eq!(flagged.link.status, LinkStatus::Quarantined);
+        assert_eq!(flagged.link.status_reason.as_deref(), Some("flagged as SOCIAL_ENGINEERING"));
+        assert_eq!(safe.link.status, LinkStatus::Active);
+        assert_eq!(interstitial_status, http::StatusCode::OK);
+        assert!(!has_location);
+        assert!(page.contains("This link may be unsafe"));
+        assert!(page.contains("https://phishing.example/login?a=1&amp;b=2"));
+        assert!(!page.contains("a=1&b=2"));
+        assert!(redirected.status().is_redirection());
+    }
+
+    #[actix_web::test]
+    async fn test_trash() {
+        // Arrange
+        let settings = setup_settings();
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::scope("/admin").service(delete).service(restore))
+                .service(web::resource("/shorten").route(web::post().to(shorten)))
+                .service(web::scope("").service(redirect))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let link: ShortenResponse = test::call_and_read_body_json(&app, test::TestRequest::post().uri("/shorten")
+            .set_json(serde_json::json!({ "long_url": "https://doc.rust-lang.org/" })).to_request()).await;
+        let short_url = link.link.short_url.clone();
+        let delete_req = || test::TestRequest::delete().uri(&format!("/admin/{}", short_url)).to_request();
+        let restore_req = || test::TestRequest::post().uri(&format!("/admin/{}/restore", short_url)).to_request();
+        let redirect_req = || test::TestRequest::get().uri(&format!("/{}", short_url)).to_request();
+
+        // Act
+        let deleted = test::call_service(&app, delete_req()).await;
+        let deleted_again = test::call_service(&app, delete_req()).await;
+        let deleted_redirect = test::call_service(&app, redirect_req()).await;
+        let trashed = appdata.lock().await.hash_service.find(&short_url).await.unwrap().unwrap();
+        let restored: LinkInfo = test::call_and_read_body_json(&app, restore_req()).await;
+        let restored_redirect = test::call_service(&app, redirect_req()).await;
+        let restored_again = test::call_service(&app, restore_req()).await;
+        let unknown = test::call_service(&app, test::TestRequest::post().uri("/admin/unknown/restore").to_request()).await;
+        test::call_service(&app, delete_req()).await;
+        let not_yet_purged = appdata.lock().await.hash_service.purge_deleted(chrono::Utc::now() - chrono::Duration::days(1)).await.unwrap();
+        let purged = appdata.lock().await.hash_service.purge_deleted(chrono::Utc::now() + chrono::Duration::seconds(1)).await.unwrap();
+        let after_purge = appdata.lock().await.hash_service.find(&short_url).await.unwrap();
+
+        // Assert
+        assert_eq!(deleted.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(deleted_again.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(deleted_redirect.status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(trashed.status, LinkStatus::Deleted);
+        assert_eq!(trashed.previous_status, Some(LinkStatus::Active));
+        assert!(trashed.deleted_at.is_some());
+        // Replicas that missed the delete or restore take over the newer version
+        assert!(antientropy::is_newer(&trashed, &link.link));
+        assert!(antientropy::is_newer(&restored, &trashed));
+        assert_eq!(restored.status, LinkStatus::Active);
+        assert!(restored.deleted_at.is_none());
+        assert!(restored.previous_status.is_none());
+        assert!(restored_redirect.status().is_redirection());
+        assert_eq!(restored_again.status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(unknown.status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(not_yet_purged, 0);
+        assert_eq!(purged, 1);
+        assert!(after_purge.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_restore_checks_url() {
+        // Arrange
+        let mut settings = setup_settings();
+        settings.destinations = Some(Destinations { blocked_domains: Some(vec![String::from("malware.example")]), ..Destinations::default() });
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let mut data = AppData::new(settings.clone(), hash_service);
+        let trash = |long_url: &str| {
+            let mut link = setup_link(&hashfunction::hash(long_url), long_url);
+            link.status = LinkStatus::Deleted;
+            link.previous_status = Some(LinkStatus::Active);
+            link.deleted_at = Some(chrono::Utc::now());
+            link
+        };
+        let blocked = trash("https://malware.example/");
+        let flagged = trash("https://phishing.example/login");
+        data.hash_service.upsert(&blocked).await.unwrap();
+        data.hash_service.upsert(&flagged).await.unwrap();
+        // Blocked and flagged after the links were deleted
+        data.destinations = Arc::new(DestinationFilter::from_settings(&settings).unwrap());
+        let prefixes = format!("{} SOCIAL_ENGINEERING", hex::encode(Sha256::digest("phishing.example/login".as_bytes())));
+        let checker = HashPrefixSafetyChecker::parse(&prefixes).unwrap();
+        data.url_safety = Arc::new(SafetyCheckers::new(vec![Box::new(checker)], std::time::Duration::from_secs(60), 10));
+        let appdata = web::Data::new(Mutex::new(data));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::scope("/admin").service(restore))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let restore_req = |short_url: &str| test::TestRequest::post().uri(&format!("/admin/{}/restore", short_url)).to_request();
+
+        // Act
+        let blocked_restore = test::call_service(&app, restore_req(&blocked.short_url)).await;
+        let blocked_status = blocked_restore.status();
+        let blocked_restore: serde_json::Value = test::read_body_json(blocked_restore).await;
+        let still_trashed = appdata.lock().await.hash_service.find(&blocked.short_url).await.unwrap().unwrap();
+        let quarantined: LinkInfo = test::call_and_read_body_json(&app, restore_req(&flagged.short_url)).await;
+
+        // Assert
+        assert_eq!(blocked_status, http::StatusCode::BAD_REQUEST);
+        assert_eq!(blocked_restore["error"], "blocked_destination");
+        assert_eq!(still_trashed.status, LinkStatus::Deleted);
+        assert_eq!(quarantined.status, LinkStatus::Quarantined);
+        assert_eq!(quarantined.status_reason.as_deref(), Some("flagged as SOCIAL_ENGINEERING"));
+        assert!(quarantined.deleted_at.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_disabled_page() {
+        // Arrange
+        let page_file = std::env::temp_dir().join(format!("disabled-page-{}.html", std::process::id()));
+        std::fs::write(&page_file, "<h1>This link was disabled</h1>").unwrap();
+        let mut settings = setup_settings();
+        settings.disabled_links = Some(DisabledLinks { status: Some(404), page_file: Some(page_file.to_str().unwrap().to_string()) });
+        let disabled_page = DisabledPage::from_settings(&settings).unwrap();
+        settings.disabled_links = Some(DisabledLinks { status: Some(500), page_file: None });
+        let invalid_status = DisabledPage::from_settings(&settings);
+        std::fs::remove_file(&page_file).unwrap();
+
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let mut data = AppData::new(settings, hash_service);
+        let mut link = data.hash_service.insert("https://doc.rust-lang.org/").await.unwrap();
+        link.status = LinkStatus::Disabled;
+        data.hash_service.update(&link.short_url.clone(), &link).await.unwrap();
+        data.disabled_page = disabled_page;
+        let appdata = web::Data::new(Mutex::new(data));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::scope("").service(redirect))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+
+        // Act
+        let resp = test::call_service(&app, test::TestRequest::get().uri(&format!("/{}", link.short_url)).to_request()).await;
+        let status = resp.status();
+        let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+        // Assert
+        assert_eq!(status, http::StatusCode::NOT_FOUND);
+        assert_eq!(page, "<h1>This link was disabled</h1>");
+        assert!(invalid_status.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_bulk_shorten() {
+        // Arrange
+        let settings = setup_settings();
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::scope("/admin").service(web::resource("/links/bulk")
+                    .app_data(web::PayloadConfig::new(MAX_BULK_BODY_SIZE))
+                    .route(web::post().to(bulk_shorten))))
+                .service(web::scope("").service(redirect))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let csv = "long_url,alias,tags,expires_at\n\
+            https://doc.rust-lang.org/,,\"docs,rust\",2999-01-01T00:00:00Z\n\
+            https://crates.io/,crates,,\n\
+            https://DOC.rust-lang.org/,,,\n\
+            javascript:alert(1),,,\n\
+            https://github.com/,crates,,\n\
+            https://example.com/,,,2001-01-01T00:00:00Z\n";
+        let bulk_req = |content_type: &str, body: &str| test::TestRequest::post().uri("/admin/links/bulk")
+            .insert_header((http::header::CONTENT_TYPE, content_type.to_string()))
+            .set_payload(body.to_string())
+            .to_request();
+
+        // Act
+        let imported: BulkResponse = test::call_and_read_body_json(&app, bulk_req("text/csv", csv)).await;
+        let again: BulkResponse = test::call_and_read_body_json(&app, bulk_req("application/x-ndjson",
+            "{\"long_url\": \"https://crates.io/\", \"alias\": \"crates\"}\n{\"long_url\": \"https://www.rust-lang.org/\"}")).await;
+        let unsupported = test::call_service(&app, bulk_req("application/xml", "<links/>")).await;
+        let malformed = test::call_service(&app, bulk_req("application/json", "{\"long_url\": \"https://crates.io/\"}")).await;
+        let large_body: Vec<String> = (0..2000).map(|index| format!("{{\"long_url\": \"https://example.com/{}/{}\"}}", index, "a".repeat(200))).collect();
+        let large: BulkResponse = test::call_and_read_body_json(&app, bulk_req("application/x-ndjson", &large_body.join("\n"))).await;
+        let alias_redirect = test::call_service(&app, test::TestRequest::get().uri("/crates").to_request()).await;
+        let tagged = appdata.lock().await.hash_service.find(imported.results[0].short_url.as_ref().unwrap()).await.unwrap().unwrap();
+
+        // Assert
+        assert_eq!((imported.created, imported.existing, imported.failed), (2, 1, 3));
+        let statuses: Vec<BulkStatus> = imported.results.iter().map(|result| result.status).collect();
+        assert_eq!(statuses, vec![BulkStatus::Created, BulkStatus::Created, BulkStatus::Existing, BulkStatus::Failed, BulkStatus::Failed, BulkStatus::Failed]);
+        assert_eq!(imported.results[1].short_url.as_deref(), Some("crates"));
+        assert_eq!(imported.results[1].short_link.as_deref(), Some("https://localhost/crates"));
+        assert_eq!(imported.results[2].short_url, imported.results[0].short_url);
+        assert_eq!(imported.results[3].error.as_deref(), Some("disallowed_scheme"));
+        assert_eq!(imported.results[4].error.as_deref(), Some("alias_taken"));
+        assert_eq!(imported.results[5].error.as_deref(), Some("invalid_expires_at"));
+        assert_eq!(tagged.tags, Some(vec![String::from("docs"), String::from("rust")]));
+        assert!(tagged.expires_at.is_some());
+        assert_eq!((again.created, again.existing, again.failed), (1, 1, 0));
+        assert_eq!(unsupported.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(malformed.status(), http::StatusCode::BAD_REQUEST);
+        assert_eq!((large.created, large.failed), (2000, 0));
+        assert!(alias_redirect.status().is_redirection());
+    }
+
+    #[actix_web::test]
+    async fn test_expired_link() {
+        // Arrange
+        let settings = setup_settings();
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+        let mut link = hash_service.insert("https://doc.rust-lang.org/").await.unwrap();
+        link.expires_at = Some(chrono::Utc::now() - chrono::Duration::minutes(1));
+        hash_service.update(&link.short_url.clone(), &link).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::scope("").service(redirect))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+
+        // Act
+        let resp = test::call_service(&app, test::TestRequest::get().uri(&format!("/{}", link.short_url)).to_request()).await;
+
+        // Assert
+        assert_eq!(resp.status(), http::StatusCode::GONE);
+    }
+
+    #[actix_web::test]
+    async fn test_import_links() {
+        // Arrange
+        let settings = setup_settings();
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+        let existing = setup_link("crates", "https://crates.io/");
+        hash_service.upsert(&existing).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::scope("/admin").service(web::resource("/links/import")
+                    .app_data(web::PayloadConfig::new(MAX_BULK_BODY_SIZE))
+                    .route(web::post().to(import_links))))
+                .service(web::scope("").service(redirect))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let dump = format!("INSERT INTO `yourls_url` (`keyword`, `url`, `title`, `timestamp`, `ip`, `clicks`) VALUES \
+            ('rust-docs','https://doc.rust-lang.org/','Docs','2024-01-01 10:00:00','127.0.0.1',12),\
+            ('{code}','https://example.com/','Other','2024-01-01 10:00:00','127.0.0.1',3),\
+            ('{code}','https://crates.io/','Crates','2024-01-01 10:00:00','127.0.0.1',5),\
+            ('admin','https://docs.rs/','Reserved','2024-01-01 10:00:00','127.0.0.1',1),\
+            ('1C96D51A','https://www.rust-lang.org/','Hash','2024-01-01 10:00:00','127.0.0.1',1);", code = existing.short_url);
+        let import_req = |query: &str| test::TestRequest::post().uri(&format!("/admin/links/import?{}", query))
+            .set_payload(dump.clone())
+            .to_request();
+
+        // Act
+        let dry_run: ImportReport = test::call_and_read_body_json(&app, import_req("source=yourls&dry_run=true")).await;
+        let not_written = appdata.lock().await.hash_service.find("rust-docs").await.unwrap();
+        let imported: ImportReport = test::call_and_read_body_json(&app, import_req("source=yourls")).await;
+        let again: ImportReport = test::call_and_read_body_json(&app, import_req("source=yourls")).await;
+        let imported_link = appdata.lock().await.hash_service.find("rust-docs").await.unwrap().unwrap();
+        let code_redirect = test::call_service(&app, test::TestRequest::get().uri("/rust-docs").to_request()).await;
+        let unknown_source = test::call_service(&app, import_req("source=tinyurl")).await;
+        let large_rows: Vec<String> = (0..2000).map(|index| format!("('large-{}','https://example.com/{}','Large','2024-01-01 10:00:00','127.0.0.1',0)", index, "a".repeat(200))).collect();
+        let large: ImportReport = test::call_and_read_body_json(&app, test::TestRequest::post().uri("/admin/links/import?source=yourls&dry_run=true")
+            .set_payload(format!("INSERT INTO `yourls_url` (`keyword`, `url`, `title`, `timestamp`, `ip`, `clicks`) VALUES {};", large_rows.join(",")))
+            .to_request()).await;
+
+        // Assert
+        assert!(dry_run.dry_run);
+        assert_eq!((dry_run.imported, dry_run.existing, dry_run.conflicts, dry_run.failed), (1, 1, 1, 2));
+        assert!(not_written.is_none());
+        assert!(!imported.dry_run);
+        let statuses: Vec<ImportStatus> = imported.results.iter().map(|result| result.status).collect();
+        assert_eq!(statuses, vec![ImportStatus::Imported, ImportStatus::Conflict, ImportStatus::Existing, ImportStatus::Failed, ImportStatus::Failed]);
+        // Codes of generated short urls are reserved
+        assert_eq!(imported.results[4].error.as_deref(), Some("invalid_code"));
+        assert_eq!(imported.results[1].existing_long_url.as_deref(), Some("https://crates.io/"));
+        assert_eq!(imported.results[1].error.as_deref(), Some("code_taken"));
+        assert_eq!((again.imported, again.existing, again.conflicts), (0, 2, 1));
+        assert_eq!(imported_link.long_url, "https://doc.rust-lang.org/");
+        assert_eq!(imported_link.imported_clicks, Some(12));
+        assert_eq!(imported_link.created_at, "2024-01-01T10:00:00Z".parse::<chrono::DateTime<chrono::Utc>>().ok());
+        assert!(code_redirect.status().is_redirection());
+        assert_eq!(unknown_source.status(), http::StatusCode::BAD_REQUEST);
+        assert_eq!((large.imported, large.failed), (2000, 0));
+    }
+
+    #[actix_web::test]
+    async fn test_export() {
+        // Arrange
+        let settings = setup_settings();
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+        // More links than one page of the export
+        for index in 0..501 {
+            hash_service.insert(&format!("https://doc.rust-lang.org/{}", index)).await.unwrap();
+        }
+        let mut tagged = hash_service.insert("https://crates.io/").await.unwrap();
+        tagged.tags = Some(vec![String::from("spring")]);
+        hash_service.update(&tagged.short_url.clone(), &tagged).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::scope("/admin").service(export))
+                .service(web::scope("").service(redirect))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        test::call_service(&app, test::TestRequest::get().uri(&format!("/{}", tagged.short_url)).to_request()).await;
+        test::call_service(&app, test::TestRequest::get().uri(&format!("/{}", tagged.short_url)).to_request()).await;
+
+        // Act
+        let csv_resp = test::call_service(&app, test::TestRequest::get().uri("/admin/export").to_request()).await;
+        let disposition = csv_resp.headers().get(http::header::CONTENT_DISPOSITION).unwrap().to_str().unwrap().to_string();
+        let csv = String::from_utf8(test::read_body(csv_resp).await.to_vec()).unwrap();
+        let ndjson = String::from_utf8(test::call_and_read_body(&app, test::TestRequest::get()
+            .uri("/admin/export?format=ndjson&tag=spring").to_request()).await.to_vec()).unwrap();
+        let future = test::call_and_read_body(&app, test::TestRequest::get()
+            .uri("/admin/export?format=ndjson&tag=spring&from=2999-01-01T00:00:00Z").to_request()).await;
+        let unknown_format = test::call_service(&app, test::TestRequest::get().uri("/admin/export?format=xlsx").to_request()).await;
+
+        // Assert
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines[0].starts_with("short_url,long_url,"));
+        assert_eq!(lines.len(), 1 + 501 + 2);
+        assert_eq!(disposition, "attachment; filename=\"links.csv\"");
+        let rows: Vec<serde_json::Value> = ndjson.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|row| row["tags"] == "spring" && row["clicked_at"].is_string()));
+        let future_rows: Vec<serde_json::Value> = String::from_utf8(future.to_vec()).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(future_rows.len(), 1);
+        assert!(future_rows[0]["clicked_at"].is_null());
+        assert_eq!(unknown_format.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_shorten_response() {
+        // Arrange
+        let mut settings = setup_settings();
+        settings.apiserver.scheme = Some(String::from("http"));
+        settings.apiserver.qr_code_url = Some(String::from("https://qr.example.com/?data={short_link}"));
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::resource("/shorten").route(web::post().to(shorten)))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let shorten_req = || test::TestRequest::post().uri("/shorten")
+            .set_json(serde_json::json!({ "long_url": "https://doc.rust-lang.org/" }))
+            .to_request();
+
+        // Act
+        let created = test::call_service(&app, shorten_req()).await;
+        let location = created.headers().get(http::header::LOCATION).map(|value| value.to_str().unwrap().to_string());
+        let created_status = created.status();
+        let created: ShortenResponse = test::read_body_json(created).await;
+        let deduplicated = test::call_service(&app, shorten_req()).await;
+        let deduplicated_status = deduplicated.status();
+        let deduplicated: ShortenResponse = test::read_body_json(deduplicated).await;
+
+        // Assert
+        let short_link = format!("http://localhost/{}", created.link.short_url);
+        assert_eq!(created_status, http::StatusCode::CREATED);
+        assert_eq!(location, Some(short_link.clone()));
+        assert_eq!(created.short_link, short_link);
+        assert_eq!(created.stats_url, format!("{}/summary", short_link));
+        assert_eq!(created.qr_code_url, Some(format!("https://qr.example.com/?data=http%3A%2F%2Flocalhost%2F{}", created.link.short_url)));
+        assert!(created.link.created_at.is_some());
+        assert!(created.link.analytics.is_none());
+        assert!(created.expires_at.is_none());
+        assert_eq!(deduplicated_status, http::StatusCode::OK);
+        assert_eq!(deduplicated.link.short_url, created.link.short_url);
+        assert_eq!(deduplicated.link.created_at, created.link.created_at);
+    }
+
     #[actix_web::test]
     async fn test_redirect() {
         // Arrange
         let long_url = "https://doc.rust-lang.org/1";
         let settings = setup_settings();
         let hash_service = create_hash_service(&settings).await.unwrap();
-        let appdata = web::Data::new(Mutex::new(AppData { settings, hash_service }));
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
 
         let app = test::init_service({
             App::new()
@@ -75,6 +635,7 @@ mod tests {
                 .service(web::resource("/shorten").route(web::post().to(shorten)))
                 .service(redirect)
                 .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
         }).await;
         
         // Act - shorten
@@ -104,7 +665,7 @@ mod tests {
         let expected_location = "Cambridge, United States";
         let settings = setup_settings();
         let hash_service = create_hash_service(&settings).await.unwrap();
-        let appdata = web::Data::new(Mutex::new(AppData { settings, hash_service }));
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
 
         let app = test::init_service({
             App::new()
@@ -115,6 +676,8 @@ mod tests {
                 .service(redirect)
                 .service(summary)
                 .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+                .app_data(web::Data::new(TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()])))
         }).await;
         
         // Act - shorten
@@ -134,6 +697,7 @@ mod tests {
         let req = test::TestRequest::get()
             .uri(&format!("/{}", short_url))
             .insert_header(("X-Forwarded-For", ip))
+            .peer_addr("10.0.0.1:5000".parse().unwrap())
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_redirection());
@@ -151,12 +715,710 @@ mod tests {
         assert_eq!(analytic.location, Some(expected_location.to_string()));
     }
 
+    #[actix_web::test]
+    async fn test_urls_ndjson() {
+        // Arrange
+        let settings = setup_settings();
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+        // More links than one page that is read at a time
+        for index in 0..502 {
+            hash_service.insert(&format!("https://doc.rust-lang.org/{}", index)).await.unwrap();
+        }
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(urls)
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let ndjson_req = |uri: &str| test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("accept", "application/x-ndjson"))
+            .to_request();
+        let read_lines = |body: web::Bytes| std::str::from_utf8(&body).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect::<Vec<LinkInfo>>();
+
+        // Act
+        let resp = test::call_service(&app, ndjson_req("/urls")).await;
+        let content_type = resp.headers().get("content-type").unwrap().clone();
+        let links = read_lines(test::read_body(resp).await);
+        // A page that spans the first and the second page read
+        let page = read_lines(test::call_and_read_body(&app, ndjson_req("/urls?skip=499&top=2")).await);
+        let last = read_lines(test::call_and_read_body(&app, ndjson_req("/urls?skip=500&top=10")).await);
+
+        // Assert
+        assert_eq!(content_type, "application/x-ndjson");
+        assert_eq!(links.len(), 502);
+        assert!(links.windows(2).all(|pair| pair[0].short_url < pair[1].short_url));
+        assert_eq!(page.iter().map(|link| &link.short_url).collect::<Vec<_>>(), links[499..501].iter().map(|link| &link.short_url).collect::<Vec<_>>());
+        assert_eq!(last.len(), 2);
+        assert_eq!(last[1].short_url, links[501].short_url);
+    }
+
+    #[actix_web::test]
+    async fn test_scoped_api_keys() {
+        // Arrange
+        let settings = setup_settings();
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+        let registry = Arc::new(ApiKeyRegistry::new(vec![
+            ApiKey {
+                name: String::from("reader"),
+                hash: apikeys::hash_secret("reader-secret"),
+                scopes: vec![Scope::LinksRead],
+                expires_at: None,
+                ratelimit: Some(RateLimit {capacity: 1, fill_rate: 1, key_extractor: None, max_clients: None, store: None, algorithm: None, allowed_keys: None, allowed_networks: None, policies: None}),
+                created_at: None,
+                tenant: None
+            },
+            ApiKey {
+                name: String::from("stats"),
+                hash: apikeys::hash_secret("stats-secret"),
+                scopes: vec![Scope::StatsRead],
+                expires_at: None,
+                ratelimit: None,
+                created_at: None,
+                tenant: None
+            }
+        ]));
+
+        let app = test::init_service({
+            App::new()
+                .service(
+                    web::scope("/admin")
+                        .wrap(ApiKeyMiddleware::new(registry, |_| Some(Scope::LinksRead)))
+                        .service(urls)
+                )
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+
+        // Act
+        let missing_key = test::call_service(&app, test::TestRequest::get().uri("/admin/urls").to_request()).await;
+        let wrong_key = test::call_service(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", "unknown-secret")).to_request()).await;
+        let missing_scope = test::call_service(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", "stats-secret")).to_request()).await;
+        let allowed = test::call_service(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", "reader-secret")).to_request()).await;
+        let limited = test::call_service(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", "reader-secret")).to_request()).await;
+
+        // Assert
+        assert_eq!(missing_key.status(), http::StatusCode::UNAUTHORIZED);
+        assert_eq!(wrong_key.status(), http::StatusCode::UNAUTHORIZED);
+        assert_eq!(missing_scope.status(), http::StatusCode::FORBIDDEN);
+        assert!(allowed.status().is_success());
+        assert_eq!(limited.status(), http::StatusCode::TOO_MANY_REQUESTS);
+        assert!(limited.headers().contains_key("retry-after"));
+    }
+
+    #[actix_web::test]
+    async fn test_manage_api_keys() {
+        // Arrange
+        let settings = setup_settings();
+        let registry = Arc::new(ApiKeyRegistry::from_settings(&settings.apiserver));
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(
+                    web::scope("/admin")
+                        .wrap(ApiKeyMiddleware::new(registry.clone(), |req| match req.path() {
+                            "/admin/urls" => Some(Scope::LinksRead),
+                            _ => Some(Scope::Admin)
+                        }))
+                        .service(urls)
+                        .service(list_keys)
+                        .service(create_key)
+                        .service(revoke_key)
+                        .service(rotate_key)
+                )
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+                .app_data(web::Data::from(registry.clone()))
+        }).await;
+
+        // Act
+        let req = test::TestRequest::post().uri("/admin/keys")
+            .insert_header(("X-API-Key", "testkey"))
+            .set_json(serde_json::json!({ "name": "reader", "scopes": ["links:read"] }))
+            .to_request();
+        let created = test::call_service(&app, req).await;
+        assert_eq!(created.status(), http::StatusCode::CREATED);
+        let issued: IssuedApiKey = test::read_body_json(created).await;
+
+        let urls_resp = test::call_service(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", issued.secret.as_str())).to_request()).await;
+        let forbidden = test::call_service(&app, test::TestRequest::get().uri("/admin/keys")
+            .insert_header(("X-API-Key", issued.secret.as_str())).to_request()).await;
+
+        let list_resp = test::call_service(&app, test::TestRequest::get().uri("/admin/keys")
+            .insert_header(("X-API-Key", "testkey")).to_request()).await;
+        let keys: Vec<ApiKeyInfo> = test::read_body_json(list_resp).await;
+
+        let rotate_resp = test::call_service(&app, test::TestRequest::post().uri("/admin/keys/reader/rotate")
+            .insert_header(("X-API-Key", "testkey")).to_request()).await;
+        let rotated: IssuedApiKey = test::read_body_json(rotate_resp).await;
+        let old_secret = test::call_service(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", issued.secret.as_str())).to_request()).await;
+
+        let revoke_resp = test::call_service(&app, test::TestRequest::delete().uri("/admin/keys/reader")
+            .insert_header(("X-API-Key", "testkey")).to_request()).await;
+        let revoked = test::call_service(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", rotated.secret.as_str())).to_request()).await;
+        let revoke_again = test::call_service(&app, test::TestRequest::delete().uri("/admin/keys/reader")
+            .insert_header(("X-API-Key", "testkey")).to_request()).await;
+
+        // Assert
+        assert_eq!(issued.key.name, "reader");
+        assert!(urls_resp.status().is_success());
+        assert_eq!(forbidden.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(keys.len(), 2);
+        assert!(keys.iter().any(|key| key.name == "default" && key.configured));
+        assert!(keys.iter().any(|key| key.name == "reader" && !key.configured));
+        assert_eq!(old_secret.status(), http::StatusCode::UNAUTHORIZED);
+        assert_eq!(revoke_resp.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(revoked.status(), http::StatusCode::UNAUTHORIZED);
+        assert_eq!(revoke_again.status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_bearer_token() {
+        // Arrange
+        let settings = setup_settings();
+        let registry = Arc::new(ApiKeyRegistry::from_settings(&settings.apiserver));
+        let jwt = Arc::new(JwtValidator::new(&Jwt {
+            issuer: String::from("https://sso.example.com/"),
+            audience: String::from("url-shortener"),
+            algorithm: JwtAlgorithm::Hs256,
+            secret: Some(String::from("shared-secret")),
+            jwks_url: None,
+            jwks_file: None,
+            jwks_refresh_interval: None,
+            leeway: None,
+            name_claim: None,
+            scopes_claim: None,
+            scope_mapping: None,
+            tenant_claim: None
+        }).await.unwrap());
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(
+                    web::scope("/admin")
+                        .wrap(ApiKeyMiddleware::new(registry, |_| Some(Scope::LinksRead)).with_jwt(Some(jwt)))
+                        .service(urls)
+                )
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let token = |scope: &str, secret: &str| jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &serde_json::json!({
+                "sub": "alice",
+                "iss": "https://sso.example.com/",
+                "aud": "url-shortener",
+                "exp": chrono::Utc::now().timestamp() + 3600,
+                "scope": scope
+            }),
+            &jsonwebtoken::EncodingKey::from_secret(secret.as_bytes())
+        ).unwrap();
+
+        // Act
+        let allowed = test::call_service(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("Authorization", format!("Bearer {}", token("links:read", "shared-secret")))).to_request()).await;
+        let missing_scope = test::call_service(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("Authorization", format!("Bearer {}", token("stats:read", "shared-secret")))).to_request()).await;
+        let wrong_signature = test::call_service(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("Authorization", format!("Bearer {}", token("links:read", "other-secret")))).to_request()).await;
+        let api_key = test::call_service(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", "testkey")).to_request()).await;
+
+        // Assert
+        assert!(allowed.status().is_success());
+        assert_eq!(missing_scope.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(wrong_signature.status(), http::StatusCode::UNAUTHORIZED);
+        assert!(api_key.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_link_ownership() {
+        // Arrange
+        let settings = setup_settings();
+        let key = |name: &str, scopes: Vec<Scope>| ApiKey {
+            name: String::from(name),
+            hash: apikeys::hash_secret(&format!("{}-secret", name)),
+            scopes,
+            expires_at: None,
+            ratelimit: None,
+            created_at: None,
+            tenant: None
+        };
+        let user_scopes = vec![Scope::LinksRead, Scope::LinksWrite, Scope::LinksDelete, Scope::StatsRead];
+        let registry = Arc::new(ApiKeyRegistry::new(vec![
+            key("alice", user_scopes.clone()),
+            key("bob", user_scopes),
+            key("admin", vec![Scope::Admin])
+        ]));
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(
+                    web::scope("/admin")
+                        .wrap(ApiKeyMiddleware::new(registry.clone(), |req| match *req.method() {
+                            http::Method::PUT => Some(Scope::LinksWrite),
+                            http::Method::DELETE => Some(Scope::LinksDelete),
+                            _ => Some(Scope::LinksRead)
+                        }))
+                        .service(urls)
+                        .service(upsert)
+                        .service(delete)
+                )
+                .service(
+                    web::scope("/me")
+                        .wrap(ApiKeyMiddleware::new(registry.clone(), |_| Some(Scope::LinksRead)))
+                        .service(my_links)
+                )
+                .service(web::resource("/shorten")
+                    .wrap(ApiKeyMiddleware::new(registry.clone(), |_| Some(Scope::LinksWrite)).optional())
+                    .route(web::post().to(shorten)))
+                .service(
+                    web::scope("")
+                        .wrap(ApiKeyMiddleware::new(registry.clone(), |_| Some(Scope::StatsRead)).optional())
+                        .service(summary)
+                )
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let shorten_as = |long_url: &str, secret: Option<&str>| {
+            let mut req = test::TestRequest::post().uri("/shorten")
+                .set_json(serde_json::json!({ "long_url": long_url }));
+            if let Some(secret) = secret {
+                req = req.insert_header(("X-API-Key", secret));
+            }
+            req.to_request()
+        };
+
+        // Act
+        let alice_link: LinkInfo = test::call_and_read_body_json(&app, shorten_as("https://doc.rust-lang.org/1", Some("alice-secret"))).await;
+        let bob_link: LinkInfo = test::call_and_read_body_json(&app, shorten_as("https://doc.rust-lang.org/2", Some("bob-secret"))).await;
+        let public_link: LinkInfo = test::call_and_read_body_json(&app, shorten_as("https://doc.rust-lang.org/3", None)).await;
+
+        let alice_links: Vec<LinkInfo> = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/me/links")
+            .insert_header(("X-API-Key", "alice-secret")).to_request()).await;
+        let anonymous_links = test::call_service(&app, test::TestRequest::get().uri("/me/links").to_request()).await;
+        let bob_urls: Vec<LinkInfo> = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", "bob-secret")).to_request()).await;
+        let admin_urls: Vec<LinkInfo> = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", "admin-secret")).to_request()).await;
+
+        let summary_uri = format!("/{}/summary", alice_link.short_url);
+        let owner_summary = test::call_service(&app, test::TestRequest::get().uri(&summary_uri)
+            .insert_header(("X-API-Key", "alice-secret")).to_request()).await;
+        let other_summary = test::call_service(&app, test::TestRequest::get().uri(&summary_uri)
+            .insert_header(("X-API-Key", "bob-secret")).to_request()).await;
+        let anonymous_summary = test::call_service(&app, test::TestRequest::get().uri(&summary_uri).to_request()).await;
+        let public_summary = test::call_service(&app, test::TestRequest::get()
+            .uri(&format!("/{}/summary", public_link.short_url)).to_request()).await;
+
+        let mut taken_over = bob_link.clone();
+        taken_over.long_url = String::from("https://example.com/");
+        let other_update = test::call_service(&app, test::TestRequest::put().uri(&format!("/admin/{}", bob_link.short_url))
+            .insert_header(("X-API-Key", "alice-secret")).set_json(&taken_over).to_request()).await;
+        let other_delete = test::call_service(&app, test::TestRequest::delete().uri(&format!("/admin/{}", alice_link.short_url))
+            .insert_header(("X-API-Key", "bob-secret")).to_request()).await;
+        let admin_delete = test::call_service(&app, test::TestRequest::delete().uri(&format!("/admin/{}", alice_link.short_url))
+            .insert_header(("X-API-Key", "admin-secret")).to_request()).await;
+        let bob_copy: LinkInfo = test::call_and_read_body_json(&app, shorten_as("https://doc.rust-lang.org/2", Some("alice-secret"))).await;
+        let claimed: LinkInfo = test::call_and_read_body_json(&app, shorten_as("https://doc.rust-lang.org/3", Some("alice-secret"))).await;
+        let public_again: LinkInfo = test::call_and_read_body_json(&app, shorten_as("https://doc.rust-lang.org/3", None)).await;
+
+        // Assert
+        assert_eq!(alice_link.owner_id, Some(String::from("key:alice")));
+        assert_eq!(bob_link.owner_id, Some(String::from("key:bob")));
+        assert!(public_link.owner_id.is_none());
+        assert_eq!(alice_links.len(), 1);
+        assert_eq!(alice_links[0].short_url, alice_link.short_url);
+        assert_eq!(anonymous_links.status(), http::StatusCode::UNAUTHORIZED);
+        assert_eq!(bob_urls.len(), 1);
+        assert_eq!(bob_urls[0].short_url, bob_link.short_url);
+        assert_eq!(admin_urls.len(), 3);
+        assert!(owner_summary.status().is_success());
+        assert_eq!(other_summary.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(anonymous_summary.status(), http::StatusCode::UNAUTHORIZED);
+        assert!(public_summary.status().is_success());
+        assert_eq!(other_update.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(other_delete.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(admin_delete.status(), http::StatusCode::NO_CONTENT);
+        assert_ne!(bob_copy.short_url, bob_link.short_url);
+        assert_eq!(bob_copy.owner_id, Some(String::from("key:alice")));
+        assert_ne!(claimed.short_url, public_link.short_url);
+        assert_eq!(public_again.short_url, public_link.short_url);
+        assert!(public_again.owner_id.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_replica_writes_need_cluster_scope() {
+        // Arrange
+        let settings = setup_settings();
+        let key = |name: &str, scopes: Vec<Scope>| ApiKey {
+            name: String::from(name),
+            hash: apikeys::hash_secret(&format!("{}-secret", name)),
+            scopes,
+            expires_at: None,
+            ratelimit: None,
+            created_at: None,
+            tenant: None
+        };
+        let registry = Arc::new(ApiKeyRegistry::new(vec![
+            key("admin", vec![Scope::Admin]),
+            key("node", vec![Scope::Cluster, Scope::LinksWrite, Scope::LinksDelete])
+        ]));
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(
+                    web::scope("/admin")
+                        .wrap(ApiKeyMiddleware::new(registry, |req| match *req.method() {
+                            http::Method::PUT => Some(Scope::LinksWrite),
+                            _ => Some(Scope::LinksDelete)
+                        }))
+                        .service(upsert)
+                        .service(delete)
+                )
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        // Replicas are stored as they are, without normalizing the url
+        let link = setup_link("replica", "HTTPS://Doc.Rust-Lang.org/1");
+        let replicate_as = |secret: &str| test::TestRequest::put().uri("/admin/replica")
+            .insert_header(("X-API-Key", secret))
+            .insert_header(("X-Replica", "true"))
+            .set_json(&link)
+            .to_request();
+
+        // Act
+        let admin_upsert = test::call_service(&app, replicate_as("admin-secret")).await;
+        let node_upsert = test::call_service(&app, replicate_as("node-secret")).await;
+        let stored = appdata.lock().await.hash_service.find("replica").await.unwrap();
+        let admin_delete = test::call_service(&app, test::TestRequest::delete().uri("/admin/replica")
+            .insert_header(("X-API-Key", "admin-secret")).insert_header(("X-Replica", "true")).to_request()).await;
+        let node_delete = test::call_service(&app, test::TestRequest::delete().uri("/admin/replica")
+            .insert_header(("X-API-Key", "node-secret")).insert_header(("X-Replica", "true")).to_request()).await;
+
+        // Assert
+        assert_eq!(admin_upsert.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(node_upsert.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(stored.unwrap().long_url, "HTTPS://Doc.Rust-Lang.org/1");
+        assert_eq!(admin_delete.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(node_delete.status(), http::StatusCode::NO_CONTENT);
+    }
+
+    #[actix_web::test]
+    async fn test_audit_trail() {
+        // Arrange
+        let settings = setup_settings();
+        let registry = Arc::new(ApiKeyRegistry::from_settings(&settings.apiserver));
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+        let audit: Arc<AuditTrail> = appdata.lock().await.audit.clone();
+
+        let app = test::init_service({
+            App::new()
+                .service(
+                    web::scope("/admin")
+                        .wrap(ApiKeyMiddleware::new(registry.clone(), |_| Some(Scope::Admin)))
+                        .service(create_key)
+                        .service(audit_events)
+                        .service(upsert)
+                        .service(delete)
+                        .service(restore)
+                )
+                .service(web::resource("/shorten")
+                    .wrap(ApiKeyMiddleware::new(registry.clone(), |_| Some(Scope::LinksWrite)).optional())
+                    .route(web::post().to(shorten)))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+                .app_data(web::Data::from(registry.clone()))
+                .app_data(web::Data::new(TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()])))
+        }).await;
+        let admin_req = |req: test::TestRequest| req.insert_header(("X-API-Key", "testkey"))
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .peer_addr("10.0.0.1:5000".parse().unwrap())
+            .to_request();
+
+        // Act
+        let link: ShortenResponse = test::call_and_read_body_json(&app, admin_req(test::TestRequest::post().uri("/shorten")
+            .set_json(serde_json::json!({ "long_url": "https://doc.rust-lang.org/" })))).await;
+        let short_url = link.link.short_url.clone();
+        let mut changed = link.link.clone();
+        changed.long_url = String::from("https://crates.io/");
+        test::call_service(&app, admin_req(test::TestRequest::put().uri(&format!("/admin/{}", short_url)).set_json(&changed))).await;
+        test::call_service(&app, admin_req(test::TestRequest::delete().uri(&format!("/admin/{}", short_url)))).await;
+        test::call_service(&app, admin_req(test::TestRequest::post().uri(&format!("/admin/{}/restore", short_url)))).await;
+        test::call_service(&app, admin_req(test::TestRequest::post().uri("/admin/keys")
+            .set_json(serde_json::json!({ "name": "reader", "scopes": ["links:read"] })))).await;
+
+        let link_events: Vec<AuditEvent> = test::call_and_read_body_json(&app, admin_req(test::TestRequest::get()
+            .uri(&format!("/admin/audit?link={}", short_url)))).await;
+        let by_actor: Vec<AuditEvent> = test::call_and_read_body_json(&app, admin_req(test::TestRequest::get()
+            .uri("/admin/audit?actor=key:default"))).await;
+        let future: Vec<AuditEvent> = test::call_and_read_body_json(&app, admin_req(test::TestRequest::get()
+            .uri("/admin/audit?since=2999-01-01T00:00:00Z"))).await;
+        let spoofed: ShortenResponse = test::call_and_read_body_json(&app, test::TestRequest::post().uri("/shorten")
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .peer_addr("192.0.2.1:5000".parse().unwrap())
+            .set_json(serde_json::json!({ "long_url": "https://github.com/rust-lang" })).to_request()).await;
+        let stored = audit.query(&Default::default()).await.unwrap();
+
+        // Assert
+        let actions: Vec<AuditAction> = link_events.iter().map(|event| event.action).collect();
+        assert_eq!(actions, vec![AuditAction::Create, AuditAction::Update, AuditAction::Delete, AuditAction::Restore]);
+        assert!(link_events.iter().all(|event| event.actor.as_deref() == Some("key:default")));
+        assert!(link_events.iter().all(|event| event.client_ip.as_deref() == Some("203.0.113.7")));
+        let update = &link_events[1];
+        assert_eq!(update.changes.len(), 1);
+        assert_eq!(update.changes[0].field, "long_url");
+        assert_eq!(update.changes[0].before, Some(serde_json::json!("https://doc.rust-lang.org/")));
+        assert_eq!(update.changes[0].after, Some(serde_json::json!("https://crates.io/")));
+        assert!(link_events[2].changes.iter().any(|change| change.field == "status" && change.after == Some(serde_json::json!("deleted"))));
+        assert_eq!(by_actor.len(), 5);
+        assert_eq!(by_actor[4].action, AuditAction::KeyCreate);
+        assert_eq!(by_actor[4].key.as_deref(), Some("reader"));
+        assert!(!by_actor[4].changes.iter().any(|change| change.field == "hash" || change.field == "secret"));
+        assert!(future.is_empty());
+        assert_eq!(stored.len(), 6);
+        // Only trusted proxies tell the client IP
+        assert_eq!(stored[5].link, Some(spoofed.link.short_url));
+        assert_eq!(stored[5].client_ip.as_deref(), Some("192.0.2.1"));
+    }
+
+    #[actix_web::test]
+    async fn test_tenants() {
+        // Arrange
+        let mut settings = setup_settings();
+        settings.tenants = Some(vec![Tenant { id: String::from("team-a"), max_links: Some(1), max_shortens_per_day: None }]);
+        let key = |name: &str, scopes: Vec<Scope>, tenant: Option<&str>| ApiKey {
+            name: String::from(name),
+            hash: apikeys::hash_secret(&format!("{}-secret", name)),
+            scopes,
+            expires_at: None,
+            ratelimit: None,
+            created_at: None,
+            tenant: tenant.map(String::from)
+        };
+        let registry = Arc::new(ApiKeyRegistry::new(vec![
+            key("alice", vec![Scope::Admin], Some("team-a")),
+            key("admin", vec![Scope::Admin], None)
+        ]));
+        let tenants = Tenants::from_settings(&settings).await.unwrap();
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let mut data = AppData::new(settings, hash_service);
+        data.tenants = tenants;
+        let appdata = web::Data::new(Mutex::new(data));
+
+        let app = test::init_service({
+            App::new()
+                .service(
+                    web::scope("/admin")
+                        .wrap(ApiKeyMiddleware::new(registry.clone(), |_| Some(Scope::Admin))
+                            .tenant_routes(|req| req.match_pattern().as_deref() != Some("/admin/keys")))
+                        .service(urls)
+                        .service(tenant_usage)
+                        .service(list_keys)
+                        .service(upsert)
+                )
+                .service(web::resource("/shorten")
+                    .wrap(ApiKeyMiddleware::new(registry.clone(), |_| Some(Scope::LinksWrite)).optional())
+                    .route(web::post().to(shorten)))
+                .service(
+                    web::scope("")
+                        .service(web::scope("/t/{tenant}").service(redirect))
+                        .service(redirect)
+                )
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+                .app_data(web::Data::from(registry.clone()))
+        }).await;
+        let shorten_as = |long_url: &str, secret: &str| test::TestRequest::post().uri("/shorten")
+            .insert_header(("X-API-Key", secret))
+            .set_json(serde_json::json!({ "long_url": long_url }))
+            .to_request();
+
+        // Act
+        let tenant_link: LinkInfo = test::call_and_read_body_json(&app, shorten_as("https://doc.rust-lang.org/1", "alice-secret")).await;
+        let over_quota = test::call_service(&app, shorten_as("https://doc.rust-lang.org/2", "alice-secret")).await;
+        let shared_link: LinkInfo = test::call_and_read_body_json(&app, shorten_as("https://doc.rust-lang.org/2", "admin-secret")).await;
+        let upsert_over_quota = test::call_service(&app, test::TestRequest::put().uri("/admin/docs")
+            .insert_header(("X-API-Key", "alice-secret"))
+            .set_json(LinkInfo { short_url: String::from("docs"), ..tenant_link.clone() }).to_request()).await;
+        let upsert_existing = test::call_service(&app, test::TestRequest::put().uri(&format!("/admin/{}", tenant_link.short_url))
+            .insert_header(("X-API-Key", "alice-secret"))
+            .set_json(&tenant_link).to_request()).await;
+
+        let tenant_urls: Vec<LinkInfo> = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", "alice-secret")).to_request()).await;
+        let shared_urls: Vec<LinkInfo> = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/admin/urls")
+            .insert_header(("X-API-Key", "admin-secret")).to_request()).await;
+        let tenant_redirect = test::call_service(&app, test::TestRequest::get()
+            .uri(&format!("/t/team-a/{}", tenant_link.short_url)).to_request()).await;
+        let shared_redirect = test::call_service(&app, test::TestRequest::get()
+            .uri(&format!("/{}", tenant_link.short_url)).to_request()).await;
+        let unknown_tenant = test::call_service(&app, test::TestRequest::get()
+            .uri(&format!("/t/team-b/{}", tenant_link.short_url)).to_request()).await;
+        let usage: Vec<TenantUsage> = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/admin/tenants")
+            .insert_header(("X-API-Key", "admin-secret")).to_request()).await;
+        let tenant_keys = test::call_service(&app, test::TestRequest::get().uri("/admin/keys")
+            .insert_header(("X-API-Key", "alice-secret")).to_request()).await;
+
+        // Assert
+        assert_eq!(over_quota.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(upsert_over_quota.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(upsert_existing.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(tenant_urls.len(), 1);
+        assert_eq!(tenant_urls[0].short_url, tenant_link.short_url);
+        assert_eq!(shared_urls.len(), 1);
+        assert_eq!(shared_urls[0].short_url, shared_link.short_url);
+        assert!(tenant_redirect.status().is_redirection());
+        assert_eq!(shared_redirect.status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(unknown_tenant.status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(usage, vec![TenantUsage {
+            tenant: String::from("team-a"),
+            links: 1,
+            max_links: Some(1),
+            shortens_today: 1,
+            max_shortens_per_day: None,
+            api_keys: 1
+        }]);
+        assert_eq!(tenant_keys.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_custom_domains() {
+        // Arrange
+        let mut settings = setup_settings();
+        settings.tenants = Some(vec![Tenant { id: String::from("team-a"), max_links: None, max_shortens_per_day: None }]);
+        let key = |name: &str, tenant: Option<&str>| ApiKey {
+            name: String::from(name),
+            hash: apikeys::hash_secret(&format!("{}-secret", name)),
+            scopes: vec![Scope::Admin],
+            expires_at: None,
+            ratelimit: None,
+            created_at: None,
+            tenant: tenant.map(String::from)
+        };
+        let registry = Arc::new(ApiKeyRegistry::new(vec![key("alice", Some("team-a")), key("admin", None)]));
+        let tenants = Tenants::from_settings(&settings).await.unwrap();
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let mut data = AppData::new(settings, hash_service);
+        data.tenants = tenants;
+        let appdata = web::Data::new(Mutex::new(data));
+
+        let app = test::init_service({
+            App::new()
+                .service(
+                    web::scope("/admin")
+                        .wrap(ApiKeyMiddleware::new(registry.clone(), |_| Some(Scope::Admin)).tenant_routes(|_| true))
+                        .service(list_domains)
+                        .service(add_domain)
+                        .service(remove_domain)
+                )
+                .service(web::resource("/shorten")
+                    .wrap(ApiKeyMiddleware::new(registry.clone(), |_| Some(Scope::LinksWrite)).optional())
+                    .route(web::post().to(shorten)))
+                .service(
+                    web::scope("")
+                        .service(redirect)
+                )
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+                .app_data(web::Data::from(registry.clone()))
+        }).await;
+        let add_as = |name: &str, tenant: Option<&str>, secret: &str| test::TestRequest::post().uri("/admin/domains")
+            .insert_header(("X-API-Key", secret))
+            .set_json(serde_json::json!({ "name": name, "tenant": tenant }))
+            .to_request();
+        let shorten_on = |host: &str, domain: Option<&str>, secret: Option<&str>| {
+            let mut req = test::TestRequest::post().uri("/shorten")
+                .insert_header(("Host", host))
+                .set_json(serde_json::json!({ "long_url": "https://doc.rust-lang.org/", "domain": domain }));
+            if let Some(secret) = secret {
+                req = req.insert_header(("X-API-Key", secret));
+            }
+            req.to_request()
+        };
+        let redirect_on = |host: &str, short_url: &str| test::TestRequest::get().uri(&format!("/{}", short_url))
+            .insert_header(("Host", host))
+            .to_request();
+
+        // Act
+        let shared_domain = test::call_service(&app, add_as("go.example.com", None, "admin-secret")).await;
+        let tenant_domain: Domain = test::call_and_read_body_json(&app, add_as("go.brand-a.com", None, "alice-secret")).await;
+        let duplicate_domain = test::call_service(&app, add_as("GO.example.com", None, "admin-secret")).await;
+        let unknown_tenant = test::call_service(&app, add_as("go.brand-b.com", Some("team-b"), "admin-secret")).await;
+        let alice_domains: Vec<Domain> = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/admin/domains")
+            .insert_header(("X-API-Key", "alice-secret")).to_request()).await;
+
+        let by_host: ShortenResponse = test::call_and_read_body_json(&app, shorten_on("go.example.com", None, None)).await;
+        let on_api_host: ShortenResponse = test::call_and_read_body_json(&app, shorten_on("localhost", None, None)).await;
+        let on_tenant_domain: ShortenResponse = test::call_and_read_body_json(&app, shorten_on("localhost", Some("go.brand-a.com"), Some("alice-secret"))).await;
+        let anonymous_on_tenant_domain = test::call_service(&app, shorten_on("localhost", Some("go.brand-a.com"), None)).await;
+        let on_unknown_domain = test::call_service(&app, shorten_on("localhost", Some("go.brand-b.com"), None)).await;
+
+        let domain_redirect = test::call_service(&app, redirect_on("go.example.com", by_host.link.code())).await;
+        let other_domain_redirect = test::call_service(&app, redirect_on("go.example.com", &on_api_host.link.short_url)).await;
+        let tenant_redirect = test::call_service(&app, redirect_on("Go.Brand-A.com:443", on_tenant_domain.link.code())).await;
+        let tenant_on_api_host = test::call_service(&app, redirect_on("localhost", on_tenant_domain.link.code())).await;
+
+        let other_tenant_delete = test::call_service(&app, test::TestRequest::delete().uri("/admin/domains/go.example.com")
+            .insert_header(("X-API-Key", "alice-secret")).to_request()).await;
+        let admin_delete = test::call_service(&app, test::TestRequest::delete().uri("/admin/domains/go.example.com")
+            .insert_header(("X-API-Key", "admin-secret")).to_request()).await;
+        let removed_domain_redirect = test::call_service(&app, redirect_on("go.example.com", by_host.link.code())).await;
+
+        // Assert
+        assert_eq!(shared_domain.status(), http::StatusCode::CREATED);
+        assert_eq!(tenant_domain.tenant, Some(String::from("team-a")));
+        assert_eq!(duplicate_domain.status(), http::StatusCode::CONFLICT);
+        assert_eq!(unknown_tenant.status(), http::StatusCode::BAD_REQUEST);
+        assert_eq!(alice_domains, vec![tenant_domain]);
+
+        assert_eq!(by_host.link.domain, Some(String::from("go.example.com")));
+        assert_eq!(by_host.link.short_url, format!("{}@go.example.com", by_host.link.code()));
+        assert_eq!(by_host.short_link, format!("https://go.example.com/{}", by_host.link.code()));
+        assert_ne!(by_host.link.short_url, on_api_host.link.short_url);
+        assert_eq!(on_api_host.short_link, format!("https://localhost/{}", on_api_host.link.short_url));
+        assert_eq!(on_tenant_domain.short_link, format!("https://go.brand-a.com/{}", on_tenant_domain.link.code()));
+        assert_eq!(anonymous_on_tenant_domain.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(on_unknown_domain.status(), http::StatusCode::BAD_REQUEST);
+
+        assert!(domain_redirect.status().is_redirection());
+        assert_eq!(other_domain_redirect.status(), http::StatusCode::NOT_FOUND);
+        assert!(tenant_redirect.status().is_redirection());
+        assert_eq!(tenant_on_api_host.status(), http::StatusCode::NOT_FOUND);
+
+        assert_eq!(other_tenant_delete.status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(admin_delete.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(removed_domain_redirect.status(), http::StatusCode::NOT_FOUND);
+    }
+
     #[actix_web::test]
     async fn test_rate_limit() {
         let settings = setup_settings();
         let hash_service = create_hash_service(&settings).await.unwrap();
-        let rate_limiter = Arc::new(Mutex::new(RateLimiter::new(settings.ratelimit)));
-        let appdata = web::Data::new(Mutex::new(AppData { settings, hash_service }));
+        let policy = RateLimitPolicies::new(&settings).await.unwrap().get(RATE_LIMIT_POLICY_SHORTEN);
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
 
         let app = test::init_service({
             App::new()
@@ -166,12 +1428,13 @@ mod tests {
                 .service(hello)
                 .service(web::resource("/shorten").wrap_fn(move|req, srv| 
                     {
-                        let rate_limiter = rate_limiter.clone();
-                        RateLimiterMiddlewareService::new(srv, rate_limiter).call(req)
+                        let policy = policy.clone();
+                        RateLimiterMiddlewareService::new(srv, policy).call(req)
                     }).route(web::post().to(shorten)))
                 .service(redirect)
                 .service(summary)
                 .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
         }).await;
         
         let payload1 = r#"{"long_url":"https://doc.rust-lang.org/1"}"#;
@@ -217,22 +1480,176 @@ mod tests {
 
     }
     
+    #[actix_web::test]
+    async fn test_rate_limit_headers() {
+        // Arrange
+        let settings = setup_settings();
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let policy = RateLimitPolicies::new(&settings).await.unwrap().get(RATE_LIMIT_POLICY_SHORTEN);
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::resource("/shorten").wrap_fn(move|req, srv|
+                    {
+                        let policy = policy.clone();
+                        RateLimiterMiddlewareService::new(srv, policy).call(req)
+                    }).route(web::post().to(shorten)))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+
+        let build_request = || test::TestRequest::post()
+            .uri("/shorten")
+            .set_payload(r#"{"long_url":"https://doc.rust-lang.org/1"}"#)
+            .insert_header(("content-type", "application/json"))
+            .to_request();
+
+        // Act
+        let first = test::call_service(&app, build_request()).await;
+        test::call_service(&app, build_request()).await;
+        let denied = test::try_call_service(&app, build_request()).await.expect_err("request wasn't rate limited");
+        let denied_response = denied.error_response();
+
+        // Assert
+        assert_eq!(first.headers().get("RateLimit-Limit").unwrap(), "2");
+        assert_eq!(first.headers().get("RateLimit-Remaining").unwrap(), "1");
+        assert!(first.headers().get(http::header::RETRY_AFTER).is_none());
+
+        assert_eq!(denied_response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(denied_response.headers().get("RateLimit-Remaining").unwrap(), "0");
+        assert_eq!(denied_response.headers().get(http::header::RETRY_AFTER).unwrap(), "1");
+        assert_eq!(denied_response.headers().get(http::header::CONTENT_TYPE).unwrap(), "application/json");
+        let body = actix_web::body::to_bytes(denied_response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], UserError::TooManyRequests.to_string());
+    }
+
+    #[actix_web::test]
+    async fn test_rate_limit_by_authenticated_caller() {
+        // Arrange
+        let settings = setup_settings();
+        let key = |name: &str| ApiKey {
+            name: String::from(name),
+            hash: apikeys::hash_secret(&format!("{}-secret", name)),
+            scopes: vec![Scope::LinksWrite],
+            expires_at: None,
+            ratelimit: None,
+            created_at: None,
+            tenant: None
+        };
+        let registry = Arc::new(ApiKeyRegistry::new(vec![key("alice"), key("bob")]));
+        let hash_service = create_hash_service(&settings).await.unwrap();
+        let policy = RateLimitPolicies::new(&settings).await.unwrap().get(RATE_LIMIT_POLICY_SHORTEN);
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(web::resource("/shorten")
+                    .wrap_fn(move|req, srv| RateLimiterMiddlewareService::new(srv, policy.clone()).call(req))
+                    .wrap(ApiKeyMiddleware::new(registry, |_| Some(Scope::LinksWrite)).optional())
+                    .route(web::post().to(shorten)))
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+        let shorten_as = |secret: Option<&str>| {
+            let mut req = test::TestRequest::post().uri("/shorten")
+                .set_json(serde_json::json!({ "long_url": "https://doc.rust-lang.org/" }));
+            if let Some(secret) = secret {
+                req = req.insert_header(("X-API-Key", secret.to_string()));
+            }
+            req.to_request()
+        };
+
+        // Act
+        let alice = test::call_service(&app, shorten_as(Some("alice-secret"))).await;
+        test::call_service(&app, shorten_as(Some("alice-secret"))).await;
+        let alice_denied = test::try_call_service(&app, shorten_as(Some("alice-secret"))).await;
+        let bob = test::call_service(&app, shorten_as(Some("bob-secret"))).await;
+        let mut unknown_keys = Vec::new();
+        for i in 0..3 {
+            unknown_keys.push(test::call_service(&app, shorten_as(Some(&format!("random-{}", i)))).await.status());
+        }
+        let anonymous = test::call_service(&app, shorten_as(None)).await;
+
+        // Assert
+        assert!(alice.status().is_success());
+        assert_eq!(alice_denied.expect_err("request wasn't rate limited").error_response().status(), http::StatusCode::TOO_MANY_REQUESTS);
+        // Every caller has a bucket of its own
+        assert!(bob.status().is_success());
+        assert_eq!(bob.headers().get("RateLimit-Remaining").unwrap(), "1");
+        // Keys that don't authenticate are rejected before they get a bucket
+        assert!(unknown_keys.iter().all(|status| *status == http::StatusCode::UNAUTHORIZED));
+        assert_eq!(anonymous.headers().get("RateLimit-Remaining").unwrap(), "1");
+    }
+
+    #[actix_web::test]
+    async fn test_route_policies() {
+        // Arrange
+        let mut settings = setup_settings();
+        let mut redirect_policy = settings.ratelimit.clone().unwrap();
+        redirect_policy.capacity = 1;
+        let mut summary_policy = settings.ratelimit.clone().unwrap();
+        summary_policy.capacity = 5;
+        settings.ratelimit.as_mut().unwrap().policies = Some(HashMap::from([
+            (String::from("redirect"), redirect_policy),
+            (String::from("summary"), summary_policy)
+        ]));
+
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+        let link = hash_service.insert("https://doc.rust-lang.org/1").await.unwrap();
+        let policies = RateLimitPolicies::new(&settings).await.unwrap();
+        let appdata = web::Data::new(Mutex::new(AppData::new(settings, hash_service)));
+
+        let app = test::init_service({
+            App::new()
+                .service(
+                    web::scope("")
+                        .wrap_fn(move|req, srv| {
+                            let policy = match req.match_pattern().as_deref() {
+                                Some("/{short_url}/summary") => policies.get("summary"),
+                                _ => policies.get("redirect")
+                            };
+                            RateLimiterMiddlewareService::new(srv, policy).call(req)
+                        })
+                        .service(redirect)
+                        .service(summary)
+                )
+                .app_data(web::Data::clone(&appdata))
+                .app_data(web::Data::new(DomainRegistry::new()))
+        }).await;
+
+        // Act
+        let first_redirect = test::call_service(&app, test::TestRequest::get().uri(&format!("/{}", link.short_url)).to_request()).await;
+        let second_redirect = test::try_call_service(&app, test::TestRequest::get().uri(&format!("/{}", link.short_url)).to_request()).await;
+        let summary_resp = test::call_service(&app, test::TestRequest::get().uri(&format!("/{}/summary", link.short_url)).to_request()).await;
+
+        // Assert
+        assert!(first_redirect.status().is_redirection());
+        assert_eq!(first_redirect.headers().get("RateLimit-Limit").unwrap(), "1");
+        assert!(second_redirect.is_err());
+        assert!(summary_resp.status().is_success());
+        assert_eq!(summary_resp.headers().get("RateLimit-Limit").unwrap(), "5");
+    }
+
+    fn setup_link(short_url: &str, long_url: &str) -> LinkInfo {
+        LinkInfo { short_url: short_url.to_string(), long_url: long_url.to_string(), analytics: Some(Vec::new()), owner_id: None, domain: None, created_at: None, status: LinkStatus::Active, status_reason: None, deleted_at: None, previous_status: None, tags: None, expires_at: None, imported_clicks: None, version: 0, updated_at: None }
+    }
+
     fn setup_settings() -> Settings {
-        return Settings {
+        Settings {
             debug: true,
-            apiserver: ApiServer { 
+            apiserver: ApiServer {
                 application_url: String::from("localhost"),
                 hostname: String::from("localhost"),
                 allow_origin: String::from("localhost"),
                 api_key: Some(String::from("testkey")),
                 google_application_credentials: Some(String::from("credentials.json")),
+                ..ApiServer::default()
             },
-            mongo_config: None,
-            firestore_config: None,
-            redis_config: None,
-            ratelimit: Some(RateLimit {capacity: 2, fill_rate: 2}),
+            ratelimit: Some(RateLimit { capacity: 2, fill_rate: 2, ..DEFAULT_RATE_LIMIT }),
             mode: rust_short_url::configuration::settings::Mode::InMemory,
-            coordinator: None
+            ..Settings::default()
         }
     }
 }
\ No newline at end of file
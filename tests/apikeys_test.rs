@@ -0,0 +1,190 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use rust_short_url::{api::apikeys::{self, ApiKeyError, ApiKeyRegistry}, configuration::settings::{ApiServer, RateLimit}, models::apikey::{ApiKey, NewApiKey, Scope}};
+
+    #[test]
+    fn test_hash_secret_is_salted() {
+        // Arrange
+        let secret = "secret";
+
+        // Act
+        let first = apikeys::hash_secret(secret);
+        let second = apikeys::hash_secret(secret);
+
+        // Assert
+        assert_ne!(first, second);
+        assert!(first.starts_with("sha256$"));
+        assert!(apikeys::verify_secret(secret, &first));
+        assert!(apikeys::verify_secret(secret, &second));
+        assert!(!apikeys::verify_secret("other", &first));
+        assert!(!apikeys::verify_secret(secret, "sha256$salt$nothex"));
+        assert!(!apikeys::verify_secret(secret, secret));
+    }
+
+    #[test]
+    fn test_authenticate_by_secret() {
+        // Arrange
+        let registry = ApiKeyRegistry::new(vec![
+            api_key("reader", "reader-secret", vec![Scope::LinksRead]),
+            api_key("writer", "writer-secret", vec![Scope::LinksRead, Scope::LinksWrite])
+        ]);
+
+        // Act
+        let reader = registry.authenticate("reader-secret").unwrap();
+        let writer = registry.authenticate("writer-secret").unwrap();
+        let unknown = registry.authenticate("unknown-secret");
+
+        // Assert
+        assert_eq!(reader.name, "reader");
+        assert!(reader.has_scope(Scope::LinksRead));
+        assert!(!reader.has_scope(Scope::LinksWrite));
+        assert_eq!(writer.name, "writer");
+        assert!(writer.has_scope(Scope::LinksWrite));
+        assert!(unknown.is_none());
+    }
+
+    #[test]
+    fn test_expired_key_is_rejected() {
+        // Arrange
+        let mut expired = api_key("expired", "expired-secret", vec![Scope::LinksRead]);
+        expired.expires_at = Some(Utc::now() - Duration::minutes(1));
+        let mut valid = api_key("valid", "valid-secret", vec![Scope::LinksRead]);
+        valid.expires_at = Some(Utc::now() + Duration::days(1));
+        let registry = ApiKeyRegistry::new(vec![expired, valid]);
+
+        // Act
+        let expired = registry.authenticate("expired-secret");
+        let valid = registry.authenticate("valid-secret");
+
+        // Assert
+        assert!(expired.is_none());
+        assert!(valid.is_some());
+    }
+
+    #[test]
+    fn test_single_api_key_has_every_scope() {
+        // Arrange
+        let apiserver = ApiServer {
+            application_url: String::from("localhost"),
+            hostname: String::from("localhost"),
+            allow_origin: String::from("localhost"),
+            api_key: Some(String::from("testkey")),
+            api_keys: Some(vec![api_key("reader", "reader-secret", vec![Scope::LinksRead])]),
+            ..ApiServer::default()
+        };
+
+        // Act
+        let registry = ApiKeyRegistry::from_settings(&apiserver);
+        let default_key = registry.authenticate("testkey").unwrap();
+
+        // Assert
+        assert_eq!(default_key.name, "default");
+        assert!(default_key.has_scope(Scope::LinksDelete));
+        assert!(default_key.has_scope(Scope::Admin));
+        assert!(registry.authenticate("reader-secret").is_some());
+    }
+
+    #[test]
+    fn test_key_rate_limit() {
+        // Arrange
+        let mut limited = api_key("limited", "limited-secret", vec![Scope::LinksRead]);
+        limited.ratelimit = Some(RateLimit {capacity: 1, fill_rate: 1, key_extractor: None, max_clients: None, store: None, algorithm: None, allowed_keys: None, allowed_networks: None, policies: None});
+        let unlimited = api_key("unlimited", "unlimited-secret", vec![Scope::LinksRead]);
+        let registry = ApiKeyRegistry::new(vec![limited.clone(), unlimited.clone()]);
+
+        // Act
+        let first = registry.consume(&limited).unwrap();
+        let second = registry.consume(&limited).unwrap();
+        let unlimited_decision = registry.consume(&unlimited);
+
+        // Assert
+        assert!(first.allowed);
+        assert!(!second.allowed);
+        assert!(unlimited_decision.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_issued_key_lifecycle() {
+        // Arrange
+        let registry = ApiKeyRegistry::new(vec![api_key("default", "testkey", vec![Scope::Admin])]);
+
+        // Act
+        let issued = registry.create(new_key("ci", vec![Scope::LinksWrite])).await.unwrap();
+        let authenticated = registry.authenticate(&issued.secret).map(|key| key.name);
+        let rotated = registry.rotate("ci").await.unwrap();
+        let old_secret = registry.authenticate(&issued.secret);
+        let new_secret = registry.authenticate(&rotated.secret).map(|key| key.name);
+        registry.revoke("ci").await.unwrap();
+        let revoked = registry.authenticate(&rotated.secret);
+
+        // Assert
+        assert_eq!(issued.key.name, "ci");
+        assert!(!issued.key.configured);
+        assert!(issued.key.created_at.is_some());
+        assert_eq!(authenticated, Some(String::from("ci")));
+        assert_ne!(rotated.secret, issued.secret);
+        assert!(old_secret.is_none());
+        assert_eq!(new_secret, Some(String::from("ci")));
+        assert!(revoked.is_none());
+        assert_eq!(registry.list().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_key_changes_are_rejected() {
+        // Arrange
+        let registry = ApiKeyRegistry::new(vec![api_key("default", "testkey", vec![Scope::Admin])]);
+        registry.create(new_key("ci", vec![Scope::LinksRead])).await.unwrap();
+
+        // Act
+        let duplicate = registry.create(new_key("ci", vec![Scope::LinksRead])).await;
+        let configured = registry.create(new_key("default", vec![Scope::LinksRead])).await;
+        let invalid_name = registry.create(new_key("ci key", vec![Scope::LinksRead])).await;
+        let no_scopes = registry.create(new_key("empty", vec![])).await;
+        let revoke_configured = registry.revoke("default").await;
+        let rotate_unknown = registry.rotate("unknown").await;
+
+        // Assert
+        assert!(matches!(duplicate, Err(ApiKeyError::DuplicateKey { .. })));
+        assert!(matches!(configured, Err(ApiKeyError::DuplicateKey { .. })));
+        assert!(matches!(invalid_name, Err(ApiKeyError::InvalidKey { .. })));
+        assert!(matches!(no_scopes, Err(ApiKeyError::InvalidKey { .. })));
+        assert!(matches!(revoke_configured, Err(ApiKeyError::ConfiguredKey { .. })));
+        assert!(matches!(rotate_unknown, Err(ApiKeyError::UnknownKey { .. })));
+    }
+
+    #[actix_web::test]
+    async fn test_authentication_is_decided_on_startup() {
+        // Arrange
+        let mut unsecured = ApiKeyRegistry::new(Vec::new());
+        let mut secured = ApiKeyRegistry::new(Vec::new());
+        secured.create(new_key("ci", vec![Scope::Admin])).await.unwrap();
+
+        // Act
+        unsecured.init().await.unwrap();
+        unsecured.create(new_key("ci", vec![Scope::Admin])).await.unwrap();
+        secured.init().await.unwrap();
+        secured.revoke("ci").await.unwrap();
+
+        // Assert
+        // Revoking the last key doesn't open the endpoints
+        assert!(!unsecured.is_required());
+        assert!(secured.is_required());
+    }
+
+    fn new_key(name: &str, scopes: Vec<Scope>) -> NewApiKey {
+        NewApiKey { name: String::from(name), scopes, expires_at: None, ratelimit: None, tenant: None }
+    }
+
+    fn api_key(name: &str, secret: &str, scopes: Vec<Scope>) -> ApiKey {
+        ApiKey {
+            name: String::from(name),
+            hash: apikeys::hash_secret(secret),
+            scopes,
+            expires_at: None,
+            ratelimit: None,
+            created_at: None,
+            tenant: None
+        }
+    }
+}
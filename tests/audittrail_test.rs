@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use rust_short_url::{api::audittrail::AuditTrail, models::{auditevent::{self, AuditAction, AuditEvent, AuditQuery}, linkinfo::{LinkInfo, LinkStatus}}};
+    use serde_json::json;
+
+    #[test]
+    fn test_diff() {
+        // Arrange
+        let before = link("https://doc.rust-lang.org/");
+        let mut after = before.clone();
+        after.long_url = String::from("https://crates.io/");
+        after.status = LinkStatus::Disabled;
+        after.analytics = Some(Vec::new());
+
+        // Act
+        let changes = auditevent::diff(Some(&before), Some(&after));
+        let created = auditevent::diff(None, Some(&before));
+        let unchanged = auditevent::diff(Some(&before), Some(&before));
+
+        // Assert
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].field, "long_url");
+        assert_eq!(changes[0].before, Some(json!("https://doc.rust-lang.org/")));
+        assert_eq!(changes[0].after, Some(json!("https://crates.io/")));
+        assert_eq!(changes[1].field, "status");
+        assert_eq!(changes[1].after, Some(json!("disabled")));
+        assert!(created.iter().all(|change| change.before.is_none()));
+        assert!(created.iter().any(|change| change.field == "short_url"));
+        assert!(!created.iter().any(|change| change.field == "analytics"));
+        assert!(unchanged.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_query() {
+        // Arrange
+        let trail = AuditTrail::new();
+        let now = Utc::now();
+        trail.record(event(AuditAction::Create, now - Duration::hours(2), Some("alice"), Some("abc"), None)).await;
+        trail.record(event(AuditAction::Update, now - Duration::hours(1), Some("bob"), Some("abc"), Some("team-a"))).await;
+        trail.record(event(AuditAction::Delete, now, Some("alice"), Some("def"), Some("team-a"))).await;
+
+        // Act
+        let all = trail.query(&AuditQuery::default()).await.unwrap();
+        let by_link = trail.query(&AuditQuery { link: Some(String::from("abc")), ..AuditQuery::default() }).await.unwrap();
+        let by_actor = trail.query(&AuditQuery { actor: Some(String::from("alice")), ..AuditQuery::default() }).await.unwrap();
+        let since = trail.query(&AuditQuery { since: Some(now - Duration::minutes(90)), ..AuditQuery::default() }).await.unwrap();
+        let by_tenant = trail.query(&AuditQuery { actor: Some(String::from("alice")), tenant: Some(String::from("team-a")), ..AuditQuery::default() }).await.unwrap();
+        let first_page = trail.query(&AuditQuery { limit: Some(2), ..AuditQuery::default() }).await.unwrap();
+        let next_page = trail.query(&AuditQuery { limit: Some(2), after: Some(first_page[1].id.clone()), ..AuditQuery::default() }).await.unwrap();
+
+        // Assert
+        assert_eq!(all.len(), 3);
+        assert_eq!(by_link.iter().map(|event| event.action).collect::<Vec<_>>(), vec![AuditAction::Create, AuditAction::Update]);
+        assert_eq!(by_actor.len(), 2);
+        assert_eq!(since.len(), 2);
+        assert_eq!(by_tenant.len(), 1);
+        assert_eq!(by_tenant[0].link.as_deref(), Some("def"));
+        assert_eq!(first_page, all[..2]);
+        assert_eq!(next_page, all[2..]);
+    }
+
+    #[test]
+    fn test_query_bounds() {
+        // Arrange
+        let event = AuditEvent::new(AuditAction::Create, Utc::now());
+        let since = AuditQuery { since: Some(event.timestamp), ..AuditQuery::default() };
+        let after = AuditQuery { since: Some(event.timestamp - Duration::hours(1)), after: Some(event.id.clone()), ..AuditQuery::default() };
+
+        // Act & Assert
+        // Ids start with the time, so backends compare them instead of the timestamps
+        assert!(event.id >= since.since_id().unwrap());
+        assert!(since.matches(&event));
+        assert!(!after.matches(&event));
+        assert_eq!(after.min_micros(), Some(event.timestamp.timestamp_micros()));
+    }
+
+    fn link(long_url: &str) -> LinkInfo {
+        LinkInfo {
+            short_url: String::from("abc"),
+            long_url: String::from(long_url),
+            analytics: None,
+            owner_id: None,
+            domain: None,
+            created_at: None,
+            status: LinkStatus::Active,
+            status_reason: None,
+            deleted_at: None,
+            previous_status: None,
+            tags: None,
+            expires_at: None,
+            imported_clicks: None,
+            version: 0,
+            updated_at: None
+        }
+    }
+
+    fn event(action: AuditAction, timestamp: chrono::DateTime<Utc>, actor: Option<&str>, link: Option<&str>, tenant: Option<&str>) -> AuditEvent {
+        let mut event = AuditEvent::new(action, timestamp);
+        event.actor = actor.map(String::from);
+        event.link = link.map(String::from);
+        event.tenant = tenant.map(String::from);
+        event
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use rust_short_url::services::bulkimport::{self, BulkFormat, BulkLink};
+
+    #[test]
+    fn test_parse_csv() {
+        // Arrange
+        let text = "name,quote\r\n\"Doe, Jane\",\"She said \"\"hi\"\"\"\n\nmulti,\"line\nbreak\"\nlast,";
+
+        // Act
+        let records = bulkimport::parse_csv(text).unwrap();
+        let unclosed = bulkimport::parse_csv("a,\"b\n");
+
+        // Assert
+        assert_eq!(records, vec![
+            vec!["name", "quote"],
+            vec!["Doe, Jane", "She said \"hi\""],
+            vec!["multi", "line\nbreak"],
+            vec!["last", ""]
+        ]);
+        assert!(unclosed.is_err());
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        // Arrange
+        let csv = "Long_URL,tags,alias,expires_at\nhttps://doc.rust-lang.org/,\"docs, rust\",,2030-01-01T00:00:00Z\n,x,,\nhttps://crates.io/,,crates,not a time\n";
+        let json = r#"[{"long_url": "https://doc.rust-lang.org/", "alias": "docs", "tags": ["docs"]}, {"alias": "missing"}]"#;
+        let ndjson = "{\"long_url\": \"https://doc.rust-lang.org/\"}\n\n{\"long_url\": 1}\n";
+
+        // Act
+        let csv_links = bulkimport::parse(BulkFormat::Csv, csv).unwrap();
+        let json_links = bulkimport::parse(BulkFormat::Json, json).unwrap();
+        let ndjson_links = bulkimport::parse(BulkFormat::Ndjson, ndjson).unwrap();
+        let not_an_array = bulkimport::parse(BulkFormat::Json, "{}");
+        let no_url_column = bulkimport::parse(BulkFormat::Csv, "url\nhttps://doc.rust-lang.org/\n");
+
+        // Assert
+        assert_eq!(csv_links.len(), 3);
+        assert_eq!(csv_links[0], Ok(BulkLink {
+            long_url: String::from("https://doc.rust-lang.org/"),
+            alias: None,
+            tags: Some(vec![String::from("docs"), String::from("rust")]),
+            expires_at: Some(Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap())
+        }));
+        assert!(csv_links[1].is_err());
+        assert!(csv_links[2].is_err());
+        assert_eq!(json_links[0].as_ref().unwrap().alias.as_deref(), Some("docs"));
+        assert!(json_links[1].is_err());
+        assert_eq!(ndjson_links.len(), 2);
+        assert!(ndjson_links[0].is_ok());
+        assert!(ndjson_links[1].is_err());
+        assert!(not_an_array.is_err());
+        assert!(no_url_column.is_err());
+        assert_eq!(BulkFormat::from_content_type("text/csv; charset=utf-8"), Some(BulkFormat::Csv));
+        assert_eq!(BulkFormat::from_content_type("application/xml"), None);
+    }
+
+    #[test]
+    fn test_validate_alias() {
+        // Arrange
+        let aliases = ["spring-sale_2026", "", "with space", "admin", &"a".repeat(65), "1C96D51A", "42", "cafe", "1C96D51A0"];
+
+        // Act
+        let results: Vec<bool> = aliases.iter().map(|alias| bulkimport::validate_alias(alias).is_ok()).collect();
+
+        // Assert
+        // Generated short urls are 1 to 8 uppercase hex digits, aliases can't squat them
+        assert_eq!(results, vec![true, false, false, false, false, false, false, true, true]);
+    }
+
+    #[test]
+    fn test_validate_short_url() {
+        // Arrange
+        let short_urls = ["1C96D51A", "spring-sale", "1C96D51A@go.example.com", "sale@go.example.com", "sale@Go.Example.com", "admin@go.example.com", "apikey:default", "tenant:acme:sale", "index:links"];
+
+        // Act
+        let results: Vec<bool> = short_urls.iter().map(|short_url| bulkimport::validate_short_url(short_url).is_ok()).collect();
+
+        // Assert
+        // Other keys of the stores are never taken for a link
+        assert_eq!(results, vec![true, true, true, true, false, false, false, false, false]);
+    }
+}
@@ -12,7 +12,7 @@ mod tests {
         header_map.append(header::USER_AGENT, HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.3"));
 
         // Act
-        let analytic = collector::collect_stats(&header_map).await;
+        let analytic = collector::collect_stats(&header_map, None).await;
 
         // Assert
         assert_eq!(analytic.language, Some("en-US".to_string()));
@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread, time::Duration};
+
+    use rust_short_url::{configuration::settings::{ApiServer, Destinations, Mode, Settings}, services::{destinationrules::{self, DestinationFilter, DestinationRules}, urlnormalizer::UrlError}};
+
+    #[test]
+    fn test_blocked_domains_and_patterns() {
+        // Arrange
+        let rules = DestinationRules::new(&Destinations {
+            blocked_domains: Some(vec![String::from("Phishing.example"), String::from("*.bücher.example")]),
+            blocked_patterns: Some(vec![String::from(r"\.exe$")]),
+            ..Destinations::default()
+        }).unwrap();
+
+        // Act
+        let domain = rules.check("https://phishing.example/login");
+        let subdomain = rules.check("https://login.phishing.example/");
+        let similar_domain = rules.check("https://notphishing.example/");
+        let international = rules.check("https://shop.xn--bcher-kva.example/");
+        let pattern = rules.check("https://downloads.example.com/setup.exe");
+        let allowed = rules.check("https://doc.rust-lang.org/");
+
+        // Assert
+        assert!(matches!(domain, Err(UrlError::BlockedDestination { .. })));
+        assert!(matches!(subdomain, Err(UrlError::BlockedDestination { .. })));
+        assert!(similar_domain.is_ok());
+        assert!(matches!(international, Err(UrlError::BlockedDestination { .. })));
+        assert!(matches!(pattern, Err(UrlError::BlockedDestination { .. })));
+        assert!(allowed.is_ok());
+    }
+
+    #[test]
+    fn test_allowlist() {
+        // Arrange
+        let rules = DestinationRules::parse("
+            # Only our own sites
+            allow example.com
+            allow-pattern ^https://docs\\.rs/
+            block internal.example.com
+        ").unwrap();
+
+        // Act
+        let allowed_domain = rules.check("https://www.example.com/");
+        let allowed_pattern = rules.check("https://docs.rs/regex");
+        let blocked_subdomain = rules.check("https://internal.example.com/");
+        let other = rules.check("https://doc.rust-lang.org/");
+
+        // Assert
+        assert!(allowed_domain.is_ok());
+        assert!(allowed_pattern.is_ok());
+        assert!(matches!(blocked_subdomain, Err(UrlError::BlockedDestination { .. })));
+        assert!(matches!(other, Err(UrlError::BlockedDestination { ref reason, .. }) if reason.contains("allowlist")));
+    }
+
+    #[test]
+    fn test_invalid_rules_are_rejected() {
+        // Arrange
+        let unknown_rule = "deny example.com";
+        let no_value = "block";
+        let invalid_pattern = "block-pattern (";
+
+        // Act
+        let unknown_rule = DestinationRules::parse(unknown_rule);
+        let no_value = DestinationRules::parse(no_value);
+        let invalid_pattern = DestinationRules::parse(invalid_pattern);
+
+        // Assert
+        assert!(unknown_rule.is_err());
+        assert!(no_value.is_err());
+        assert!(invalid_pattern.is_err());
+    }
+
+    #[test]
+    fn test_redirect_loop() {
+        // Arrange
+        let filter = DestinationFilter::default();
+        let own_hosts = vec![String::from("surl.example.com"), String::from("go.brand-a.com")];
+
+        // Act
+        let own_hostname = filter.check("https://surl.example.com/abc123", &own_hosts);
+        let custom_domain = filter.check("http://go.brand-a.com/abc123", &own_hosts);
+        let other = filter.check("https://example.com/", &own_hosts);
+
+        // Assert
+        assert!(matches!(own_hostname, Err(UrlError::RedirectLoop { .. })));
+        assert!(matches!(custom_domain, Err(UrlError::RedirectLoop { .. })));
+        assert!(other.is_ok());
+        assert!(destinationrules::is_on_domain("https://a.b.example.com/", "example.com"));
+        assert!(!destinationrules::is_on_domain("https://example.com.evil/", "example.com"));
+    }
+
+    #[test]
+    fn test_rules_file_is_reloaded() {
+        // Arrange
+        let rules_file = std::env::temp_dir().join(format!("destination-rules-{}.txt", std::process::id()));
+        fs::write(&rules_file, "block phishing.example\n").unwrap();
+        let settings = setup_settings(rules_file.to_str().unwrap());
+        let filter = DestinationFilter::from_settings(&settings).unwrap();
+        let before = filter.check("https://phishing.example/", &[]).is_err();
+
+        // Act
+        // File systems with coarse modification times need the change to land in a later tick
+        thread::sleep(Duration::from_millis(1100));
+        fs::write(&rules_file, "block malware.example\n").unwrap();
+        let reloaded = filter.reload().unwrap();
+        let unchanged = filter.reload().unwrap();
+        thread::sleep(Duration::from_millis(1100));
+        fs::write(&rules_file, "block-pattern (\n").unwrap();
+        let broken = filter.reload();
+        fs::remove_file(&rules_file).unwrap();
+
+        // Assert
+        assert!(before);
+        assert!(reloaded);
+        assert!(!unchanged);
+        assert!(broken.is_err());
+        assert!(filter.check("https://phishing.example/", &[]).is_ok());
+        assert!(filter.check("https://malware.example/", &[]).is_err());
+        assert!(filter.check("https://blocked.example/", &[]).is_err());
+    }
+
+    fn setup_settings(rules_file: &str) -> Settings {
+        Settings {
+            debug: true,
+            apiserver: ApiServer {
+                application_url: String::from("localhost"),
+                hostname: String::from("localhost"),
+                allow_origin: String::from("localhost"),
+                ..ApiServer::default()
+            },
+            mode: Mode::InMemory,
+            destinations: Some(Destinations {
+                rules_file: Some(String::from(rules_file)),
+                blocked_domains: Some(vec![String::from("blocked.example")]),
+                ..Destinations::default()
+            }),
+            ..Settings::default()
+        }
+    }
+}
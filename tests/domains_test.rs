@@ -0,0 +1,80 @@
+#[cfg(test)]
+mod tests {
+    use rust_short_url::{api::domains::{self, DomainError, DomainRegistry}, models::domain::NewDomain, services::hashfunction};
+
+    #[test]
+    fn test_host_name() {
+        // Arrange
+        let hosts = ["Go.Example.com", "go.example.com:8080", "[::1]:8080", "localhost"];
+
+        // Act
+        let names: Vec<String> = hosts.iter().map(|host| domains::host_name(host)).collect();
+
+        // Assert
+        assert_eq!(names, vec!["go.example.com", "go.example.com", "[::1]", "localhost"]);
+    }
+
+    #[test]
+    fn test_hash_on_domain() {
+        // Arrange
+        let long_url = "https://doc.rust-lang.org/";
+
+        // Act
+        let shared = hashfunction::hash_on_domain(long_url, None);
+        let on_domain = hashfunction::hash_on_domain(long_url, Some("go.example.com"));
+        let on_other_domain = hashfunction::hash_on_domain(long_url, Some("links.example.org"));
+
+        // Assert
+        assert_eq!(shared, hashfunction::hash(long_url));
+        assert_ne!(on_domain, shared);
+        assert_ne!(on_domain, on_other_domain);
+    }
+
+    #[actix_web::test]
+    async fn test_domain_lifecycle() {
+        // Arrange
+        let registry = DomainRegistry::new();
+
+        // Act
+        let added = registry.add(new_domain("Go.Example.com", Some("team-a"))).await.unwrap();
+        let found = registry.find("go.example.com:443").map(|domain| domain.tenant);
+        let listed = registry.list().len();
+        registry.remove("go.example.com").await.unwrap();
+        let removed = registry.find("go.example.com");
+
+        // Assert
+        assert_eq!(added.name, "go.example.com");
+        assert!(added.created_at.is_some());
+        assert_eq!(found, Some(Some(String::from("team-a"))));
+        assert_eq!(listed, 1);
+        assert!(removed.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_domain_changes_are_rejected() {
+        // Arrange
+        let registry = DomainRegistry::new();
+        registry.add(new_domain("go.example.com", None)).await.unwrap();
+
+        // Act
+        let duplicate = registry.add(new_domain("GO.example.com", None)).await;
+        let single_label = registry.add(new_domain("localhost", None)).await;
+        let invalid_label = registry.add(new_domain("-go.example.com", None)).await;
+        let with_path = registry.add(new_domain("example.com/go", None)).await;
+        let unknown = registry.remove("links.example.org").await;
+
+        // Assert
+        assert!(matches!(duplicate, Err(DomainError::DuplicateDomain { .. })));
+        assert!(matches!(single_label, Err(DomainError::InvalidDomain { .. })));
+        assert!(matches!(invalid_label, Err(DomainError::InvalidDomain { .. })));
+        assert!(matches!(with_path, Err(DomainError::InvalidDomain { .. })));
+        assert!(matches!(unknown, Err(DomainError::UnknownDomain { .. })));
+    }
+
+    fn new_domain(name: &str, tenant: Option<&str>) -> NewDomain {
+        NewDomain {
+            name: String::from(name),
+            tenant: tenant.map(String::from)
+        }
+    }
+}
@@ -0,0 +1,133 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use rust_short_url::services::gossip::{GossipAgent, GossipOptions, MemberState};
+
+    fn options(http_url: &str, seeds: Vec<String>) -> GossipOptions {
+        GossipOptions {
+            bind_address: String::from("127.0.0.1:0"),
+            advertise_address: None,
+            http_url: http_url.to_string(),
+            seeds,
+            protocol_period: Duration::from_millis(50),
+            suspect_timeout: Duration::from_millis(300),
+            indirect_checks: 1,
+            secret: String::from("gossip-secret")
+        }
+    }
+
+    async fn wait_for_state(agent: &Arc<GossipAgent>, address: &str, state: MemberState) -> bool {
+        for _ in 0..100 {
+            if agent.members().iter().any(|member| member.address == address && member.state == state) {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        false
+    }
+
+    #[actix_web::test]
+    async fn test_members_discover_each_other_through_seed() {
+        // Arrange
+        let (seed, _seed_events) = GossipAgent::start(options("http://127.0.0.1:1", Vec::new())).await.unwrap();
+        let seed_address = seed.local_member().address;
+        let (first, _first_events) = GossipAgent::start(options("http://127.0.0.1:2", vec![seed_address.clone()])).await.unwrap();
+        let (second, _second_events) = GossipAgent::start(options("http://127.0.0.1:3", vec![seed_address.clone()])).await.unwrap();
+
+        // Act
+        let first_sees_second = wait_for_state(&first, &second.local_member().address, MemberState::Alive).await;
+        let second_sees_first = wait_for_state(&second, &first.local_member().address, MemberState::Alive).await;
+
+        // Assert
+        assert!(first_sees_second);
+        assert!(second_sees_first);
+        assert_eq!(seed.members().len(), 2);
+        assert!(seed.members().iter().any(|member| member.http_url == "http://127.0.0.1:2"));
+
+        seed.shutdown();
+        first.shutdown();
+        second.shutdown();
+    }
+
+    #[actix_web::test]
+    async fn test_stopped_member_is_suspected_then_dead() {
+        // Arrange
+        let (seed, _seed_events) = GossipAgent::start(options("http://127.0.0.1:1", Vec::new())).await.unwrap();
+        let (member, _member_events) = GossipAgent::start(options("http://127.0.0.1:2", vec![seed.local_member().address])).await.unwrap();
+        let member_address = member.local_member().address;
+        assert!(wait_for_state(&seed, &member_address, MemberState::Alive).await);
+
+        // Act
+        member.shutdown();
+
+        // Assert
+        assert!(wait_for_state(&seed, &member_address, MemberState::Suspect).await);
+        assert!(wait_for_state(&seed, &member_address, MemberState::Dead).await);
+
+        seed.shutdown();
+    }
+
+    #[actix_web::test]
+    async fn test_events_report_new_members() {
+        // Arrange
+        let (seed, mut seed_events) = GossipAgent::start(options("http://127.0.0.1:1", Vec::new())).await.unwrap();
+
+        // Act
+        let (member, _member_events) = GossipAgent::start(options("http://127.0.0.1:2", vec![seed.local_member().address])).await.unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), seed_events.recv()).await.unwrap().unwrap();
+
+        // Assert
+        assert_eq!(event.address, member.local_member().address);
+        assert_eq!(event.http_url, "http://127.0.0.1:2");
+        assert_eq!(event.state, MemberState::Alive);
+
+        seed.shutdown();
+        member.shutdown();
+    }
+
+    #[actix_web::test]
+    async fn test_members_with_another_secret_are_ignored() {
+        // Arrange
+        let (seed, mut seed_events) = GossipAgent::start(options("http://127.0.0.1:1", Vec::new())).await.unwrap();
+        let mut other_options = options("http://127.0.0.1:2", vec![seed.local_member().address]);
+        other_options.secret = String::from("another-secret");
+
+        // Act
+        let (other, _other_events) = GossipAgent::start(other_options).await.unwrap();
+        let event = tokio::time::timeout(Duration::from_millis(500), seed_events.recv()).await;
+
+        // Assert
+        assert!(event.is_err());
+        assert!(seed.members().is_empty());
+        assert!(other.members().is_empty());
+
+        seed.shutdown();
+        other.shutdown();
+    }
+
+    #[actix_web::test]
+    async fn test_unsigned_messages_are_ignored() {
+        // Arrange
+        let (seed, mut seed_events) = GossipAgent::start(options("http://127.0.0.1:1", Vec::new())).await.unwrap();
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let ping = serde_json::json!({ "Ping": { "seq": 1, "updates": [], "from": {
+            "address": socket.local_addr().unwrap().to_string(), "http_url": "http://attacker.example", "incarnation": 1, "state": "Alive"
+        } } });
+        let unsigned = serde_json::to_vec(&ping).unwrap();
+        let mut forged = vec![0u8; 32];
+        forged.extend(serde_json::to_vec(&serde_json::json!({ "sent_at": 0, "message": ping })).unwrap());
+
+        // Act
+        socket.send_to(&unsigned, seed.local_member().address).await.unwrap();
+        socket.send_to(&forged, seed.local_member().address).await.unwrap();
+        let event = tokio::time::timeout(Duration::from_millis(500), seed_events.recv()).await;
+
+        // Assert
+        assert!(event.is_err());
+        assert!(seed.members().is_empty());
+
+        seed.shutdown();
+    }
+}
@@ -32,4 +32,22 @@ mod tests {
         // Assert
         assert_ne!(key1, key2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_hash_for_owner() {
+        // Arrange
+        let long_url = "https://doc.rust-lang.org/";
+
+        // Act
+        let anonymous = hashfunction::hash_for_owner(long_url, None, None);
+        let alice = hashfunction::hash_for_owner(long_url, None, Some("key:alice"));
+        let bob = hashfunction::hash_for_owner(long_url, None, Some("key:bob"));
+        let alice_on_domain = hashfunction::hash_for_owner(long_url, Some("go.example.com"), Some("key:alice"));
+
+        // Assert
+        assert_eq!(anonymous, hashfunction::hash(long_url));
+        assert_ne!(alice, anonymous);
+        assert_ne!(alice, bob);
+        assert_ne!(alice_on_domain, alice);
+    }
+}
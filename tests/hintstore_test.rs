@@ -0,0 +1,64 @@
+#[cfg(test)]
+mod tests {
+    use rust_short_url::{models::linkinfo::{LinkInfo, LinkStatus}, services::hintstore::{Hint, HintStore}};
+
+    #[test]
+    fn test_latest_hint_per_key_wins() {
+        // Arrange
+        let mut hints = HintStore::new(10);
+
+        // Act
+        hints.add("node1", Hint::Upsert(Box::new(setup_link("A", "https://doc.rust-lang.org/"))));
+        hints.add("node1", Hint::Upsert(Box::new(setup_link("B", "https://crates.io/"))));
+        hints.add("node1", Hint::Delete(String::from("A")));
+
+        // Assert
+        assert_eq!(hints.len("node1"), 2);
+        let pending: Vec<Hint> = hints.take("node1").into_iter().collect();
+        assert_eq!(pending, vec![
+            Hint::Upsert(Box::new(setup_link("B", "https://crates.io/"))),
+            Hint::Delete(String::from("A"))
+        ]);
+        assert!(hints.is_empty());
+    }
+
+    #[test]
+    fn test_oldest_hints_are_dropped_when_full() {
+        // Arrange
+        let mut hints = HintStore::new(2);
+
+        // Act
+        hints.add("node1", Hint::Delete(String::from("A")));
+        hints.add("node1", Hint::Delete(String::from("B")));
+        hints.add("node1", Hint::Delete(String::from("C")));
+
+        // Assert
+        let pending: Vec<String> = hints.take("node1").iter().map(|hint| hint.key().to_string()).collect();
+        assert_eq!(pending, vec!["B", "C"]);
+    }
+
+    #[test]
+    fn test_restore_keeps_newer_hints() {
+        // Arrange
+        let mut hints = HintStore::new(10);
+        hints.add("node1", Hint::Upsert(Box::new(setup_link("A", "https://doc.rust-lang.org/"))));
+        hints.add("node1", Hint::Upsert(Box::new(setup_link("B", "https://crates.io/"))));
+        let pending = hints.take("node1");
+
+        // Act - a newer write for A arrives while the replay is in flight
+        hints.add("node1", Hint::Delete(String::from("A")));
+        hints.restore("node1", pending);
+
+        // Assert
+        assert_eq!(hints.nodes(), vec![String::from("node1")]);
+        let restored: Vec<Hint> = hints.take("node1").into_iter().collect();
+        assert_eq!(restored, vec![
+            Hint::Upsert(Box::new(setup_link("B", "https://crates.io/"))),
+            Hint::Delete(String::from("A"))
+        ]);
+    }
+
+    fn setup_link(short_url: &str, long_url: &str) -> LinkInfo {
+        LinkInfo { short_url: short_url.to_string(), long_url: long_url.to_string(), analytics: None, owner_id: None, domain: None, created_at: None, status: LinkStatus::Active, status_reason: None, deleted_at: None, previous_status: None, tags: None, expires_at: None, imported_clicks: None, version: 0, updated_at: None }
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use rust_short_url::{configuration::settings::{Settings, ApiServer}, services::hashservicefactory::create_hash_service, models::queryparams::QueryParams};
+    use rust_short_url::{configuration::settings::{Settings, ApiServer}, services::{hashfunction, hashserviceerror::HashServiceError, hashservicefactory::create_hash_service}, models::{linkinfo, queryparams::QueryParams}};
     
     #[actix_rt::test]
     async fn test_successful_hashing() {
@@ -58,6 +58,78 @@ mod tests {
         assert_eq!(linkinfo_result.is_none(), true);
     }
 
+    #[actix_rt::test]
+    async fn test_find_many() {
+        // Arrange
+        let settings = setup_settings();
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+        let docs = hash_service.insert("https://doc.rust-lang.org/").await.unwrap();
+        let crates = hash_service.insert("https://crates.io/").await.unwrap();
+
+        // Act
+        let found = hash_service.find_many(&[docs.short_url.clone(), String::from("non_existing_key"), crates.short_url.clone()]).await.unwrap();
+        let count = hash_service.count_links().await.unwrap();
+
+        // Assert
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[&docs.short_url].long_url, "https://doc.rust-lang.org/");
+        assert_eq!(found[&crates.short_url].long_url, "https://crates.io/");
+        assert_eq!(count, 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_links_after() {
+        // Arrange
+        let settings = setup_settings();
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+        for index in 0..5 {
+            hash_service.insert(&format!("https://doc.rust-lang.org/{}", index)).await.unwrap();
+        }
+        let owned = hash_service.insert_owned("https://crates.io/", None, "owner").await.unwrap();
+
+        // Act
+        let first = hash_service.get_links_after(None, None, 4).await.unwrap();
+        let last_of_first = first.last().unwrap().short_url.clone();
+        // A link deleted between the pages doesn't shift the next one
+        hash_service.delete(&first[0].short_url).await.unwrap();
+        let second = hash_service.get_links_after(Some(&last_of_first), None, 4).await.unwrap();
+        let of_owner = hash_service.get_links_after(None, Some("owner"), 4).await.unwrap();
+        let after_owned = hash_service.get_links_after(Some(&owned.short_url), Some("owner"), 4).await.unwrap();
+
+        // Assert
+        assert_eq!(first.len(), 4);
+        assert!(first.windows(2).all(|pair| pair[0].short_url < pair[1].short_url));
+        assert_eq!(second.len(), 2);
+        assert!(second.iter().all(|link| link.short_url > last_of_first));
+        assert_eq!(of_owner.len(), 1);
+        assert_eq!(of_owner[0].short_url, owned.short_url);
+        assert!(after_owned.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_purge_deleted() {
+        // Arrange
+        let settings = setup_settings();
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+        let active = hash_service.insert_owned("https://doc.rust-lang.org/", None, "owner").await.unwrap();
+        let mut trashed = hash_service.insert_owned("https://crates.io/", None, "owner").await.unwrap();
+        trashed.status = linkinfo::LinkStatus::Deleted;
+        trashed.deleted_at = Some(chrono::Utc::now() - chrono::Duration::days(2));
+        hash_service.upsert(&trashed).await.unwrap();
+
+        // Act
+        let not_yet_purged = hash_service.purge_deleted(chrono::Utc::now() - chrono::Duration::days(3)).await.unwrap();
+        let purged = hash_service.purge_deleted(chrono::Utc::now()).await.unwrap();
+        let owned = hash_service.get_links_by_owner("owner", None).await.unwrap();
+
+        // Assert
+        assert_eq!(not_yet_purged, 0);
+        assert_eq!(purged, 1);
+        assert_eq!(owned.len(), 1);
+        assert_eq!(owned[0].short_url, active.short_url);
+        assert!(hash_service.find(&trashed.short_url).await.unwrap().is_none());
+    }
+
     #[actix_rt::test]
     async fn test_summary() {
         // Arrange
@@ -158,22 +230,104 @@ mod tests {
 
     }
 
+    #[actix_rt::test]
+    async fn test_get_links_by_owner() {
+        // Arrange
+        let settings = setup_settings();
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+
+        let mut alice1 = hash_service.insert("https://doc.rust-lang.org/").await.unwrap();
+        let mut alice2 = hash_service.insert("https://crates.io/").await.unwrap();
+        let mut bob = hash_service.insert("https://github.com/rust-lang").await.unwrap();
+        hash_service.insert("https://www.reddit.com/r/rust/").await.unwrap();
+        alice1.owner_id = Some(String::from("alice"));
+        alice2.owner_id = Some(String::from("alice"));
+        bob.owner_id = Some(String::from("alice"));
+        hash_service.update(&alice1.short_url.clone(), &alice1).await.unwrap();
+        hash_service.update(&alice2.short_url.clone(), &alice2).await.unwrap();
+        hash_service.update(&bob.short_url.clone(), &bob).await.unwrap();
+        // Handing a link over moves it to the index of the new owner
+        bob.owner_id = Some(String::from("bob"));
+        hash_service.upsert(&bob).await.unwrap();
+        hash_service.delete(&alice2.short_url).await.unwrap();
+
+        // Act
+        let alice_links = hash_service.get_links_by_owner("alice", None).await.unwrap();
+        let bob_links = hash_service.get_links_by_owner("bob", None).await.unwrap();
+        let skipped = hash_service.get_links_by_owner("bob", Some(QueryParams{ top: None, skip: Some(1) })).await.unwrap();
+        let unknown = hash_service.get_links_by_owner("carol", None).await.unwrap();
+        let reinserted = hash_service.insert("https://doc.rust-lang.org/").await.unwrap();
+
+        // Assert
+        assert_eq!(alice_links.len(), 1);
+        assert_eq!(alice_links[0].short_url, alice1.short_url);
+        assert_eq!(bob_links.len(), 1);
+        assert_eq!(bob_links[0].long_url, "https://github.com/rust-lang");
+        assert!(skipped.is_empty());
+        assert!(unknown.is_empty());
+        assert_eq!(reinserted.owner_id, Some(String::from("alice")));
+    }
+
+    #[actix_rt::test]
+    async fn test_insert_rejects_colliding_links() {
+        // Arrange
+        let settings = setup_settings();
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+        let long_url = "https://doc.rust-lang.org/";
+        let mut squatter = hash_service.insert("https://example.com/").await.unwrap();
+        squatter.short_url = hashfunction::hash(long_url);
+        hash_service.upsert(&squatter).await.unwrap();
+
+        // Act
+        let inserted = hash_service.insert(long_url).await;
+        let on_domain = hash_service.insert_on_domain("https://example.com/", "go.example.com").await.unwrap();
+        let mut domain_squatter = on_domain.clone();
+        domain_squatter.short_url = linkinfo::link_key(Some("go.example.com"), &hashfunction::hash_on_domain(long_url, Some("go.example.com")));
+        hash_service.upsert(&domain_squatter).await.unwrap();
+        let inserted_on_domain = hash_service.insert_on_domain(long_url, "go.example.com").await;
+
+        // Assert
+        assert!(matches!(inserted, Err(HashServiceError::ShortUrlTaken { short_url }) if short_url == squatter.short_url));
+        assert!(matches!(inserted_on_domain, Err(HashServiceError::ShortUrlTaken { .. })));
+        assert_eq!(hash_service.find(&squatter.short_url).await.unwrap().unwrap().long_url, "https://example.com/");
+    }
+
+    #[actix_rt::test]
+    async fn test_codes_per_domain() {
+        // Arrange
+        let settings = setup_settings();
+        let mut hash_service = create_hash_service(&settings).await.unwrap();
+        let mut brand_a = hash_service.insert_on_domain("https://brand-a.example/sale", "go.brand-a.com").await.unwrap();
+        let mut brand_b = hash_service.insert_on_domain("https://brand-b.example/sale", "l.brand-b.io").await.unwrap();
+        hash_service.delete(&brand_a.short_url).await.unwrap();
+        hash_service.delete(&brand_b.short_url).await.unwrap();
+        brand_a.short_url = linkinfo::link_key(Some("go.brand-a.com"), "sale");
+        brand_b.short_url = linkinfo::link_key(Some("l.brand-b.io"), "sale");
+
+        // Act
+        hash_service.upsert(&brand_a).await.unwrap();
+        hash_service.upsert(&brand_b).await.unwrap();
+        let found_a = hash_service.find("sale@go.brand-a.com").await.unwrap().unwrap();
+        let found_b = hash_service.find("sale@l.brand-b.io").await.unwrap().unwrap();
+
+        // Assert
+        assert_eq!(found_a.long_url, "https://brand-a.example/sale");
+        assert_eq!(found_b.long_url, "https://brand-b.example/sale");
+        assert_eq!((found_a.code(), found_b.code()), ("sale", "sale"));
+        assert!(hash_service.find("sale").await.unwrap().is_none());
+    }
+
     fn setup_settings() -> Settings {
-        return Settings {
+        Settings {
             debug: true,
             apiserver: ApiServer {
                 application_url: String::from("localhost"),
                 hostname: String::from("localhost"),
                 allow_origin: String::from("localhost"),
-                api_key: None,
-                google_application_credentials: None,
+                ..ApiServer::default()
             },
-            mongo_config: None,
-            redis_config: None,
-            firestore_config: None,
-            ratelimit: None,
             mode: rust_short_url::configuration::settings::Mode::InMemory,
-            coordinator: None
+            ..Settings::default()
         }
     }
 }
\ No newline at end of file
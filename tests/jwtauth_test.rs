@@ -0,0 +1,150 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use rust_short_url::{api::jwtauth::{JwtError, JwtValidator}, configuration::settings::{Jwt, JwtAlgorithm}, models::apikey::Scope};
+    use serde_json::json;
+
+    const SECRET: &str = "shared-secret";
+    const JWKS_SECRET: &str = "jwks-signing-secret-for-tests";
+    // base64url of JWKS_SECRET
+    const JWKS_SECRET_B64: &str = "andrcy1zaWduaW5nLXNlY3JldC1mb3ItdGVzdHM";
+
+    #[actix_web::test]
+    async fn test_valid_token_grants_scopes() {
+        // Arrange
+        let validator = JwtValidator::new(&setup_config()).await.unwrap();
+        let token = sign(&claims("alice", json!("links:read stats:read unknown")), None, SECRET);
+
+        // Act
+        let principal = validator.validate(&token).unwrap();
+
+        // Assert
+        assert_eq!(principal.id, "jwt:https://sso.example.com//alice");
+        assert_eq!(principal.name, "alice");
+        assert_eq!(principal.scopes, vec![Scope::LinksRead, Scope::StatsRead]);
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_tokens_are_rejected() {
+        // Arrange
+        let validator = JwtValidator::new(&setup_config()).await.unwrap();
+        let mut wrong_issuer = claims("alice", json!("links:read"));
+        wrong_issuer["iss"] = json!("https://other.example.com/");
+        let mut wrong_audience = claims("alice", json!("links:read"));
+        wrong_audience["aud"] = json!("other-service");
+        let mut expired = claims("alice", json!("links:read"));
+        expired["exp"] = json!(chrono::Utc::now().timestamp() - 3600);
+        let mut no_expiry = claims("alice", json!("links:read"));
+        no_expiry.as_object_mut().unwrap().remove("exp");
+
+        // Act
+        let results = vec![
+            validator.validate(&sign(&wrong_issuer, None, SECRET)),
+            validator.validate(&sign(&wrong_audience, None, SECRET)),
+            validator.validate(&sign(&expired, None, SECRET)),
+            validator.validate(&sign(&no_expiry, None, SECRET)),
+            validator.validate(&sign(&claims("alice", json!("links:read")), None, "other-secret")),
+            validator.validate("not-a-token")
+        ];
+
+        // Assert
+        for result in results {
+            assert!(matches!(result, Err(JwtError::InvalidToken(_))));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_scope_mapping() {
+        // Arrange
+        let mut config = setup_config();
+        config.scopes_claim = Some(String::from("groups"));
+        config.name_claim = Some(String::from("email"));
+        config.tenant_claim = Some(String::from("org"));
+        config.scope_mapping = Some(HashMap::from([
+            (String::from("shortener-admins"), vec![Scope::Admin]),
+            (String::from("marketing"), vec![Scope::LinksRead, Scope::LinksWrite])
+        ]));
+        let validator = JwtValidator::new(&config).await.unwrap();
+        let mut token_claims = claims("alice", json!(null));
+        token_claims["email"] = json!("alice@example.com");
+        token_claims["groups"] = json!(["marketing", "links:delete"]);
+        token_claims["org"] = json!("team-a");
+
+        // Act
+        let principal = validator.validate(&sign(&token_claims, None, SECRET)).unwrap();
+
+        // Assert
+        assert_eq!(principal.name, "alice@example.com");
+        assert_eq!(principal.scopes, vec![Scope::LinksRead, Scope::LinksWrite, Scope::LinksDelete]);
+        assert_eq!(principal.tenant, Some(String::from("team-a")));
+    }
+
+    #[actix_web::test]
+    async fn test_keys_from_jwks_file() {
+        // Arrange
+        let jwks_file = std::env::temp_dir().join(format!("jwks-{}.json", std::process::id()));
+        let jwks = json!({ "keys": [{ "kty": "oct", "kid": "key-1", "alg": "HS256", "k": JWKS_SECRET_B64 }] });
+        std::fs::write(&jwks_file, jwks.to_string()).unwrap();
+        let mut config = setup_config();
+        config.secret = None;
+        config.jwks_file = Some(jwks_file.to_string_lossy().to_string());
+        let validator = JwtValidator::new(&config).await.unwrap();
+
+        // Act
+        let valid = validator.validate(&sign(&claims("alice", json!("links:read")), Some("key-1"), JWKS_SECRET));
+        let unknown_key = validator.validate(&sign(&claims("alice", json!("links:read")), Some("key-2"), JWKS_SECRET));
+        std::fs::remove_file(&jwks_file).unwrap();
+
+        // Assert
+        assert!(validator.uses_jwks());
+        assert_eq!(valid.unwrap().scopes, vec![Scope::LinksRead]);
+        assert!(matches!(unknown_key, Err(JwtError::UnknownKey { .. })));
+    }
+
+    #[actix_web::test]
+    async fn test_missing_key_configuration() {
+        // Arrange
+        let mut config = setup_config();
+        config.algorithm = JwtAlgorithm::Rs256;
+
+        // Act
+        let result = JwtValidator::new(&config).await;
+
+        // Assert
+        assert!(matches!(result, Err(JwtError::MissingConfiguration { .. })));
+    }
+
+    fn sign(claims: &serde_json::Value, kid: Option<&str>, secret: &str) -> String {
+        let header = Header { kid: kid.map(String::from), ..Header::default() };
+        encode(&header, claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+    }
+
+    fn claims(subject: &str, scope: serde_json::Value) -> serde_json::Value {
+        json!({
+            "sub": subject,
+            "iss": "https://sso.example.com/",
+            "aud": "url-shortener",
+            "exp": chrono::Utc::now().timestamp() + 3600,
+            "scope": scope
+        })
+    }
+
+    fn setup_config() -> Jwt {
+        Jwt {
+            issuer: String::from("https://sso.example.com/"),
+            audience: String::from("url-shortener"),
+            algorithm: JwtAlgorithm::Hs256,
+            secret: Some(String::from(SECRET)),
+            jwks_url: None,
+            jwks_file: None,
+            jwks_refresh_interval: None,
+            leeway: None,
+            name_claim: None,
+            scopes_claim: None,
+            scope_mapping: None,
+            tenant_claim: None
+        }
+    }
+}
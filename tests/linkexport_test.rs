@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use rust_short_url::{models::{analytic::Analytic, linkinfo::{LinkInfo, LinkStatus}}, services::linkexport::{self, ExportFormat, ExportQuery, EXPORT_COLUMNS}};
+
+    #[test]
+    fn test_rows() {
+        // Arrange
+        let link = link(vec![click(1), click(15), click(28)]);
+        let october = ExportQuery {
+            from: Some(Utc.with_ymd_and_hms(2026, 10, 10, 0, 0, 0).unwrap()),
+            to: Some(Utc.with_ymd_and_hms(2026, 10, 20, 0, 0, 0).unwrap()),
+            ..ExportQuery::default()
+        };
+        let before_created = ExportQuery { to: Some(Utc.with_ymd_and_hms(2026, 9, 1, 0, 0, 0).unwrap()), ..ExportQuery::default() };
+        let other_tag = ExportQuery { tag: Some(String::from("autumn")), ..ExportQuery::default() };
+        let no_clicks = ExportQuery { from: Some(Utc.with_ymd_and_hms(2027, 1, 1, 0, 0, 0).unwrap()), tag: Some(String::from("spring")), ..ExportQuery::default() };
+
+        // Act
+        let all = ExportQuery::default().rows(&link);
+        let in_range = october.rows(&link);
+        let created_later = before_created.rows(&link);
+        let untagged = other_tag.rows(&link);
+        let without_clicks = no_clicks.rows(&link);
+
+        // Assert
+        assert_eq!(all.len(), 3);
+        assert_eq!(in_range.len(), 1);
+        assert_eq!(in_range[0].clicked_at.as_deref(), Some("2026-10-15T12:00:00.000Z"));
+        assert_eq!(in_range[0].tags.as_deref(), Some("spring,email"));
+        assert_eq!(in_range[0].status.as_deref(), Some("active"));
+        assert!(created_later.is_empty());
+        assert!(untagged.is_empty());
+        assert_eq!(without_clicks.len(), 1);
+        assert!(without_clicks[0].clicked_at.is_none());
+        assert_eq!(without_clicks[0].short_url.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn test_encode() {
+        // Arrange
+        let mut row = ExportQuery::default().rows(&link(vec![click(1)])).remove(0);
+        row.referrer = Some(String::from("https://example.com/?a=1,b=\"2\""));
+
+        // Act
+        let header = ExportFormat::Csv.header().unwrap();
+        let csv = ExportFormat::Csv.encode(&row);
+        let ndjson = ExportFormat::Ndjson.encode(&row);
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&ndjson).unwrap();
+
+        // Assert
+        assert_eq!(header, format!("{}\n", EXPORT_COLUMNS.join(",")));
+        assert!(csv.starts_with("abc,https://doc.rust-lang.org/,,alice,active,\"spring,email\",2026-09-30T00:00:00.000Z,,,"));
+        assert!(csv.contains(",\"https://example.com/?a=1,b=\"\"2\"\"\","));
+        assert!(csv.ends_with('\n'));
+        assert!(ndjson.ends_with('\n'));
+        assert_eq!(object.len(), EXPORT_COLUMNS.len());
+        assert!(EXPORT_COLUMNS.iter().all(|column| object.contains_key(*column)));
+        assert_eq!(linkexport::csv_record([Some("a"), None, Some("b\nc")].into_iter()), "a,,\"b\nc\"\n");
+        assert!(ExportQuery { format: Some(String::from("parquet")), ..ExportQuery::default() }.format().is_err());
+    }
+
+    fn link(analytics: Vec<Analytic>) -> LinkInfo {
+        LinkInfo {
+            short_url: String::from("abc"),
+            long_url: String::from("https://doc.rust-lang.org/"),
+            analytics: Some(analytics),
+            owner_id: Some(String::from("alice")),
+            domain: None,
+            created_at: Some(Utc.with_ymd_and_hms(2026, 9, 30, 0, 0, 0).unwrap()),
+            status: LinkStatus::Active,
+            status_reason: None,
+            deleted_at: None,
+            previous_status: None,
+            tags: Some(vec![String::from("spring"), String::from("email")]),
+            expires_at: None,
+            imported_clicks: None,
+            version: 0,
+            updated_at: None
+        }
+    }
+
+    fn click(day: u32) -> Analytic {
+        Analytic {
+            created_at: Utc.with_ymd_and_hms(2026, 10, day, 12, 0, 0).unwrap(),
+            language: Some(String::from("en-US")),
+            os: None,
+            ip: Some(String::from("203.0.113.7")),
+            location: None,
+            referrer: None,
+            device_type: None,
+            browser: None,
+            user_agent: None
+        }
+    }
+}
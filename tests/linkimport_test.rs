@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use rust_short_url::services::linkimport::{self, ImportSource, ImportStatus, ImportedLink};
+    use rust_short_url::services::urlnormalizer::UrlNormalizer;
+
+    #[test]
+    fn test_parse_bitly_and_shlink() {
+        // Arrange
+        let bitly = "\u{feff}Bitlink,Long URL,Created,Clicks,Tags\nbit.ly/3xYzAbc,https://doc.rust-lang.org/,2024-03-01 09:30:00 UTC,42,\"docs,rust\"\nhttps://bit.ly/2AbC,https://crates.io/,,,\nbit.ly/bad,https://docs.rs/,,many,\n";
+        let shlink_json = r#"{"shortUrls": {"data": [
+            {"shortCode": "rust", "shortUrl": "https://s.test/rust", "longUrl": "https://www.rust-lang.org/", "dateCreated": "2024-01-02T03:04:05+02:00",
+             "visitsSummary": {"total": 7, "nonBots": 5}, "tags": ["lang"], "meta": {"validUntil": "2030-01-01T00:00:00+00:00"}},
+            {"shortUrl": "https://s.test/", "longUrl": "https://example.com/"}]}}"#;
+        let shlink_csv = "createdAt,shortUrl,longUrl,title,tags,visits\n2024-01-02T03:04:05+00:00,https://s.test/docs,https://docs.rs/,Docs,a|b,3\n";
+
+        // Act
+        let bitly_links = linkimport::parse(ImportSource::Bitly, bitly).unwrap();
+        let shlink_links = linkimport::parse(ImportSource::Shlink, shlink_json).unwrap();
+        let shlink_csv_links = linkimport::parse(ImportSource::Shlink, shlink_csv).unwrap();
+        let no_links = linkimport::parse(ImportSource::Shlink, r#"{"shortUrls": 1}"#);
+
+        // Assert
+        assert_eq!(bitly_links.len(), 3);
+        assert_eq!(bitly_links[0], Ok(ImportedLink {
+            short_url: String::from("3xYzAbc"),
+            long_url: String::from("https://doc.rust-lang.org/"),
+            clicks: Some(42),
+            created_at: Some(Utc.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap()),
+            tags: Some(vec![String::from("docs"), String::from("rust")]),
+            expires_at: None
+        }));
+        assert_eq!(bitly_links[1].as_ref().unwrap().short_url, "2AbC");
+        assert_eq!(bitly_links[1].as_ref().unwrap().clicks, None);
+        assert!(bitly_links[2].as_ref().unwrap_err().contains("many"));
+        assert_eq!(shlink_links.len(), 2);
+        let rust = shlink_links[0].as_ref().unwrap();
+        assert_eq!((rust.short_url.as_str(), rust.clicks), ("rust", Some(7)));
+        assert_eq!(rust.created_at, Some(Utc.with_ymd_and_hms(2024, 1, 2, 1, 4, 5).unwrap()));
+        assert_eq!(rust.expires_at, Some(Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap()));
+        assert_eq!(rust.tags, Some(vec![String::from("lang")]));
+        assert!(shlink_links[1].is_err());
+        let docs = shlink_csv_links[0].as_ref().unwrap();
+        assert_eq!((docs.short_url.as_str(), docs.clicks), ("docs", Some(3)));
+        assert_eq!(docs.tags, Some(vec![String::from("a"), String::from("b")]));
+        assert!(no_links.is_err());
+    }
+
+    #[test]
+    fn test_parse_yourls() {
+        // Arrange
+        let sql = "-- MySQL dump\n/*!40101 SET NAMES utf8mb4 */;\n\
+            INSERT INTO `yourls_log` VALUES (1,'2024-01-01 00:00:00','abc','https://t.co/','Mozilla','1.2.3.4','US');\n\
+            INSERT INTO `yourls_url` (`keyword`, `url`, `title`, `timestamp`, `ip`, `clicks`) VALUES \
+            ('abc','https://doc.rust-lang.org/?a=1&b=it\\'s','Rust, \"docs\"','2024-01-01 10:00:00','1.2.3.4',12),\
+            ('def','https://crates.io/',NULL,'2024-01-02 10:00:00','1.2.3.4',0);\n\
+            INSERT INTO yourls_url VALUES ('ghi','https://docs.rs/','It''s','2024-01-03 10:00:00','1.2.3.4',5);\n";
+        let json = r#"{"result": "success", "links": {"link_1": {"shorturl": "https://sho.rt/abc", "url": "https://doc.rust-lang.org/", "timestamp": "2024-01-01 10:00:00", "clicks": "12"}}}"#;
+
+        // Act
+        let sql_links = linkimport::parse(ImportSource::Yourls, sql).unwrap();
+        let json_links = linkimport::parse(ImportSource::Yourls, json).unwrap();
+        let unclosed = linkimport::parse(ImportSource::Yourls, "INSERT INTO yourls_url VALUES ('abc");
+
+        // Assert
+        let links: Vec<ImportedLink> = sql_links.into_iter().map(Result::unwrap).collect();
+        assert_eq!(links.len(), 3);
+        assert_eq!(links[0].short_url, "abc");
+        assert_eq!(links[0].long_url, "https://doc.rust-lang.org/?a=1&b=it's");
+        assert_eq!(links[0].clicks, Some(12));
+        assert_eq!(links[0].created_at, Some(Utc.with_ymd_and_hms(2024, 1, 1, 10, 0, 0).unwrap()));
+        assert_eq!((links[1].short_url.as_str(), links[1].clicks), ("def", Some(0)));
+        assert_eq!((links[2].short_url.as_str(), links[2].clicks), ("ghi", Some(5)));
+        assert_eq!(json_links, vec![Ok(ImportedLink {
+            short_url: String::from("abc"),
+            long_url: String::from("https://doc.rust-lang.org/"),
+            clicks: Some(12),
+            created_at: Some(Utc.with_ymd_and_hms(2024, 1, 1, 10, 0, 0).unwrap()),
+            tags: None,
+            expires_at: None
+        })]);
+        assert!(unclosed.is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        // Arrange
+        let normalizer = UrlNormalizer::new(vec![String::from("https")], false);
+        let link = |short_url: &str, long_url: &str| Ok(ImportedLink { short_url: short_url.to_string(), long_url: long_url.to_string(), ..ImportedLink::default() });
+        let rows = vec![
+            link("abc", "HTTPS://Doc.Rust-Lang.org"),
+            link("a/b", "https://crates.io/"),
+            link("shorten", "https://crates.io/"),
+            link("def", "ftp://example.com/"),
+            Err(String::from("the link has no short code"))
+        ];
+
+        // Act
+        let (checked, results) = linkimport::check(rows, |long_url| normalizer.normalize(long_url));
+
+        // Assert
+        assert_eq!(checked.len(), 1);
+        assert_eq!(checked[0].row, 1);
+        assert_eq!(checked[0].link.long_url, "https://doc.rust-lang.org/");
+        assert!(results.iter().all(|result| result.status == ImportStatus::Failed));
+        let errors: Vec<(usize, Option<&str>)> = results.iter().map(|result| (result.row, result.error.as_deref())).collect();
+        assert_eq!(errors, vec![(2, Some("invalid_code")), (3, Some("invalid_code")), (4, Some("disallowed_scheme")), (5, Some("invalid_row"))]);
+    }
+}
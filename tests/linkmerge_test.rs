@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod tests {
+    use rust_short_url::{models::linkinfo::{LinkInfo, LinkStatus}, services::{hashserviceerror::HashServiceError, linkmerge::{merge_links, LinkMerge, LinkSource}}};
+    use async_trait::async_trait;
+    use futures_util::StreamExt;
+    use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+    #[actix_rt::test]
+    async fn test_merge_sorts_and_deduplicates() {
+        // Arrange
+        let sources = setup_sources(vec![vec!["A", "C", "E"], vec!["B", "C", "F"], vec!["A", "D"]]);
+
+        // Act
+        let links = merge_links(sources, 0, None).await;
+
+        // Assert
+        let short_urls: Vec<&str> = links.iter().map(|link| link.short_url.as_str()).collect();
+        assert_eq!(short_urls, vec!["A", "B", "C", "D", "E", "F"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_merge_top_skip() {
+        // Arrange
+        let sources = setup_sources(vec![vec!["A", "C", "E"], vec!["B", "C", "F"], vec!["A", "D"]]);
+
+        // Act
+        let links = merge_links(sources, 2, Some(3)).await;
+
+        // Assert
+        let short_urls: Vec<&str> = links.iter().map(|link| link.short_url.as_str()).collect();
+        assert_eq!(short_urls, vec!["C", "D", "E"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_merge_prefers_newer_version() {
+        // Arrange
+        let stale = setup_link("A");
+        let mut visited = setup_link("A");
+        visited.analytics = Some(vec![rust_short_url::models::analytic::Analytic {
+            created_at: chrono::Utc::now(),
+            language: None,
+            os: None,
+            ip: None,
+            location: None,
+            referrer: None,
+            device_type: None,
+            browser: None,
+            user_agent: None,
+        }]);
+        let sources: Vec<Box<dyn LinkSource>> = vec![
+            Box::new(vec![stale].into_iter()),
+            Box::new(vec![visited.clone()].into_iter())
+        ];
+
+        // Act
+        let links = merge_links(sources, 0, None).await;
+
+        // Assert
+        assert_eq!(links, vec![visited]);
+    }
+
+    #[actix_rt::test]
+    async fn test_merge_stream_reads_sources_as_needed() {
+        // Arrange
+        let reads = Arc::new(AtomicUsize::new(0));
+        let sources: Vec<Box<dyn LinkSource>> = vec![
+            Box::new(CountingSource { links: vec![setup_link("A"), setup_link("C"), setup_link("E")].into_iter(), reads: reads.clone() }),
+            Box::new(CountingSource { links: vec![setup_link("B"), setup_link("D")].into_iter(), reads: reads.clone() })
+        ];
+        let mut stream = LinkMerge::new(sources, 0, None).into_stream().boxed();
+
+        // Act
+        let first = stream.next().await.unwrap();
+        let reads_after_first = reads.load(Ordering::SeqCst);
+        let rest: Vec<LinkInfo> = stream.collect().await;
+
+        // Assert
+        assert_eq!(first.short_url, "A");
+        // The heads of both sources and the link after the first one
+        assert_eq!(reads_after_first, 3);
+        let short_urls: Vec<&str> = rest.iter().map(|link| link.short_url.as_str()).collect();
+        assert_eq!(short_urls, vec!["B", "C", "D", "E"]);
+    }
+
+    /// Source that counts the links read from it.
+    struct CountingSource {
+        links: std::vec::IntoIter<LinkInfo>,
+        reads: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl LinkSource for CountingSource {
+        async fn next_link(&mut self) -> Result<Option<LinkInfo>, HashServiceError> {
+            let link = self.links.next();
+            if link.is_some() {
+                self.reads.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(link)
+        }
+    }
+
+    fn setup_sources(sources: Vec<Vec<&str>>) -> Vec<Box<dyn LinkSource>> {
+        sources.into_iter().map(|short_urls| {
+            let links: Vec<LinkInfo> = short_urls.into_iter().map(setup_link).collect();
+            Box::new(links.into_iter()) as Box<dyn LinkSource>
+        }).collect()
+    }
+
+    fn setup_link(short_url: &str) -> LinkInfo {
+        LinkInfo { short_url: short_url.to_string(), long_url: format!("https://doc.rust-lang.org/{}", short_url), analytics: None, owner_id: None, domain: None, created_at: None, status: LinkStatus::Active, status_reason: None, deleted_at: None, previous_status: None, tags: None, expires_at: None, imported_clicks: None, version: 0, updated_at: None }
+    }
+}
@@ -11,22 +11,17 @@ mod tests {
     }
 
     fn setup_settings() -> Settings {
-        return Settings { 
+        Settings {
             debug: true,
-            apiserver: ApiServer
-            { 
+            apiserver: ApiServer {
                 application_url: String::from("localhost"),
                 hostname: String::from("localhost"),
                 allow_origin: String::from("localhost"),
-                api_key: None,
-                google_application_credentials: None,
+                ..ApiServer::default()
             },
             mongo_config: Some(MongoConfig { connection_string: String::from("invalid_string"), database_name: String::from("database"), collection_name: String::from("collection") }),
-            redis_config: None,
-            firestore_config: None,
-            ratelimit: None,
             mode: rust_short_url::configuration::settings::Mode::Mongo,
-            coordinator: None,
+            ..Settings::default()
         }
     }
 }
\ No newline at end of file
@@ -1,10 +1,15 @@
 #[cfg(test)]
 mod tests {
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
     
-    use rust_short_url::api::ratelimiter::RateLimiter;
-    use rust_short_url::configuration::settings::RateLimit;
+    use actix_web::http::header::{HeaderMap, HeaderValue};
+    use rust_short_url::api::clientratelimiter::{self, ClientRateLimiter, TrustedProxies};
+    use rust_short_url::api::ratelimiter::{self, Limiter, RateLimiter};
+    use rust_short_url::api::ratelimitpolicy::RateLimitPolicy;
+    use rust_short_url::api::ratelimitstore::{create_rate_limit_store, RateLimitStoreError};
+    use rust_short_url::models::apikey::{Principal, Scope};
+    use rust_short_url::configuration::settings::{ApiServer, Mode, RateLimit, RateLimitAlgorithm, RateLimitKey, RateLimitStoreMode, Settings};
 
     #[test]
     fn test_ratelimiter() {
@@ -25,7 +30,296 @@ mod tests {
         assert_eq!(rate_limiter.tokens, 0); // Remaining tokens should be 0
     }
     
+    #[test]
+    fn test_token_bucket_keeps_fractional_refills() {
+        // Arrange
+        let mut rate_limiter = RateLimiter::new(Some(setup_ratelimit_settings()));
+        let start = Instant::now();
+        assert!(rate_limiter.try_consume_at(10, start).allowed);
+
+        // Act
+        let after_400ms = rate_limiter.try_consume_at(1, start + Duration::from_millis(400));
+        let after_600ms = rate_limiter.try_consume_at(1, start + Duration::from_millis(600));
+        let after_1000ms = rate_limiter.try_consume_at(1, start + Duration::from_millis(1000));
+
+        // Assert
+        assert!(!after_400ms.allowed);
+        assert_eq!(after_400ms.reset, Duration::from_millis(100));
+        assert!(after_600ms.allowed);
+        // The 100ms past the first token count towards the second one
+        assert_eq!(after_600ms.reset, Duration::from_millis(400));
+        assert!(after_1000ms.allowed);
+        assert_eq!(after_1000ms.remaining, 0);
+    }
+
+    #[test]
+    fn test_sliding_window_log() {
+        // Arrange
+        let mut ratelimit_options = setup_ratelimit_settings();
+        ratelimit_options.capacity = 2;
+        ratelimit_options.fill_rate = 1;
+        ratelimit_options.algorithm = Some(RateLimitAlgorithm::SlidingWindowLog);
+        let mut rate_limiter = ratelimiter::build_limiter(&ratelimit_options);
+        let start = Instant::now();
+
+        // Act
+        let first = rate_limiter.try_consume_at(1, start);
+        let second = rate_limiter.try_consume_at(1, start + Duration::from_millis(1000));
+        let third = rate_limiter.try_consume_at(1, start + Duration::from_millis(1500));
+        let after_window = rate_limiter.try_consume_at(1, start + Duration::from_millis(2000));
+
+        // Assert
+        assert!(first.allowed);
+        assert!(second.allowed);
+        assert!(!third.allowed);
+        assert_eq!(third.remaining, 0);
+        assert_eq!(third.retry_after(), Some(Duration::from_millis(500)));
+        assert!(after_window.allowed);
+    }
+
+    #[test]
+    fn test_sliding_window_counter() {
+        // Arrange
+        let mut ratelimit_options = setup_ratelimit_settings();
+        ratelimit_options.capacity = 4;
+        ratelimit_options.algorithm = Some(RateLimitAlgorithm::SlidingWindowCounter);
+        let mut rate_limiter = ratelimiter::build_limiter(&ratelimit_options);
+        let start = Instant::now();
+
+        // Act
+        let burst = rate_limiter.try_consume_at(4, start);
+        let over_limit = rate_limiter.try_consume_at(1, start);
+        // Halfway through the next window, half of the previous window still counts
+        let next_window = start + Duration::from_millis(3000);
+        let first = rate_limiter.try_consume_at(1, next_window);
+        let second = rate_limiter.try_consume_at(1, next_window);
+        let third = rate_limiter.try_consume_at(1, next_window);
+
+        // Assert
+        assert!(burst.allowed);
+        assert!(!over_limit.allowed);
+        assert!(first.allowed);
+        assert!(second.allowed);
+        assert!(!third.allowed);
+        assert_eq!(third.remaining, 0);
+    }
+
+    #[test]
+    fn test_gcra() {
+        // Arrange
+        let mut ratelimit_options = setup_ratelimit_settings();
+        ratelimit_options.capacity = 2;
+        ratelimit_options.algorithm = Some(RateLimitAlgorithm::Gcra);
+        let mut rate_limiter = ratelimiter::build_limiter(&ratelimit_options);
+        let start = Instant::now();
+
+        // Act
+        let first = rate_limiter.try_consume_at(1, start);
+        let second = rate_limiter.try_consume_at(1, start);
+        let third = rate_limiter.try_consume_at(1, start);
+        let after_interval = rate_limiter.try_consume_at(1, start + Duration::from_millis(500));
+
+        // Assert
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+        assert!(second.allowed);
+        assert_eq!(second.remaining, 0);
+        assert!(!third.allowed);
+        assert_eq!(third.retry_after(), Some(Duration::from_millis(500)));
+        assert!(after_interval.allowed);
+    }
+
+    #[test]
+    fn test_clients_have_separate_buckets() {
+        // Arrange
+        let mut rate_limiter = ClientRateLimiter::new(Some(setup_ratelimit_settings()));
+
+        // Act
+        let first_client = rate_limiter.consume("ip:10.0.0.1", 10).allowed;
+        let first_client_again = rate_limiter.consume("ip:10.0.0.1", 1).allowed;
+        let second_client = rate_limiter.consume("ip:10.0.0.2", 10).allowed;
+
+        // Assert
+        assert!(first_client);
+        assert!(!first_client_again);
+        assert!(second_client);
+    }
+
+    #[test]
+    fn test_least_recently_used_clients_are_evicted() {
+        // Arrange
+        let mut ratelimit_options = setup_ratelimit_settings();
+        ratelimit_options.max_clients = Some(2);
+        let mut rate_limiter = ClientRateLimiter::new(Some(ratelimit_options));
+
+        // Act
+        assert!(rate_limiter.consume("ip:10.0.0.1", 10).allowed);
+        assert!(rate_limiter.consume("ip:10.0.0.2", 10).allowed);
+        assert!(rate_limiter.consume("ip:10.0.0.3", 10).allowed);
+
+        // Assert
+        assert_eq!(rate_limiter.len(), 2);
+        // The evicted client starts over with a full bucket
+        assert!(rate_limiter.consume("ip:10.0.0.1", 10).allowed);
+        assert!(!rate_limiter.consume("ip:10.0.0.3", 1).allowed);
+    }
+
+    #[test]
+    fn test_client_key() {
+        // Arrange
+        let principal = Principal { id: String::from("key:ci"), name: String::from("ci"), scopes: vec![Scope::LinksWrite], tenant: None };
+        let client_ip = "203.0.113.7".parse().ok();
+
+        // Act & Assert
+        assert_eq!(clientratelimiter::client_key(RateLimitKey::ApiKeyOrIp, Some(&principal), client_ip), "key:ci");
+        assert_eq!(clientratelimiter::client_key(RateLimitKey::ApiKeyOrIp, None, client_ip), "ip:203.0.113.7");
+        assert_eq!(clientratelimiter::client_key(RateLimitKey::Ip, Some(&principal), client_ip), "ip:203.0.113.7");
+        assert_eq!(clientratelimiter::client_key(RateLimitKey::Ip, None, None), "ip:unknown");
+        assert_eq!(clientratelimiter::client_key(RateLimitKey::Global, Some(&principal), client_ip), "global");
+    }
+
+    #[actix_rt::test]
+    async fn test_in_memory_store() {
+        // Arrange
+        let settings = setup_settings(setup_ratelimit_settings());
+        let store = create_rate_limit_store(&settings).await.unwrap();
+
+        // Act & Assert
+        assert!(store.consume("ip:10.0.0.1", 10).await.unwrap().allowed);
+        assert!(!store.consume("ip:10.0.0.1", 1).await.unwrap().allowed);
+        assert!(store.consume("ip:10.0.0.2", 1).await.unwrap().allowed);
+    }
+
+    #[actix_rt::test]
+    async fn test_redis_store_requires_redis_config() {
+        // Arrange
+        let mut ratelimit_options = setup_ratelimit_settings();
+        ratelimit_options.store = Some(RateLimitStoreMode::Redis);
+        let settings = setup_settings(ratelimit_options);
+
+        // Act
+        let result = create_rate_limit_store(&settings).await;
+
+        // Assert
+        assert!(matches!(result, Err(RateLimitStoreError::MissingConfiguration { .. })));
+    }
+
+    #[actix_rt::test]
+    async fn test_redis_store_supports_only_token_bucket() {
+        // Arrange
+        let mut ratelimit_options = setup_ratelimit_settings();
+        ratelimit_options.store = Some(RateLimitStoreMode::Redis);
+        ratelimit_options.algorithm = Some(RateLimitAlgorithm::SlidingWindowLog);
+        let settings = setup_settings(ratelimit_options);
+
+        // Act
+        let result = create_rate_limit_store(&settings).await;
+
+        // Assert
+        assert!(matches!(result, Err(RateLimitStoreError::UnsupportedAlgorithm { .. })));
+    }
+
+    #[actix_rt::test]
+    async fn test_policy_bypasses_allowlisted_clients() {
+        // Arrange
+        let mut ratelimit_options = setup_ratelimit_settings();
+        ratelimit_options.allowed_keys = Some(vec![String::from("internal")]);
+        ratelimit_options.allowed_networks = Some(vec![String::from("10.0.0.0/8"), String::from("2001:db8::1")]);
+        let settings = setup_settings(ratelimit_options.clone());
+        let store = create_rate_limit_store(&settings).await.unwrap();
+        let policy = RateLimitPolicy::new("redirect", &ratelimit_options, store).unwrap()
+            .with_trusted_proxies(TrustedProxies::new(vec!["192.0.2.10/32".parse().unwrap()]));
+
+        let internal = principal("internal");
+        let other = principal("other");
+        let mut unauthenticated_key = HeaderMap::new();
+        unauthenticated_key.append("X-API-Key".parse().unwrap(), HeaderValue::from_static("internal"));
+        let mut proxied = HeaderMap::new();
+        proxied.append("X-Real-IP".parse().unwrap(), HeaderValue::from_static("10.1.2.3"));
+
+        // Act & Assert
+        assert!(policy.bypasses(Some(&internal), &HeaderMap::new(), None));
+        assert!(!policy.bypasses(Some(&other), &HeaderMap::new(), None));
+        // Keys are only allowlisted once they authenticated
+        assert!(!policy.bypasses(None, &unauthenticated_key, None));
+        assert!(policy.bypasses(None, &HeaderMap::new(), "10.1.2.3:5000".parse().ok()));
+        assert!(policy.bypasses(None, &HeaderMap::new(), "[2001:db8::1]:5000".parse().ok()));
+        assert!(!policy.bypasses(None, &HeaderMap::new(), "192.0.2.1:5000".parse().ok()));
+        // Only trusted proxies tell the client IP
+        assert!(!policy.bypasses(None, &proxied, "192.0.2.1:5000".parse().ok()));
+        assert!(policy.bypasses(None, &proxied, "192.0.2.10:5000".parse().ok()));
+        assert_eq!(policy.client_key(None, &proxied, "192.0.2.1:5000".parse().ok()), "redirect:ip:192.0.2.1");
+        assert_eq!(policy.client_key(Some(&other), &proxied, "192.0.2.1:5000".parse().ok()), "redirect:key:other");
+    }
+
+    #[test]
+    fn test_trusted_proxies() {
+        // Arrange
+        let proxies = TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()]);
+        let mut forwarded = HeaderMap::new();
+        forwarded.append("X-Forwarded-For".parse().unwrap(), HeaderValue::from_static("198.51.100.1, 203.0.113.7, 10.0.0.2"));
+        let mut cdn = HeaderMap::new();
+        cdn.append("CF-Connecting-IP".parse().unwrap(), HeaderValue::from_static("203.0.113.9"));
+        let proxy = "10.0.0.1:5000".parse().ok();
+        let client = "192.0.2.1:5000".parse().ok();
+
+        // Act & Assert
+        // The rightmost address that isn't a trusted proxy is the client, the ones before it could be made up
+        assert_eq!(proxies.client_ip(&forwarded, proxy), "203.0.113.7".parse().ok());
+        assert_eq!(proxies.client_ip(&cdn, proxy), "203.0.113.9".parse().ok());
+        assert_eq!(proxies.client_ip(&forwarded, client), "192.0.2.1".parse().ok());
+        assert_eq!(proxies.client_ip(&cdn, client), "192.0.2.1".parse().ok());
+        assert_eq!(proxies.client_ip(&HeaderMap::new(), proxy), "10.0.0.1".parse().ok());
+        assert_eq!(TrustedProxies::default().client_ip(&forwarded, proxy), "10.0.0.1".parse().ok());
+    }
+
+    #[test]
+    fn test_invalid_trusted_proxy() {
+        // Arrange
+        let apiserver = ApiServer { trusted_proxies: Some(vec![String::from("proxy.example.com")]), ..ApiServer::default() };
+
+        // Act
+        let result = TrustedProxies::from_settings(&apiserver);
+
+        // Assert
+        assert!(matches!(result, Err(RateLimitStoreError::InvalidTrustedProxy { .. })));
+    }
+
+    #[actix_rt::test]
+    async fn test_policy_rejects_invalid_network() {
+        // Arrange
+        let mut ratelimit_options = setup_ratelimit_settings();
+        ratelimit_options.allowed_networks = Some(vec![String::from("10.0.0.0/33")]);
+        let settings = setup_settings(ratelimit_options.clone());
+        let store = create_rate_limit_store(&settings).await.unwrap();
+
+        // Act
+        let result = RateLimitPolicy::new("admin", &ratelimit_options, store);
+
+        // Assert
+        assert!(matches!(result, Err(RateLimitStoreError::InvalidNetwork { .. })));
+    }
+
+    fn setup_settings(ratelimit: RateLimit) -> Settings {
+        Settings {
+            debug: true,
+            apiserver: ApiServer {
+                application_url: String::from("localhost"),
+                hostname: String::from("localhost"),
+                allow_origin: String::from("localhost"),
+                ..ApiServer::default()
+            },
+            ratelimit: Some(ratelimit),
+            mode: Mode::InMemory,
+            ..Settings::default()
+        }
+    }
+
+    fn principal(key_name: &str) -> Principal {
+        Principal { id: format!("key:{}", key_name), name: key_name.to_string(), scopes: vec![Scope::LinksRead], tenant: None }
+    }
+
     fn setup_ratelimit_settings() -> RateLimit {
-        return RateLimit { capacity: 10, fill_rate: 2 }
+        return RateLimit { capacity: 10, fill_rate: 2, key_extractor: None, max_clients: None, store: None, algorithm: None, allowed_keys: None, allowed_networks: None, policies: None }
     }
 }
\ No newline at end of file
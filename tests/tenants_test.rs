@@ -0,0 +1,137 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use tokio::sync::Mutex;
+    use rust_short_url::{configuration::settings::{ApiServer, Mode, Settings, Tenant}, services::{hashserviceerror::HashServiceError, hashservicefactory::create_hash_service, hashfunction, tenants::{QuotaError, TenantLinks, Tenants}, usagestore::{InMemoryUsageStore, UsageStore}}};
+
+    #[actix_rt::test]
+    async fn test_tenant_links_are_isolated() {
+        // Arrange
+        let settings = setup_settings(Mode::InMemory, vec![tenant("team-a", None, None), tenant("team-b", None, None)]);
+        let mut shared = create_hash_service(&settings).await.unwrap();
+        let mut tenants = Tenants::from_settings(&settings).await.unwrap();
+
+        // Act
+        let link = tenants.get_mut("team-a").unwrap().hash_service.insert("https://doc.rust-lang.org/").await.unwrap();
+        let in_team_a = tenants.get_mut("team-a").unwrap().hash_service.find(&link.short_url).await.unwrap();
+        let in_team_b = tenants.get_mut("team-b").unwrap().hash_service.find(&link.short_url).await.unwrap();
+        let in_shared = shared.find(&link.short_url).await.unwrap();
+
+        // Assert
+        assert_eq!(tenants.ids(), vec![String::from("team-a"), String::from("team-b")]);
+        assert!(in_team_a.is_some());
+        assert!(in_team_b.is_none());
+        assert!(in_shared.is_none());
+        assert!(tenants.get_mut("team-c").is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_max_links() {
+        // Arrange
+        let settings = setup_settings(Mode::InMemory, vec![tenant("team-a", Some(2), None)]);
+        let mut tenants = Tenants::from_settings(&settings).await.unwrap();
+        let tenant_links = tenants.get_mut("team-a").unwrap();
+        let now = Utc::now();
+        for url in ["https://doc.rust-lang.org/", "https://crates.io/"] {
+            tenant_links.check_shorten(&hashfunction::hash(url), now).await.unwrap();
+            tenant_links.hash_service.insert(url).await.unwrap();
+            tenant_links.record_shortens(1, now).await.unwrap();
+        }
+
+        // Act
+        let new_url = tenant_links.check_shorten(&hashfunction::hash("https://github.com/rust-lang"), now).await;
+        let shortened_before = tenant_links.check_shorten(&hashfunction::hash("https://crates.io/"), now).await;
+        let usage = tenant_links.usage(now, 1).await.unwrap();
+
+        // Assert
+        assert!(matches!(new_url, Err(QuotaError::MaxLinks { max_links: 2, .. })));
+        assert!(shortened_before.is_ok());
+        assert_eq!(usage.links, 2);
+        assert_eq!(usage.max_links, Some(2));
+        assert_eq!(usage.shortens_today, 2);
+        assert_eq!(usage.api_keys, 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_max_shortens_per_day() {
+        // Arrange
+        let settings = setup_settings(Mode::InMemory, vec![tenant("team-a", None, Some(1))]);
+        let mut tenants = Tenants::from_settings(&settings).await.unwrap();
+        let tenant_links = tenants.get_mut("team-a").unwrap();
+        let today = Utc.with_ymd_and_hms(2026, 3, 1, 23, 0, 0).unwrap();
+        tenant_links.record_shortens(1, today).await.unwrap();
+
+        // Act
+        let same_day = tenant_links.check_shorten(&hashfunction::hash("https://doc.rust-lang.org/"), today + Duration::minutes(30)).await;
+        let next_day = tenant_links.check_shorten(&hashfunction::hash("https://doc.rust-lang.org/"), today + Duration::hours(2)).await;
+        let usage = tenant_links.usage(today + Duration::hours(2), 0).await.unwrap();
+
+        // Assert
+        assert!(matches!(same_day, Err(QuotaError::MaxShortensPerDay { max_shortens_per_day: 1, .. })));
+        assert!(next_day.is_ok());
+        assert_eq!(usage.shortens_today, 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_shortens_are_counted_in_the_usage_store() {
+        // Arrange
+        let settings = setup_settings(Mode::InMemory, vec![]);
+        let usage_store: Box<dyn UsageStore> = Box::new(InMemoryUsageStore::new());
+        let usage_store = Arc::new(Mutex::new(usage_store));
+        // Two instances serving the tenant
+        let mut first = TenantLinks::new(tenant("team-a", None, Some(2)), create_hash_service(&settings).await.unwrap())
+            .with_usage_store(usage_store.clone());
+        let mut second = TenantLinks::new(tenant("team-a", None, Some(2)), create_hash_service(&settings).await.unwrap())
+            .with_usage_store(usage_store.clone());
+        let now = Utc::now();
+
+        // Act
+        first.record_shortens(1, now).await.unwrap();
+        second.record_shortens(1, now).await.unwrap();
+        let over_quota = first.check_shorten(&hashfunction::hash("https://doc.rust-lang.org/"), now).await;
+        let usage = second.usage(now, 0).await.unwrap();
+
+        // Assert
+        assert!(matches!(over_quota, Err(QuotaError::MaxShortensPerDay { max_shortens_per_day: 2, .. })));
+        assert_eq!(usage.shortens_today, 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid_tenants_are_rejected() {
+        // Arrange
+        let invalid_id = setup_settings(Mode::InMemory, vec![tenant("team a", None, None)]);
+        let duplicate_id = setup_settings(Mode::InMemory, vec![tenant("team-a", None, None), tenant("team-a", None, None)]);
+        let coordinator = setup_settings(Mode::Coordinator, vec![tenant("team-a", None, None)]);
+
+        // Act
+        let invalid_id = Tenants::from_settings(&invalid_id).await;
+        let duplicate_id = Tenants::from_settings(&duplicate_id).await;
+        let coordinator = Tenants::from_settings(&coordinator).await;
+
+        // Assert
+        assert!(matches!(invalid_id, Err(HashServiceError::InvalidConfiguration { .. })));
+        assert!(matches!(duplicate_id, Err(HashServiceError::InvalidConfiguration { .. })));
+        assert!(matches!(coordinator, Err(HashServiceError::InvalidConfiguration { .. })));
+    }
+
+    fn tenant(id: &str, max_links: Option<usize>, max_shortens_per_day: Option<usize>) -> Tenant {
+        Tenant { id: String::from(id), max_links, max_shortens_per_day }
+    }
+
+    fn setup_settings(mode: Mode, tenants: Vec<Tenant>) -> Settings {
+        Settings {
+            debug: true,
+            apiserver: ApiServer {
+                application_url: String::from("localhost"),
+                hostname: String::from("localhost"),
+                allow_origin: String::from("localhost"),
+                ..ApiServer::default()
+            },
+            mode,
+            tenants: Some(tenants),
+            ..Settings::default()
+        }
+    }
+}
@@ -0,0 +1,80 @@
+#[cfg(test)]
+mod tests {
+    use rust_short_url::services::urlnormalizer::{UrlError, UrlNormalizer};
+
+    #[test]
+    fn test_equivalent_urls_are_normalized() {
+        // Arrange
+        let normalizer = normalizer(false);
+
+        // Act
+        let mixed_case = normalizer.normalize("HTTPS://Doc.Rust-Lang.org/Std?Q=1").unwrap();
+        let default_port = normalizer.normalize("https://doc.rust-lang.org:443/std").unwrap();
+        let other_port = normalizer.normalize("http://doc.rust-lang.org:8080/std").unwrap();
+        let no_path = normalizer.normalize("  https://doc.rust-lang.org  ").unwrap();
+        let international = normalizer.normalize("https://bücher.example/").unwrap();
+
+        // Assert
+        assert_eq!(mixed_case, "https://doc.rust-lang.org/Std?Q=1");
+        assert_eq!(default_port, "https://doc.rust-lang.org/std");
+        assert_eq!(other_port, "http://doc.rust-lang.org:8080/std");
+        assert_eq!(no_path, "https://doc.rust-lang.org/");
+        assert_eq!(international, "https://xn--bcher-kva.example/");
+    }
+
+    #[test]
+    fn test_sort_query_params() {
+        // Arrange
+        let sorting = normalizer(true);
+        let keeping = normalizer(false);
+        let long_url = "https://example.com/search?q=rust%20lang&a=2&b&a=1#top";
+
+        // Act
+        let sorted = sorting.normalize(long_url).unwrap();
+        let kept = keeping.normalize(long_url).unwrap();
+
+        // Assert
+        assert_eq!(sorted, "https://example.com/search?a=2&a=1&b&q=rust%20lang#top");
+        assert_eq!(kept, long_url);
+    }
+
+    #[test]
+    fn test_invalid_urls_are_rejected() {
+        // Arrange
+        let normalizer = normalizer(false);
+
+        // Act
+        let javascript = normalizer.normalize("javascript:alert(1)");
+        let data = normalizer.normalize("data:text/html,<script>alert(1)</script>");
+        let ftp = normalizer.normalize("ftp://example.com/file");
+        let relative = normalizer.normalize("/relative/path");
+        let empty = normalizer.normalize("");
+        let no_host = normalizer.normalize("https:");
+
+        // Assert
+        assert!(matches!(javascript, Err(UrlError::DisallowedScheme { ref scheme, .. }) if scheme == "javascript"));
+        assert!(matches!(data, Err(UrlError::DisallowedScheme { .. })));
+        assert!(matches!(ftp, Err(UrlError::DisallowedScheme { .. })));
+        assert!(matches!(relative, Err(UrlError::InvalidUrl { .. })));
+        assert!(matches!(empty, Err(UrlError::InvalidUrl { .. })));
+        assert!(matches!(no_host, Err(UrlError::InvalidUrl { .. })));
+    }
+
+    #[test]
+    fn test_allowed_schemes_are_configurable() {
+        // Arrange
+        let normalizer = UrlNormalizer::new(vec![String::from("HTTPS"), String::from("ftp")], false);
+
+        // Act
+        let ftp = normalizer.normalize("ftp://example.com/file");
+        let http = normalizer.normalize("http://example.com/");
+
+        // Assert
+        assert!(ftp.is_ok());
+        assert!(matches!(http, Err(UrlError::DisallowedScheme { ref allowed_schemes, .. }) if allowed_schemes == &vec![String::from("https"), String::from("ftp")]));
+    }
+
+    fn normalizer(sort_query_params: bool) -> UrlNormalizer {
+        UrlNormalizer::new(vec![String::from("http"), String::from("https")], sort_query_params)
+    }
+}
@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+    use std::time::Duration;
+
+    use actix_web::{post, web, App, HttpResponse, HttpServer};
+    use async_trait::async_trait;
+    use rust_short_url::services::{hashprefixsafetychecker, hashserviceerror::HashServiceError, urlsafetychecker::{HashPrefixSafetyChecker, HttpSafetyChecker, SafetyCheckers, UrlSafetyChecker, Verdict}};
+    use sha2::{Digest, Sha256};
+    use url::Url;
+
+    #[test]
+    fn test_expressions() {
+        // Arrange
+        let url = Url::parse("http://a.b.c.d.e.f.g/1/2.html?param=1").unwrap();
+        let ip = Url::parse("http://1.2.3.4/1/").unwrap();
+
+        // Act
+        let expressions = hashprefixsafetychecker::expressions(&url);
+        let ip_expressions = hashprefixsafetychecker::expressions(&ip);
+
+        // Assert
+        assert_eq!(expressions.len(), 20);
+        assert_eq!(&expressions[..4], &["a.b.c.d.e.f.g/1/2.html?param=1", "a.b.c.d.e.f.g/1/2.html", "a.b.c.d.e.f.g/", "a.b.c.d.e.f.g/1/"]);
+        assert!(expressions.contains(&String::from("c.d.e.f.g/1/")));
+        assert!(expressions.contains(&String::from("f.g/")));
+        assert!(!expressions.contains(&String::from("b.c.d.e.f.g/")));
+        assert!(!expressions.contains(&String::from("g/")));
+        assert_eq!(ip_expressions, vec!["1.2.3.4/1/", "1.2.3.4/"]);
+    }
+
+    #[actix_web::test]
+    async fn test_hash_prefix_checker() {
+        // Arrange
+        let list = format!("# Safe Browsing hashes\n{} MALWARE\n{}\n", prefix("malware.example/", 32), prefix("phishing.example/login/", 32));
+        let checker = HashPrefixSafetyChecker::parse(&list).unwrap();
+
+        // Act
+        let malware = checker.check("https://www.malware.example/download?id=1").await.unwrap();
+        let phishing = checker.check("https://phishing.example/login/index.html").await.unwrap();
+        let other_path = checker.check("https://phishing.example/about").await.unwrap();
+        let safe = checker.check("https://doc.rust-lang.org/").await.unwrap();
+        let invalid_hash = HashPrefixSafetyChecker::parse("abc");
+        // A 4 byte prefix alone would flag the safe urls that share it
+        let prefix_only = HashPrefixSafetyChecker::parse(&prefix("malware.example/", 4));
+
+        // Assert
+        assert_eq!(malware, Verdict::Flagged { threat: String::from("MALWARE") });
+        assert_eq!(phishing, Verdict::Flagged { threat: String::from("UNSAFE") });
+        assert_eq!(other_path, Verdict::Safe);
+        assert_eq!(safe, Verdict::Safe);
+        assert!(invalid_hash.is_err());
+        assert!(prefix_only.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_verdicts_are_cached_per_url() {
+        // Arrange
+        let calls = Arc::new(AtomicUsize::new(0));
+        let checkers = SafetyCheckers::new(vec![Box::new(CountingChecker { calls: calls.clone(), fail: false })], Duration::from_secs(60), 10);
+
+        // Act
+        let first = checkers.check("https://sites.example/flagged").await;
+        let same_url = checkers.check("https://sites.example/flagged").await;
+        // One flagged page doesn't quarantine the other pages of the host
+        let other_path = checkers.check("https://sites.example/docs").await;
+
+        // Assert
+        assert_eq!(first, Verdict::Flagged { threat: String::from("MALWARE") });
+        assert_eq!(same_url, first);
+        assert_eq!(other_path, Verdict::Safe);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_failed_checks_are_safe_and_not_cached() {
+        // Arrange
+        let calls = Arc::new(AtomicUsize::new(0));
+        let checkers = SafetyCheckers::new(vec![Box::new(CountingChecker { calls: calls.clone(), fail: true })], Duration::from_secs(60), 10);
+
+        // Act
+        let first = checkers.check("https://flagged.example/1").await;
+        let second = checkers.check("https://flagged.example/2").await;
+
+        // Assert
+        assert_eq!(first, Verdict::Safe);
+        assert_eq!(second, Verdict::Safe);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_http_checker() {
+        // Arrange
+        let checker = HttpSafetyChecker::new(&format!("http://{}/check", start_scanner()));
+
+        // Act
+        let flagged = checker.check("https://malware.example/").await.unwrap();
+        let safe = checker.check("https://doc.rust-lang.org/").await.unwrap();
+        let unreachable = HttpSafetyChecker::new("http://127.0.0.1:1/check").check("https://doc.rust-lang.org/").await;
+
+        // Assert
+        assert_eq!(flagged, Verdict::Flagged { threat: String::from("MALWARE") });
+        assert_eq!(safe, Verdict::Safe);
+        assert!(unreachable.is_err());
+    }
+
+    struct CountingChecker {
+        calls: Arc<AtomicUsize>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl UrlSafetyChecker for CountingChecker {
+        async fn check(&self, long_url: &str) -> Result<Verdict, HashServiceError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            match (self.fail, long_url.contains("flagged")) {
+                (true, _) => Err(HashServiceError::Unknown),
+                (false, true) => Ok(Verdict::Flagged { threat: String::from("MALWARE") }),
+                (false, false) => Ok(Verdict::Safe)
+            }
+        }
+    }
+
+    #[post("/check")]
+    async fn scan(request: web::Json<serde_json::Value>) -> HttpResponse {
+        let flagged = request["url"].as_str().unwrap_or_default().contains("malware");
+        HttpResponse::Ok().json(serde_json::json!({ "flagged": flagged, "threat": if flagged { Some("MALWARE") } else { None } }))
+    }
+
+    fn start_scanner() -> String {
+        let server = HttpServer::new(|| App::new().service(scan))
+            .workers(1)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let address = server.addrs()[0];
+        actix_rt::spawn(server.run());
+
+        format!("127.0.0.1:{}", address.port())
+    }
+
+    fn prefix(expression: &str, length: usize) -> String {
+        hex::encode(&Sha256::digest(expression.as_bytes())[..length])
+    }
+}